@@ -0,0 +1,223 @@
+//! Input idle time and screen lock state, read through a platform backend.
+//!
+//! The poll loop feeds each reading to an [`IdleTracker`], which reports when
+//! the user went idle (backdated to their last input) and when they came back.
+
+use anyhow::bail;
+use std::process::Command;
+use std::time::Duration;
+
+/// Source of idle readings. `None` means the reading is unavailable.
+pub trait IdleBackend: Send {
+    /// Time since the last keyboard or mouse input.
+    fn idle_time(&self) -> Option<Duration>;
+    /// Whether the screen is locked.
+    fn screen_locked(&self) -> Option<bool>;
+}
+
+/// The backend `name` from `collectors.appmon.idle_backend`.
+pub fn backend(name: &str) -> anyhow::Result<Box<dyn IdleBackend>> {
+    Ok(match name {
+        "auto" if cfg!(target_os = "macos") => Box::new(MacOs),
+        "auto" => Box::new(X11),
+        "macos" => Box::new(MacOs),
+        "x11" => Box::new(X11),
+        "none" => Box::new(Disabled),
+        other => bail!("unknown idle backend '{other}' (expected auto, macos, x11 or none)"),
+    })
+}
+
+/// `ioreg`: `HIDIdleTime` of the HID system, and the console session's lock flag.
+struct MacOs;
+
+impl IdleBackend for MacOs {
+    fn idle_time(&self) -> Option<Duration> {
+        let out = run("ioreg", &["-c", "IOHIDSystem", "-d", "4"])?;
+        let line = out.lines().find(|l| l.contains("\"HIDIdleTime\""))?;
+        let nanos = line.rsplit('=').next()?.trim().parse().ok()?;
+        Some(Duration::from_nanos(nanos))
+    }
+
+    fn screen_locked(&self) -> Option<bool> {
+        let out = run("ioreg", &["-n", "Root", "-d", "1"])?;
+        Some(out.contains("\"CGSSessionScreenIsLocked\"=Yes"))
+    }
+}
+
+/// `xprintidle` for input, logind's `LockedHint` for the lock.
+struct X11;
+
+impl IdleBackend for X11 {
+    fn idle_time(&self) -> Option<Duration> {
+        let ms = run("xprintidle", &[])?.trim().parse().ok()?;
+        Some(Duration::from_millis(ms))
+    }
+
+    fn screen_locked(&self) -> Option<bool> {
+        let session = std::env::var("XDG_SESSION_ID").unwrap_or_else(|_| "self".to_string());
+        let out = run(
+            "loginctl",
+            &["show-session", &session, "-p", "LockedHint", "--value"],
+        )?;
+        Some(out.trim() == "yes")
+    }
+}
+
+struct Disabled;
+
+impl IdleBackend for Disabled {
+    fn idle_time(&self) -> Option<Duration> {
+        None
+    }
+
+    fn screen_locked(&self) -> Option<bool> {
+        None
+    }
+}
+
+fn run(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdleReason {
+    Input,
+    Locked,
+}
+
+impl IdleReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Input => "input",
+            Self::Locked => "locked",
+        }
+    }
+}
+
+/// A transition between active and idle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdleChange {
+    Started { at: i64, reason: IdleReason },
+    Ended { at: i64 },
+}
+
+/// Turns idle readings into transitions.
+pub struct IdleTracker {
+    /// Input idle this long counts as idle.
+    after: Duration,
+    idle: bool,
+}
+
+impl IdleTracker {
+    pub fn new(after: Duration) -> Self {
+        Self { after, idle: false }
+    }
+
+    /// Feed a reading taken at `now` (ms). Transitions are dated to the
+    /// last input before them.
+    pub fn update(
+        &mut self,
+        now: i64,
+        idle_for: Option<Duration>,
+        locked: bool,
+    ) -> Option<IdleChange> {
+        let reason = if locked {
+            Some(IdleReason::Locked)
+        } else if idle_for.is_some_and(|d| d >= self.after) {
+            Some(IdleReason::Input)
+        } else {
+            None
+        };
+        let last_input = now - idle_for.map_or(0, |d| d.as_millis() as i64);
+        match (self.idle, reason) {
+            (false, Some(reason)) => {
+                self.idle = true;
+                Some(IdleChange::Started {
+                    at: last_input,
+                    reason,
+                })
+            }
+            (true, None) => {
+                self.idle = false;
+                Some(IdleChange::Ended { at: last_input })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AFTER: Duration = Duration::from_secs(60);
+
+    fn secs(s: u64) -> Option<Duration> {
+        Some(Duration::from_secs(s))
+    }
+
+    #[test]
+    fn idle_starts_at_the_threshold_backdated_to_the_last_input() {
+        let mut tracker = IdleTracker::new(AFTER);
+        assert_eq!(tracker.update(100_000, secs(59), false), None);
+        assert_eq!(
+            tracker.update(105_000, secs(60), false),
+            Some(IdleChange::Started {
+                at: 45_000,
+                reason: IdleReason::Input
+            })
+        );
+    }
+
+    #[test]
+    fn input_ends_idle_at_the_first_input() {
+        let mut tracker = IdleTracker::new(AFTER);
+        tracker.update(100_000, secs(90), false);
+        assert_eq!(
+            tracker.update(200_000, secs(2), false),
+            Some(IdleChange::Ended { at: 198_000 })
+        );
+    }
+
+    #[test]
+    fn locking_starts_idle_without_an_idle_time() {
+        let mut tracker = IdleTracker::new(AFTER);
+        assert_eq!(
+            tracker.update(100_000, None, true),
+            Some(IdleChange::Started {
+                at: 100_000,
+                reason: IdleReason::Locked
+            })
+        );
+        assert_eq!(
+            tracker.update(130_000, None, false),
+            Some(IdleChange::Ended { at: 130_000 })
+        );
+    }
+
+    #[test]
+    fn readings_in_the_same_state_change_nothing() {
+        let mut tracker = IdleTracker::new(AFTER);
+        assert_eq!(tracker.update(100_000, secs(1), false), None);
+        assert_eq!(tracker.update(105_000, secs(6), false), None);
+        assert!(tracker.update(200_000, secs(100), false).is_some());
+        assert_eq!(tracker.update(205_000, secs(105), false), None);
+        assert_eq!(tracker.update(210_000, secs(110), true), None);
+        assert_eq!(tracker.update(215_000, None, true), None);
+    }
+
+    #[test]
+    fn unlocking_while_input_is_still_idle_stays_idle() {
+        let mut tracker = IdleTracker::new(AFTER);
+        assert!(tracker.update(100_000, secs(10), true).is_some());
+        assert_eq!(tracker.update(400_000, secs(310), false), None);
+        assert_eq!(
+            tracker.update(410_000, secs(1), false),
+            Some(IdleChange::Ended { at: 409_000 })
+        );
+    }
+}
@@ -1,13 +1,21 @@
+mod idle;
+
 use anyhow::{Context, Result};
 use cronos_common::{CronosConfig, CronosPaths};
 use cronos_model::*;
-use cronos_proto::{read_frame, write_frame, Message, MessageKind, PROTOCOL_VERSION};
+use cronos_proto::transport::{
+    self, daemon_endpoint, send_heartbeat, Endpoint, HEARTBEAT_INTERVAL,
+};
+use cronos_proto::{
+    read_frame, supported_features, write_frame, write_frame_with, Codec, Message, MessageKind,
+    Negotiated, VersionRange, PROTOCOL_VERSION,
+};
 use std::collections::HashMap;
-use std::path::{Path, PathBuf};
 use std::process::Command;
-use tokio::net::UnixStream;
 use tracing::{debug, error, info, warn};
 
+use idle::{IdleBackend, IdleChange, IdleTracker};
+
 /// Get the currently focused application name and its front window title via osascript.
 ///
 /// Returns `None` if the query fails (e.g. no window focused, permission denied).
@@ -76,16 +84,57 @@ fn build_event(app_name: &str, window_title: &str) -> Event {
     }
 }
 
+/// Build a cronos `Event` for the user going idle or coming back on `device`.
+fn build_idle_event(device: &str, change: IdleChange) -> Event {
+    let (kind, timestamp, metadata) = match change {
+        IdleChange::Started { at, reason } => (
+            EventKind::IdleStarted,
+            at,
+            HashMap::from([(
+                "reason".to_string(),
+                serde_json::Value::String(reason.as_str().to_string()),
+            )]),
+        ),
+        IdleChange::Ended { at } => (EventKind::IdleEnded, at, HashMap::new()),
+    };
+
+    Event {
+        id: EventId::new(),
+        timestamp,
+        source: CollectorSource::AppMonitor,
+        kind,
+        subject: EntityRef {
+            kind: EntityKind::Custom("device".to_string()),
+            identity: device.to_string(),
+            attributes: HashMap::new(),
+        },
+        context: vec![],
+        metadata,
+    }
+}
+
+/// This machine's host name, naming the device idle events are about.
+fn device_name() -> String {
+    Command::new("hostname")
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "localhost".to_string())
+}
+
 /// Perform the handshake with the daemon and then stream events.
 async fn run_session(
-    socket_path: &Path,
+    endpoint: &Endpoint,
+    token: Option<String>,
     rx: &mut tokio::sync::mpsc::Receiver<Event>,
 ) -> Result<()> {
-    info!(path = %socket_path.display(), "connecting to daemon");
+    info!(endpoint = %endpoint, "connecting to daemon");
 
-    let stream = UnixStream::connect(socket_path)
+    let stream = transport::connect(endpoint)
         .await
-        .context("failed to connect to daemon socket")?;
+        .context("failed to connect to daemon")?;
 
     let (mut reader, mut writer) = tokio::io::split(stream);
 
@@ -96,48 +145,87 @@ async fn run_session(
             name: "cronos-collect-appmon".into(),
             collector_version: env!("CARGO_PKG_VERSION").into(),
             source: CollectorSource::AppMonitor,
+            token,
+            protocol: Some(VersionRange::SUPPORTED),
+            features: supported_features(),
+            codecs: Codec::ALL.to_vec(),
         },
     );
     write_frame(&mut writer, &handshake).await?;
 
     let ack = read_frame(&mut reader).await?;
-    match &ack.kind {
-        MessageKind::Ack { .. } => info!("handshake accepted"),
-        MessageKind::Error { message, .. } => {
-            anyhow::bail!("handshake rejected: {message}");
+    // Events are written with the codec the daemon picked.
+    let codec = match Negotiated::from_response(&ack.kind) {
+        Some(Negotiated {
+            version,
+            features,
+            codec,
+        }) => {
+            info!(version, ?features, ?codec, "handshake accepted");
+            codec
         }
-        other => {
-            anyhow::bail!("unexpected handshake response: {other:?}");
-        }
-    }
+        None => match ack.kind {
+            MessageKind::Error { message, .. } => {
+                anyhow::bail!("handshake rejected: {message}");
+            }
+            other => {
+                anyhow::bail!("unexpected handshake response: {other:?}");
+            }
+        },
+    };
 
     // --- event loop -------------------------------------------------------
+    let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+    heartbeat.tick().await;
     loop {
-        let event = match rx.recv().await {
-            Some(ev) => ev,
-            None => {
-                info!("poll channel closed, exiting session");
-                return Ok(());
+        let event = tokio::select! {
+            ev = rx.recv() => match ev {
+                Some(ev) => ev,
+                None => {
+                    info!("poll channel closed, exiting session");
+                    return Ok(());
+                }
+            },
+            _ = heartbeat.tick() => {
+                send_heartbeat(&mut reader, &mut writer, codec).await?;
+                continue;
             }
         };
 
         let msg_id = event.id.to_string();
         let msg = Message::new(msg_id, MessageKind::EmitEvent { event });
 
-        debug!("sending app focus event");
-        write_frame(&mut writer, &msg).await?;
+        debug!("sending app monitor event");
+        write_frame_with(&mut writer, &msg, codec).await?;
         let _ack = read_frame(&mut reader).await?;
     }
 }
 
-/// Poll active window at a fixed interval, emitting events only on change.
-async fn poll_loop(tx: tokio::sync::mpsc::Sender<Event>, poll_interval_ms: u64) {
+/// Poll active window and idle state at a fixed interval, emitting events
+/// only on change.
+async fn poll_loop(
+    tx: tokio::sync::mpsc::Sender<Event>,
+    poll_interval_ms: u64,
+    idle_backend: Box<dyn IdleBackend>,
+    mut idle_tracker: IdleTracker,
+) {
     let mut last_app = String::new();
     let mut last_title = String::new();
+    let device = device_name();
 
     let interval = std::time::Duration::from_millis(poll_interval_ms);
 
     loop {
+        let locked = idle_backend.screen_locked().unwrap_or(false);
+        let now = cronos_common::now_ms();
+        if let Some(change) = idle_tracker.update(now, idle_backend.idle_time(), locked) {
+            debug!(?change, "idle state changed");
+            if tx.send(build_idle_event(&device, change)).await.is_err() {
+                error!("event channel closed");
+                return;
+            }
+        }
+
         if let Some((app, title)) = get_active_window() {
             if app != last_app || title != last_title {
                 debug!(app = %app, title = %title, "focus changed");
@@ -162,32 +250,31 @@ async fn main() -> Result<()> {
 
     cronos_common::init_tracing(&config.daemon.log_level);
 
-    // Resolve socket path (config override or default).
-    let socket_path = if config.daemon.socket_path.is_empty() {
-        paths.socket_file.clone()
-    } else {
-        PathBuf::from(&config.daemon.socket_path)
-    };
+    // Resolve the daemon endpoint (Unix socket or remote TCP).
+    let endpoint = daemon_endpoint(&config, &paths);
+    let token = Some(config.daemon.remote.token.clone()).filter(|t| !t.is_empty());
 
     // --- set up polling channel -------------------------------------------
     let (tx, mut rx) = tokio::sync::mpsc::channel::<Event>(4096);
 
-    // Default 3s poll interval
-    let poll_interval_ms = 3000;
+    let appmon = &config.collectors.appmon;
+    let poll_interval_ms = appmon.poll_interval_ms;
+    let idle_backend = idle::backend(&appmon.idle_backend)?;
+    let idle_tracker = IdleTracker::new(std::time::Duration::from_secs(appmon.idle_after_secs));
 
     tokio::spawn(async move {
-        poll_loop(tx, poll_interval_ms).await;
+        poll_loop(tx, poll_interval_ms, idle_backend, idle_tracker).await;
     });
 
     // --- connect with reconnection loop ----------------------------------
     info!(
         version = PROTOCOL_VERSION,
-        socket = %socket_path.display(),
+        endpoint = %endpoint,
         "cronos-collect-appmon starting"
     );
 
     loop {
-        match run_session(&socket_path, &mut rx).await {
+        match run_session(&endpoint, token.clone(), &mut rx).await {
             Ok(()) => {
                 info!("session ended cleanly");
                 break;
@@ -1,12 +1,17 @@
 use anyhow::{Context, Result};
 use cronos_common::{CronosConfig, CronosPaths};
 use cronos_model::*;
-use cronos_proto::{read_frame, write_frame, Message, MessageKind, PROTOCOL_VERSION};
+use cronos_proto::transport::{
+    self, daemon_endpoint, send_heartbeat, Endpoint, HEARTBEAT_INTERVAL,
+};
+use cronos_proto::{
+    read_frame, supported_features, write_frame, write_frame_with, Codec, Message, MessageKind,
+    Negotiated, VersionRange, PROTOCOL_VERSION,
+};
 use glob_match::glob_match;
 use notify::{EventKind as NotifyEventKind, RecursiveMode, Watcher};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use tokio::net::UnixStream;
 use tracing::{debug, error, info, warn};
 
 /// Project-root marker files. Walking up from a changed file, the first
@@ -104,14 +109,15 @@ fn build_event(path: &Path, kind: EventKind) -> Event {
 
 /// Perform the handshake with the daemon and then stream events.
 async fn run_session(
-    socket_path: &Path,
+    endpoint: &Endpoint,
+    token: Option<String>,
     rx: &mut tokio::sync::mpsc::Receiver<notify::Event>,
 ) -> Result<()> {
-    info!(path = %socket_path.display(), "connecting to daemon");
+    info!(endpoint = %endpoint, "connecting to daemon");
 
-    let stream = UnixStream::connect(socket_path)
+    let stream = transport::connect(endpoint)
         .await
-        .context("failed to connect to daemon socket")?;
+        .context("failed to connect to daemon")?;
 
     let (mut reader, mut writer) = tokio::io::split(stream);
 
@@ -122,28 +128,50 @@ async fn run_session(
             name: "cronos-collect-fs".into(),
             collector_version: env!("CARGO_PKG_VERSION").into(),
             source: CollectorSource::Filesystem,
+            token,
+            protocol: Some(VersionRange::SUPPORTED),
+            features: supported_features(),
+            codecs: Codec::ALL.to_vec(),
         },
     );
     write_frame(&mut writer, &handshake).await?;
 
     let ack = read_frame(&mut reader).await?;
-    match &ack.kind {
-        MessageKind::Ack { .. } => info!("handshake accepted"),
-        MessageKind::Error { message, .. } => {
-            anyhow::bail!("handshake rejected: {message}");
+    // Events are written with the codec the daemon picked.
+    let codec = match Negotiated::from_response(&ack.kind) {
+        Some(Negotiated {
+            version,
+            features,
+            codec,
+        }) => {
+            info!(version, ?features, ?codec, "handshake accepted");
+            codec
         }
-        other => {
-            anyhow::bail!("unexpected handshake response: {other:?}");
-        }
-    }
+        None => match ack.kind {
+            MessageKind::Error { message, .. } => {
+                anyhow::bail!("handshake rejected: {message}");
+            }
+            other => {
+                anyhow::bail!("unexpected handshake response: {other:?}");
+            }
+        },
+    };
 
     // --- event loop -------------------------------------------------------
+    let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+    heartbeat.tick().await;
     loop {
-        let notify_event = match rx.recv().await {
-            Some(ev) => ev,
-            None => {
-                info!("watcher channel closed, exiting session");
-                return Ok(());
+        let notify_event = tokio::select! {
+            ev = rx.recv() => match ev {
+                Some(ev) => ev,
+                None => {
+                    info!("watcher channel closed, exiting session");
+                    return Ok(());
+                }
+            },
+            _ = heartbeat.tick() => {
+                send_heartbeat(&mut reader, &mut writer, codec).await?;
+                continue;
             }
         };
 
@@ -158,7 +186,7 @@ async fn run_session(
             let msg = Message::new(msg_id, MessageKind::EmitEvent { event });
 
             debug!(path = %path.display(), "sending event");
-            write_frame(&mut writer, &msg).await?;
+            write_frame_with(&mut writer, &msg, codec).await?;
             let _ack = read_frame(&mut reader).await?;
         }
     }
@@ -177,12 +205,9 @@ async fn main() -> Result<()> {
         return Ok(());
     }
 
-    // Resolve socket path (config override or default).
-    let socket_path = if config.daemon.socket_path.is_empty() {
-        paths.socket_file.clone()
-    } else {
-        PathBuf::from(&config.daemon.socket_path)
-    };
+    // Resolve the daemon endpoint (Unix socket or remote TCP).
+    let endpoint = daemon_endpoint(&config, &paths);
+    let token = Some(config.daemon.remote.token.clone()).filter(|t| !t.is_empty());
 
     // --- set up file watcher ---------------------------------------------
     let ignore_patterns = fs_cfg.ignore_patterns.clone();
@@ -226,12 +251,12 @@ async fn main() -> Result<()> {
     // --- connect with reconnection loop ----------------------------------
     info!(
         version = PROTOCOL_VERSION,
-        socket = %socket_path.display(),
+        endpoint = %endpoint,
         "cronos-collect-fs starting"
     );
 
     loop {
-        match run_session(&socket_path, &mut rx).await {
+        match run_session(&endpoint, token.clone(), &mut rx).await {
             Ok(()) => {
                 info!("session ended cleanly");
                 break;
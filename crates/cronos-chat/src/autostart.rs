@@ -48,30 +48,3 @@ pub async fn ensure_daemon(socket_path: &Path) -> Result<()> {
 
     anyhow::bail!("daemon did not become ready within 5 seconds")
 }
-
-/// Attempt to spawn the filesystem collector if the binary exists alongside cronos.
-pub fn spawn_collector_if_absent() {
-    spawn_binary_if_present("cronos-collect-fs");
-}
-
-/// Attempt to spawn the app monitor collector if the binary exists alongside cronos.
-pub fn spawn_appmon_if_absent() {
-    spawn_binary_if_present("cronos-collect-appmon");
-}
-
-fn spawn_binary_if_present(name: &str) {
-    let Ok(exe) = std::env::current_exe() else { return };
-    let Some(dir) = exe.parent() else { return };
-    let binary = dir.join(name);
-
-    if !binary.exists() {
-        return;
-    }
-
-    // Fire-and-forget
-    let _ = Command::new(&binary)
-        .stdin(Stdio::null())
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .spawn();
-}
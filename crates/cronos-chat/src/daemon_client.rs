@@ -4,22 +4,79 @@ use std::path::Path;
 use tokio::net::UnixStream;
 
 /// Send a request to the daemon over the Unix socket and return the response.
+///
+/// The connection first negotiates the protocol, so the daemon may answer in
+/// MessagePack and split large query results; both are undone here. When
+/// `CRONOS_TOKEN` is set, the connection then authenticates with it so the
+/// request runs with that token's capabilities.
+///
+/// Daemons that predate negotiation cannot parse `Hello` and close the
+/// connection, so the request is retried once on a fresh connection without
+/// negotiating. Such daemons have no tokens and speak only JSON.
 pub async fn send_request(msg: Message, socket_path: &Path) -> Result<Message> {
-    let stream = UnixStream::connect(socket_path)
+    let (mut reader, mut writer) = connect(socket_path).await?.into_split();
+    let hello = Message::new(
+        ulid::Ulid::new().to_string(),
+        MessageKind::Hello {
+            protocol: VersionRange::SUPPORTED,
+            features: supported_features(),
+            codecs: Codec::ALL.to_vec(),
+        },
+    );
+    write_frame(&mut writer, &hello)
         .await
-        .with_context(|| {
-            format!(
-                "cannot connect to daemon at {}. Is it running?",
-                socket_path.display()
-            )
-        })?;
+        .context("sending hello")?;
+    let welcome = match read_frame(&mut reader).await {
+        Ok(welcome) => welcome,
+        Err(FrameError::ConnectionClosed) => return send_unnegotiated(msg, socket_path).await,
+        Err(e) => return Err(e).context("reading hello response"),
+    };
+    if let MessageKind::Error {
+        code: ErrorCode::IncompatibleVersion,
+        message,
+        ..
+    } = welcome.kind
+    {
+        anyhow::bail!("daemon is incompatible with this client: {message}");
+    }
+    if let Ok(token) = std::env::var("CRONOS_TOKEN") {
+        let auth = Message::new(ulid::Ulid::new().to_string(), MessageKind::Authenticate { token });
+        write_frame(&mut writer, &auth)
+            .await
+            .context("sending token")?;
+        let response = read_frame(&mut reader)
+            .await
+            .context("reading authentication response")?;
+        if let MessageKind::Error { message, .. } = response.kind {
+            anyhow::bail!("authentication failed: {message}");
+        }
+    }
+    write_frame(&mut writer, &msg)
+        .await
+        .context("sending request")?;
+    let response = read_response(&mut reader)
+        .await
+        .context("reading response")?;
+    Ok(response)
+}
 
-    let (mut reader, mut writer) = stream.into_split();
+/// Send `msg` as the only frame of a new connection, for version 1 daemons.
+async fn send_unnegotiated(msg: Message, socket_path: &Path) -> Result<Message> {
+    let (mut reader, mut writer) = connect(socket_path).await?.into_split();
     write_frame(&mut writer, &msg)
         .await
         .context("sending request")?;
     let response = read_frame(&mut reader)
         .await
-        .context("reading response")?;
+        .context("reading response from a daemon that predates protocol negotiation")?;
     Ok(response)
 }
+
+async fn connect(socket_path: &Path) -> Result<UnixStream> {
+    UnixStream::connect(socket_path).await.with_context(|| {
+        format!(
+            "cannot connect to daemon at {}. Is it running?",
+            socket_path.display()
+        )
+    })
+}
@@ -1,12 +1,83 @@
 use anyhow::{bail, Result};
-use cronos_model::EntityId;
+use cronos_model::{Annotation, AnnotationId, AnnotationTarget, EntityId};
 use cronos_proto::*;
 use std::path::Path;
 
 use crate::daemon_client;
 
-/// Return the OpenAI function-calling tool schema for the 5 Cronos tools.
+/// Tools that list events, entities or sessions and accept `filter_properties`.
+const LIST_TOOLS: &[&str] = &[
+    "cronos_recent",
+    "cronos_search",
+    "cronos_timeline",
+    "cronos_related",
+    "cronos_sessions",
+];
+
+/// JSON schema for the filter and pagination arguments shared by list tools.
+fn filter_properties() -> serde_json::Value {
+    serde_json::json!({
+        "sources": {
+            "type": "array",
+            "items": {
+                "type": "string",
+                "enum": ["filesystem", "browser", "git", "terminal", "app_monitor"]
+            },
+            "description": "Only events from these collectors"
+        },
+        "event_kinds": {
+            "type": "array",
+            "items": {
+                "type": "string",
+                "enum": [
+                    "file_opened", "file_modified", "file_created", "file_deleted",
+                    "url_visited", "tab_focused", "commit_created", "branch_changed",
+                    "command_executed", "app_focused"
+                ]
+            },
+            "description": "Only events of these kinds"
+        },
+        "entity_kinds": {
+            "type": "array",
+            "items": {
+                "type": "string",
+                "enum": [
+                    "project", "file", "repository", "branch", "commit", "url",
+                    "domain", "app", "terminal_session", "terminal_command"
+                ]
+            },
+            "description": "Only events whose subject is, or entities that are, of these kinds"
+        },
+        "project": {
+            "type": "string",
+            "description": "Only activity in this project (path or directory name)"
+        },
+        "cursor": {
+            "type": "string",
+            "description": "next_cursor from a previous result, to fetch the next page"
+        }
+    })
+}
+
+/// Return the OpenAI function-calling tool schema for the Cronos tools.
 pub fn tool_definitions() -> Vec<serde_json::Value> {
+    let mut tools = base_tool_definitions();
+    for tool in &mut tools {
+        let function = &mut tool["function"];
+        if !LIST_TOOLS.contains(&function["name"].as_str().unwrap_or_default()) {
+            continue;
+        }
+        if let (Some(props), serde_json::Value::Object(extra)) = (
+            function["parameters"]["properties"].as_object_mut(),
+            filter_properties(),
+        ) {
+            props.extend(extra);
+        }
+    }
+    tools
+}
+
+fn base_tool_definitions() -> Vec<serde_json::Value> {
     vec![
         serde_json::json!({
             "type": "function",
@@ -60,6 +131,10 @@ pub fn tool_definitions() -> Vec<serde_json::Value> {
                         "to_ms": {
                             "type": "integer",
                             "description": "End timestamp in milliseconds since Unix epoch"
+                        },
+                        "limit": {
+                            "type": "integer",
+                            "description": "Maximum number of events per page (default 1000)"
                         }
                     },
                     "required": ["from_ms", "to_ms"]
@@ -70,7 +145,7 @@ pub fn tool_definitions() -> Vec<serde_json::Value> {
             "type": "function",
             "function": {
                 "name": "cronos_related",
-                "description": "Traverse the context graph from an entity to find related entities.",
+                "description": "Find the entities most strongly related to an entity in the context graph. Results are ranked by score (product of edge strengths along the strongest path, 0..1); `related` gives each entity's score and the ids of the `edges` on the path that explains the connection.",
                 "parameters": {
                     "type": "object",
                     "properties": {
@@ -81,12 +156,52 @@ pub fn tool_definitions() -> Vec<serde_json::Value> {
                         "depth": {
                             "type": "integer",
                             "description": "Traversal depth (default 2)"
+                        },
+                        "limit": {
+                            "type": "integer",
+                            "description": "Maximum number of entities per page (default 100)"
+                        },
+                        "relations": {
+                            "type": "array",
+                            "items": {
+                                "type": "string",
+                                "enum": [
+                                    "belongs_to", "contains", "references", "occurred_during",
+                                    "visited", "related_to"
+                                ]
+                            },
+                            "description": "Only follow edges with these relations"
                         }
                     },
                     "required": ["entity_id"]
                 }
             }
         }),
+        serde_json::json!({
+            "type": "function",
+            "function": {
+                "name": "cronos_connection",
+                "description": "Explain how two entities are connected (e.g. a URL and a commit): the strongest path between them in the context graph. `entities` lists the path in order from `from_id` to `to_id`, `edges` the edges between consecutive entities with their relation, strength and created_at/last_reinforced times, and `related` the path's score. An empty result means they are not connected within max_depth edges.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "from_id": {
+                            "type": "string",
+                            "description": "ULID of the entity the path starts at"
+                        },
+                        "to_id": {
+                            "type": "string",
+                            "description": "ULID of the entity the path ends at"
+                        },
+                        "max_depth": {
+                            "type": "integer",
+                            "description": "Longest path to consider, in edges (default 4)"
+                        }
+                    },
+                    "required": ["from_id", "to_id"]
+                }
+            }
+        }),
         serde_json::json!({
             "type": "function",
             "function": {
@@ -134,15 +249,173 @@ pub fn tool_definitions() -> Vec<serde_json::Value> {
                         "date": {
                             "type": "string",
                             "description": "Date in YYYY-MM-DD format"
+                        },
+                        "timezone": {
+                            "type": "string",
+                            "description": "IANA timezone the day is counted in, e.g. Europe/Berlin; defaults to the user's"
                         }
                     },
                     "required": ["date"]
                 }
             }
         }),
+        serde_json::json!({
+            "type": "function",
+            "function": {
+                "name": "cronos_summary",
+                "description": "Get totals for the day, week (Monday to Sunday) or month containing a date — time per category, app and project, time per hour of day, the most-touched files and most-visited domains, the longest focus blocks and the number of context switches (as the focus report counts them). Prefer this over adding up sessions yourself.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "period": {
+                            "type": "string",
+                            "enum": ["day", "week", "month"],
+                            "description": "Length of the period to summarize"
+                        },
+                        "date": {
+                            "type": "string",
+                            "description": "Any date in the period, YYYY-MM-DD"
+                        },
+                        "timezone": {
+                            "type": "string",
+                            "description": "IANA timezone the period is counted in, e.g. Europe/Berlin; defaults to the user's"
+                        }
+                    },
+                    "required": ["period", "date"]
+                }
+            }
+        }),
+        serde_json::json!({
+            "type": "function",
+            "function": {
+                "name": "cronos_focus_report",
+                "description": "Measure deep work in a time range: focus blocks (long uninterrupted stretches on one project), context switches per hour, a fragmentation score from 0 (one unbroken stretch) to 1 (many short pieces), and which communication apps interrupted project work.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "from_ms": {
+                            "type": "integer",
+                            "description": "Start of range as Unix timestamp in milliseconds"
+                        },
+                        "to_ms": {
+                            "type": "integer",
+                            "description": "End of range as Unix timestamp in milliseconds"
+                        }
+                    },
+                    "required": ["from_ms", "to_ms"]
+                }
+            }
+        }),
+        serde_json::json!({
+            "type": "function",
+            "function": {
+                "name": "cronos_annotations",
+                "description": "List notes the user attached to time ranges, sessions or entities, with their tags, e.g. \"on call\" or \"client workshop\". Use these to explain what the user was doing beyond what was tracked. Proposed annotations were suggested by you and not yet accepted.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "from_ms": {
+                            "type": "integer",
+                            "description": "Only annotations overlapping a range starting here, as Unix timestamp in milliseconds"
+                        },
+                        "to_ms": {
+                            "type": "integer",
+                            "description": "End of that range as Unix timestamp in milliseconds"
+                        },
+                        "tag": {
+                            "type": "string",
+                            "description": "Only annotations with this tag"
+                        },
+                        "entity_id": {
+                            "type": "string",
+                            "description": "Only annotations on this entity"
+                        }
+                    }
+                }
+            }
+        }),
+        serde_json::json!({
+            "type": "function",
+            "function": {
+                "name": "cronos_propose_annotation",
+                "description": "Propose an annotation on a time range, a session or an entity, e.g. to label a stretch of work the user described. It is stored as a proposal that the user accepts or removes with `cronos annotation`; tell them you proposed it. Give either from_ms and to_ms, session_id, or entity_id.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "text": {
+                            "type": "string",
+                            "description": "The note, in a sentence or less"
+                        },
+                        "tags": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "Short lowercase tags, e.g. [\"meeting\", \"acme\"]"
+                        },
+                        "from_ms": {
+                            "type": "integer",
+                            "description": "Start of the range as Unix timestamp in milliseconds"
+                        },
+                        "to_ms": {
+                            "type": "integer",
+                            "description": "End of the range as Unix timestamp in milliseconds"
+                        },
+                        "session_id": {
+                            "type": "string",
+                            "description": "Session to annotate"
+                        },
+                        "entity_id": {
+                            "type": "string",
+                            "description": "Entity to annotate"
+                        }
+                    },
+                    "required": ["text"]
+                }
+            }
+        }),
+        serde_json::json!({
+            "type": "function",
+            "function": {
+                "name": "cronos_query",
+                "description": "Run a query in Cronos's pipeline language. A query names a source (events, entities or sessions), then `|`-separated stages: `where <cond>`, `linked <entity kind> [via <relation>] [where <cond>]`, `group by <fields>`, aggregates (`count`, `sum(f)`, `avg(f)`, `min(f)`, `max(f)`), `sort <field> [desc]`, `limit <n>`. Conditions use = != < <= > >= ~ (contains) and `in [a, b]`, combined with and/or/not. Event fields: kind, source, time, hour, weekday, subject, entity_kind, project. Entity fields: kind, name, time, first_seen, hour, weekday, project. Session fields: app, category, title, project, time, end, duration, events, hour, weekday. Times: -7d, -3h, today, yesterday, \"2024-05-01 18:00\"; durations: 30m, 2h. Example: `sessions where time >= -7d and category = coding | group by project | sum(duration)`. Aggregating queries return a table of rows.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "query": {
+                            "type": "string",
+                            "description": "The query, e.g. events where kind = file_modified and project = cronos and hour >= 18 | limit 50"
+                        }
+                    },
+                    "required": ["query"]
+                }
+            }
+        }),
     ]
 }
 
+/// An optional array argument; a missing one is empty.
+fn list<T: serde::de::DeserializeOwned>(args: &serde_json::Value, key: &str) -> Result<Vec<T>> {
+    match &args[key] {
+        serde_json::Value::Null => Ok(vec![]),
+        // Models sometimes pass a single value instead of an array
+        v @ serde_json::Value::String(_) => Ok(vec![serde_json::from_value(v.clone())
+            .map_err(|e| anyhow::anyhow!("invalid {key}: {e}"))?]),
+        v => serde_json::from_value(v.clone()).map_err(|e| anyhow::anyhow!("invalid {key}: {e}")),
+    }
+}
+
+/// Build a query from a list tool's arguments, applying any filters and cursor.
+fn list_query(kind: QueryKind, args: &serde_json::Value) -> Result<QueryRequest> {
+    let mut query = QueryRequest::new(kind);
+    query.filter = QueryFilter {
+        sources: list(args, "sources")?,
+        event_kinds: list(args, "event_kinds")?,
+        entity_kinds: list(args, "entity_kinds")?,
+        project: args["project"].as_str().map(str::to_string),
+    };
+    query.cursor = args["cursor"].as_str().map(str::to_string);
+    Ok(query)
+}
+
 /// Dispatch a tool call to the Cronos daemon and return the JSON result string.
 pub async fn dispatch_tool_call(
     name: &str,
@@ -157,9 +430,7 @@ pub async fn dispatch_tool_call(
             Message::new(
                 request_id,
                 MessageKind::Query {
-                    query: QueryRequest {
-                        kind: QueryKind::Recent { limit },
-                    },
+                    query: list_query(QueryKind::Recent { limit }, args)?,
                 },
             )
         }
@@ -172,21 +443,20 @@ pub async fn dispatch_tool_call(
             Message::new(
                 request_id,
                 MessageKind::Query {
-                    query: QueryRequest {
-                        kind: QueryKind::Search { text, limit },
-                    },
+                    query: list_query(QueryKind::Search { text, limit }, args)?,
                 },
             )
         }
         "cronos_timeline" => {
             let from = args["from_ms"].as_i64().unwrap_or(0);
             let to = args["to_ms"].as_i64().unwrap_or(0);
+            let limit = args["limit"]
+                .as_u64()
+                .map_or(DEFAULT_TIMELINE_LIMIT, |l| l as u32);
             Message::new(
                 request_id,
                 MessageKind::Query {
-                    query: QueryRequest {
-                        kind: QueryKind::Timeline { from, to },
-                    },
+                    query: list_query(QueryKind::Timeline { from, to, limit }, args)?,
                 },
             )
         }
@@ -197,15 +467,41 @@ pub async fn dispatch_tool_call(
             let ulid = ulid::Ulid::from_string(entity_id_str)
                 .map_err(|e| anyhow::anyhow!("invalid entity ID '{}': {}", entity_id_str, e))?;
             let depth = args["depth"].as_u64().unwrap_or(2) as u8;
+            let limit = args["limit"]
+                .as_u64()
+                .map_or(DEFAULT_RELATED_LIMIT, |l| l as u32);
+            let relations = list(args, "relations")?;
+            let kind = QueryKind::Related {
+                entity_id: EntityId(ulid),
+                depth,
+                limit,
+                relations,
+            };
             Message::new(
                 request_id,
                 MessageKind::Query {
-                    query: QueryRequest {
-                        kind: QueryKind::Related {
-                            entity_id: EntityId(ulid),
-                            depth,
-                        },
-                    },
+                    query: list_query(kind, args)?,
+                },
+            )
+        }
+        "cronos_connection" => {
+            let entity_id = |key: &str| {
+                let id = args[key].as_str().unwrap_or("");
+                ulid::Ulid::from_string(id)
+                    .map(EntityId)
+                    .map_err(|e| anyhow::anyhow!("invalid entity ID '{}': {}", id, e))
+            };
+            let kind = QueryKind::Connection {
+                from: entity_id("from_id")?,
+                to: entity_id("to_id")?,
+                max_depth: args["max_depth"]
+                    .as_u64()
+                    .map_or(DEFAULT_CONNECTION_DEPTH, |d| d as u8),
+            };
+            Message::new(
+                request_id,
+                MessageKind::Query {
+                    query: QueryRequest::new(kind),
                 },
             )
         }
@@ -217,9 +513,7 @@ pub async fn dispatch_tool_call(
             Message::new(
                 request_id,
                 MessageKind::Query {
-                    query: QueryRequest {
-                        kind: QueryKind::Sessions { from, to, limit },
-                    },
+                    query: list_query(QueryKind::Sessions { from, to, limit }, args)?,
                 },
             )
         }
@@ -228,15 +522,124 @@ pub async fn dispatch_tool_call(
                 .as_str()
                 .unwrap_or("")
                 .to_string();
+            let timezone = args["timezone"].as_str().map(String::from);
             Message::new(
                 request_id,
                 MessageKind::Query {
-                    query: QueryRequest {
-                        kind: QueryKind::DaySummary { date },
+                    query: QueryRequest::new(QueryKind::DaySummary { date, timezone }),
+                },
+            )
+        }
+        "cronos_summary" => {
+            let period = serde_json::from_value(args["period"].clone())
+                .map_err(|_| anyhow::anyhow!("cronos_summary requires a period of day, week or month"))?;
+            let anchor = args["date"].as_str().unwrap_or("").to_string();
+            let timezone = args["timezone"].as_str().map(String::from);
+            Message::new(
+                request_id,
+                MessageKind::Query {
+                    query: QueryRequest::new(QueryKind::Summary {
+                        period,
+                        anchor,
+                        timezone,
+                    }),
+                },
+            )
+        }
+        "cronos_focus_report" => {
+            let from = args["from_ms"].as_i64().unwrap_or(0);
+            let to = args["to_ms"].as_i64().unwrap_or(0);
+            Message::new(
+                request_id,
+                MessageKind::Query {
+                    query: QueryRequest::new(QueryKind::FocusReport { from, to }),
+                },
+            )
+        }
+        "cronos_annotations" => {
+            let entity_id = match args["entity_id"].as_str() {
+                Some(id) => Some(
+                    ulid::Ulid::from_string(id)
+                        .map(EntityId)
+                        .map_err(|e| anyhow::anyhow!("invalid entity ID '{}': {}", id, e))?,
+                ),
+                None => None,
+            };
+            let kind = QueryKind::Annotations {
+                from: args["from_ms"].as_i64(),
+                to: args["to_ms"].as_i64(),
+                tag: args["tag"].as_str().map(String::from),
+                session_id: None,
+                entity_id,
+                proposed: false,
+            };
+            Message::new(
+                request_id,
+                MessageKind::Query {
+                    query: QueryRequest::new(kind),
+                },
+            )
+        }
+        "cronos_propose_annotation" => {
+            let Some(text) = args["text"].as_str() else {
+                bail!("cronos_propose_annotation requires a text");
+            };
+            let target = match (
+                args["from_ms"].as_i64(),
+                args["to_ms"].as_i64(),
+                args["session_id"].as_str(),
+                args["entity_id"].as_str(),
+            ) {
+                (Some(start), Some(end), _, _) => {
+                    if start >= end {
+                        bail!("cronos_propose_annotation requires to_ms after from_ms");
+                    }
+                    AnnotationTarget::Range { start, end }
+                }
+                (Some(_), None, _, _) | (None, Some(_), _, _) => {
+                    bail!("cronos_propose_annotation requires both from_ms and to_ms for a time range")
+                }
+                (None, None, Some(session_id), _) => AnnotationTarget::Session {
+                    session_id: session_id.to_string(),
+                },
+                (None, None, None, Some(id)) => AnnotationTarget::Entity {
+                    entity_id: ulid::Ulid::from_string(id)
+                        .map(EntityId)
+                        .map_err(|e| anyhow::anyhow!("invalid entity ID '{}': {}", id, e))?,
+                },
+                (None, None, None, None) => {
+                    bail!("cronos_propose_annotation requires a target: from_ms and to_ms, session_id or entity_id")
+                }
+            };
+            Message::new(
+                request_id,
+                MessageKind::AddAnnotation {
+                    annotation: Annotation {
+                        id: AnnotationId::new(),
+                        target,
+                        text: text.to_string(),
+                        tags: list(args, "tags")?,
+                        start: None,
+                        end: None,
+                        proposed: true,
+                        created_at: cronos_common::now_ms(),
                     },
                 },
             )
         }
+        "cronos_query" => {
+            let Some(query) = args["query"].as_str() else {
+                bail!("cronos_query requires a query");
+            };
+            Message::new(
+                request_id,
+                MessageKind::Query {
+                    query: QueryRequest::new(QueryKind::Expr {
+                        query: query.to_string(),
+                    }),
+                },
+            )
+        }
         _ => bail!("unknown tool: {}", name),
     };
 
@@ -1,3 +1,4 @@
+use cronos_model::CollectorSource;
 use serde::{Deserialize, Serialize};
 use std::path::Path;
 
@@ -21,12 +22,182 @@ pub struct DaemonConfig {
     pub log_level: String,
     #[serde(default = "default_channel_size")]
     pub event_channel_size: usize,
+    /// IANA zone that days and weeks are counted in; empty means the
+    /// system zone.
+    #[serde(default)]
+    pub timezone: String,
     #[serde(default)]
     pub dedup: DedupConfig,
     #[serde(default)]
     pub linker: LinkerConfig,
     #[serde(default)]
     pub aggregator: AggregatorConfig,
+    #[serde(default)]
+    pub focus: FocusConfig,
+    #[serde(default)]
+    pub supervisor: SupervisorConfig,
+    #[serde(default)]
+    pub tcp: TcpConfig,
+    #[serde(default)]
+    pub remote: RemoteConfig,
+    #[serde(default)]
+    pub auth: AuthConfig,
+    #[serde(default)]
+    pub sql: SqlConfig,
+    #[serde(default)]
+    pub backup: BackupConfig,
+    #[serde(default)]
+    pub encryption: EncryptionConfig,
+    #[serde(default)]
+    pub categories: CategoriesConfig,
+}
+
+/// Ad-hoc SQL against the store (`QueryKind::Sql`), served from a separate
+/// read-only connection.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SqlConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Rows returned before the result is cut off and marked truncated.
+    #[serde(default = "default_sql_max_rows")]
+    pub max_rows: usize,
+    /// Statements running longer than this are interrupted.
+    #[serde(default = "default_sql_timeout")]
+    pub timeout_ms: u64,
+}
+
+/// Rolling online backups of the store, taken by the daemon.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Directory for backups; empty means `backups/` in the data directory.
+    #[serde(default)]
+    pub dir: String,
+    #[serde(default = "default_backup_interval")]
+    pub interval_hours: u64,
+    /// Most recent backups kept; older ones are deleted.
+    #[serde(default = "default_backup_keep")]
+    pub keep: usize,
+}
+
+/// At-rest encryption of the database with SQLCipher. Needs a build with
+/// the `encryption` feature. Turning it on encrypts an existing plaintext
+/// database the next time it is opened.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptionConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// `"keyring"`: a random key kept in the OS keyring, created on first use.
+    /// `"passphrase"`: a passphrase from `CRONOS_DB_PASSPHRASE`.
+    #[serde(default = "default_key_source")]
+    pub key_source: String,
+}
+
+/// Who may talk to the daemon and what they may do.
+///
+/// Capabilities are `"emit"`, `"query"` and `"admin"`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthConfig {
+    /// Reject Unix socket peers whose uid differs from the daemon's (SO_PEERCRED).
+    #[serde(default = "default_true")]
+    pub require_same_uid: bool,
+    /// Capabilities granted to Unix socket peers without a token. `admin`
+    /// only applies while `require_same_uid` is on, when every peer is the
+    /// user who owns the database anyway.
+    #[serde(default = "default_local_capabilities")]
+    pub local_capabilities: Vec<String>,
+    /// Tokens that grant extra capabilities via `Authenticate` or `CollectorHandshake`.
+    #[serde(default)]
+    pub tokens: Vec<TokenGrant>,
+}
+
+/// How app sessions are put into categories. Rules are tried in priority
+/// order, user rules before built-in ones of the same priority; the first
+/// match wins and sessions nothing matches are `other`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CategoriesConfig {
+    /// Categories rules may assign beyond the built-in ones.
+    #[serde(default)]
+    pub custom: Vec<String>,
+    /// Keep the built-in rules for common apps after the user's rules.
+    #[serde(default = "default_true")]
+    pub builtin_rules: bool,
+    #[serde(default)]
+    pub rules: Vec<CategoryRule>,
+}
+
+/// Assigns `category` to sessions whose app name and any window title match.
+/// A rule needs at least one of `app` and `title`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CategoryRule {
+    pub category: String,
+    /// How `app` and `title` are matched: "exact" or "glob" (both ignore
+    /// case) or "regex".
+    #[serde(rename = "match", default = "default_rule_match")]
+    pub match_kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Higher priorities are tried first.
+    #[serde(default)]
+    pub priority: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenGrant {
+    pub token: String,
+    pub capabilities: Vec<String>,
+}
+
+/// Optional TCP listener so collectors outside this machine's Unix socket
+/// namespace (containers, VMs) can report to the daemon.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TcpConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_tcp_listen_addr")]
+    pub listen_addr: String,
+    /// Pre-shared token collectors must present in `CollectorHandshake`.
+    #[serde(default)]
+    pub token: String,
+    /// Capabilities of peers that present `token` or a client certificate
+    /// signed by `client_ca`. Collectors only need `emit`; add `query` or
+    /// `admin` here for trusted remote clients, or give them a scoped token
+    /// in `daemon.auth.tokens`.
+    #[serde(default = "default_remote_capabilities")]
+    pub capabilities: Vec<String>,
+    /// PEM server certificate chain; TLS is enabled when set.
+    #[serde(default)]
+    pub tls_cert: String,
+    /// PEM private key for `tls_cert`.
+    #[serde(default)]
+    pub tls_key: String,
+    /// PEM CA bundle; when set, clients must present a certificate signed by it.
+    #[serde(default)]
+    pub client_ca: String,
+}
+
+/// How collectors reach a daemon over TCP instead of the local Unix socket.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RemoteConfig {
+    /// `host:port` of the daemon's TCP listener; empty means use the Unix socket.
+    #[serde(default)]
+    pub addr: String,
+    #[serde(default)]
+    pub token: String,
+    /// PEM CA used to verify the daemon's certificate; TLS is used when set.
+    #[serde(default)]
+    pub ca_cert: String,
+    /// Name to verify the daemon certificate against (defaults to the host in `addr`).
+    #[serde(default)]
+    pub server_name: String,
+    /// PEM client certificate and key for mutual TLS.
+    #[serde(default)]
+    pub client_cert: String,
+    #[serde(default)]
+    pub client_key: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -49,6 +220,48 @@ pub struct AggregatorConfig {
     pub interval_secs: u64,
     #[serde(default = "default_session_gap")]
     pub session_gap_ms: u64,
+    /// Idle periods at least this long end sessions; shorter ones count as
+    /// active time.
+    #[serde(default = "default_idle_threshold")]
+    pub idle_threshold_ms: u64,
+    /// Each run rebuilds the sessions of this long before the last finished
+    /// one, so events that arrive late still land in a session.
+    #[serde(default = "default_late_window")]
+    pub late_window_ms: u64,
+    /// Sources that make sessions where window focus is unknown, most
+    /// preferred first. Each fills only the time earlier ones leave uncovered.
+    #[serde(default = "default_fallback_sources")]
+    pub fallback_sources: Vec<CollectorSource>,
+}
+
+/// What counts as deep work in focus reports (`QueryKind::FocusReport`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FocusConfig {
+    /// Uninterrupted time on one project needed to count as a focus block.
+    #[serde(default = "default_focus_min_block")]
+    pub min_block_ms: u64,
+    /// Gaps and unattributed sessions this short do not break focus; longer
+    /// gaps are breaks rather than context switches.
+    #[serde(default = "default_focus_grace")]
+    pub grace_ms: u64,
+    /// Categories whose sessions always break focus, reported as
+    /// interruptions.
+    #[serde(default = "default_interruption_categories")]
+    pub interruption_categories: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SupervisorConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default = "default_supervisor_interval")]
+    pub check_interval_secs: u64,
+    #[serde(default = "default_initial_backoff")]
+    pub initial_backoff_ms: u64,
+    #[serde(default = "default_max_backoff")]
+    pub max_backoff_ms: u64,
+    #[serde(default = "default_heartbeat_timeout")]
+    pub heartbeat_timeout_ms: u64,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -67,6 +280,13 @@ pub struct AppMonitorConfig {
     pub enabled: bool,
     #[serde(default = "default_poll_interval")]
     pub poll_interval_ms: u64,
+    /// How input idle time and screen locks are read: "auto", "macos",
+    /// "x11" (xprintidle and logind) or "none".
+    #[serde(default = "default_idle_backend")]
+    pub idle_backend: String,
+    /// No input for this long is reported as the start of an idle period.
+    #[serde(default = "default_idle_after")]
+    pub idle_after_secs: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -133,6 +353,36 @@ fn default_dwell_time() -> u64 { 3000 }
 fn default_poll_interval() -> u64 { 3000 }
 fn default_aggregator_interval() -> u64 { 300 }
 fn default_session_gap() -> u64 { 30_000 }
+fn default_idle_threshold() -> u64 { 300_000 }
+fn default_late_window() -> u64 { 600_000 }
+fn default_fallback_sources() -> Vec<CollectorSource> {
+    vec![
+        CollectorSource::Filesystem,
+        CollectorSource::Terminal,
+        CollectorSource::Git,
+        CollectorSource::Browser,
+    ]
+}
+fn default_focus_min_block() -> u64 { 1_500_000 }
+fn default_focus_grace() -> u64 { 120_000 }
+fn default_interruption_categories() -> Vec<String> { vec!["communication".to_string()] }
+fn default_idle_backend() -> String { "auto".to_string() }
+fn default_idle_after() -> u64 { 60 }
+fn default_local_capabilities() -> Vec<String> {
+    vec!["emit".to_string(), "query".to_string(), "admin".to_string()]
+}
+fn default_remote_capabilities() -> Vec<String> { vec!["emit".to_string()] }
+fn default_tcp_listen_addr() -> String { "127.0.0.1:19281".to_string() }
+fn default_supervisor_interval() -> u64 { 2 }
+fn default_initial_backoff() -> u64 { 1000 }
+fn default_max_backoff() -> u64 { 60_000 }
+fn default_heartbeat_timeout() -> u64 { 90_000 }
+fn default_sql_max_rows() -> usize { 1000 }
+fn default_sql_timeout() -> u64 { 5000 }
+fn default_backup_interval() -> u64 { 24 }
+fn default_backup_keep() -> usize { 7 }
+fn default_key_source() -> String { "keyring".to_string() }
+fn default_rule_match() -> String { "exact".to_string() }
 
 // Default impls for config structs with non-trivial defaults
 impl Default for DaemonConfig {
@@ -140,8 +390,14 @@ impl Default for DaemonConfig {
         Self {
             socket_path: String::new(), db_path: String::new(),
             log_level: default_log_level(), event_channel_size: default_channel_size(),
+            timezone: String::new(),
             dedup: DedupConfig::default(), linker: LinkerConfig::default(),
-            aggregator: AggregatorConfig::default(),
+            aggregator: AggregatorConfig::default(), focus: FocusConfig::default(),
+            supervisor: SupervisorConfig::default(),
+            tcp: TcpConfig::default(), remote: RemoteConfig::default(),
+            auth: AuthConfig::default(), sql: SqlConfig::default(),
+            backup: BackupConfig::default(), encryption: EncryptionConfig::default(),
+            categories: CategoriesConfig::default(),
         }
     }
 }
@@ -165,12 +421,70 @@ impl Default for BrowserCollectorConfig {
 }
 impl Default for AppMonitorConfig {
     fn default() -> Self {
-        Self { enabled: default_true(), poll_interval_ms: default_poll_interval() }
+        Self {
+            enabled: default_true(), poll_interval_ms: default_poll_interval(),
+            idle_backend: default_idle_backend(), idle_after_secs: default_idle_after(),
+        }
     }
 }
 impl Default for AggregatorConfig {
     fn default() -> Self {
-        Self { interval_secs: default_aggregator_interval(), session_gap_ms: default_session_gap() }
+        Self {
+            interval_secs: default_aggregator_interval(), session_gap_ms: default_session_gap(),
+            idle_threshold_ms: default_idle_threshold(),
+            late_window_ms: default_late_window(),
+            fallback_sources: default_fallback_sources(),
+        }
+    }
+}
+impl Default for FocusConfig {
+    fn default() -> Self {
+        Self {
+            min_block_ms: default_focus_min_block(), grace_ms: default_focus_grace(),
+            interruption_categories: default_interruption_categories(),
+        }
+    }
+}
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_true(), check_interval_secs: default_supervisor_interval(),
+            initial_backoff_ms: default_initial_backoff(), max_backoff_ms: default_max_backoff(),
+            heartbeat_timeout_ms: default_heartbeat_timeout(),
+        }
+    }
+}
+impl Default for TcpConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false, listen_addr: default_tcp_listen_addr(), token: String::new(),
+            capabilities: default_remote_capabilities(), tls_cert: String::new(), tls_key: String::new(), client_ca: String::new(),
+        }
+    }
+}
+impl Default for SqlConfig {
+    fn default() -> Self {
+        Self { enabled: default_true(), max_rows: default_sql_max_rows(), timeout_ms: default_sql_timeout() }
+    }
+}
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self { enabled: false, dir: String::new(), interval_hours: default_backup_interval(), keep: default_backup_keep() }
+    }
+}
+impl Default for EncryptionConfig {
+    fn default() -> Self {
+        Self { enabled: false, key_source: default_key_source() }
+    }
+}
+impl Default for CategoriesConfig {
+    fn default() -> Self {
+        Self { custom: Vec::new(), builtin_rules: default_true(), rules: Vec::new() }
+    }
+}
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self { require_same_uid: default_true(), local_capabilities: default_local_capabilities(), tokens: Vec::new() }
     }
 }
 
@@ -186,6 +500,8 @@ impl CronosConfig {
         if let Ok(v) = std::env::var("CRONOS_LOG_LEVEL") { config.daemon.log_level = v; }
         if let Ok(v) = std::env::var("CRONOS_SOCKET_PATH") { config.daemon.socket_path = v; }
         if let Ok(v) = std::env::var("CRONOS_DB_PATH") { config.daemon.db_path = v; }
+        if let Ok(v) = std::env::var("CRONOS_DAEMON_ADDR") { config.daemon.remote.addr = v; }
+        if let Ok(v) = std::env::var("CRONOS_DAEMON_TOKEN") { config.daemon.remote.token = v; }
         if let Ok(v) = std::env::var("OPENAI_API_KEY") { config.ai.api_key = v; }
         Ok(config)
     }
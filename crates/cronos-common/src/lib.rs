@@ -2,6 +2,7 @@ pub mod config;
 pub mod consent;
 pub mod error;
 pub mod paths;
+pub mod tz;
 
 pub use config::CronosConfig;
 pub use error::{CronosError, Result};
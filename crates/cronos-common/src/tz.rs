@@ -0,0 +1,112 @@
+//! The user's timezone, which decides where days begin and end.
+
+use chrono::{Days, NaiveDate, NaiveDateTime, TimeZone};
+use chrono_tz::Tz;
+use cronos_model::Timestamp;
+
+#[derive(Debug, thiserror::Error)]
+#[error("unknown timezone '{0}' (expected an IANA name like America/Los_Angeles)")]
+pub struct UnknownTimezone(pub String);
+
+/// The zone named `name`, or the system zone when `name` is empty. A system
+/// zone that cannot be determined is taken to be UTC.
+pub fn resolve(name: &str) -> Result<Tz, UnknownTimezone> {
+    if !name.is_empty() {
+        return name.parse().map_err(|_| UnknownTimezone(name.to_string()));
+    }
+    match iana_time_zone::get_timezone() {
+        Ok(system) => system.parse().or_else(|_| {
+            tracing::warn!(zone = %system, "system timezone unknown to the tz database, using UTC");
+            Ok(Tz::UTC)
+        }),
+        Err(e) => {
+            tracing::warn!("cannot determine the system timezone, using UTC: {e}");
+            Ok(Tz::UTC)
+        }
+    }
+}
+
+/// The first instant of `date` in `tz`: midnight, or later where a DST
+/// change skips midnight.
+pub fn day_start(tz: Tz, date: NaiveDate) -> Timestamp {
+    let midnight = date.and_hms_opt(0, 0, 0).expect("midnight exists");
+    (0..24)
+        .find_map(|hour| local_millis(tz, midnight + chrono::Duration::hours(hour)))
+        .expect("every day has a valid local hour")
+}
+
+/// `[start, end)` of `date` in `tz`; 23 or 25 hours long on DST changes.
+pub fn day_bounds(tz: Tz, date: NaiveDate) -> (Timestamp, Timestamp) {
+    let next = date.checked_add_days(Days::new(1)).unwrap_or(date);
+    (day_start(tz, date), day_start(tz, next))
+}
+
+/// The date `t` falls on in `tz`.
+pub fn date_of(tz: Tz, t: Timestamp) -> NaiveDate {
+    chrono::DateTime::from_timestamp_millis(t)
+        .unwrap_or_default()
+        .with_timezone(&tz)
+        .date_naive()
+}
+
+/// The current local time in `tz` for people and models to read, e.g.
+/// `Tuesday 2024-03-05 09:30 PST (America/Los_Angeles, UTC-08:00)`.
+pub fn describe_now(tz: Tz) -> String {
+    let now = chrono::Utc::now().with_timezone(&tz);
+    format!(
+        "{} ({tz}, UTC{})",
+        now.format("%A %Y-%m-%d %H:%M %Z"),
+        now.format("%:z")
+    )
+}
+
+/// `naive` read as local time in `tz`; the earlier instant when it is
+/// ambiguous, `None` when a DST change skips it.
+pub fn local_millis(tz: Tz, naive: NaiveDateTime) -> Option<Timestamp> {
+    Some(
+        tz.from_local_datetime(&naive)
+            .earliest()?
+            .timestamp_millis(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn day_bounds_follow_the_zone() {
+        let la: Tz = resolve("America/Los_Angeles").unwrap();
+        // 2024-03-05 00:00 PST is 08:00 UTC
+        assert_eq!(
+            day_bounds(la, date("2024-03-05")),
+            (1_709_625_600_000, 1_709_712_000_000)
+        );
+        assert_eq!(day_bounds(Tz::UTC, date("2024-03-05")).0, 1_709_596_800_000);
+    }
+
+    #[test]
+    fn dst_days_are_23_or_25_hours() {
+        let la: Tz = resolve("America/Los_Angeles").unwrap();
+        let hours = |d| {
+            let (start, end) = day_bounds(la, date(d));
+            (end - start) / 3_600_000
+        };
+        assert_eq!(hours("2024-03-10"), 23);
+        assert_eq!(hours("2024-11-03"), 25);
+        // Midnight does not exist in Santiago on the day DST starts
+        let santiago: Tz = resolve("America/Santiago").unwrap();
+        let start = day_start(santiago, date("2024-09-08"));
+        assert_eq!(date_of(santiago, start), date("2024-09-08"));
+    }
+
+    #[test]
+    fn unknown_zones_are_rejected() {
+        assert!(resolve("Mars/Olympus_Mons").is_err());
+        assert!(resolve("").is_ok());
+    }
+}
@@ -1,7 +1,11 @@
-use crate::storage::repo::Session;
+use crate::attribution::ProjectAttributor;
+use crate::categorize::{Categorizer, OTHER};
+use crate::storage::repo::{Session, StoredEvent};
 use crate::storage::Repository;
-use cronos_model::{CollectorSource, EntityId, Timestamp};
+use cronos_common::config::AggregatorConfig;
+use cronos_model::{CollectorSource, EntityId, EntityKind, EventKind, Timestamp};
 use std::collections::HashMap;
+use std::sync::Arc;
 
 /// A resolved event with the app name looked up from the entity table.
 struct ResolvedEvent {
@@ -10,78 +14,213 @@ struct ResolvedEvent {
     window_title: Option<String>,
 }
 
-/// Groups raw `AppFocused` events into meaningful activity sessions.
+/// Window titles kept for a session from a fallback source, which can touch
+/// many files or URLs at once.
+const MAX_FALLBACK_TITLES: usize = 20;
+
+/// Counts from [`SessionAggregator::rebuild`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rebuilt {
+    /// Sessions deleted, including ones built again unchanged.
+    pub removed: u64,
+    /// Sessions inserted.
+    pub created: u64,
+    /// Inserted sessions that replace none of the deleted ones.
+    pub new: u64,
+}
+
+/// Groups raw events into meaningful activity sessions.
+///
+/// Window focus from the app monitor is preferred. Where it leaves time
+/// uncovered, events from the fallback sources make sessions in its place.
 pub struct SessionAggregator {
     /// Maximum gap (in ms) between events to be considered part of the same session.
     session_gap_ms: u64,
+    /// Minimum length (in ms) of an idle period that ends a session.
+    idle_threshold_ms: u64,
+    /// How far before the last closed session (in ms) each run rebuilds.
+    late_window_ms: u64,
+    /// Most preferred first.
+    fallback_sources: Vec<CollectorSource>,
+    categorizer: Arc<Categorizer>,
 }
 
 impl SessionAggregator {
-    pub fn new(session_gap_ms: u64) -> Self {
-        Self { session_gap_ms }
+    pub fn new(config: &AggregatorConfig, categorizer: Arc<Categorizer>) -> Self {
+        Self {
+            session_gap_ms: config.session_gap_ms,
+            idle_threshold_ms: config.idle_threshold_ms,
+            late_window_ms: config.late_window_ms,
+            fallback_sources: config.fallback_sources.clone(),
+            categorizer,
+        }
     }
 
-    /// Aggregate raw AppMonitor events since the last session watermark into sessions.
+    /// Bring sessions up to date with raw events.
     ///
-    /// Returns the number of sessions created.
+    /// Rebuilds from `late_window_ms` before the end of the last closed
+    /// session, which extends the open session and takes in late events.
+    /// Returns the number of sessions that did not exist before.
     pub fn aggregate(&self, repo: &Repository) -> rusqlite::Result<usize> {
-        let watermark = repo.last_session_end_time()?.unwrap_or(0);
-        let now = cronos_common::now_ms();
-
-        let all_events = repo.events_in_range(watermark, now)?;
-
-        // Filter to AppMonitor source only, skip events at exact watermark
-        let app_events: Vec<_> = all_events
-            .iter()
-            .filter(|e| e.source == CollectorSource::AppMonitor)
-            .filter(|e| e.timestamp > watermark || watermark == 0)
-            .collect();
+        let watermark = match repo.last_session_end_time()? {
+            Some(end) => Some(end),
+            None => repo.first_open_session_start()?,
+        };
+        let from = watermark.map_or(0, |t| t - self.late_window_ms as i64);
+        Ok(self.rebuild(repo, from, cronos_common::now_ms())?.new as usize)
+    }
 
-        if app_events.is_empty() {
-            return Ok(0);
-        }
+    /// Replace the sessions in `[from, to)` with ones built from raw events.
+    ///
+    /// Sessions crossing either bound, or ending less than a session gap
+    /// before `from`, are rebuilt whole. A rebuilt session with the app and
+    /// start of a deleted one keeps its id. Annotations of the deleted
+    /// sessions move to the rebuilt session that replaced each one.
+    pub fn rebuild(&self, repo: &Repository, from: Timestamp, to: Timestamp) -> rusqlite::Result<Rebuilt> {
+        let now = cronos_common::now_ms();
+        repo.transaction(|repo| {
+            let old = repo.sessions_overlapping(from - self.session_gap_ms as i64, to)?;
+            let from = old.iter().map(|s| s.start_time).fold(from, Timestamp::min);
+            let to = old.iter().map(|s| s.end_time + 1).fold(to, Timestamp::max);
+            for session in &old {
+                repo.delete_session(&session.id)?;
+            }
 
-        // Resolve entity names (app names) via subject_id lookups, with caching
-        let mut name_cache: HashMap<EntityId, String> = HashMap::new();
-        let mut resolved: Vec<ResolvedEvent> = Vec::with_capacity(app_events.len());
+            let events = repo.events_in_range(from, to - 1)?;
+            let app_events: Vec<_> = events
+                .iter()
+                .filter(|e| e.source == CollectorSource::AppMonitor)
+                .collect();
+            let idle = self.idle_periods(&app_events, from, to.min(now));
+            let resolved = resolve_events(repo, &app_events, &idle)?;
+
+            let mut sessions = self.build_sessions(&resolved, &idle);
+            for source in &self.fallback_sources {
+                let Some((app_name, category)) = fallback_activity(source) else {
+                    continue;
+                };
+                let covered = covered_spans(&sessions, self.session_gap_ms as i64);
+                let events: Vec<_> = events.iter().filter(|e| e.source == *source).collect();
+                let resolved = resolve_fallback_events(repo, &events, app_name, &idle, &covered)?;
+                for mut session in self.build_sessions(&resolved, &idle) {
+                    if session.category == OTHER {
+                        session.category = category.to_string();
+                    }
+                    session.window_titles.truncate(MAX_FALLBACK_TITLES);
+                    session.metadata.insert(
+                        "source".to_string(),
+                        serde_json::to_value(source).expect("sources serialize"),
+                    );
+                    sessions.push(session);
+                }
+            }
+            sessions.sort_by_key(|s| s.start_time);
 
-        for event in &app_events {
-            let app_name = if let Some(name) = name_cache.get(&event.subject_id) {
-                name.clone()
-            } else {
-                let name = repo
-                    .get_entity(event.subject_id)?
-                    .map(|e| e.name)
-                    .unwrap_or_else(|| "Unknown".to_string());
-                name_cache.insert(event.subject_id, name.clone());
-                name
+            if let Some(last) = sessions.iter_mut().max_by_key(|s| s.end_time) {
+                // Activity may yet continue it, unless the user has gone idle since
+                last.open = now - last.end_time < self.session_gap_ms as i64
+                    && !idle.iter().any(|&(start, _)| start >= last.end_time);
+            }
+            self.attribute_projects(repo, &mut sessions)?;
+
+            let old_ids: HashMap<(&str, Timestamp), &str> = old
+                .iter()
+                .map(|s| ((s.app_name.as_str(), s.start_time), s.id.as_str()))
+                .collect();
+            let mut rebuilt = Rebuilt {
+                removed: old.len() as u64,
+                ..Rebuilt::default()
             };
+            for session in &mut sessions {
+                match old_ids.get(&(session.app_name.as_str(), session.start_time)) {
+                    Some(id) => session.id = id.to_string(),
+                    None => rebuilt.new += 1,
+                }
+                repo.insert_session(session)?;
+                rebuilt.created += 1;
+            }
+            for session in &old {
+                if let Some(successor) = successor(session, &sessions) {
+                    repo.retarget_session_annotations(&session.id, successor)?;
+                }
+            }
+            Ok(rebuilt)
+        })
+    }
 
-            let window_title = event
-                .metadata
-                .get("window_title")
-                .and_then(|v| v.as_str())
-                .map(|s| s.to_string());
-
-            resolved.push(ResolvedEvent {
-                timestamp: event.timestamp,
-                app_name,
-                window_title,
-            });
+    /// Idle periods of at least `idle_threshold_ms`, as `(start, end)`, from
+    /// the app monitor's `IdleStarted`/`IdleEnded` events. A period already
+    /// open at `from` starts there; one still open runs until `now`.
+    fn idle_periods(
+        &self,
+        events: &[&StoredEvent],
+        from: Timestamp,
+        now: Timestamp,
+    ) -> Vec<(Timestamp, Timestamp)> {
+        let mut periods = Vec::new();
+        let mut started: Option<Timestamp> = None;
+        let mut seen_any = false;
+        for event in events {
+            match event.kind {
+                EventKind::IdleStarted => {
+                    started.get_or_insert(event.timestamp);
+                }
+                EventKind::IdleEnded => {
+                    let start = match started.take() {
+                        Some(start) => start,
+                        None if !seen_any => from,
+                        None => continue,
+                    };
+                    periods.push((start, event.timestamp));
+                }
+                _ => continue,
+            }
+            seen_any = true;
         }
-
-        let sessions = self.build_sessions(&resolved);
-        let count = sessions.len();
-
-        for session in &sessions {
-            repo.insert_session(session)?;
+        if let Some(start) = started {
+            periods.push((start, now));
         }
+        periods.retain(|&(start, end)| end - start >= self.idle_threshold_ms as i64);
+        periods
+    }
 
-        Ok(count)
+    /// Fill in the project of each session from activity around it and its
+    /// window titles.
+    fn attribute_projects(&self, repo: &Repository, sessions: &mut [Session]) -> rusqlite::Result<()> {
+        let (Some(start), Some(end)) = (
+            sessions.iter().map(|s| s.start_time).min(),
+            sessions.iter().map(|s| s.end_time).max(),
+        ) else {
+            return Ok(());
+        };
+        // Activity within one session gap of a session counts towards it
+        let margin = self.session_gap_ms as i64;
+        let activity = repo.project_activity(start - margin, end + margin)?;
+        let projects = repo.entities_of_kind(&EntityKind::Project)?;
+        let attributor = ProjectAttributor::new(&projects, activity, margin);
+
+        for session in sessions {
+            if let Some(found) = attributor.attribute(
+                &session.app_name,
+                &session.window_titles,
+                session.start_time,
+                session.end_time,
+            ) {
+                session.project = Some(found.project);
+                session.project_id = Some(found.project_id);
+                session.project_confidence = found.confidence;
+            }
+        }
+        Ok(())
     }
 
-    /// Build sessions from a sorted list of resolved events.
-    fn build_sessions(&self, events: &[ResolvedEvent]) -> Vec<Session> {
+    /// Build sessions from a sorted list of resolved events, ending a session
+    /// at any of the `idle` periods.
+    fn build_sessions(
+        &self,
+        events: &[ResolvedEvent],
+        idle: &[(Timestamp, Timestamp)],
+    ) -> Vec<Session> {
         if events.is_empty() {
             return vec![];
         }
@@ -100,8 +239,11 @@ impl SessionAggregator {
 
         for event in events.iter().skip(1) {
             let gap = event.timestamp - current_end;
+            let went_idle = idle
+                .iter()
+                .any(|&(start, _)| start >= current_end && start < event.timestamp);
 
-            if event.app_name == current_app && gap < self.session_gap_ms as i64 {
+            if event.app_name == current_app && gap < self.session_gap_ms as i64 && !went_idle {
                 // Extend current session
                 current_end = event.timestamp;
                 current_count += 1;
@@ -112,7 +254,7 @@ impl SessionAggregator {
                 }
             } else {
                 // Finalize current session
-                sessions.push(make_session(
+                sessions.push(self.make_session(
                     &current_app,
                     &current_titles,
                     current_start,
@@ -133,7 +275,7 @@ impl SessionAggregator {
         }
 
         // Finalize last session
-        sessions.push(make_session(
+        sessions.push(self.make_session(
             &current_app,
             &current_titles,
             current_start,
@@ -143,91 +285,174 @@ impl SessionAggregator {
 
         sessions
     }
+
+    fn make_session(
+        &self,
+        app_name: &str,
+        window_titles: &[String],
+        start_time: Timestamp,
+        end_time: Timestamp,
+        event_count: i64,
+    ) -> Session {
+        let duration_secs = (end_time - start_time) / 1000;
+        Session {
+            id: ulid::Ulid::new().to_string(),
+            app_name: app_name.to_string(),
+            window_titles: window_titles.to_vec(),
+            project: None,
+            project_id: None,
+            project_confidence: 0.0,
+            category: self.categorizer.categorize(app_name, window_titles).to_string(),
+            start_time,
+            end_time,
+            duration_secs,
+            event_count,
+            metadata: HashMap::new(),
+            open: false,
+        }
+    }
+}
+
+/// Look up the app names of AppMonitor focus events, skipping those inside
+/// `idle` periods.
+fn resolve_events(
+    repo: &Repository,
+    events: &[&StoredEvent],
+    idle: &[(Timestamp, Timestamp)],
+) -> rusqlite::Result<Vec<ResolvedEvent>> {
+    let mut name_cache: HashMap<EntityId, String> = HashMap::new();
+    let mut resolved = Vec::with_capacity(events.len());
+
+    for event in events {
+        // Focus changes while idle are not activity
+        if event.kind != EventKind::AppFocused
+            || idle
+                .iter()
+                .any(|&(start, end)| event.timestamp >= start && event.timestamp < end)
+        {
+            continue;
+        }
+        let app_name = entity_name(repo, &mut name_cache, event.subject_id)?;
+        let window_title = event
+            .metadata
+            .get("window_title")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string());
+
+        resolved.push(ResolvedEvent {
+            timestamp: event.timestamp,
+            app_name,
+            window_title,
+        });
+    }
+    Ok(resolved)
 }
 
-fn make_session(
+/// App name and category of sessions from a fallback source, which know no
+/// focused app. Custom sources make no sessions.
+fn fallback_activity(source: &CollectorSource) -> Option<(&'static str, &'static str)> {
+    match source {
+        CollectorSource::Filesystem => Some(("File editing", "coding")),
+        CollectorSource::Terminal => Some(("Shell", "coding")),
+        CollectorSource::Git => Some(("Git", "coding")),
+        CollectorSource::Browser => Some(("Web browsing", "browsing")),
+        CollectorSource::AppMonitor | CollectorSource::Custom(_) => None,
+    }
+}
+
+/// Treat fallback `events` as activity in `app_name`, titled by their
+/// subject, skipping those inside `idle` periods or `covered` spans.
+fn resolve_fallback_events(
+    repo: &Repository,
+    events: &[&StoredEvent],
     app_name: &str,
-    window_titles: &[String],
-    start_time: Timestamp,
-    end_time: Timestamp,
-    event_count: i64,
-) -> Session {
-    let duration_secs = (end_time - start_time) / 1000;
-    Session {
-        id: ulid::Ulid::new().to_string(),
-        app_name: app_name.to_string(),
-        window_titles: window_titles.to_vec(),
-        project: None,
-        category: categorize_app(app_name).to_string(),
-        start_time,
-        end_time,
-        duration_secs,
-        event_count,
-        metadata: HashMap::new(),
+    idle: &[(Timestamp, Timestamp)],
+    covered: &[(Timestamp, Timestamp)],
+) -> rusqlite::Result<Vec<ResolvedEvent>> {
+    let mut name_cache: HashMap<EntityId, String> = HashMap::new();
+    let mut resolved = Vec::new();
+
+    for event in events {
+        if is_within(covered, event.timestamp)
+            || idle
+                .iter()
+                .any(|&(start, end)| event.timestamp >= start && event.timestamp < end)
+        {
+            continue;
+        }
+        resolved.push(ResolvedEvent {
+            timestamp: event.timestamp,
+            app_name: app_name.to_string(),
+            window_title: Some(entity_name(repo, &mut name_cache, event.subject_id)?),
+        });
+    }
+    Ok(resolved)
+}
+
+fn entity_name(
+    repo: &Repository,
+    cache: &mut HashMap<EntityId, String>,
+    id: EntityId,
+) -> rusqlite::Result<String> {
+    if let Some(name) = cache.get(&id) {
+        return Ok(name.clone());
+    }
+    let name = repo
+        .get_entity(id)?
+        .map(|e| e.name)
+        .unwrap_or_else(|| "Unknown".to_string());
+    cache.insert(id, name.clone());
+    Ok(name)
+}
+
+/// The time `sessions` cover, widened by `margin` on both sides, as sorted
+/// disjoint `(start, end)` spans.
+fn covered_spans(sessions: &[Session], margin: i64) -> Vec<(Timestamp, Timestamp)> {
+    let mut spans: Vec<_> = sessions
+        .iter()
+        .map(|s| (s.start_time - margin, s.end_time + margin))
+        .collect();
+    spans.sort_unstable();
+    let mut merged: Vec<(Timestamp, Timestamp)> = Vec::with_capacity(spans.len());
+    for (start, end) in spans {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
     }
+    merged
 }
 
-/// Categorize an app by name into a broad activity category.
-pub fn categorize_app(app_name: &str) -> &'static str {
-    let lower = app_name.to_lowercase();
-    if lower.contains("code")
-        || lower.contains("xcode")
-        || lower.contains("intellij")
-        || lower.contains("terminal")
-        || lower.contains("iterm")
-        || lower.contains("warp")
-        || lower.contains("alacritty")
-        || lower.contains("kitty")
-        || lower.contains("cursor")
-    {
-        "coding"
-    } else if lower.contains("discord")
-        || lower.contains("slack")
-        || lower.contains("messages")
-        || lower.contains("telegram")
-        || lower.contains("teams")
-        || lower.contains("mail")
-    {
-        "communication"
-    } else if lower.contains("chrome")
-        || lower.contains("firefox")
-        || lower.contains("safari")
-        || lower.contains("arc")
-        || lower.contains("brave")
-        || lower.contains("edge")
-    {
-        "browsing"
-    } else if lower.contains("notion")
-        || lower.contains("obsidian")
-        || lower.contains("notes")
-        || lower.contains("pages")
-        || lower.contains("docs")
-    {
-        "productivity"
-    } else if lower.contains("spotify")
-        || lower.contains("music")
-        || lower.contains("vlc")
-    {
-        "media"
-    } else if lower.contains("finder") || lower.contains("preview") {
-        "system"
-    } else {
-        "other"
+/// The rebuilt session that replaced `old`: the one keeping its id, else the
+/// one overlapping it most, preferring the same app.
+fn successor<'a>(old: &Session, sessions: &'a [Session]) -> Option<&'a Session> {
+    if let Some(same) = sessions.iter().find(|s| s.id == old.id) {
+        return Some(same);
     }
+    sessions
+        .iter()
+        .map(|s| (s, s.end_time.min(old.end_time) - s.start_time.max(old.start_time)))
+        .filter(|&(_, overlap)| overlap > 0)
+        .max_by_key(|&(s, overlap)| (s.app_name == old.app_name, overlap))
+        .map(|(s, _)| s)
+}
+
+/// Whether `t` falls in one of the sorted, disjoint `spans`.
+fn is_within(spans: &[(Timestamp, Timestamp)], t: Timestamp) -> bool {
+    let i = spans.partition_point(|&(start, _)| start <= t);
+    i > 0 && t <= spans[i - 1].1
 }
 
 /// Spawn a background task that periodically aggregates events into sessions.
 pub fn spawn_aggregator(
     engine: std::sync::Arc<crate::engine::Engine>,
     interval_secs: u64,
-    session_gap_ms: u64,
 ) {
-    let aggregator = SessionAggregator::new(session_gap_ms);
     tokio::spawn(async move {
         let interval = std::time::Duration::from_secs(interval_secs);
         loop {
             tokio::time::sleep(interval).await;
-            match engine.run_aggregator(&aggregator) {
+            match engine.run_aggregator() {
                 Ok(count) => {
                     if count > 0 {
                         tracing::info!(sessions = count, "aggregated new sessions");
@@ -244,45 +469,18 @@ pub fn spawn_aggregator(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::storage::repo::AnnotationFilter;
+    use crate::test_fixtures as fixtures;
     use cronos_model::*;
     use std::collections::HashMap;
 
-    #[test]
-    fn categorize_app_identifies_coding_apps() {
-        assert_eq!(categorize_app("VS Code"), "coding");
-        assert_eq!(categorize_app("Cursor"), "coding");
-        assert_eq!(categorize_app("Terminal"), "coding");
-        assert_eq!(categorize_app("iTerm2"), "coding");
-        assert_eq!(categorize_app("Xcode"), "coding");
-    }
-
-    #[test]
-    fn categorize_app_identifies_communication_apps() {
-        assert_eq!(categorize_app("Discord"), "communication");
-        assert_eq!(categorize_app("Slack"), "communication");
-        assert_eq!(categorize_app("Messages"), "communication");
-        assert_eq!(categorize_app("Microsoft Teams"), "communication");
-    }
-
-    #[test]
-    fn categorize_app_identifies_browsing_apps() {
-        assert_eq!(categorize_app("Google Chrome"), "browsing");
-        assert_eq!(categorize_app("Arc"), "browsing");
-        assert_eq!(categorize_app("Safari"), "browsing");
-        assert_eq!(categorize_app("Firefox"), "browsing");
-    }
-
-    #[test]
-    fn categorize_app_identifies_other_categories() {
-        assert_eq!(categorize_app("Notion"), "productivity");
-        assert_eq!(categorize_app("Spotify"), "media");
-        assert_eq!(categorize_app("Finder"), "system");
-        assert_eq!(categorize_app("SomeRandomApp"), "other");
+    fn aggregator() -> SessionAggregator {
+        SessionAggregator::new(&AggregatorConfig::default(), Arc::new(Categorizer::default()))
     }
 
     #[test]
     fn build_sessions_groups_consecutive_same_app() {
-        let aggregator = SessionAggregator::new(30_000);
+        let aggregator = aggregator();
         let events = vec![
             ResolvedEvent {
                 timestamp: 1000,
@@ -300,7 +498,7 @@ mod tests {
                 window_title: Some("main.rs".to_string()),
             },
         ];
-        let sessions = aggregator.build_sessions(&events);
+        let sessions = aggregator.build_sessions(&events, &[]);
         assert_eq!(sessions.len(), 1);
         assert_eq!(sessions[0].app_name, "VS Code");
         assert_eq!(sessions[0].start_time, 1000);
@@ -313,7 +511,7 @@ mod tests {
 
     #[test]
     fn build_sessions_splits_on_app_change() {
-        let aggregator = SessionAggregator::new(30_000);
+        let aggregator = aggregator();
         let events = vec![
             ResolvedEvent {
                 timestamp: 1000,
@@ -331,7 +529,7 @@ mod tests {
                 window_title: Some("#random".to_string()),
             },
         ];
-        let sessions = aggregator.build_sessions(&events);
+        let sessions = aggregator.build_sessions(&events, &[]);
         assert_eq!(sessions.len(), 2);
         assert_eq!(sessions[0].app_name, "VS Code");
         assert_eq!(sessions[0].category, "coding");
@@ -342,7 +540,7 @@ mod tests {
 
     #[test]
     fn build_sessions_splits_on_large_gap() {
-        let aggregator = SessionAggregator::new(30_000); // 30s gap
+        let aggregator = aggregator(); // 30s gap
         let events = vec![
             ResolvedEvent {
                 timestamp: 1000,
@@ -355,7 +553,7 @@ mod tests {
                 window_title: None,
             },
         ];
-        let sessions = aggregator.build_sessions(&events);
+        let sessions = aggregator.build_sessions(&events, &[]);
         assert_eq!(sessions.len(), 2);
         assert_eq!(sessions[0].app_name, "VS Code");
         assert_eq!(sessions[1].app_name, "VS Code");
@@ -363,7 +561,7 @@ mod tests {
 
     #[test]
     fn build_sessions_deduplicates_window_titles() {
-        let aggregator = SessionAggregator::new(30_000);
+        let aggregator = aggregator();
         let events = vec![
             ResolvedEvent {
                 timestamp: 1000,
@@ -376,7 +574,7 @@ mod tests {
                 window_title: Some("main.rs".to_string()),
             },
         ];
-        let sessions = aggregator.build_sessions(&events);
+        let sessions = aggregator.build_sessions(&events, &[]);
         assert_eq!(sessions[0].window_titles.len(), 1);
     }
 
@@ -418,7 +616,7 @@ mod tests {
             repo.insert_event(&event, entity.id, &[]).unwrap();
         }
 
-        let aggregator = SessionAggregator::new(30_000);
+        let aggregator = aggregator();
         let count = aggregator.aggregate(&repo).unwrap();
         assert_eq!(count, 1);
 
@@ -427,8 +625,335 @@ mod tests {
         assert_eq!(sessions[0].app_name, "VS Code");
         assert_eq!(sessions[0].event_count, 3);
 
-        // Running again should produce 0 new sessions (watermark prevents reprocessing)
+        // Running again rebuilds the same session under the same id
         let count2 = aggregator.aggregate(&repo).unwrap();
         assert_eq!(count2, 0);
+        assert_eq!(repo.sessions_in_range(0, 100_000, 50).unwrap(), sessions);
+    }
+
+    /// An app entity and a function inserting focus events on it.
+    fn focus_events<'a>(repo: &'a Repository, app: &str) -> impl Fn(Timestamp) + 'a {
+        let entity = fixtures::entity(EntityKind::App, app, 0);
+        repo.insert_entity(&entity).unwrap();
+        move |ts| {
+            let event = Event {
+                id: EventId::new(),
+                timestamp: ts,
+                source: CollectorSource::AppMonitor,
+                kind: EventKind::AppFocused,
+                subject: EntityRef {
+                    kind: EntityKind::App,
+                    identity: entity.name.clone(),
+                    attributes: HashMap::new(),
+                },
+                context: vec![],
+                metadata: HashMap::new(),
+            };
+            repo.insert_event(&event, entity.id, &[]).unwrap();
+        }
+    }
+
+    /// Insert an event from `source` on a new entity named `subject`, in `project`.
+    fn signal(
+        repo: &Repository,
+        source: CollectorSource,
+        kind: EventKind,
+        subject: &str,
+        project: Option<EntityId>,
+        ts: Timestamp,
+    ) {
+        let entity = fixtures::entity(EntityKind::File, subject, ts);
+        repo.insert_entity(&entity).unwrap();
+        let event = Event {
+            id: EventId::new(),
+            timestamp: ts,
+            source,
+            kind,
+            subject: EntityRef {
+                kind: EntityKind::File,
+                identity: subject.to_string(),
+                attributes: HashMap::new(),
+            },
+            context: vec![],
+            metadata: HashMap::new(),
+        };
+        let context: Vec<_> = project.into_iter().collect();
+        repo.insert_event(&event, entity.id, &context).unwrap();
+    }
+
+    #[test]
+    fn aggregate_builds_sessions_from_file_events_without_app_monitor() {
+        let repo = Repository::open_in_memory().unwrap();
+        let project = fixtures::entity(EntityKind::Project, "/work/cronos", 0);
+        repo.insert_entity(&project).unwrap();
+        let save = |path: &str, ts| {
+            signal(&repo, CollectorSource::Filesystem, EventKind::FileModified, path, Some(project.id), ts)
+        };
+        save("/work/cronos/src/main.rs", 1_000);
+        save("/work/cronos/src/lib.rs", 10_000);
+        save("/work/cronos/src/main.rs", 20_000);
+        save("/work/cronos/README.md", 500_000);
+
+        assert_eq!(aggregator().aggregate(&repo).unwrap(), 2);
+        let sessions = repo.sessions_in_range(0, 1_000_000, 10).unwrap();
+        assert_eq!(sessions[0].app_name, "File editing");
+        assert_eq!(sessions[0].category, "coding");
+        assert_eq!((sessions[0].start_time, sessions[0].end_time), (1_000, 20_000));
+        assert_eq!(sessions[0].event_count, 3);
+        assert_eq!(sessions[0].window_titles.len(), 2);
+        assert_eq!(sessions[0].project_id, Some(project.id));
+        assert_eq!(sessions[0].metadata["source"], "filesystem");
+        assert_eq!(sessions[1].start_time, 500_000);
+    }
+
+    #[test]
+    fn aggregate_prefers_focus_then_fallback_sources_in_order() {
+        let repo = Repository::open_in_memory().unwrap();
+        let code = focus_events(&repo, "VS Code");
+        code(1_000);
+        code(10_000);
+        let fs = |ts| signal(&repo, CollectorSource::Filesystem, EventKind::FileModified, "/a.rs", None, ts);
+        let git = |ts| signal(&repo, CollectorSource::Git, EventKind::CommitCreated, "/repo", None, ts);
+        // Saved while the editor had focus
+        fs(5_000);
+        // The app monitor stopped; files and git carry on
+        fs(200_000);
+        git(205_000);
+        fs(210_000);
+        git(400_000);
+
+        assert_eq!(aggregator().aggregate(&repo).unwrap(), 3);
+        let sessions = repo.sessions_in_range(0, 1_000_000, 10).unwrap();
+        let summary: Vec<_> = sessions
+            .iter()
+            .map(|s| (s.app_name.as_str(), s.start_time, s.event_count))
+            .collect();
+        assert_eq!(
+            summary,
+            [("VS Code", 1_000, 2), ("File editing", 200_000, 2), ("Git", 400_000, 1)]
+        );
+    }
+
+    #[test]
+    fn aggregate_extends_the_open_session_across_runs() {
+        let repo = Repository::open_in_memory().unwrap();
+        let focus = focus_events(&repo, "VS Code");
+        let now = cronos_common::now_ms();
+        focus(now - 20_000);
+        focus(now - 15_000);
+
+        let aggregator = aggregator();
+        assert_eq!(aggregator.aggregate(&repo).unwrap(), 1);
+        let first = repo.sessions_in_range(0, i64::MAX, 10).unwrap();
+        assert_eq!(first.len(), 1);
+        assert!(first[0].open);
+
+        focus(now - 1_000);
+        assert_eq!(aggregator.aggregate(&repo).unwrap(), 0);
+        let second = repo.sessions_in_range(0, i64::MAX, 10).unwrap();
+        assert_eq!(second.len(), 1);
+        assert_eq!(second[0].id, first[0].id);
+        assert_eq!(second[0].end_time, now - 1_000);
+        assert_eq!(second[0].event_count, 3);
+    }
+
+    #[test]
+    fn aggregate_takes_in_late_events() {
+        let repo = Repository::open_in_memory().unwrap();
+        let code = focus_events(&repo, "VS Code");
+        let slack = focus_events(&repo, "Slack");
+        code(1_000);
+        code(4_000);
+        slack(60_000);
+
+        let aggregator = aggregator();
+        assert_eq!(aggregator.aggregate(&repo).unwrap(), 2);
+        let before = repo.sessions_in_range(0, 100_000, 10).unwrap();
+        assert!(before.iter().all(|s| !s.open));
+
+        // Spooled by the collector and delivered after the run
+        code(20_000);
+        assert_eq!(aggregator.aggregate(&repo).unwrap(), 0);
+        let after = repo.sessions_in_range(0, 100_000, 10).unwrap();
+        assert_eq!(after.len(), 2);
+        assert_eq!(after[0].id, before[0].id);
+        assert_eq!(after[0].end_time, 20_000);
+        assert_eq!(after[0].event_count, 3);
+    }
+
+    #[test]
+    fn rebuild_replaces_sessions_in_range_and_those_crossing_it() {
+        let repo = Repository::open_in_memory().unwrap();
+        let code = focus_events(&repo, "VS Code");
+        for ts in [1_000, 10_000, 20_000, 100_000, 110_000, 300_000] {
+            code(ts);
+        }
+        let aggregator = aggregator();
+        aggregator.aggregate(&repo).unwrap();
+        let before = repo.sessions_in_range(0, i64::MAX, 10).unwrap();
+        assert_eq!(before.len(), 3);
+
+        // Starts inside the first session, so that one is rebuilt whole
+        let rebuilt = aggregator.rebuild(&repo, 15_000, 200_000).unwrap();
+        assert_eq!(
+            rebuilt,
+            Rebuilt {
+                removed: 2,
+                created: 2,
+                new: 0
+            }
+        );
+        assert_eq!(repo.sessions_in_range(0, i64::MAX, 10).unwrap(), before);
+    }
+
+    #[test]
+    fn rebuild_moves_annotations_to_the_replacing_session() {
+        let repo = Repository::open_in_memory().unwrap();
+        let code = focus_events(&repo, "VS Code");
+        for ts in [1_000, 10_000, 20_000] {
+            code(ts);
+        }
+        let aggregator = aggregator();
+        aggregator.aggregate(&repo).unwrap();
+        let before = repo.sessions_in_range(0, i64::MAX, 10).unwrap();
+        repo.insert_annotation(&Annotation {
+            id: AnnotationId::new(),
+            target: AnnotationTarget::Session {
+                session_id: before[0].id.clone(),
+            },
+            text: "refactoring".to_string(),
+            tags: vec![],
+            start: Some(before[0].start_time),
+            end: Some(before[0].end_time),
+            proposed: false,
+            created_at: 0,
+        })
+        .unwrap();
+
+        // An earlier event moves the start, so the session gets a new id
+        code(500);
+        aggregator.rebuild(&repo, 0, 100_000).unwrap();
+        let after = repo.sessions_in_range(0, i64::MAX, 10).unwrap();
+        assert_ne!(after[0].id, before[0].id);
+
+        let filter = AnnotationFilter {
+            session_id: Some(after[0].id.clone()),
+            ..AnnotationFilter::default()
+        };
+        let annotations = repo.annotations(&filter).unwrap();
+        assert_eq!(annotations.len(), 1);
+        assert_eq!(
+            annotations[0].target,
+            AnnotationTarget::Session {
+                session_id: after[0].id.clone()
+            }
+        );
+        assert_eq!(annotations[0].start, Some(500));
+    }
+
+    #[test]
+    fn build_sessions_splits_at_idle_period() {
+        let aggregator = aggregator();
+        let event = |timestamp| ResolvedEvent {
+            timestamp,
+            app_name: "VS Code".to_string(),
+            window_title: None,
+        };
+        let events = vec![event(1000), event(20_000), event(25_000)];
+        let sessions = aggregator.build_sessions(&events, &[(21_000, 24_000)]);
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].end_time, 20_000);
+        assert_eq!(sessions[1].start_time, 25_000);
+    }
+
+    #[test]
+    fn aggregate_ignores_short_idle_and_splits_on_long_idle() {
+        let repo = Repository::open_in_memory().unwrap();
+        let app = fixtures::entity(EntityKind::App, "VS Code", 0);
+        repo.insert_entity(&app).unwrap();
+        let insert = |kind: EventKind, ts: Timestamp| {
+            let event = Event {
+                id: EventId::new(),
+                timestamp: ts,
+                source: CollectorSource::AppMonitor,
+                kind,
+                subject: EntityRef {
+                    kind: EntityKind::App,
+                    identity: "VS Code".to_string(),
+                    attributes: HashMap::new(),
+                },
+                context: vec![],
+                metadata: HashMap::new(),
+            };
+            repo.insert_event(&event, app.id, &[]).unwrap();
+        };
+        insert(EventKind::AppFocused, 1_000);
+        // One minute away is below the 5 minute threshold
+        insert(EventKind::IdleStarted, 2_000);
+        insert(EventKind::IdleEnded, 62_000);
+        insert(EventKind::AppFocused, 63_000);
+        // Ten minutes away ends the session; the focus change while away is dropped
+        insert(EventKind::IdleStarted, 64_000);
+        insert(EventKind::AppFocused, 300_000);
+        insert(EventKind::IdleEnded, 664_000);
+        insert(EventKind::AppFocused, 665_000);
+
+        let config = AggregatorConfig {
+            session_gap_ms: 120_000,
+            ..AggregatorConfig::default()
+        };
+        let aggregator = SessionAggregator::new(&config, Arc::new(Categorizer::default()));
+        assert_eq!(aggregator.aggregate(&repo).unwrap(), 2);
+        let sessions = repo.sessions_in_range(0, 1_000_000, 10).unwrap();
+        assert_eq!((sessions[0].start_time, sessions[0].end_time), (1_000, 63_000));
+        assert_eq!(sessions[0].event_count, 2);
+        assert_eq!(sessions[1].start_time, 665_000);
+    }
+
+    #[test]
+    fn aggregate_attributes_sessions_to_projects() {
+        let repo = Repository::open_in_memory().unwrap();
+        let entity = |kind: EntityKind, name: &str| {
+            let entity = fixtures::entity(kind, name, 0);
+            repo.insert_entity(&entity).unwrap();
+            entity
+        };
+        let cronos = entity(EntityKind::Project, "/work/cronos");
+        entity(EntityKind::Project, "/work/other");
+        let browser = entity(EntityKind::App, "Safari");
+        let file = entity(EntityKind::File, "/work/cronos/src/main.rs");
+
+        let insert = |source: CollectorSource, subject: &Entity, ts: Timestamp, context: &[EntityId]| {
+            let event = Event {
+                id: EventId::new(),
+                timestamp: ts,
+                source,
+                kind: EventKind::AppFocused,
+                subject: EntityRef {
+                    kind: subject.kind.clone(),
+                    identity: subject.name.clone(),
+                    attributes: HashMap::new(),
+                },
+                context: vec![],
+                metadata: HashMap::from([(
+                    "window_title".to_string(),
+                    serde_json::json!("Rust docs"),
+                )]),
+            };
+            repo.insert_event(&event, subject.id, context).unwrap();
+        };
+        // Reading docs while saving files in cronos, then idle browsing
+        insert(CollectorSource::AppMonitor, &browser, 1_000, &[]);
+        insert(CollectorSource::Filesystem, &file, 2_000, &[cronos.id]);
+        insert(CollectorSource::AppMonitor, &browser, 4_000, &[]);
+        insert(CollectorSource::AppMonitor, &browser, 200_000, &[]);
+
+        assert_eq!(aggregator().aggregate(&repo).unwrap(), 2);
+        let sessions = repo.sessions_in_range(0, 300_000, 10).unwrap();
+        assert_eq!(sessions[0].project.as_deref(), Some("/work/cronos"));
+        assert_eq!(sessions[0].project_id, Some(cronos.id));
+        assert!(sessions[0].project_confidence >= crate::attribution::MIN_CONFIDENCE);
+        assert_eq!(sessions[1].project, None);
+        assert_eq!(sessions[1].project_confidence, 0.0);
     }
 }
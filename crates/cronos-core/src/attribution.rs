@@ -0,0 +1,441 @@
+//! Which project an app session was spent on.
+//!
+//! Two kinds of evidence count. Filesystem, git and terminal events during
+//! the session (give or take a margin) whose context names a project show
+//! what was being worked on. Window titles name the workspace (VS Code,
+//! Cursor), the project (JetBrains IDEs) or a working directory (vim, tmux,
+//! shell prompts). Evidence for the same project is combined, and the best
+//! supported project is attributed if its confidence reaches
+//! [`MIN_CONFIDENCE`].
+
+use cronos_model::{Entity, EntityId, Timestamp};
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+/// Sessions with weaker evidence than this are left without a project.
+pub const MIN_CONFIDENCE: f32 = 0.3;
+
+/// Confidence when all activity around a session is in one project.
+const ACTIVITY_WEIGHT: f32 = 0.8;
+/// Confidence of a project named by an editor or IDE title.
+const EDITOR_TITLE_WEIGHT: f32 = 0.9;
+/// Confidence of a working directory in a vim or shell title.
+const PATH_TITLE_WEIGHT: f32 = 0.7;
+/// Confidence of a tmux session name, which is often but not always the project.
+const TMUX_TITLE_WEIGHT: f32 = 0.5;
+
+/// Apps whose titles read `[file - ]workspace[ - app]`.
+const VSCODE_LIKE: &[&str] = &[
+    "code",
+    "visual studio code",
+    "vscodium",
+    "cursor",
+    "windsurf",
+];
+/// Apps whose titles read `project [path] – file`.
+const JETBRAINS: &[&str] = &[
+    "intellij idea",
+    "pycharm",
+    "webstorm",
+    "goland",
+    "clion",
+    "rustrover",
+    "rider",
+    "phpstorm",
+    "rubymine",
+    "android studio",
+];
+
+/// `main.rs (~/work/cronos/src) - NVIM`
+static VIM_TITLE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\((~?/[^)]*)\) - N?VIM\d*$").unwrap());
+/// `cronos:0:nvim - "..."`, tmux's default `set-titles-string`.
+static TMUX_TITLE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^([\w.-]+):\d+:").unwrap());
+/// `me@host: ~/work/cronos`, the usual shell prompt title.
+static SHELL_TITLE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[\w.-]+@[\w.-]+:\s*(~?/\S*)$").unwrap());
+
+/// The project a session is attributed to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attribution {
+    pub project_id: EntityId,
+    /// The project entity's name.
+    pub project: String,
+    pub confidence: f32,
+}
+
+/// What a window title says about the project.
+#[derive(Debug, Clone, PartialEq)]
+enum TitleHint {
+    /// A directory name, matched against the last component of project paths.
+    Name(String),
+    /// A directory inside the project.
+    Path(String),
+}
+
+struct KnownProject {
+    id: EntityId,
+    path: String,
+    /// Last path component.
+    name: String,
+}
+
+/// Attributes sessions to the projects known when it was built.
+pub struct ProjectAttributor {
+    projects: Vec<KnownProject>,
+    /// Project activity ordered by time.
+    activity: Vec<(Timestamp, EntityId)>,
+    /// Activity this long before a session starts or after it ends counts too.
+    margin_ms: i64,
+    home: Option<String>,
+}
+
+impl ProjectAttributor {
+    /// `projects` are the project entities; `activity` is when they were
+    /// touched, ordered by time (see `Repository::project_activity`).
+    pub fn new(projects: &[Entity], activity: Vec<(Timestamp, EntityId)>, margin_ms: i64) -> Self {
+        let projects = projects
+            .iter()
+            .map(|p| KnownProject {
+                id: p.id,
+                path: p.name.trim_end_matches('/').to_string(),
+                name: p
+                    .name
+                    .trim_end_matches('/')
+                    .rsplit('/')
+                    .next()
+                    .unwrap_or("")
+                    .to_string(),
+            })
+            .collect();
+        Self {
+            projects,
+            activity,
+            margin_ms,
+            home: std::env::var("HOME").ok(),
+        }
+    }
+
+    /// The project a session of `app_name` from `start` to `end` showing
+    /// `window_titles` was most likely spent on.
+    pub fn attribute(
+        &self,
+        app_name: &str,
+        window_titles: &[String],
+        start: Timestamp,
+        end: Timestamp,
+    ) -> Option<Attribution> {
+        let mut scores: HashMap<EntityId, f32> = HashMap::new();
+
+        // Titles of one workspace repeat; count each project's best title once
+        let mut from_titles: HashMap<EntityId, f32> = HashMap::new();
+        for title in window_titles {
+            if let Some((hint, weight)) = title_hint(app_name, title) {
+                let matches = self.resolve(&hint);
+                for id in &matches {
+                    let score = weight / matches.len() as f32;
+                    let best = from_titles.entry(*id).or_default();
+                    *best = best.max(score);
+                }
+            }
+        }
+        for (id, score) in from_titles {
+            combine(scores.entry(id).or_default(), score);
+        }
+
+        let from = self
+            .activity
+            .partition_point(|(t, _)| *t < start - self.margin_ms);
+        let to = self
+            .activity
+            .partition_point(|(t, _)| *t <= end + self.margin_ms);
+        let nearby = &self.activity[from..to];
+        let mut counts: HashMap<EntityId, usize> = HashMap::new();
+        for (_, id) in nearby {
+            *counts.entry(*id).or_default() += 1;
+        }
+        for (id, count) in counts {
+            let share = count as f32 / nearby.len() as f32;
+            combine(scores.entry(id).or_default(), ACTIVITY_WEIGHT * share);
+        }
+
+        let (id, confidence) = scores
+            .into_iter()
+            // Ties go to the lower id so results do not depend on hash order
+            .max_by(|a, b| a.1.total_cmp(&b.1).then(b.0 .0.cmp(&a.0 .0)))?;
+        if confidence < MIN_CONFIDENCE {
+            return None;
+        }
+        let project = self.projects.iter().find(|p| p.id == id)?;
+        Some(Attribution {
+            project_id: id,
+            project: project.path.clone(),
+            confidence,
+        })
+    }
+
+    /// Projects a hint refers to: the innermost project containing a path,
+    /// or every project with a matching name.
+    fn resolve(&self, hint: &TitleHint) -> Vec<EntityId> {
+        match hint {
+            TitleHint::Name(name) => self
+                .projects
+                .iter()
+                .filter(|p| p.name == *name)
+                .map(|p| p.id)
+                .collect(),
+            TitleHint::Path(path) => {
+                let path = match (path.strip_prefix('~'), &self.home) {
+                    (Some(rest), Some(home)) => format!("{}{rest}", home.trim_end_matches('/')),
+                    _ => path.clone(),
+                };
+                let path = path.trim_end_matches('/');
+                self.projects
+                    .iter()
+                    .filter(|p| {
+                        !p.path.is_empty()
+                            && (path == p.path
+                                || path
+                                    .strip_prefix(&p.path)
+                                    .is_some_and(|r| r.starts_with('/')))
+                    })
+                    .max_by_key(|p| p.path.len())
+                    .map(|p| p.id)
+                    .into_iter()
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Add independent evidence `score` to `total`.
+fn combine(total: &mut f32, score: f32) {
+    *total = 1.0 - (1.0 - *total) * (1.0 - score);
+}
+
+/// What `title`, shown by `app_name`, says about the project, and how much
+/// to trust it.
+fn title_hint(app_name: &str, title: &str) -> Option<(TitleHint, f32)> {
+    let app = app_name.to_lowercase();
+    // `Code - Insiders` and `IntelliJ IDEA 2024.1` are still VS Code and IntelliJ
+    let is = |names: &[&str]| {
+        names
+            .iter()
+            .any(|n| app == *n || app.strip_prefix(n).is_some_and(|r| r.starts_with(' ')))
+    };
+    if is(VSCODE_LIKE) {
+        return vscode_workspace(title).map(|w| (TitleHint::Name(w), EDITOR_TITLE_WEIGHT));
+    }
+    if is(JETBRAINS) {
+        return jetbrains_project(title).map(|h| (h, EDITOR_TITLE_WEIGHT));
+    }
+    if let Some(c) = VIM_TITLE.captures(title) {
+        return Some((TitleHint::Path(c[1].to_string()), PATH_TITLE_WEIGHT));
+    }
+    if let Some(c) = SHELL_TITLE.captures(title) {
+        return Some((TitleHint::Path(c[1].to_string()), PATH_TITLE_WEIGHT));
+    }
+    if let Some(c) = TMUX_TITLE.captures(title) {
+        return Some((TitleHint::Name(c[1].to_string()), TMUX_TITLE_WEIGHT));
+    }
+    None
+}
+
+/// `● main.rs - cronos - Visual Studio Code` or `main.rs — cronos [SSH: box]`
+/// name the workspace last, before the app.
+fn vscode_workspace(title: &str) -> Option<String> {
+    let title = title.trim_start_matches(['●', ' ']).replace(" — ", " - ");
+    let mut parts: Vec<&str> = title.split(" - ").map(str::trim).collect();
+    if parts.len() > 1
+        && parts
+            .last()
+            .is_some_and(|p| VSCODE_LIKE.contains(&p.to_lowercase().as_str()))
+    {
+        parts.pop();
+    }
+    let workspace = parts.pop()?;
+    let workspace = workspace
+        .split(" [")
+        .next()
+        .unwrap_or(workspace)
+        .trim_end_matches(" (Workspace)")
+        .trim();
+    (!workspace.is_empty()).then(|| workspace.to_string())
+}
+
+/// `cronos [~/work/cronos] – main.rs` or `cronos – main.rs` name the project
+/// first.
+fn jetbrains_project(title: &str) -> Option<TitleHint> {
+    let title = title.replace(" – ", " - ");
+    let first = title.split(" - ").next()?.trim();
+    if let Some((_, rest)) = first.split_once(" [") {
+        if let Some(path) = rest.strip_suffix(']') {
+            return Some(TitleHint::Path(path.to_string()));
+        }
+    }
+    (!first.is_empty()).then(|| TitleHint::Name(first.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures as fixtures;
+    use cronos_model::EntityKind;
+
+    fn project(path: &str) -> Entity {
+        fixtures::entity(EntityKind::Project, path, 0)
+    }
+
+    fn titles(t: &[&str]) -> Vec<String> {
+        t.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn editor_titles_name_the_project() {
+        let cases = [
+            (
+                "Code",
+                "● main.rs - cronos - Visual Studio Code",
+                Some(TitleHint::Name("cronos".into())),
+            ),
+            (
+                "Code",
+                "main.rs — cronos",
+                Some(TitleHint::Name("cronos".into())),
+            ),
+            (
+                "Cursor",
+                "lib.rs - cronos [SSH: devbox] - Cursor",
+                Some(TitleHint::Name("cronos".into())),
+            ),
+            (
+                "RustRover",
+                "cronos [~/work/cronos] – src/main.rs",
+                Some(TitleHint::Path("~/work/cronos".into())),
+            ),
+            (
+                "IntelliJ IDEA",
+                "cronos – Main.java",
+                Some(TitleHint::Name("cronos".into())),
+            ),
+            (
+                "iTerm2",
+                "main.rs (~/work/cronos/src) - NVIM",
+                Some(TitleHint::Path("~/work/cronos/src".into())),
+            ),
+            (
+                "Terminal",
+                "me@laptop: ~/work/cronos",
+                Some(TitleHint::Path("~/work/cronos".into())),
+            ),
+            (
+                "kitty",
+                "cronos:1:nvim - \"main.rs\"",
+                Some(TitleHint::Name("cronos".into())),
+            ),
+            ("Safari", "Rust Documentation", None),
+        ];
+        for (app, title, expected) in cases {
+            assert_eq!(
+                title_hint(app, title).map(|(h, _)| h),
+                expected,
+                "{app}: {title}"
+            );
+        }
+    }
+
+    #[test]
+    fn titles_resolve_to_the_innermost_project() {
+        let outer = project("/work/monorepo");
+        let inner = project("/work/monorepo/tools/cronos");
+        let attributor = ProjectAttributor::new(&[outer.clone(), inner.clone()], vec![], 0);
+
+        let found = attributor
+            .attribute(
+                "iTerm2",
+                &titles(&["main.rs (/work/monorepo/tools/cronos/src) - VIM"]),
+                0,
+                10,
+            )
+            .unwrap();
+        assert_eq!(found.project_id, inner.id);
+        assert_eq!(found.project, "/work/monorepo/tools/cronos");
+
+        let found = attributor
+            .attribute(
+                "Terminal",
+                &titles(&["me@laptop: /work/monorepo/docs"]),
+                0,
+                10,
+            )
+            .unwrap();
+        assert_eq!(found.project_id, outer.id);
+        // Not inside either project
+        assert!(attributor
+            .attribute(
+                "Terminal",
+                &titles(&["me@laptop: /work/monorepository"]),
+                0,
+                10
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn overlapping_activity_attributes_sessions_without_title_hints() {
+        let cronos = project("/work/cronos");
+        let other = project("/work/other");
+        let activity = vec![
+            (500, other.id),
+            (1_000, cronos.id),
+            (2_000, cronos.id),
+            (3_000, cronos.id),
+            (3_500, other.id),
+            (90_000, other.id),
+        ];
+        let attributor = ProjectAttributor::new(&[cronos.clone(), other.clone()], activity, 500);
+
+        // Three of the five events within the margin are in cronos
+        let found = attributor
+            .attribute("Safari", &titles(&["Docs"]), 900, 3_000)
+            .unwrap();
+        assert_eq!(found.project_id, cronos.id);
+        assert!((found.confidence - ACTIVITY_WEIGHT * 0.6).abs() < 1e-6);
+
+        // Nothing nearby, and no title to go on
+        assert!(attributor
+            .attribute("Safari", &titles(&["Docs"]), 50_000, 60_000)
+            .is_none());
+    }
+
+    #[test]
+    fn agreeing_evidence_raises_confidence_and_titles_beat_weak_activity() {
+        let cronos = project("/work/cronos");
+        let other = project("/work/other");
+        let activity = vec![(1_000, cronos.id), (2_000, other.id)];
+        let attributor = ProjectAttributor::new(&[cronos.clone(), other.clone()], activity, 0);
+
+        let found = attributor
+            .attribute(
+                "Code",
+                &titles(&["main.rs - cronos - Visual Studio Code"]),
+                0,
+                5_000,
+            )
+            .unwrap();
+        assert_eq!(found.project_id, cronos.id);
+        assert!(found.confidence > EDITOR_TITLE_WEIGHT);
+
+        let found = attributor
+            .attribute(
+                "Code",
+                &titles(&["a.rs - other - Visual Studio Code"]),
+                0,
+                5_000,
+            )
+            .unwrap();
+        assert_eq!(found.project_id, other.id);
+    }
+}
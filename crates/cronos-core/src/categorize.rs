@@ -0,0 +1,347 @@
+//! Rules that put app sessions into activity categories.
+//!
+//! A rule matches an app name and/or window titles exactly, by glob or by
+//! regex. Rules from `daemon.categories` are tried highest priority first,
+//! ahead of the built-in rules for common apps; the first match names the
+//! category and sessions nothing matches are [`OTHER`].
+
+use cronos_common::config::{CategoriesConfig, CategoryRule};
+use regex::Regex;
+
+/// Category for sessions no rule matches.
+pub const OTHER: &str = "other";
+
+/// Categories that exist without configuration.
+pub const BUILTIN_CATEGORIES: &[&str] = &[
+    "coding",
+    "communication",
+    "browsing",
+    "productivity",
+    "media",
+    "system",
+    OTHER,
+];
+
+/// Built-in rules as `(category, match, app pattern)`.
+const BUILTIN_RULES: &[(&str, &str, &str)] = &[
+    ("coding", "exact", "code"),
+    ("coding", "exact", "vs code"),
+    ("coding", "glob", "visual studio code*"),
+    ("coding", "exact", "cursor"),
+    ("coding", "exact", "xcode"),
+    ("coding", "glob", "intellij idea*"),
+    ("coding", "exact", "terminal"),
+    ("coding", "glob", "iterm*"),
+    ("coding", "exact", "warp"),
+    ("coding", "exact", "alacritty"),
+    ("coding", "exact", "kitty"),
+    ("coding", "exact", "wezterm"),
+    ("coding", "exact", "ghostty"),
+    ("communication", "exact", "discord"),
+    ("communication", "exact", "slack"),
+    ("communication", "exact", "messages"),
+    ("communication", "exact", "telegram"),
+    ("communication", "glob", "*teams"),
+    ("communication", "exact", "mail"),
+    ("communication", "exact", "zoom.us"),
+    ("browsing", "glob", "google chrome*"),
+    ("browsing", "exact", "chromium"),
+    ("browsing", "glob", "firefox*"),
+    ("browsing", "exact", "safari"),
+    ("browsing", "exact", "arc"),
+    ("browsing", "glob", "brave browser*"),
+    ("browsing", "glob", "microsoft edge*"),
+    ("productivity", "exact", "notion"),
+    ("productivity", "exact", "obsidian"),
+    ("productivity", "exact", "notes"),
+    ("productivity", "exact", "pages"),
+    ("media", "exact", "spotify"),
+    ("media", "exact", "music"),
+    ("media", "exact", "vlc"),
+    ("system", "exact", "finder"),
+    ("system", "exact", "preview"),
+];
+
+#[derive(Debug, thiserror::Error)]
+pub enum RuleError {
+    #[error("rule for '{0}' needs an app or title pattern")]
+    Empty(String),
+    #[error("unknown category '{0}' (add it to daemon.categories.custom)")]
+    UnknownCategory(String),
+    #[error("unknown match '{0}' (expected exact, glob or regex)")]
+    UnknownMatch(String),
+    #[error("bad regex '{pattern}': {source}")]
+    BadRegex {
+        pattern: String,
+        source: regex::Error,
+    },
+}
+
+#[derive(Debug)]
+enum Pattern {
+    /// Lowercased.
+    Exact(String),
+    /// Lowercased.
+    Glob(String),
+    Regex(Regex),
+}
+
+impl Pattern {
+    fn parse(match_kind: &str, pattern: &str) -> Result<Self, RuleError> {
+        match match_kind {
+            "exact" => Ok(Self::Exact(pattern.to_lowercase())),
+            "glob" => Ok(Self::Glob(pattern.to_lowercase())),
+            "regex" => Regex::new(pattern)
+                .map(Self::Regex)
+                .map_err(|source| RuleError::BadRegex {
+                    pattern: pattern.to_string(),
+                    source,
+                }),
+            other => Err(RuleError::UnknownMatch(other.to_string())),
+        }
+    }
+
+    fn matches(&self, text: &str) -> bool {
+        match self {
+            Self::Exact(p) => text.to_lowercase() == *p,
+            Self::Glob(p) => glob_match::glob_match(p, &text.to_lowercase()),
+            Self::Regex(r) => r.is_match(text),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Rule {
+    category: String,
+    app: Option<Pattern>,
+    title: Option<Pattern>,
+    priority: i32,
+}
+
+impl Rule {
+    fn matches(&self, app_name: &str, window_titles: &[String]) -> bool {
+        self.app.as_ref().is_none_or(|p| p.matches(app_name))
+            && self
+                .title
+                .as_ref()
+                .is_none_or(|p| window_titles.iter().any(|t| p.matches(t)))
+    }
+}
+
+/// Compiled categorization rules.
+#[derive(Debug)]
+pub struct Categorizer {
+    /// Tried in order.
+    rules: Vec<Rule>,
+    categories: Vec<String>,
+}
+
+impl Default for Categorizer {
+    /// Only the built-in rules.
+    fn default() -> Self {
+        Self::from_config(&CategoriesConfig::default()).expect("built-in rules are valid")
+    }
+}
+
+impl Categorizer {
+    pub fn from_config(config: &CategoriesConfig) -> Result<Self, RuleError> {
+        let mut categories: Vec<String> =
+            BUILTIN_CATEGORIES.iter().map(|c| c.to_string()).collect();
+        for custom in &config.custom {
+            if !categories.contains(custom) {
+                categories.push(custom.clone());
+            }
+        }
+
+        let mut rules = Vec::new();
+        for rule in &config.rules {
+            if !categories.contains(&rule.category) {
+                return Err(RuleError::UnknownCategory(rule.category.clone()));
+            }
+            rules.push(compile(rule)?);
+        }
+        if config.builtin_rules {
+            for (category, match_kind, app) in BUILTIN_RULES {
+                rules.push(Rule {
+                    category: category.to_string(),
+                    app: Some(Pattern::parse(match_kind, app)?),
+                    title: None,
+                    priority: 0,
+                });
+            }
+        }
+        // Stable, so user rules stay ahead of built-in ones of equal priority
+        rules.sort_by_key(|r| std::cmp::Reverse(r.priority));
+        Ok(Self { rules, categories })
+    }
+
+    /// Category for a session of `app_name` showing `window_titles`.
+    pub fn categorize(&self, app_name: &str, window_titles: &[String]) -> &str {
+        self.rules
+            .iter()
+            .find(|r| r.matches(app_name, window_titles))
+            .map_or(OTHER, |r| r.category.as_str())
+    }
+
+    /// Built-in and custom categories.
+    pub fn categories(&self) -> &[String] {
+        &self.categories
+    }
+}
+
+fn compile(rule: &CategoryRule) -> Result<Rule, RuleError> {
+    if rule.app.is_none() && rule.title.is_none() {
+        return Err(RuleError::Empty(rule.category.clone()));
+    }
+    let pattern = |p: &Option<String>| {
+        p.as_deref()
+            .map(|p| Pattern::parse(&rule.match_kind, p))
+            .transpose()
+    };
+    Ok(Rule {
+        category: rule.category.clone(),
+        app: pattern(&rule.app)?,
+        title: pattern(&rule.title)?,
+        priority: rule.priority,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app(name: &str) -> String {
+        Categorizer::default().categorize(name, &[]).to_string()
+    }
+
+    fn rule(
+        category: &str,
+        match_kind: &str,
+        app: Option<&str>,
+        title: Option<&str>,
+    ) -> CategoryRule {
+        CategoryRule {
+            category: category.to_string(),
+            match_kind: match_kind.to_string(),
+            app: app.map(String::from),
+            title: title.map(String::from),
+            priority: 0,
+        }
+    }
+
+    #[test]
+    fn builtin_rules_identify_coding_apps() {
+        for name in ["VS Code", "Code", "Cursor", "Terminal", "iTerm2", "Xcode"] {
+            assert_eq!(app(name), "coding", "{name}");
+        }
+    }
+
+    #[test]
+    fn builtin_rules_identify_communication_apps() {
+        for name in ["Discord", "Slack", "Messages", "Microsoft Teams"] {
+            assert_eq!(app(name), "communication", "{name}");
+        }
+    }
+
+    #[test]
+    fn builtin_rules_identify_browsing_apps() {
+        for name in ["Google Chrome", "Arc", "Safari", "Firefox"] {
+            assert_eq!(app(name), "browsing", "{name}");
+        }
+    }
+
+    #[test]
+    fn builtin_rules_identify_other_categories() {
+        assert_eq!(app("Notion"), "productivity");
+        assert_eq!(app("Spotify"), "media");
+        assert_eq!(app("Finder"), "system");
+        assert_eq!(app("SomeRandomApp"), "other");
+    }
+
+    #[test]
+    fn builtin_rules_do_not_match_inside_words() {
+        for name in [
+            "Archive Utility",
+            "Barcode Scanner",
+            "Knowledge",
+            "Passcodes",
+        ] {
+            assert_eq!(app(name), OTHER, "{name}");
+        }
+    }
+
+    #[test]
+    fn user_rules_win_by_priority_then_order() {
+        let config = CategoriesConfig {
+            custom: vec!["writing".to_string(), "meetings".to_string()],
+            builtin_rules: true,
+            rules: vec![
+                rule("writing", "glob", Some("*code*"), Some("*.md*")),
+                rule("meetings", "regex", None, Some(r"(?i)\bstandup\b")),
+                CategoryRule {
+                    priority: 5,
+                    ..rule("browsing", "exact", Some("code"), Some("localhost:3000"))
+                },
+            ],
+        };
+        let categorizer = Categorizer::from_config(&config).unwrap();
+        let titles = |t: &[&str]| t.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            categorizer.categorize("Code", &titles(&["README.md - cronos"])),
+            "writing"
+        );
+        assert_eq!(
+            categorizer.categorize("Code", &titles(&["main.rs - cronos"])),
+            "coding"
+        );
+        assert_eq!(
+            categorizer.categorize("Zoom", &titles(&["Daily Standup"])),
+            "meetings"
+        );
+        // Higher priority beats an earlier rule that also matches
+        assert_eq!(
+            categorizer.categorize("Code", &titles(&["notes.md", "localhost:3000"])),
+            "browsing"
+        );
+        assert!(categorizer.categories().contains(&"meetings".to_string()));
+    }
+
+    #[test]
+    fn builtin_rules_can_be_turned_off() {
+        let config = CategoriesConfig {
+            builtin_rules: false,
+            ..CategoriesConfig::default()
+        };
+        assert_eq!(
+            Categorizer::from_config(&config)
+                .unwrap()
+                .categorize("Code", &[]),
+            OTHER
+        );
+    }
+
+    #[test]
+    fn invalid_rules_are_rejected() {
+        let config = |rule: CategoryRule| CategoriesConfig {
+            rules: vec![rule],
+            ..CategoriesConfig::default()
+        };
+        assert!(matches!(
+            Categorizer::from_config(&config(rule("gaming", "exact", Some("Steam"), None))),
+            Err(RuleError::UnknownCategory(_))
+        ));
+        assert!(matches!(
+            Categorizer::from_config(&config(rule("media", "fuzzy", Some("vlc"), None))),
+            Err(RuleError::UnknownMatch(_))
+        ));
+        assert!(matches!(
+            Categorizer::from_config(&config(rule("media", "regex", Some("("), None))),
+            Err(RuleError::BadRegex { .. })
+        ));
+        assert!(matches!(
+            Categorizer::from_config(&config(rule("media", "exact", None, None))),
+            Err(RuleError::Empty(_))
+        ));
+    }
+}
@@ -1,23 +1,95 @@
-use crate::graph::ContextGraph;
-use crate::ingest::IngestPipeline;
+use crate::aggregator::{Rebuilt, SessionAggregator};
+use crate::categorize::Categorizer;
+use crate::graph::{ContextGraph, RankedEntity};
+use crate::ingest::{IngestPipeline, Rejection};
 use crate::linker::Linker;
+use crate::metrics::FocusMetrics;
+use crate::storage::backup::{self, BackupError};
+use crate::storage::encryption;
+use crate::storage::repo::{AnnotationFilter, Cursor, Filter, Order, QueryRows, StoredEvent};
+use crate::storage::sql::SqlReader;
 use crate::storage::Repository;
+use crate::supervisor::{Supervisor, SupervisorError};
 use cronos_common::config::DaemonConfig;
 use cronos_model::*;
 use cronos_proto::*;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
+/// Per-connection state tracked by the server for the lifetime of a socket.
+#[derive(Debug)]
+pub struct ConnectionState {
+    /// Unique per connection, so a collector's old connection can be told
+    /// apart from the one it reconnected on.
+    pub id: u64,
+    /// Name of the collector that completed a handshake on this connection.
+    pub collector: Option<String>,
+    /// What the peer is allowed to do; grows when it presents a token.
+    pub capabilities: HashSet<Capability>,
+    /// Protocol version agreed through `Hello` or `CollectorHandshake`.
+    pub protocol_version: Option<u8>,
+    /// Features both sides support, from the same negotiation.
+    pub features: Vec<String>,
+    /// Codec the server writes responses with.
+    pub codec: Codec,
+}
+
+impl ConnectionState {
+    pub fn new(capabilities: impl IntoIterator<Item = Capability>) -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            collector: None,
+            capabilities: capabilities.into_iter().collect(),
+            protocol_version: None,
+            features: Vec::new(),
+            codec: Codec::Json,
+        }
+    }
+
+    /// An in-process caller with every capability.
+    pub fn trusted() -> Self {
+        Self::new(Capability::ALL)
+    }
+
+    pub fn can(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.features.iter().any(|f| f == feature)
+    }
+}
+
 pub struct Engine {
     repo: Mutex<Repository>,
+    /// Read-only connection for `QueryKind::Sql`; `None` when disabled.
+    sql: Option<Mutex<SqlReader>>,
     graph: Mutex<ContextGraph>,
     ingest: Mutex<IngestPipeline>,
     linker: Linker,
+    categorizer: Arc<Categorizer>,
+    aggregator: SessionAggregator,
+    focus: FocusMetrics,
+    /// Zone that days are counted in unless a query names another.
+    timezone: chrono_tz::Tz,
     start_time: Instant,
     collectors: Mutex<HashMap<String, CollectorInfo>>,
+    /// Connection each collector last completed a handshake on. Locked
+    /// after `collectors`.
+    collector_connections: Mutex<HashMap<String, u64>>,
+    supervisor: Mutex<Supervisor>,
+    heartbeat_timeout_ms: u64,
+    /// Capabilities of Unix socket peers before any token.
+    local_capabilities: HashSet<Capability>,
+    /// Capabilities of TCP peers with the `tcp.token` or a verified client certificate.
+    remote_capabilities: HashSet<Capability>,
+    /// Tokens accepted by `Authenticate` and `CollectorHandshake`, with what they grant.
+    tokens: Vec<(String, HashSet<Capability>)>,
+    require_same_uid: bool,
     tracking_paused: AtomicBool,
 }
 
@@ -26,7 +98,22 @@ impl Engine {
         if let Some(parent) = db_path.parent() {
             std::fs::create_dir_all(parent)?;
         }
-        let repo = Repository::open(db_path)?;
+        let key = encryption::resolve_key(&config.encryption, db_path)?;
+        let repo = match &key {
+            Some(key) => Repository::open_encrypted(db_path, key)?,
+            None => Repository::open(db_path)?,
+        };
+        let sql = if config.sql.enabled {
+            let timeout = std::time::Duration::from_millis(config.sql.timeout_ms);
+            Some(Mutex::new(SqlReader::open(
+                db_path,
+                key.as_ref(),
+                config.sql.max_rows,
+                timeout,
+            )?))
+        } else {
+            None
+        };
 
         let entities = repo.all_entities()?;
         let edges = repo.all_edges()?;
@@ -37,17 +124,93 @@ impl Engine {
             "rebuilt in-memory graph"
         );
 
+        let categorizer = Categorizer::from_config(&config.categories)
+            .map_err(|e| anyhow::anyhow!("daemon.categories: {e}"))?;
+        let categorizer = Arc::new(categorizer);
+        let timezone = cronos_common::tz::resolve(&config.timezone)
+            .map_err(|e| anyhow::anyhow!("daemon.timezone: {e}"))?;
+        let mut local_capabilities = parse_capabilities(&config.auth.local_capabilities)?;
+        // Without the uid check, other users' processes could reach admin actions.
+        if !config.auth.require_same_uid && local_capabilities.remove(&Capability::Admin) {
+            tracing::warn!("not granting admin to local peers: daemon.auth.require_same_uid is off");
+        }
+        let remote_capabilities = parse_capabilities(&config.tcp.capabilities)?;
+        let mut tokens = Vec::new();
+        if !config.tcp.token.is_empty() {
+            tokens.push((config.tcp.token.clone(), remote_capabilities.clone()));
+        }
+        for grant in &config.auth.tokens {
+            if grant.token.is_empty() {
+                anyhow::bail!("daemon.auth.tokens: empty token");
+            }
+            tokens.push((grant.token.clone(), parse_capabilities(&grant.capabilities)?));
+        }
+
         Ok(Self {
             repo: Mutex::new(repo),
+            sql,
             graph: Mutex::new(graph),
             ingest: Mutex::new(IngestPipeline::new(config.dedup.window_ms)),
             linker: Linker::new(config.linker.temporal_window_ms),
+            aggregator: SessionAggregator::new(&config.aggregator, Arc::clone(&categorizer)),
+            categorizer,
+            focus: FocusMetrics::new(&config.focus),
+            timezone,
             start_time: Instant::now(),
             collectors: Mutex::new(HashMap::new()),
+            collector_connections: Mutex::new(HashMap::new()),
+            supervisor: Mutex::new(Supervisor::default()),
+            heartbeat_timeout_ms: config.supervisor.heartbeat_timeout_ms,
+            local_capabilities,
+            remote_capabilities,
+            tokens,
+            require_same_uid: config.auth.require_same_uid,
             tracking_paused: AtomicBool::new(false),
         })
     }
 
+    /// Hand collector lifecycle over to `supervisor` and start all of its collectors.
+    pub fn install_supervisor(&self, mut supervisor: Supervisor) {
+        supervisor.start_all();
+        *self.supervisor.lock().unwrap() = supervisor;
+    }
+
+    /// Restart crashed collectors and disconnect collectors whose heartbeats stopped.
+    pub fn supervise(&self) {
+        self.supervisor.lock().unwrap().tick(Instant::now());
+
+        let now = cronos_common::now_ms();
+        let timeout = self.heartbeat_timeout_ms as i64;
+        for info in self.collectors.lock().unwrap().values_mut() {
+            let missed = info.last_heartbeat.is_none_or(|hb| now - hb > timeout);
+            if info.connected && missed {
+                tracing::warn!(collector = %info.name, "collector missed heartbeats, marking disconnected");
+                info.connected = false;
+                info.stale = true;
+            }
+        }
+    }
+
+    /// Mark the collector bound to a closed connection as disconnected,
+    /// unless it has since reconnected on another one.
+    pub fn collector_disconnected(&self, conn: &ConnectionState) {
+        let Some(name) = &conn.collector else { return };
+        let mut collectors = self.collectors.lock().unwrap();
+        if self.collector_connections.lock().unwrap().get(name) != Some(&conn.id) {
+            return;
+        }
+        if let Some(info) = collectors.get_mut(name) {
+            tracing::info!(collector = %name, "collector disconnected");
+            info.connected = false;
+            info.connected_at = None;
+        }
+    }
+
+    /// Stop all supervised collectors (used on daemon shutdown).
+    pub fn stop_collectors(&self) {
+        self.supervisor.lock().unwrap().stop_all();
+    }
+
     pub fn is_tracking_paused(&self) -> bool {
         self.tracking_paused.load(Ordering::Relaxed)
     }
@@ -56,16 +219,98 @@ impl Engine {
         self.tracking_paused.store(paused, Ordering::Relaxed);
     }
 
+    /// Whether a Unix socket peer running as `uid` may connect at all.
+    pub fn accepts_local_peer(&self, uid: u32) -> bool {
+        // SAFETY: getuid has no preconditions and cannot fail.
+        !self.require_same_uid || uid == unsafe { libc::getuid() }
+    }
+
+    /// State for a new peer on the local Unix socket (already uid-checked).
+    pub fn local_connection(&self) -> ConnectionState {
+        ConnectionState::new(self.local_capabilities.iter().copied())
+    }
+
+    /// State for a new TCP peer. A verified client certificate grants
+    /// `tcp.capabilities`; otherwise the peer starts with nothing and must
+    /// present a token.
+    pub fn remote_connection(&self, verified: bool) -> ConnectionState {
+        if verified {
+            ConnectionState::new(self.remote_capabilities.iter().copied())
+        } else {
+            ConnectionState::new([])
+        }
+    }
+
+    /// Handle a message from a trusted in-process caller.
     pub fn handle_message(&self, msg: Message) -> Message {
+        self.handle_connection_message(&mut ConnectionState::trusted(), msg)
+    }
+
+    /// Handle a message received on a connection, updating its `ConnectionState`.
+    pub fn handle_connection_message(&self, conn: &mut ConnectionState, msg: Message) -> Message {
         let request_id = msg.id.clone();
+        if let MessageKind::Hello {
+            protocol,
+            features,
+            codecs,
+        } = msg.kind
+        {
+            return negotiate(conn, request_id, protocol, &features, &codecs);
+        }
+        if !VersionRange::SUPPORTED.contains(msg.version) {
+            return incompatible(request_id, VersionRange::exactly(msg.version));
+        }
+        if let MessageKind::CollectorHandshake { token: Some(token), .. } = &msg.kind {
+            if let Some(granted) = self.token_capabilities(token) {
+                conn.capabilities.extend(granted);
+            }
+        }
+        if let Some(required) = msg.kind.required_capability() {
+            if !conn.can(required) {
+                tracing::warn!(%required, "rejecting unauthorized message");
+                return Message::error(
+                    request_id,
+                    ErrorCode::Unauthorized,
+                    format!("missing capability: {required}"),
+                );
+            }
+        }
         match msg.kind {
-            MessageKind::EmitEvent { event } => self.handle_emit_event(request_id, event),
+            MessageKind::Authenticate { token } => match self.token_capabilities(&token) {
+                Some(granted) => {
+                    conn.capabilities.extend(granted);
+                    let mut capabilities: Vec<_> = conn.capabilities.iter().copied().collect();
+                    capabilities.sort();
+                    Message::new(request_id, MessageKind::Authenticated { capabilities })
+                }
+                None => {
+                    tracing::warn!("rejecting invalid token");
+                    Message::error(request_id, ErrorCode::Unauthorized, "invalid token")
+                }
+            },
+            MessageKind::EmitEvent { event } => self.handle_emit_event(conn, request_id, event),
             MessageKind::CollectorHandshake {
                 name,
-                collector_version,
                 source,
-            } => self.handle_handshake(request_id, name, collector_version, source),
-            MessageKind::Heartbeat => Message::ack(request_id),
+                protocol,
+                features,
+                codecs,
+                ..
+            } => {
+                // Collectors that predate negotiation speak only the version they stamp.
+                let peer = protocol.unwrap_or(VersionRange::exactly(msg.version));
+                let response = negotiate(conn, request_id.clone(), peer, &features, &codecs);
+                if matches!(response.kind, MessageKind::Error { .. }) {
+                    return response;
+                }
+                self.handle_handshake(conn, name, source);
+                if protocol.is_some() {
+                    response
+                } else {
+                    Message::ack(request_id)
+                }
+            }
+            MessageKind::Heartbeat => self.handle_heartbeat(conn, request_id),
             MessageKind::Query { query } => self.handle_query(request_id, query),
             MessageKind::Status => self.handle_status(request_id),
             MessageKind::ListCollectors => self.handle_list_collectors(request_id),
@@ -74,20 +319,170 @@ impl Engine {
                 tracing::info!(paused, "tracking paused state changed");
                 Message::new(request_id, MessageKind::TrackingStatus { paused })
             }
+            MessageKind::StartCollector { name } => {
+                let result = self.supervisor.lock().unwrap().start(&name);
+                supervisor_response(request_id, result)
+            }
+            MessageKind::StopCollector { name } => {
+                let result = self.supervisor.lock().unwrap().stop(&name);
+                supervisor_response(request_id, result)
+            }
+            MessageKind::RestartCollector { name } => {
+                let result = self.supervisor.lock().unwrap().restart(&name);
+                supervisor_response(request_id, result)
+            }
+            MessageKind::Backup { path } => {
+                let path = Path::new(&path);
+                if !path.is_absolute() {
+                    return Message::error(
+                        request_id,
+                        ErrorCode::BadRequest,
+                        "backup path must be absolute",
+                    );
+                }
+                match self.backup(path) {
+                    Ok(()) => {
+                        tracing::info!(path = %path.display(), "backed up database");
+                        Message::ack(request_id)
+                    }
+                    Err(e @ BackupError::WouldReplace(_)) => Message::error(request_id, ErrorCode::BadRequest, e.to_string()),
+                    Err(e) => Message::error(request_id, ErrorCode::InternalError, e.to_string()),
+                }
+            }
+            MessageKind::RecategorizeSessions { from, to } => match self.recategorize_sessions(from, to) {
+                Ok(updated) => {
+                    tracing::info!(updated, "recategorized sessions");
+                    Message::new(request_id, MessageKind::Recategorized { updated })
+                }
+                Err(e) => Message::error(request_id, ErrorCode::InternalError, e.to_string()),
+            },
+            MessageKind::RebuildSessions { from, to } => match self.rebuild_sessions(from, to) {
+                Ok(Rebuilt { removed, created, .. }) => {
+                    tracing::info!(removed, created, "rebuilt sessions");
+                    Message::new(request_id, MessageKind::SessionsRebuilt { removed, created })
+                }
+                Err(e) => Message::error(request_id, ErrorCode::InternalError, e.to_string()),
+            },
+            MessageKind::AddTimeOverride { record } => {
+                if record.start >= record.end {
+                    return Message::error(request_id, ErrorCode::BadRequest, "override ends before it starts");
+                }
+                match self.repo.lock().unwrap().insert_time_override(&record) {
+                    Ok(()) => Message::ack(request_id),
+                    Err(e) => Message::error(request_id, ErrorCode::InternalError, e.to_string()),
+                }
+            }
+            MessageKind::RemoveTimeOverride { id } => match self.repo.lock().unwrap().delete_time_override(id) {
+                Ok(true) => Message::ack(request_id),
+                Ok(false) => Message::error(request_id, ErrorCode::NotFound, format!("no override {id}")),
+                Err(e) => Message::error(request_id, ErrorCode::InternalError, e.to_string()),
+            },
+            MessageKind::AddAnnotation { annotation } => self.handle_add_annotation(request_id, annotation),
+            MessageKind::DeleteAnnotation { id } => match self.repo.lock().unwrap().delete_annotation(id) {
+                Ok(true) => Message::ack(request_id),
+                Ok(false) => Message::error(request_id, ErrorCode::NotFound, format!("no annotation {id}")),
+                Err(e) => Message::error(request_id, ErrorCode::InternalError, e.to_string()),
+            },
+            MessageKind::AcceptAnnotation { id } => match self.repo.lock().unwrap().accept_annotation(id) {
+                Ok(true) => Message::ack(request_id),
+                Ok(false) => Message::error(request_id, ErrorCode::NotFound, format!("no annotation {id}")),
+                Err(e) => Message::error(request_id, ErrorCode::InternalError, e.to_string()),
+            },
             _ => Message::error(request_id, ErrorCode::BadRequest, "unexpected message type"),
         }
     }
 
-    fn handle_emit_event(&self, request_id: String, event: Event) -> Message {
+    /// Write a consistent copy of the database to `path`.
+    pub fn backup(&self, path: &Path) -> Result<(), BackupError> {
+        let repo = self.repo.lock().unwrap();
+        backup::write(&repo, path)
+    }
+
+    /// The rules new sessions are categorized with.
+    pub fn categorizer(&self) -> Arc<Categorizer> {
+        Arc::clone(&self.categorizer)
+    }
+
+    /// Re-apply the categorization rules to sessions starting in `[from, to)`;
+    /// returns how many changed category.
+    pub fn recategorize_sessions(&self, from: Timestamp, to: Timestamp) -> rusqlite::Result<u64> {
+        let repo = self.repo.lock().unwrap();
+        repo.transaction(|repo| {
+            let mut updated = 0;
+            for session in repo.sessions_for_day(from, to)? {
+                let category = self.categorizer.categorize(&session.app_name, &session.window_titles);
+                if category != session.category {
+                    repo.set_session_category(&session.id, category)?;
+                    updated += 1;
+                }
+            }
+            Ok(updated)
+        })
+    }
+
+    /// Store `annotation` with its tags normalized and its span resolved
+    /// from the target.
+    fn handle_add_annotation(&self, request_id: String, mut annotation: Annotation) -> Message {
+        annotation.text = annotation.text.trim().to_string();
+        if annotation.text.is_empty() {
+            return Message::error(request_id, ErrorCode::BadRequest, "annotation text is empty");
+        }
+        annotation.tags = normalize_tags(&annotation.tags);
+
+        let repo = self.repo.lock().unwrap();
+        let span = match &annotation.target {
+            AnnotationTarget::Range { start, end } if start >= end => {
+                return Message::error(request_id, ErrorCode::BadRequest, "annotation ends before it starts");
+            }
+            AnnotationTarget::Range { start, end } => Ok(Some((*start, *end))),
+            AnnotationTarget::Session { session_id } => match repo.session_span(session_id) {
+                Ok(None) => {
+                    return Message::error(request_id, ErrorCode::NotFound, format!("no session {session_id}"));
+                }
+                found => found,
+            },
+            AnnotationTarget::Entity { entity_id } => match repo.get_entity(*entity_id) {
+                Ok(None) => {
+                    return Message::error(request_id, ErrorCode::NotFound, format!("no entity {entity_id}"));
+                }
+                found => found.map(|_| None),
+            },
+        };
+        let span = match span {
+            Ok(span) => span,
+            Err(e) => return Message::error(request_id, ErrorCode::InternalError, e.to_string()),
+        };
+        annotation.start = span.map(|(start, _)| start);
+        annotation.end = span.map(|(_, end)| end);
+        match repo.insert_annotation(&annotation) {
+            Ok(()) => Message::ack(request_id),
+            Err(e) => Message::error(request_id, ErrorCode::InternalError, e.to_string()),
+        }
+    }
+
+    fn handle_emit_event(
+        &self,
+        conn: &ConnectionState,
+        request_id: String,
+        event: Event,
+    ) -> Message {
         if self.is_tracking_paused() {
             return Message::ack(request_id);
         }
+        let source = event.source.clone();
         let processed = {
             let mut ingest = self.ingest.lock().unwrap();
-            ingest.process(event)
+            ingest.check(event)
         };
-        let Some(event) = processed else {
-            return Message::ack(request_id);
+        let event = match processed {
+            Ok(event) => event,
+            Err(rejection) => {
+                self.update_collector(conn, &source, |info| match rejection {
+                    Rejection::Invalid => info.dropped += 1,
+                    Rejection::Duplicate => info.deduplicated += 1,
+                });
+                return Message::ack(request_id);
+            }
         };
         let result = {
             let repo = self.repo.lock().unwrap();
@@ -96,117 +491,266 @@ impl Engine {
         };
         match result {
             Ok(()) => {
-                let source_str = format!("{:?}", event.source);
-                if let Some(info) = self
-                    .collectors
-                    .lock()
-                    .unwrap()
-                    .values_mut()
-                    .find(|c| format!("{:?}", c.source) == source_str)
-                {
-                    info.events_sent += 1;
-                }
+                self.update_collector(conn, &source, |info| info.events_sent += 1);
                 Message::ack(request_id)
             }
-            Err(e) => Message::error(request_id, ErrorCode::InternalError, e.to_string()),
+            Err(e) => {
+                self.update_collector(conn, &source, |info| info.errors += 1);
+                Message::error(request_id, ErrorCode::InternalError, e.to_string())
+            }
         }
     }
 
-    fn handle_handshake(
+    /// Capabilities granted by `token`, or `None` if it is not configured.
+    fn token_capabilities(&self, token: &str) -> Option<&HashSet<Capability>> {
+        self.tokens
+            .iter()
+            .find(|(expected, _)| constant_time_eq(expected.as_bytes(), token.as_bytes()))
+            .map(|(_, capabilities)| capabilities)
+    }
+
+    /// Apply `f` to the collector that sent a message: the one bound to the
+    /// connection if any, otherwise the first collector reporting `source`.
+    fn update_collector(
         &self,
-        request_id: String,
-        name: String,
-        _version: String,
-        source: CollectorSource,
-    ) -> Message {
+        conn: &ConnectionState,
+        source: &CollectorSource,
+        f: impl FnOnce(&mut CollectorInfo),
+    ) {
+        let mut collectors = self.collectors.lock().unwrap();
+        let info = match &conn.collector {
+            Some(name) => collectors.get_mut(name),
+            None => collectors.values_mut().find(|c| &c.source == source),
+        };
+        if let Some(info) = info {
+            info.last_heartbeat = Some(cronos_common::now_ms());
+            f(info);
+        }
+    }
+
+    fn handle_handshake(&self, conn: &mut ConnectionState, name: String, source: CollectorSource) {
         let now = cronos_common::now_ms();
-        self.collectors.lock().unwrap().insert(
-            name.clone(),
-            CollectorInfo {
-                name,
-                source,
-                connected: true,
-                last_heartbeat: Some(now),
-                events_sent: 0,
-            },
-        );
+        // Counters are per connection, so a reconnect starts from a fresh entry.
+        let mut info = CollectorInfo::new(name.clone(), source);
+        info.connected = true;
+        info.connected_at = Some(now);
+        info.last_heartbeat = Some(now);
+        let mut collectors = self.collectors.lock().unwrap();
+        collectors.insert(name.clone(), info);
+        self.collector_connections.lock().unwrap().insert(name.clone(), conn.id);
+        conn.collector = Some(name);
+    }
+
+    fn handle_heartbeat(&self, conn: &ConnectionState, request_id: String) -> Message {
+        if let Some(name) = &conn.collector {
+            if let Some(info) = self.collectors.lock().unwrap().get_mut(name) {
+                info.last_heartbeat = Some(cronos_common::now_ms());
+                info.connected = true;
+                info.stale = false;
+            }
+        }
         Message::ack(request_id)
     }
 
     fn handle_query(&self, request_id: String, query: QueryRequest) -> Message {
+        let after = match query.cursor.as_deref().map(Cursor::decode) {
+            None => None,
+            Some(Some(cursor)) => Some(cursor),
+            Some(None) => {
+                return Message::error(request_id, ErrorCode::BadRequest, "invalid cursor");
+            }
+        };
+        let after = after.as_ref();
+        // An empty page could never carry a cursor to the next one
+        if let QueryKind::Search { limit: 0, .. }
+        | QueryKind::Timeline { limit: 0, .. }
+        | QueryKind::Related { limit: 0, .. }
+        | QueryKind::Recent { limit: 0 }
+        | QueryKind::Sessions { limit: 0, .. } = query.kind
+        {
+            return Message::error(request_id, ErrorCode::BadRequest, "limit must be at least 1");
+        }
+        let filter = Filter {
+            sources: query.filter.sources,
+            event_kinds: query.filter.event_kinds,
+            entity_kinds: query.filter.entity_kinds,
+            project: query.filter.project,
+        };
+        // Each page is fetched with one extra row to learn whether another follows.
         let result = match query.kind {
             QueryKind::Search { text, limit } => {
                 let repo = self.repo.lock().unwrap();
-                repo.search_entities(&text, limit).map(|entities| {
-                    QueryResponse {
-                        entities,
-                        edges: vec![],
-                        events: vec![],
-                        sessions: vec![],
-                    }
-                })
+                repo.search_entities_page(&text, &filter, after, limit.saturating_add(1))
+                    .and_then(|entities| {
+                        let (entities, next_cursor) = paginate(entities, limit, |e| Cursor {
+                            key: e.last_seen,
+                            id: e.id.to_string(),
+                        });
+                        // Annotations are few, so they all come with the first page.
+                        let annotations = match after {
+                            None => repo.search_annotations(&text, limit)?,
+                            Some(_) => Vec::new(),
+                        };
+                        Ok(QueryResponse {
+                            entities,
+                            next_cursor,
+                            annotations,
+                            ..QueryResponse::default()
+                        })
+                    })
             }
             QueryKind::Recent { limit } => {
                 let repo = self.repo.lock().unwrap();
-                repo.recent_events(limit).map(|stored| {
-                    let events = stored
-                        .into_iter()
-                        .filter_map(|se| stored_event_to_event(&se, &repo))
-                        .collect();
-                    QueryResponse {
-                        entities: vec![],
-                        edges: vec![],
-                        events,
-                        sessions: vec![],
+                repo.events_page(None, None, &filter, after, Order::Desc, limit.saturating_add(1))
+                    .map(|stored| events_response(stored, limit, &repo))
+            }
+            QueryKind::Timeline { from, to, limit } => {
+                let repo = self.repo.lock().unwrap();
+                repo.events_page(
+                    Some(from),
+                    Some(to),
+                    &filter,
+                    after,
+                    Order::Asc,
+                    limit.saturating_add(1),
+                )
+                .map(|stored| events_response(stored, limit, &repo))
+            }
+            QueryKind::Related {
+                entity_id,
+                depth,
+                limit,
+                relations,
+            } => {
+                let ranked = self
+                    .graph
+                    .lock()
+                    .unwrap()
+                    .ranked_related(&entity_id, depth, &relations);
+                let repo = self.repo.lock().unwrap();
+                related_response(ranked, &filter, after, limit, &repo)
+            }
+            QueryKind::Connection {
+                from,
+                to,
+                max_depth,
+            } => {
+                let found = self.graph.lock().unwrap().connection(&from, &to, max_depth);
+                let repo = self.repo.lock().unwrap();
+                connection_response(from, found, &repo)
+            }
+            QueryKind::Sessions { from, to, limit } => {
+                let repo = self.repo.lock().unwrap();
+                repo.sessions_page(from, to, &filter, after, limit.saturating_add(1))
+                    .map(|sessions| {
+                        let (sessions, next_cursor) = paginate(sessions, limit, |s| Cursor {
+                            key: s.start_time,
+                            id: s.id.clone(),
+                        });
+                        QueryResponse {
+                            sessions: sessions.into_iter().map(session_to_info).collect(),
+                            next_cursor,
+                            ..QueryResponse::default()
+                        }
+                    })
+            }
+            QueryKind::DaySummary { date, timezone } => {
+                let (date, tz) = match self.local_date(&date, timezone.as_deref()) {
+                    Ok(resolved) => resolved,
+                    Err(message) => {
+                        return Message::error(request_id, ErrorCode::BadRequest, message);
+                    }
+                };
+                self.handle_day_summary(cronos_common::tz::day_bounds(tz, date))
+            }
+            QueryKind::Summary { period, anchor, timezone } => {
+                let (date, tz) = match self.local_date(&anchor, timezone.as_deref()) {
+                    Ok(resolved) => resolved,
+                    Err(message) => {
+                        return Message::error(request_id, ErrorCode::BadRequest, message);
                     }
+                };
+                let repo = self.repo.lock().unwrap();
+                crate::summary::summarize(&repo, period, date, tz, &self.focus).map(|summary| QueryResponse {
+                    summary: Some(summary),
+                    ..QueryResponse::default()
                 })
             }
-            QueryKind::Timeline { from, to } => {
+            QueryKind::FocusReport { from, to } => {
                 let repo = self.repo.lock().unwrap();
-                repo.events_in_range(from, to).map(|stored| {
-                    let events = stored
-                        .into_iter()
-                        .filter_map(|se| stored_event_to_event(&se, &repo))
-                        .collect();
-                    QueryResponse {
-                        entities: vec![],
-                        edges: vec![],
-                        events,
-                        sessions: vec![],
-                    }
+                self.focus.report(&repo, from, to).map(|focus| QueryResponse {
+                    focus: Some(focus),
+                    ..QueryResponse::default()
                 })
             }
-            QueryKind::Related { entity_id, depth } => {
-                let graph = self.graph.lock().unwrap();
-                let related_ids = graph.related(&entity_id, depth);
+            QueryKind::Timesheet { from, to, by, round_secs } => {
                 let repo = self.repo.lock().unwrap();
-                let mut entities = Vec::new();
-                for id in related_ids {
-                    if let Ok(Some(e)) = repo.get_entity(id) {
-                        entities.push(e);
-                    }
-                }
-                Ok(QueryResponse {
-                    entities,
-                    edges: vec![],
-                    events: vec![],
-                    sessions: vec![],
+                crate::timesheet::build(&repo, from, to, by, round_secs).map(|timesheet| QueryResponse {
+                    timesheet: Some(timesheet),
+                    ..QueryResponse::default()
                 })
             }
-            QueryKind::Sessions { from, to, limit } => {
+            QueryKind::TimeOverrides { from, to } => {
                 let repo = self.repo.lock().unwrap();
-                repo.sessions_in_range(from, to, limit).map(|sessions| {
-                    let infos = sessions.into_iter().map(session_to_info).collect();
-                    QueryResponse {
-                        entities: vec![],
-                        edges: vec![],
-                        events: vec![],
-                        sessions: infos,
-                    }
+                repo.time_overrides_overlapping(from, to).map(|overrides| QueryResponse {
+                    overrides,
+                    ..QueryResponse::default()
+                })
+            }
+            QueryKind::Annotations {
+                from,
+                to,
+                tag,
+                session_id,
+                entity_id,
+                proposed,
+            } => {
+                let filter = AnnotationFilter {
+                    from,
+                    to,
+                    tag: tag.map(|t| normalize_tags(&[t]).pop().unwrap_or_default()),
+                    session_id,
+                    entity_id,
+                    proposed_only: proposed,
+                };
+                let repo = self.repo.lock().unwrap();
+                repo.annotations(&filter).map(|annotations| QueryResponse {
+                    annotations,
+                    ..QueryResponse::default()
                 })
             }
-            QueryKind::DaySummary { date } => {
-                self.handle_day_summary(&date)
+            QueryKind::Expr { query } => {
+                let compiled = match crate::query::compile(&query, cronos_common::now_ms(), self.timezone) {
+                    Ok(compiled) => compiled,
+                    Err(e) => {
+                        return Message::error(request_id, ErrorCode::BadRequest, e.to_string());
+                    }
+                };
+                let repo = self.repo.lock().unwrap();
+                repo.run_query(&compiled).map(|rows| expr_response(rows, &repo))
+            }
+            QueryKind::Sql { statement } => {
+                let Some(sql) = &self.sql else {
+                    return Message::error(
+                        request_id,
+                        ErrorCode::BadRequest,
+                        "SQL queries are disabled (daemon.sql.enabled)",
+                    );
+                };
+                // Statement errors are the caller's, so all are BadRequest.
+                match sql.lock().unwrap().query(&statement) {
+                    Ok(rows) => Ok(QueryResponse {
+                        table: Some(Table {
+                            columns: rows.columns,
+                            rows: rows.rows,
+                            truncated: rows.truncated,
+                        }),
+                        ..QueryResponse::default()
+                    }),
+                    Err(e) => {
+                        return Message::error(request_id, ErrorCode::BadRequest, e.to_string());
+                    }
+                }
             }
         };
         match result {
@@ -222,73 +766,340 @@ impl Engine {
             entity_count: repo.entity_count().unwrap_or(0) as u64,
             edge_count: repo.edge_count().unwrap_or(0) as u64,
             event_count: repo.event_count().unwrap_or(0) as u64,
-            connected_collectors: self.collectors.lock().unwrap().len() as u32,
+            connected_collectors: self
+                .collectors
+                .lock()
+                .unwrap()
+                .values()
+                .filter(|c| c.connected)
+                .count() as u32,
         };
         Message::new(request_id, MessageKind::StatusResult { info })
     }
 
     /// Run the session aggregator against the repository.
-    pub fn run_aggregator(
-        &self,
-        aggregator: &crate::aggregator::SessionAggregator,
-    ) -> rusqlite::Result<usize> {
+    pub fn run_aggregator(&self) -> rusqlite::Result<usize> {
+        let repo = self.repo.lock().unwrap();
+        self.aggregator.aggregate(&repo)
+    }
+
+    /// Recompute the sessions in `[from, to)` from raw events.
+    pub fn rebuild_sessions(&self, from: Timestamp, to: Timestamp) -> rusqlite::Result<Rebuilt> {
         let repo = self.repo.lock().unwrap();
-        aggregator.aggregate(&repo)
+        self.aggregator.rebuild(&repo, from, to)
     }
 
     fn handle_list_collectors(&self, request_id: String) -> Message {
-        let collectors: Vec<CollectorInfo> =
-            self.collectors.lock().unwrap().values().cloned().collect();
+        let mut collectors = self.collectors.lock().unwrap().clone();
+        let supervisor = self.supervisor.lock().unwrap();
+        for name in supervisor.names() {
+            let Some(spec) = supervisor.spec(&name) else { continue };
+            let info = collectors
+                .entry(name.clone())
+                .or_insert_with(|| CollectorInfo::new(name.clone(), spec.source.clone()));
+            info.pid = supervisor.pid(&name);
+            info.restarts = supervisor.restarts(&name);
+        }
+
+        let now = cronos_common::now_ms();
+        let mut collectors: Vec<CollectorInfo> = collectors.into_values().collect();
+        for info in &mut collectors {
+            if let (true, Some(since)) = (info.connected, info.connected_at) {
+                let uptime_ms = (now - since).max(0);
+                info.uptime_secs = (uptime_ms / 1000) as u64;
+                if uptime_ms > 0 {
+                    info.events_per_min = info.events_sent as f64 * 60_000.0 / uptime_ms as f64;
+                }
+            }
+        }
+        collectors.sort_by(|a, b| a.name.cmp(&b.name));
         Message::new(request_id, MessageKind::CollectorList { collectors })
     }
 
-    fn handle_day_summary(&self, date: &str) -> rusqlite::Result<QueryResponse> {
-        // Parse date string "YYYY-MM-DD" into day start/end timestamps
-        let naive = chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
-            .unwrap_or_else(|_| chrono::Utc::now().date_naive());
-        let day_start = naive
-            .and_hms_opt(0, 0, 0)
-            .unwrap()
-            .and_utc()
-            .timestamp_millis();
-        let day_end = naive
-            .succ_opt()
-            .unwrap_or(naive)
-            .and_hms_opt(0, 0, 0)
-            .unwrap()
-            .and_utc()
-            .timestamp_millis();
+    /// `date` (`YYYY-MM-DD`) and the zone it is read in: `timezone`, or the
+    /// configured zone when `None`.
+    fn local_date(&self, date: &str, timezone: Option<&str>) -> Result<(chrono::NaiveDate, chrono_tz::Tz), String> {
+        let date = chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map_err(|_| format!("invalid date '{date}', expected YYYY-MM-DD"))?;
+        let tz = match timezone {
+            Some(name) => cronos_common::tz::resolve(name).map_err(|e| e.to_string())?,
+            None => self.timezone,
+        };
+        Ok((date, tz))
+    }
 
+    fn handle_day_summary(&self, (day_start, day_end): (Timestamp, Timestamp)) -> rusqlite::Result<QueryResponse> {
         let repo = self.repo.lock().unwrap();
         let sessions = repo.sessions_for_day(day_start, day_end)?;
         let infos = sessions.into_iter().map(session_to_info).collect();
+        let annotations = repo.annotations(&AnnotationFilter {
+            from: Some(day_start),
+            to: Some(day_end),
+            ..AnnotationFilter::default()
+        })?;
         Ok(QueryResponse {
-            entities: vec![],
-            edges: vec![],
-            events: vec![],
             sessions: infos,
+            annotations,
+            ..QueryResponse::default()
         })
     }
 }
 
+/// Tags trimmed, lowercased and without a leading `#`, sorted and without
+/// duplicates or empty ones.
+fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut tags: Vec<String> = tags
+        .iter()
+        .map(|t| t.trim().trim_start_matches('#').to_lowercase())
+        .filter(|t| !t.is_empty())
+        .collect();
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Agree on a protocol version and codec with a peer speaking `peer`,
+/// recording the result on the connection. Replies `Welcome` or
+/// `IncompatibleVersion`.
+fn negotiate(
+    conn: &mut ConnectionState,
+    request_id: String,
+    peer: VersionRange,
+    features: &[String],
+    codecs: &[Codec],
+) -> Message {
+    match VersionRange::SUPPORTED.negotiate(&peer) {
+        Some(version) => {
+            conn.protocol_version = Some(version);
+            conn.features = common_features(features);
+            conn.codec = Codec::choose(codecs);
+            Message::new(
+                request_id,
+                MessageKind::Welcome {
+                    version,
+                    features: conn.features.clone(),
+                    codec: conn.codec,
+                },
+            )
+        }
+        None => incompatible(request_id, peer),
+    }
+}
+
+fn incompatible(request_id: String, peer: VersionRange) -> Message {
+    tracing::warn!(min = peer.min, max = peer.max, "rejecting incompatible protocol version");
+    let supported = VersionRange::SUPPORTED;
+    Message::error(
+        request_id,
+        ErrorCode::IncompatibleVersion,
+        format!(
+            "daemon speaks protocol {}..={}, peer speaks {}..={}",
+            supported.min, supported.max, peer.min, peer.max
+        ),
+    )
+}
+
+fn parse_capabilities(names: &[String]) -> anyhow::Result<HashSet<Capability>> {
+    names
+        .iter()
+        .map(|name| name.parse().map_err(|e: String| anyhow::anyhow!("daemon.auth: {e}")))
+        .collect()
+}
+
+fn supervisor_response(request_id: String, result: Result<(), SupervisorError>) -> Message {
+    match result {
+        Ok(()) => Message::ack(request_id),
+        Err(e @ SupervisorError::UnknownCollector(_)) => {
+            Message::error(request_id, ErrorCode::NotFound, e.to_string())
+        }
+        Err(e) => Message::error(request_id, ErrorCode::InternalError, e.to_string()),
+    }
+}
+
 fn session_to_info(s: crate::storage::repo::Session) -> SessionInfo {
     SessionInfo {
         id: s.id,
         app_name: s.app_name,
         window_titles: s.window_titles,
         project: s.project,
+        project_id: s.project_id,
+        project_confidence: s.project_confidence,
         category: s.category,
         start_time: s.start_time,
         end_time: s.end_time,
         duration_secs: s.duration_secs,
         event_count: s.event_count,
+        open: s.open,
+    }
+}
+
+/// Trim a page fetched with one extra item to `limit`, returning the cursor
+/// of the last kept item when more items follow.
+fn paginate<T>(mut items: Vec<T>, limit: u32, key: impl Fn(&T) -> Cursor) -> (Vec<T>, Option<String>) {
+    if items.len() <= limit as usize {
+        return (items, None);
+    }
+    items.truncate(limit as usize);
+    let next_cursor = items.last().map(|last| key(last).encode());
+    (items, next_cursor)
+}
+
+fn events_response(stored: Vec<StoredEvent>, limit: u32, repo: &Repository) -> QueryResponse {
+    let (stored, next_cursor) = paginate(stored, limit, |e| Cursor {
+        key: e.timestamp,
+        id: e.id.to_string(),
+    });
+    QueryResponse {
+        events: stored
+            .iter()
+            .filter_map(|se| stored_event_to_event(se, repo))
+            .collect(),
+        next_cursor,
+        ..QueryResponse::default()
+    }
+}
+
+/// One page of ranked entities that pass `filter`. Pages resume after the
+/// cursor's entity, or after its rank if that entity is no longer related.
+fn related_response(
+    ranked: Vec<RankedEntity>,
+    filter: &Filter,
+    after: Option<&Cursor>,
+    limit: u32,
+    repo: &Repository,
+) -> rusqlite::Result<QueryResponse> {
+    let ids: Vec<EntityId> = ranked.iter().map(|r| r.entity_id).collect();
+    let mut entities: HashMap<EntityId, Entity> = repo
+        .entities_page(&ids, filter, None, u32::try_from(ids.len()).unwrap_or(u32::MAX))?
+        .into_iter()
+        .map(|e| (e.id, e))
+        .collect();
+    let ranked: Vec<RankedEntity> = ranked
+        .into_iter()
+        .filter(|r| entities.contains_key(&r.entity_id))
+        .collect();
+    let start = match after {
+        None => 0,
+        Some(cursor) => ranked
+            .iter()
+            .position(|r| r.entity_id.to_string() == cursor.id)
+            .unwrap_or(cursor.key.max(0) as usize)
+            + 1,
+    };
+    let page: Vec<(usize, RankedEntity)> = ranked
+        .into_iter()
+        .enumerate()
+        .skip(start)
+        .take(limit as usize + 1)
+        .collect();
+    let (page, next_cursor) = paginate(page, limit, |(rank, r)| Cursor {
+        key: *rank as i64,
+        id: r.entity_id.to_string(),
+    });
+
+    let mut seen = HashSet::new();
+    let edge_ids: Vec<EdgeId> = page
+        .iter()
+        .flat_map(|(_, r)| r.path.iter().copied())
+        .filter(|id| seen.insert(*id))
+        .collect();
+    Ok(QueryResponse {
+        entities: page
+            .iter()
+            .filter_map(|(_, r)| entities.remove(&r.entity_id))
+            .collect(),
+        edges: repo.edges_by_ids(&edge_ids)?,
+        related: page
+            .into_iter()
+            .map(|(_, r)| RelatedEntity {
+                entity_id: r.entity_id,
+                score: r.score,
+                path: r.path,
+            })
+            .collect(),
+        next_cursor,
+        ..QueryResponse::default()
+    })
+}
+
+/// The entities along `found`'s path from `from`, its edges in order, and
+/// its score.
+fn connection_response(
+    from: EntityId,
+    found: Option<RankedEntity>,
+    repo: &Repository,
+) -> rusqlite::Result<QueryResponse> {
+    let Some(found) = found else {
+        return Ok(QueryResponse::default());
+    };
+    let mut edges: HashMap<EdgeId, Edge> = repo
+        .edges_by_ids(&found.path)?
+        .into_iter()
+        .map(|e| (e.id, e))
+        .collect();
+    let edges: Vec<Edge> = found.path.iter().filter_map(|id| edges.remove(id)).collect();
+
+    // Walk the path: each edge may point either way
+    let mut ids = vec![from];
+    for edge in &edges {
+        let here = *ids.last().unwrap();
+        ids.push(if edge.from == here { edge.to } else { edge.from });
+    }
+    let mut entities = Vec::with_capacity(ids.len());
+    for id in &ids {
+        if let Some(entity) = repo.get_entity(*id)? {
+            entities.push(entity);
+        }
+    }
+
+    Ok(QueryResponse {
+        entities,
+        edges,
+        related: vec![RelatedEntity {
+            entity_id: found.entity_id,
+            score: found.score,
+            path: found.path,
+        }],
+        ..QueryResponse::default()
+    })
+}
+
+fn expr_response(rows: QueryRows, repo: &Repository) -> QueryResponse {
+    match rows {
+        QueryRows::Events(stored) => QueryResponse {
+            events: stored
+                .iter()
+                .filter_map(|se| stored_event_to_event(se, repo))
+                .collect(),
+            ..QueryResponse::default()
+        },
+        QueryRows::Entities(entities) => QueryResponse {
+            entities,
+            ..QueryResponse::default()
+        },
+        QueryRows::Sessions(sessions) => QueryResponse {
+            sessions: sessions.into_iter().map(session_to_info).collect(),
+            ..QueryResponse::default()
+        },
+        QueryRows::Table { columns, rows } => QueryResponse {
+            table: Some(Table {
+                columns,
+                rows,
+                truncated: false,
+            }),
+            ..QueryResponse::default()
+        },
     }
 }
 
 /// Convert a `StoredEvent` back into an `Event` by looking up the subject entity
 /// from the repository to reconstruct the `EntityRef`.
 fn stored_event_to_event(
-    stored: &crate::storage::repo::StoredEvent,
+    stored: &StoredEvent,
     repo: &Repository,
 ) -> Option<Event> {
     let entity = repo.get_entity(stored.subject_id).ok()??;
@@ -312,6 +1123,9 @@ mod tests {
     use super::*;
     use std::collections::HashMap;
     use tempfile::TempDir;
+    use cronos_common::config::{CategoryRule, TokenGrant};
+    use crate::storage::repo::Session;
+    use crate::test_fixtures as fixtures;
 
     fn make_engine(dir: &TempDir) -> Engine {
         let db_path = dir.path().join("test.db");
@@ -379,6 +1193,10 @@ mod tests {
                 name: "fs-collector".to_string(),
                 collector_version: "0.1.0".to_string(),
                 source: CollectorSource::Filesystem,
+                token: None,
+                protocol: None,
+                features: vec![],
+                codecs: vec![],
             },
         );
         let resp = engine.handle_message(msg);
@@ -437,12 +1255,10 @@ mod tests {
         let query_msg = Message::new(
             "q1",
             MessageKind::Query {
-                query: QueryRequest {
-                    kind: QueryKind::Search {
-                        text: "main".to_string(),
-                        limit: 10,
-                    },
-                },
+                query: QueryRequest::new(QueryKind::Search {
+                    text: "main".to_string(),
+                    limit: 10,
+                }),
             },
         );
         let resp = engine.handle_message(query_msg);
@@ -454,6 +1270,420 @@ mod tests {
         }
     }
 
+    #[test]
+    fn engine_backs_up_the_database_on_request() {
+        let dir = TempDir::new().unwrap();
+        let engine = make_engine(&dir);
+        engine.handle_message(make_emit_event("/src/a.rs"));
+
+        let relative = Message::new("b1", MessageKind::Backup { path: "backup.db".to_string() });
+        assert!(matches!(
+            engine.handle_message(relative).kind,
+            MessageKind::Error {
+                code: ErrorCode::BadRequest,
+                ..
+            }
+        ));
+
+        let path = dir.path().join("backup.db");
+        let msg = Message::new(
+            "b2",
+            MessageKind::Backup {
+                path: path.display().to_string(),
+            },
+        );
+        assert!(matches!(engine.handle_message(msg).kind, MessageKind::Ack { .. }));
+
+        // Files that are not backups are never overwritten
+        let config = dir.path().join("config.toml");
+        std::fs::write(&config, "[daemon]\n").unwrap();
+        let msg = Message::new("b3", MessageKind::Backup { path: config.display().to_string() });
+        assert!(matches!(
+            engine.handle_message(msg).kind,
+            MessageKind::Error {
+                code: ErrorCode::BadRequest,
+                ..
+            }
+        ));
+        assert_eq!(std::fs::read_to_string(&config).unwrap(), "[daemon]\n");
+
+        let copy = Repository::open(&path).unwrap();
+        assert_eq!(copy.event_count().unwrap(), 1);
+        assert_eq!(copy.entity_count().unwrap(), 2);
+    }
+
+    #[test]
+    fn engine_recategorizes_stored_sessions_with_current_rules() {
+        let dir = TempDir::new().unwrap();
+        let mut config = DaemonConfig::default();
+        config.categories.custom = vec!["writing".to_string()];
+        config.categories.rules = vec![CategoryRule {
+            category: "writing".to_string(),
+            match_kind: "glob".to_string(),
+            app: None,
+            title: Some("*.md*".to_string()),
+            priority: 0,
+        }];
+        let engine = Engine::open(&dir.path().join("test.db"), &config).unwrap();
+
+        let session = |id: &str, app: &str, title: &str, start: Timestamp| Session {
+            id: id.to_string(),
+            window_titles: vec![title.to_string()],
+            // As categorized by the old substring matching
+            ..fixtures::session(app, "coding", start, start + 1000)
+        };
+        {
+            let repo = engine.repo.lock().unwrap();
+            repo.insert_session(&session("s1", "Code", "README.md", 1000)).unwrap();
+            repo.insert_session(&session("s2", "Barcode Scanner", "scan", 2000)).unwrap();
+            repo.insert_session(&session("s3", "Code", "main.rs", 3000)).unwrap();
+            repo.insert_session(&session("s4", "Barcode Scanner", "scan", 9000)).unwrap();
+        }
+
+        let msg = Message::new("r1", MessageKind::RecategorizeSessions { from: 0, to: 5000 });
+        assert!(matches!(
+            engine.handle_message(msg).kind,
+            MessageKind::Recategorized { updated: 2 }
+        ));
+        let categories: Vec<_> = engine
+            .repo
+            .lock()
+            .unwrap()
+            .sessions_in_range(0, 10_000, 10)
+            .unwrap()
+            .into_iter()
+            .map(|s| s.category)
+            .collect();
+        // s4 starts outside the range and keeps its old category
+        assert_eq!(categories, ["writing", "other", "coding", "coding"]);
+    }
+
+    #[test]
+    fn engine_day_summary_counts_days_in_the_configured_zone() {
+        let dir = TempDir::new().unwrap();
+        let config = DaemonConfig {
+            timezone: "America/Los_Angeles".to_string(),
+            ..DaemonConfig::default()
+        };
+        let engine = Engine::open(&dir.path().join("test.db"), &config).unwrap();
+        // 2024-03-05 23:30 in Los Angeles, already 2024-03-06 in UTC
+        let start = 1_709_710_200_000;
+        let session = Session {
+            id: "late".to_string(),
+            event_count: 2,
+            ..fixtures::session("Code", "coding", start, start + 600_000)
+        };
+        engine.repo.lock().unwrap().insert_session(&session).unwrap();
+
+        let summary = |date: &str, timezone: Option<&str>| {
+            let kind = QueryKind::DaySummary {
+                date: date.to_string(),
+                timezone: timezone.map(String::from),
+            };
+            engine
+                .handle_message(Message::new("q", MessageKind::Query { query: QueryRequest::new(kind) }))
+                .kind
+        };
+        let sessions = |kind: MessageKind| match kind {
+            MessageKind::QueryResult { response } => response.sessions.len(),
+            other => panic!("unexpected {other:?}"),
+        };
+        assert_eq!(sessions(summary("2024-03-05", None)), 1);
+        assert_eq!(sessions(summary("2024-03-06", None)), 0);
+        assert_eq!(sessions(summary("2024-03-06", Some("UTC"))), 1);
+
+        for (date, timezone) in [("yesterday", None), ("2024-03-05", Some("Moon/Base"))] {
+            assert!(matches!(
+                summary(date, timezone),
+                MessageKind::Error {
+                    code: ErrorCode::BadRequest,
+                    ..
+                }
+            ));
+        }
+    }
+
+    #[test]
+    fn engine_rebuilds_sessions_from_raw_events() {
+        let dir = TempDir::new().unwrap();
+        let engine = make_engine(&dir);
+        // No focus events back this session, so a rebuild drops it
+        let stale = Session {
+            id: "stale".to_string(),
+            ..fixtures::session("Code", "coding", 1000, 2000)
+        };
+        engine.repo.lock().unwrap().insert_session(&stale).unwrap();
+
+        let msg = Message::new("r1", MessageKind::RebuildSessions { from: 0, to: 5000 });
+        assert!(matches!(
+            engine.handle_message(msg).kind,
+            MessageKind::SessionsRebuilt {
+                removed: 1,
+                created: 0
+            }
+        ));
+        assert_eq!(engine.repo.lock().unwrap().session_count().unwrap(), 0);
+    }
+
+    #[test]
+    fn engine_stores_time_overrides_and_bills_with_them() {
+        let dir = TempDir::new().unwrap();
+        let engine = make_engine(&dir);
+        let session = Session {
+            id: "s1".to_string(),
+            project: Some("acme".to_string()),
+            ..fixtures::session("Code", "coding", 0, 3_600_000)
+        };
+        engine.repo.lock().unwrap().insert_session(&session).unwrap();
+        let record = TimeOverride {
+            id: OverrideId::new(),
+            start: 1_800_000,
+            end: 3_600_000,
+            action: OverrideAction::Exclude,
+            note: "lunch".to_string(),
+            created_at: 1,
+        };
+        let add = Message::new("r1", MessageKind::AddTimeOverride { record: record.clone() });
+        assert!(matches!(engine.handle_message(add).kind, MessageKind::Ack { .. }));
+
+        let query = |kind| {
+            let msg = Message::new("q", MessageKind::Query { query: QueryRequest::new(kind) });
+            match engine.handle_message(msg).kind {
+                MessageKind::QueryResult { response } => response,
+                other => panic!("unexpected {other:?}"),
+            }
+        };
+        let listed = query(QueryKind::TimeOverrides { from: 0, to: 3_600_000 });
+        assert_eq!(listed.overrides, std::slice::from_ref(&record));
+        let sheet = query(QueryKind::Timesheet {
+            from: 0,
+            to: 3_600_000,
+            by: TimesheetGroup::Project,
+            round_secs: 0,
+        })
+        .timesheet
+        .unwrap();
+        assert_eq!(sheet.blocks.len(), 1);
+        assert_eq!(sheet.blocks[0].billed_secs, 1800);
+
+        let remove = || Message::new("r2", MessageKind::RemoveTimeOverride { id: record.id });
+        assert!(matches!(engine.handle_message(remove()).kind, MessageKind::Ack { .. }));
+        assert!(matches!(
+            engine.handle_message(remove()).kind,
+            MessageKind::Error {
+                code: ErrorCode::NotFound,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn engine_annotates_sessions_and_finds_annotations() {
+        let dir = TempDir::new().unwrap();
+        let engine = make_engine(&dir);
+        let session = Session {
+            id: "s1".to_string(),
+            ..fixtures::session("Code", "coding", 600_000, 1_200_000)
+        };
+        engine.repo.lock().unwrap().insert_session(&session).unwrap();
+        let annotate = |target, proposed| {
+            let annotation = Annotation {
+                id: AnnotationId::new(),
+                target,
+                text: " Incident review with the on-call team ".to_string(),
+                tags: vec!["#Incident".to_string(), "incident".to_string(), " ".to_string()],
+                start: None,
+                end: None,
+                proposed,
+                created_at: 1,
+            };
+            let reply = engine.handle_message(Message::new(
+                "a",
+                MessageKind::AddAnnotation {
+                    annotation: annotation.clone(),
+                },
+            ));
+            (annotation.id, reply.kind)
+        };
+        let (id, reply) = annotate(
+            AnnotationTarget::Session {
+                session_id: "s1".to_string(),
+            },
+            true,
+        );
+        assert!(matches!(reply, MessageKind::Ack { .. }));
+        let (_, reply) = annotate(
+            AnnotationTarget::Session {
+                session_id: "missing".to_string(),
+            },
+            false,
+        );
+        assert!(matches!(
+            reply,
+            MessageKind::Error {
+                code: ErrorCode::NotFound,
+                ..
+            }
+        ));
+
+        let query = |kind| {
+            let msg = Message::new("q", MessageKind::Query { query: QueryRequest::new(kind) });
+            match engine.handle_message(msg).kind {
+                MessageKind::QueryResult { response } => response,
+                other => panic!("unexpected {other:?}"),
+            }
+        };
+        let listed = query(QueryKind::Annotations {
+            from: Some(0),
+            to: Some(3_600_000),
+            tag: Some("incident".to_string()),
+            session_id: None,
+            entity_id: None,
+            proposed: true,
+        })
+        .annotations;
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].text, "Incident review with the on-call team");
+        assert_eq!(listed[0].tags, ["incident"]);
+        assert_eq!((listed[0].start, listed[0].end), (Some(600_000), Some(1_200_000)));
+
+        let found = query(QueryKind::Search {
+            text: "incident".to_string(),
+            limit: 10,
+        });
+        assert_eq!(found.annotations.len(), 1);
+
+        let accept = Message::new("r", MessageKind::AcceptAnnotation { id });
+        assert!(matches!(engine.handle_message(accept).kind, MessageKind::Ack { .. }));
+        let proposals = query(QueryKind::Annotations {
+            from: None,
+            to: None,
+            tag: None,
+            session_id: None,
+            entity_id: None,
+            proposed: true,
+        });
+        assert!(proposals.annotations.is_empty());
+
+        let delete = || Message::new("r", MessageKind::DeleteAnnotation { id });
+        assert!(matches!(engine.handle_message(delete()).kind, MessageKind::Ack { .. }));
+        assert!(matches!(
+            engine.handle_message(delete()).kind,
+            MessageKind::Error {
+                code: ErrorCode::NotFound,
+                ..
+            }
+        ));
+        let found = query(QueryKind::Search {
+            text: "incident".to_string(),
+            limit: 10,
+        });
+        assert!(found.annotations.is_empty());
+    }
+
+    #[test]
+    fn engine_ranks_related_entities_with_paths() {
+        let dir = TempDir::new().unwrap();
+        let engine = make_engine(&dir);
+        for path in ["/src/a.rs", "/src/b.rs", "/src/c.rs"] {
+            engine.handle_message(make_emit_event(path));
+        }
+        let start = engine
+            .repo
+            .lock()
+            .unwrap()
+            .find_entity_by_kind_and_name(&EntityKind::File, "/src/a.rs")
+            .unwrap()
+            .unwrap()
+            .id;
+        let page = |relations: Vec<Relation>, cursor: Option<String>| {
+            let mut query = QueryRequest::new(QueryKind::Related {
+                entity_id: start,
+                depth: 2,
+                limit: 1,
+                relations,
+            });
+            query.cursor = cursor;
+            match engine.handle_message(Message::new("q", MessageKind::Query { query })).kind {
+                MessageKind::QueryResult { response } => response,
+                other => panic!("expected QueryResult, got {:?}", other),
+            }
+        };
+
+        // Walk every page: scores never increase and each path is explained
+        let mut cursor = None;
+        let mut seen = Vec::new();
+        let mut last_score = 1.0;
+        loop {
+            let response = page(vec![], cursor);
+            assert_eq!(response.entities.len(), response.related.len());
+            for (entity, related) in response.entities.iter().zip(&response.related) {
+                assert_eq!(entity.id, related.entity_id);
+                assert!(related.score <= last_score && related.score > 0.0);
+                last_score = related.score;
+                for edge_id in &related.path {
+                    assert!(response.edges.iter().any(|e| e.id == *edge_id));
+                }
+                seen.push(entity.name.clone());
+            }
+            match response.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        seen.sort();
+        assert_eq!(seen, ["/src/b.rs", "/src/c.rs", "test-project"]);
+
+        let response = page(vec![Relation::BelongsTo], None);
+        assert_eq!(response.entities[0].name, "test-project");
+        assert_eq!(response.related[0].path.len(), 1);
+        assert_eq!(response.edges[0].relation, Relation::BelongsTo);
+    }
+
+    #[test]
+    fn engine_explains_connections_between_entities() {
+        let dir = TempDir::new().unwrap();
+        let engine = make_engine(&dir);
+        for path in ["/src/a.rs", "/src/b.rs"] {
+            engine.handle_message(make_emit_event(path));
+        }
+        let id = |kind: EntityKind, name: &str| {
+            engine
+                .repo
+                .lock()
+                .unwrap()
+                .find_entity_by_kind_and_name(&kind, name)
+                .unwrap()
+                .unwrap()
+                .id
+        };
+        let (a, b) = (id(EntityKind::File, "/src/a.rs"), id(EntityKind::File, "/src/b.rs"));
+        let connection = |from, to, max_depth| {
+            let query = QueryRequest::new(QueryKind::Connection { from, to, max_depth });
+            match engine.handle_message(Message::new("q", MessageKind::Query { query })).kind {
+                MessageKind::QueryResult { response } => response,
+                other => panic!("expected QueryResult, got {:?}", other),
+            }
+        };
+
+        // Both files belong to the project: a -> project <- b
+        let response = connection(a, b, 4);
+        let names: Vec<&str> = response.entities.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["/src/a.rs", "test-project", "/src/b.rs"]);
+        assert_eq!(response.edges.len(), 2);
+        assert!(response.edges.iter().all(|e| e.relation == Relation::BelongsTo));
+        assert_eq!(response.related.len(), 1);
+        assert_eq!(response.related[0].entity_id, b);
+        assert_eq!(
+            response.related[0].path,
+            response.edges.iter().map(|e| e.id).collect::<Vec<_>>()
+        );
+
+        // One hop is not enough
+        let response = connection(a, b, 1);
+        assert!(response.entities.is_empty() && response.related.is_empty());
+    }
+
     #[test]
     fn engine_handles_recent_query() {
         let dir = TempDir::new().unwrap();
@@ -465,9 +1695,7 @@ mod tests {
         let query_msg = Message::new(
             "q2",
             MessageKind::Query {
-                query: QueryRequest {
-                    kind: QueryKind::Recent { limit: 10 },
-                },
+                query: QueryRequest::new(QueryKind::Recent { limit: 10 }),
             },
         );
         let resp = engine.handle_message(query_msg);
@@ -479,6 +1707,403 @@ mod tests {
         }
     }
 
+    #[test]
+    fn engine_rejects_unknown_collector_control() {
+        let dir = TempDir::new().unwrap();
+        let engine = make_engine(&dir);
+        let msg = Message::new(
+            "sc1",
+            MessageKind::StartCollector {
+                name: "cronos-collect-nope".to_string(),
+            },
+        );
+        let resp = engine.handle_message(msg);
+        match resp.kind {
+            MessageKind::Error { code, .. } => assert!(matches!(code, ErrorCode::NotFound)),
+            other => panic!("expected Error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn engine_disconnects_silent_collectors() {
+        let dir = TempDir::new().unwrap();
+        let db_path = dir.path().join("test.db");
+        let mut config = DaemonConfig::default();
+        config.supervisor.heartbeat_timeout_ms = 0;
+        let engine = Engine::open(&db_path, &config).unwrap();
+
+        engine.handle_message(Message::new(
+            "c1",
+            MessageKind::CollectorHandshake {
+                name: "fs-collector".to_string(),
+                collector_version: "0.1.0".to_string(),
+                source: CollectorSource::Filesystem,
+                token: None,
+                protocol: None,
+                features: vec![],
+                codecs: vec![],
+            },
+        ));
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        engine.supervise();
+
+        match engine.handle_message(Message::new("l1", MessageKind::ListCollectors)).kind {
+            MessageKind::CollectorList { collectors } => {
+                assert!(collectors[0].stale);
+                assert!(!collectors[0].connected);
+            }
+            other => panic!("expected CollectorList, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn engine_tracks_collector_per_connection() {
+        let dir = TempDir::new().unwrap();
+        let engine = make_engine(&dir);
+        let mut conn = engine.local_connection();
+        let handshake = || {
+            Message::new(
+                "c1",
+                MessageKind::CollectorHandshake {
+                    name: "fs-collector".to_string(),
+                    collector_version: "0.1.0".to_string(),
+                    source: CollectorSource::Filesystem,
+                    token: None,
+                    protocol: None,
+                    features: vec![],
+                    codecs: vec![],
+                },
+            )
+        };
+
+        engine.handle_connection_message(&mut conn, handshake());
+        assert_eq!(conn.collector.as_deref(), Some("fs-collector"));
+
+        engine.handle_connection_message(&mut conn, make_emit_event("/src/main.rs"));
+        // Same subject within the dedup window
+        engine.handle_connection_message(&mut conn, make_emit_event("/src/main.rs"));
+        let resp = engine.handle_connection_message(&mut conn, Message::new("h1", MessageKind::Heartbeat));
+        assert!(matches!(resp.kind, MessageKind::Ack { .. }));
+
+        let list = |engine: &Engine| match engine
+            .handle_message(Message::new("l1", MessageKind::ListCollectors))
+            .kind
+        {
+            MessageKind::CollectorList { collectors } => collectors,
+            other => panic!("expected CollectorList, got {:?}", other),
+        };
+        let collectors = list(&engine);
+        assert_eq!(collectors[0].events_sent, 1);
+        assert_eq!(collectors[0].deduplicated, 1);
+        assert!(collectors[0].connected);
+        assert!(collectors[0].connected_at.is_some());
+
+        // The collector reconnects before its old connection is closed
+        let mut old = std::mem::replace(&mut conn, engine.local_connection());
+        engine.handle_connection_message(&mut conn, handshake());
+        engine.collector_disconnected(&old);
+        assert!(list(&engine)[0].connected);
+        // A handshake again on the old connection takes it back
+        engine.handle_connection_message(&mut old, handshake());
+        engine.collector_disconnected(&conn);
+        assert!(list(&engine)[0].connected);
+
+        engine.collector_disconnected(&old);
+        let collectors = list(&engine);
+        assert!(!collectors[0].connected);
+        assert!(!collectors[0].stale);
+    }
+
+    #[test]
+    fn engine_requires_token_on_remote_connections() {
+        let dir = TempDir::new().unwrap();
+        let db_path = dir.path().join("test.db");
+        let mut config = DaemonConfig::default();
+        config.tcp.token = "s3cret".to_string();
+        let engine = Engine::open(&db_path, &config).unwrap();
+
+        let handshake = |token: Option<&str>| {
+            Message::new(
+                "c1",
+                MessageKind::CollectorHandshake {
+                    name: "fs-collector".to_string(),
+                    collector_version: "0.1.0".to_string(),
+                    source: CollectorSource::Filesystem,
+                    token: token.map(str::to_string),
+                    protocol: None,
+                    features: vec![],
+                    codecs: vec![],
+                },
+            )
+        };
+
+        let mut conn = engine.remote_connection(false);
+        let resp = engine.handle_connection_message(&mut conn, Message::new("s1", MessageKind::Status));
+        assert!(matches!(
+            resp.kind,
+            MessageKind::Error { code: ErrorCode::Unauthorized, .. }
+        ));
+        let resp = engine.handle_connection_message(&mut conn, handshake(Some("wrong")));
+        assert!(matches!(resp.kind, MessageKind::Error { .. }));
+        assert!(conn.capabilities.is_empty());
+
+        let resp = engine.handle_connection_message(&mut conn, handshake(Some("s3cret")));
+        assert!(matches!(resp.kind, MessageKind::Ack { .. }));
+        assert!(conn.can(Capability::Emit));
+        let resp = engine.handle_connection_message(&mut conn, make_emit_event("/src/main.rs"));
+        assert!(matches!(resp.kind, MessageKind::Ack { .. }));
+        // Collectors may only emit unless the config says otherwise
+        assert!(!conn.can(Capability::Query));
+        assert!(!conn.can(Capability::Admin));
+        let verified = engine.remote_connection(true);
+        assert!(verified.can(Capability::Emit));
+        assert!(!verified.can(Capability::Query));
+
+        config.tcp.capabilities = vec!["emit".to_string(), "query".to_string()];
+        let engine = Engine::open(&db_path, &config).unwrap();
+        let verified = engine.remote_connection(true);
+        assert!(verified.can(Capability::Query));
+        assert!(!verified.can(Capability::Admin));
+    }
+
+    #[test]
+    fn engine_withholds_admin_from_local_peers_of_other_users() {
+        let dir = TempDir::new().unwrap();
+        let db_path = dir.path().join("test.db");
+        let mut config = DaemonConfig::default();
+        assert!(Engine::open(&db_path, &config).unwrap().local_connection().can(Capability::Admin));
+
+        config.auth.require_same_uid = false;
+        let conn = Engine::open(&db_path, &config).unwrap().local_connection();
+        assert!(conn.can(Capability::Query));
+        assert!(!conn.can(Capability::Admin));
+    }
+
+    #[test]
+    fn engine_enforces_capabilities() {
+        let dir = TempDir::new().unwrap();
+        let db_path = dir.path().join("test.db");
+        let mut config = DaemonConfig::default();
+        config.auth.local_capabilities = vec!["emit".to_string()];
+        config.auth.tokens = vec![TokenGrant {
+            token: "dashboard".to_string(),
+            capabilities: vec!["query".to_string()],
+        }];
+        let engine = Engine::open(&db_path, &config).unwrap();
+        let unauthorized = |resp: Message| {
+            matches!(resp.kind, MessageKind::Error { code: ErrorCode::Unauthorized, .. })
+        };
+
+        // A collector may emit but not query
+        let mut collector = engine.local_connection();
+        let resp = engine.handle_connection_message(&mut collector, make_emit_event("/src/main.rs"));
+        assert!(matches!(resp.kind, MessageKind::Ack { .. }));
+        let query = Message::new(
+            "q1",
+            MessageKind::Query {
+                query: QueryRequest::new(QueryKind::Recent { limit: 10 }),
+            },
+        );
+        assert!(unauthorized(engine.handle_connection_message(&mut collector, query.clone())));
+
+        // A read-only token adds query but not admin
+        let mut dashboard = engine.local_connection();
+        let auth = |token: &str| {
+            Message::new("a1", MessageKind::Authenticate { token: token.to_string() })
+        };
+        assert!(unauthorized(engine.handle_connection_message(&mut dashboard, auth("nope"))));
+        match engine.handle_connection_message(&mut dashboard, auth("dashboard")).kind {
+            MessageKind::Authenticated { capabilities } => {
+                assert_eq!(capabilities, vec![Capability::Emit, Capability::Query]);
+            }
+            other => panic!("expected Authenticated, got {:?}", other),
+        }
+        let resp = engine.handle_connection_message(&mut dashboard, query);
+        assert!(matches!(resp.kind, MessageKind::QueryResult { .. }));
+        let pause = Message::new("p1", MessageKind::SetTrackingPaused { paused: true });
+        assert!(unauthorized(engine.handle_connection_message(&mut dashboard, pause)));
+        assert!(!engine.is_tracking_paused());
+    }
+
+    #[test]
+    fn engine_rejects_unknown_capability() {
+        let dir = TempDir::new().unwrap();
+        let mut config = DaemonConfig::default();
+        config.auth.local_capabilities = vec!["root".to_string()];
+        assert!(Engine::open(&dir.path().join("test.db"), &config).is_err());
+    }
+
+    #[test]
+    fn engine_negotiates_protocol_version() {
+        let dir = TempDir::new().unwrap();
+        let engine = make_engine(&dir);
+        let mut conn = engine.local_connection();
+        let incompatible = |resp: Message| {
+            matches!(resp.kind, MessageKind::Error { code: ErrorCode::IncompatibleVersion, .. })
+        };
+
+        let hello = |min, max| {
+            Message::new(
+                "h1",
+                MessageKind::Hello {
+                    protocol: VersionRange { min, max },
+                    features: vec!["heartbeat".to_string(), "teleport".to_string()],
+                    codecs: vec![],
+                },
+            )
+        };
+        assert!(incompatible(engine.handle_connection_message(&mut conn, hello(90, 99))));
+        assert_eq!(conn.protocol_version, None);
+
+        match engine.handle_connection_message(&mut conn, hello(1, 99)).kind {
+            MessageKind::Welcome { version, features, .. } => {
+                assert_eq!(version, PROTOCOL_VERSION);
+                assert_eq!(features, vec!["heartbeat".to_string()]);
+            }
+            other => panic!("expected Welcome, got {:?}", other),
+        }
+        assert_eq!(conn.protocol_version, Some(PROTOCOL_VERSION));
+
+        // Messages stamped with a version the daemon doesn't speak are rejected
+        let mut status = Message::new("s1", MessageKind::Status);
+        status.version = 99;
+        assert!(incompatible(engine.handle_connection_message(&mut conn, status)));
+
+        // A legacy handshake (no range) negotiates its stamped version and gets an Ack
+        let mut handshake = Message::new(
+            "c1",
+            MessageKind::CollectorHandshake {
+                name: "old-collector".to_string(),
+                collector_version: "0.1.0".to_string(),
+                source: CollectorSource::Filesystem,
+                token: None,
+                protocol: None,
+                features: vec![],
+                codecs: vec![],
+            },
+        );
+        handshake.version = 1;
+        let mut legacy = engine.local_connection();
+        let resp = engine.handle_connection_message(&mut legacy, handshake);
+        assert!(matches!(resp.kind, MessageKind::Ack { .. }));
+        assert_eq!(legacy.protocol_version, Some(1));
+    }
+
+    #[test]
+    fn engine_paginates_recent_events() {
+        let dir = TempDir::new().unwrap();
+        let engine = make_engine(&dir);
+        for path in ["/src/a.rs", "/src/b.rs", "/src/c.rs"] {
+            engine.handle_message(make_emit_event(path));
+        }
+
+        let page = |cursor: Option<String>| {
+            let mut query = QueryRequest::new(QueryKind::Recent { limit: 2 });
+            query.cursor = cursor;
+            match engine.handle_message(Message::new("q", MessageKind::Query { query })).kind {
+                MessageKind::QueryResult { response } => response,
+                other => panic!("expected QueryResult, got {:?}", other),
+            }
+        };
+        let first = page(None);
+        assert_eq!(first.events.len(), 2);
+        let second = page(Some(first.next_cursor.expect("more events follow")));
+        assert_eq!(second.events.len(), 1);
+        assert!(second.next_cursor.is_none());
+
+        let mut query = QueryRequest::new(QueryKind::Recent { limit: 2 });
+        query.cursor = Some("not-a-cursor".to_string());
+        let resp = engine.handle_message(Message::new("q", MessageKind::Query { query }));
+        assert!(matches!(
+            resp.kind,
+            MessageKind::Error { code: ErrorCode::BadRequest, .. }
+        ));
+
+        let query = QueryRequest::new(QueryKind::Recent { limit: 0 });
+        let resp = engine.handle_message(Message::new("q", MessageKind::Query { query }));
+        assert!(matches!(
+            resp.kind,
+            MessageKind::Error { code: ErrorCode::BadRequest, .. }
+        ));
+    }
+
+    #[test]
+    fn engine_answers_pipeline_queries() {
+        let dir = TempDir::new().unwrap();
+        let engine = make_engine(&dir);
+        for path in ["/src/a.rs", "/src/b.rs", "/lib/b.rs"] {
+            engine.handle_message(make_emit_event(path));
+        }
+        let run = |query: &str| {
+            let query = QueryRequest::new(QueryKind::Expr {
+                query: query.to_string(),
+            });
+            engine.handle_message(Message::new("q", MessageKind::Query { query })).kind
+        };
+        let result = |query: &str| match run(query) {
+            MessageKind::QueryResult { response } => response,
+            other => panic!("expected QueryResult, got {:?}", other),
+        };
+
+        let response = result(
+            "events where kind = file_modified and project = test-project and time >= -1h \
+             | linked project | where subject ~ \"b.rs\"",
+        );
+        assert_eq!(response.events.len(), 2);
+        assert!(response.events.iter().all(|e| e.subject.identity.ends_with("/b.rs")));
+
+        let table = result("events | group by kind, source | count")
+            .table
+            .expect("aggregates return a table");
+        assert_eq!(table.columns, ["kind", "source", "count"]);
+        assert_eq!(
+            table.rows,
+            vec![vec![
+                serde_json::json!("file_modified"),
+                serde_json::json!("filesystem"),
+                serde_json::json!(3)
+            ]]
+        );
+
+        let entities = result("entities where kind = file | sort name").entities;
+        assert_eq!(entities.len(), 3);
+        assert_eq!(entities[0].name, "/lib/b.rs");
+
+        assert!(matches!(
+            run("events where"),
+            MessageKind::Error { code: ErrorCode::BadRequest, .. }
+        ));
+    }
+
+    #[test]
+    fn engine_runs_read_only_sql() {
+        let dir = TempDir::new().unwrap();
+        let engine = make_engine(&dir);
+        engine.handle_message(make_emit_event("/src/a.rs"));
+        let run = |statement: &str| {
+            let query = QueryRequest::new(QueryKind::Sql {
+                statement: statement.to_string(),
+            });
+            engine.handle_message(Message::new("q", MessageKind::Query { query })).kind
+        };
+
+        match run("SELECT name FROM entities WHERE kind = '\"file\"'") {
+            MessageKind::QueryResult { response } => {
+                let table = response.table.unwrap();
+                assert_eq!(table.columns, ["name"]);
+                assert_eq!(table.rows, vec![vec![serde_json::json!("/src/a.rs")]]);
+            }
+            other => panic!("expected QueryResult, got {:?}", other),
+        }
+        match run("DELETE FROM events") {
+            MessageKind::Error { code, message, .. } => {
+                assert!(matches!(code, ErrorCode::BadRequest));
+                assert!(message.contains("not allowed"), "{message}");
+            }
+            other => panic!("expected Error, got {:?}", other),
+        }
+    }
+
     #[test]
     fn engine_handles_unexpected_message() {
         let dir = TempDir::new().unwrap();
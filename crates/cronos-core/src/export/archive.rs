@@ -0,0 +1,476 @@
+//! Whole-store archives for backups and for moving history between machines.
+//!
+//! An archive is NDJSON. The first line is a [`Manifest`]; every line after it
+//! is one record tagged by `type`: all `entity` records, then `edge`, `event`
+//! and `session` records. Events carry their subject and context as entity
+//! ids from the same archive.
+//!
+//! Importing merges into an existing store. Entities are matched by kind and
+//! name, so a file or URL seen on both machines becomes one entity; edges are
+//! matched by endpoints and relation. Events and sessions already present
+//! (same id) are skipped, so importing the same archive twice changes nothing.
+
+use cronos_model::{
+    Attributes, CollectorSource, Edge, EdgeId, Entity, EntityId, EventId, EventKind, Timestamp,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use crate::storage::repo::{Session, StoredEvent};
+use crate::storage::Repository;
+
+/// Identifies a Cronos archive in its manifest.
+pub const ARCHIVE_FORMAT: &str = "cronos-archive";
+/// Version of the archive layout written by this build; older ones import.
+pub const ARCHIVE_VERSION: u32 = 1;
+
+/// Events are read from the store this many at a time.
+const EVENT_PAGE: u32 = 1000;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ArchiveError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("line {line}: {source}")]
+    Json {
+        line: usize,
+        source: serde_json::Error,
+    },
+    #[error("not a Cronos archive")]
+    NotAnArchive,
+    #[error("archive version {0} is newer than this build supports ({ARCHIVE_VERSION})")]
+    UnsupportedVersion(u32),
+    #[error("line {line}: unknown entity {id}")]
+    UnknownEntity { line: usize, id: EntityId },
+    #[error("archive is incomplete: manifest lists {expected:?}, found {found:?}")]
+    Incomplete { expected: Counts, found: Counts },
+}
+
+/// First line of an archive.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: String,
+    pub version: u32,
+    /// Schema version of the store the archive was taken from.
+    pub schema_version: i32,
+    pub exported_at: Timestamp,
+    pub counts: Counts,
+}
+
+/// Records in an archive, by type.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Counts {
+    pub entities: u64,
+    pub edges: u64,
+    pub events: u64,
+    pub sessions: u64,
+}
+
+/// What an import changed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportStats {
+    pub entities_added: u64,
+    /// Entities that matched one already in the store.
+    pub entities_merged: u64,
+    pub edges_added: u64,
+    pub edges_merged: u64,
+    pub events_added: u64,
+    /// Events already in the store.
+    pub events_skipped: u64,
+    pub sessions_added: u64,
+    pub sessions_skipped: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Record {
+    Entity(Entity),
+    Edge(Edge),
+    Event(ArchivedEvent),
+    Session(Session),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ArchivedEvent {
+    id: EventId,
+    timestamp: Timestamp,
+    source: CollectorSource,
+    kind: EventKind,
+    subject: EntityId,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    context: Vec<EntityId>,
+    #[serde(default)]
+    metadata: Attributes,
+}
+
+/// Write everything in `repo` to `out` as one consistent snapshot.
+pub fn export(repo: &Repository, out: &mut dyn Write) -> Result<Manifest, ArchiveError> {
+    repo.transaction(|repo| {
+        let manifest = Manifest {
+            format: ARCHIVE_FORMAT.to_string(),
+            version: ARCHIVE_VERSION,
+            schema_version: repo.schema_version()?,
+            exported_at: cronos_common::now_ms(),
+            counts: Counts {
+                entities: repo.entity_count()? as u64,
+                edges: repo.edge_count()? as u64,
+                events: repo.event_count()? as u64,
+                sessions: repo.session_count()? as u64,
+            },
+        };
+        write_line(out, &manifest)?;
+
+        for entity in repo.all_entities()? {
+            write_line(out, &Record::Entity(entity))?;
+        }
+        for edge in repo.all_edges()? {
+            write_line(out, &Record::Edge(edge))?;
+        }
+        let mut after = None;
+        loop {
+            let page = repo.events_with_context(after, EVENT_PAGE)?;
+            let Some((last, _)) = page.last() else { break };
+            after = Some(last.id);
+            for (event, context) in page {
+                write_line(out, &Record::Event(archived_event(event, context)))?;
+            }
+        }
+        for session in repo.sessions_in_range(Timestamp::MIN, Timestamp::MAX, u32::MAX)? {
+            write_line(out, &Record::Session(session))?;
+        }
+        out.flush()?;
+        Ok(manifest)
+    })
+}
+
+/// Merge the archive read from `input` into `repo`. Nothing is imported
+/// unless the whole archive is.
+pub fn import(repo: &Repository, input: &mut dyn BufRead) -> Result<ImportStats, ArchiveError> {
+    repo.transaction(|repo| {
+        let mut lines = input.lines().enumerate().map(|(i, line)| (i + 1, line));
+        let manifest: Manifest = match lines.next() {
+            Some((line, text)) => serde_json::from_str(&text?)
+                .map_err(|source| ArchiveError::Json { line, source })?,
+            None => return Err(ArchiveError::NotAnArchive),
+        };
+        if manifest.format != ARCHIVE_FORMAT {
+            return Err(ArchiveError::NotAnArchive);
+        }
+        if manifest.version > ARCHIVE_VERSION {
+            return Err(ArchiveError::UnsupportedVersion(manifest.version));
+        }
+
+        let mut importer = Importer {
+            repo,
+            ids: HashMap::new(),
+            found: Counts::default(),
+            stats: ImportStats::default(),
+        };
+        for (line, text) in lines {
+            let text = text?;
+            if text.trim().is_empty() {
+                continue;
+            }
+            let record = serde_json::from_str(&text)
+                .map_err(|source| ArchiveError::Json { line, source })?;
+            importer.record(line, record)?;
+        }
+        if importer.found != manifest.counts {
+            return Err(ArchiveError::Incomplete {
+                expected: manifest.counts,
+                found: importer.found,
+            });
+        }
+        Ok(importer.stats)
+    })
+}
+
+struct Importer<'a> {
+    repo: &'a Repository,
+    /// Archive entity id to the id of the same entity in the store.
+    ids: HashMap<EntityId, EntityId>,
+    found: Counts,
+    stats: ImportStats,
+}
+
+impl Importer<'_> {
+    fn record(&mut self, line: usize, record: Record) -> Result<(), ArchiveError> {
+        match record {
+            Record::Entity(entity) => {
+                self.found.entities += 1;
+                self.entity(entity)?;
+            }
+            Record::Edge(mut edge) => {
+                self.found.edges += 1;
+                edge.from = self.id(line, edge.from)?;
+                edge.to = self.id(line, edge.to)?;
+                self.edge(edge)?;
+            }
+            Record::Event(event) => {
+                self.found.events += 1;
+                if self.repo.has_event(event.id)? {
+                    self.stats.events_skipped += 1;
+                    return Ok(());
+                }
+                let subject = self.id(line, event.subject)?;
+                let context = event
+                    .context
+                    .iter()
+                    .map(|id| self.id(line, *id))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let stored = StoredEvent {
+                    id: event.id,
+                    timestamp: event.timestamp,
+                    source: event.source,
+                    kind: event.kind,
+                    subject_id: subject,
+                    metadata: event.metadata,
+                };
+                self.repo.insert_stored_event(&stored, &context)?;
+                self.stats.events_added += 1;
+            }
+            Record::Session(mut session) => {
+                self.found.sessions += 1;
+                if self.repo.has_session(&session.id)? {
+                    self.stats.sessions_skipped += 1;
+                } else {
+                    session.project_id = session
+                        .project_id
+                        .map(|id| self.id(line, id))
+                        .transpose()?;
+                    self.repo.insert_session(&session)?;
+                    self.stats.sessions_added += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn id(&self, line: usize, id: EntityId) -> Result<EntityId, ArchiveError> {
+        self.ids
+            .get(&id)
+            .copied()
+            .ok_or(ArchiveError::UnknownEntity { line, id })
+    }
+
+    fn entity(&mut self, mut entity: Entity) -> rusqlite::Result<()> {
+        let archived_id = entity.id;
+        if let Some(existing) = self
+            .repo
+            .find_entity_by_kind_and_name(&entity.kind, &entity.name)?
+        {
+            // The newer side's attributes win
+            let (mut attributes, newer) = if entity.last_seen > existing.last_seen {
+                (existing.attributes, entity.attributes)
+            } else {
+                (entity.attributes, existing.attributes)
+            };
+            attributes.extend(newer);
+            let merged = Entity {
+                attributes,
+                first_seen: existing.first_seen.min(entity.first_seen),
+                last_seen: existing.last_seen.max(entity.last_seen),
+                ..existing
+            };
+            self.repo.insert_entity(&merged)?;
+            self.ids.insert(archived_id, merged.id);
+            self.stats.entities_merged += 1;
+            return Ok(());
+        }
+        // A different entity already has this id: give this one a new id
+        if self.repo.get_entity(entity.id)?.is_some() {
+            entity.id = EntityId::new();
+        }
+        self.repo.insert_entity(&entity)?;
+        self.ids.insert(archived_id, entity.id);
+        self.stats.entities_added += 1;
+        Ok(())
+    }
+
+    fn edge(&mut self, mut edge: Edge) -> rusqlite::Result<()> {
+        if let Some(existing) = self.repo.find_edge(edge.from, edge.to, &edge.relation)? {
+            let merged = Edge {
+                strength: existing.strength.max(edge.strength),
+                last_reinforced: existing.last_reinforced.max(edge.last_reinforced),
+                ..existing
+            };
+            self.repo.insert_edge(&merged)?;
+            self.stats.edges_merged += 1;
+            return Ok(());
+        }
+        if !self.repo.edges_by_ids(&[edge.id])?.is_empty() {
+            edge.id = EdgeId::new();
+        }
+        self.repo.insert_edge(&edge)?;
+        self.stats.edges_added += 1;
+        Ok(())
+    }
+}
+
+fn archived_event(event: StoredEvent, context: Vec<EntityId>) -> ArchivedEvent {
+    ArchivedEvent {
+        id: event.id,
+        timestamp: event.timestamp,
+        source: event.source,
+        kind: event.kind,
+        subject: event.subject_id,
+        context,
+        metadata: event.metadata,
+    }
+}
+
+fn write_line(out: &mut dyn Write, value: &impl Serialize) -> io::Result<()> {
+    serde_json::to_writer(&mut *out, value)?;
+    out.write_all(b"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures as fixtures;
+    use cronos_model::{EntityKind, Relation};
+
+    fn event(
+        subject: &Entity,
+        context: &[&Entity],
+        at: Timestamp,
+    ) -> (cronos_model::Event, Vec<EntityId>) {
+        let event = cronos_model::Event {
+            id: EventId::new(),
+            timestamp: at,
+            source: CollectorSource::Filesystem,
+            kind: EventKind::FileModified,
+            subject: cronos_model::EntityRef {
+                kind: subject.kind.clone(),
+                identity: subject.name.clone(),
+                attributes: HashMap::new(),
+            },
+            context: vec![],
+            metadata: HashMap::from([("size".to_string(), serde_json::json!(1))]),
+        };
+        (event, context.iter().map(|e| e.id).collect())
+    }
+
+    fn session(id: &str, start: Timestamp, project: &Entity) -> Session {
+        Session {
+            id: id.to_string(),
+            window_titles: vec!["main.rs".to_string()],
+            project: Some(project.name.clone()),
+            project_id: Some(project.id),
+            project_confidence: 0.9,
+            ..fixtures::session("code", "coding", start, start + 60_000)
+        }
+    }
+
+    /// A project with one file, an event on it and a session, seen at `at`.
+    fn populate(repo: &Repository, file: &str, session_id: &str, at: Timestamp) {
+        let project = fixtures::entity(EntityKind::Project, "/work/cronos", at);
+        let file = fixtures::entity(EntityKind::File, file, at);
+        repo.insert_entity(&project).unwrap();
+        repo.insert_entity(&file).unwrap();
+        let belongs = fixtures::edge(file.id, project.id, Relation::BelongsTo, 0.5, at);
+        repo.insert_edge(&belongs).unwrap();
+        let (ev, context) = event(&file, &[&project], at);
+        repo.insert_event(&ev, file.id, &context).unwrap();
+        repo.insert_session(&session(session_id, at, &project)).unwrap();
+    }
+
+    fn archive(repo: &Repository) -> Vec<u8> {
+        let mut out = Vec::new();
+        export(repo, &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn export_writes_a_manifest_then_one_record_per_line() {
+        let repo = Repository::open_in_memory().unwrap();
+        populate(&repo, "/work/cronos/main.rs", "s1", 1000);
+        let text = String::from_utf8(archive(&repo)).unwrap();
+        let lines: Vec<serde_json::Value> = text
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+
+        assert_eq!(lines[0]["format"], ARCHIVE_FORMAT);
+        assert_eq!(lines[0]["version"], ARCHIVE_VERSION);
+        assert_eq!(
+            lines[0]["counts"],
+            serde_json::json!({"entities": 2, "edges": 1, "events": 1, "sessions": 1})
+        );
+        let types: Vec<&str> = lines[1..]
+            .iter()
+            .map(|l| l["type"].as_str().unwrap())
+            .collect();
+        assert_eq!(types, ["entity", "entity", "edge", "event", "session"]);
+        assert_eq!(lines[4]["context"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn import_merges_matching_entities_and_skips_known_records() {
+        let source = Repository::open_in_memory().unwrap();
+        populate(&source, "/work/cronos/main.rs", "s1", 1000);
+        let bytes = archive(&source);
+
+        // The target saw the same project later, and has its own file
+        let target = Repository::open_in_memory().unwrap();
+        populate(&target, "/work/cronos/lib.rs", "s2", 5000);
+
+        let stats = import(&target, &mut bytes.as_slice()).unwrap();
+        assert_eq!(stats.entities_added, 1);
+        assert_eq!(stats.entities_merged, 1);
+        assert_eq!(stats.edges_added, 1);
+        assert_eq!(stats.events_added, 1);
+        assert_eq!(stats.sessions_added, 1);
+
+        assert_eq!(target.entity_count().unwrap(), 3);
+        assert_eq!(target.edge_count().unwrap(), 2);
+        assert_eq!(target.event_count().unwrap(), 2);
+        assert_eq!(target.session_count().unwrap(), 2);
+        let project = target
+            .find_entity_by_kind_and_name(&EntityKind::Project, "/work/cronos")
+            .unwrap()
+            .unwrap();
+        assert_eq!((project.first_seen, project.last_seen), (1000, 5000));
+
+        // The imported event and session point at the target's project entity
+        let events = target.events_with_context(None, 10).unwrap();
+        assert!(events
+            .iter()
+            .all(|(_, context)| context == &vec![project.id]));
+        let sessions = target.sessions_in_range(0, 10_000, 10).unwrap();
+        assert!(sessions.iter().all(|s| s.project_id == Some(project.id)));
+
+        // A second import finds everything already there
+        let again = import(&target, &mut bytes.as_slice()).unwrap();
+        assert_eq!(again.entities_merged, 2);
+        assert_eq!(again.edges_merged, 1);
+        assert_eq!(again.events_skipped, 1);
+        assert_eq!(again.sessions_skipped, 1);
+        assert_eq!(target.event_count().unwrap(), 2);
+    }
+
+    #[test]
+    fn broken_archives_import_nothing() {
+        let source = Repository::open_in_memory().unwrap();
+        populate(&source, "/work/cronos/main.rs", "s1", 1000);
+        let text = String::from_utf8(archive(&source)).unwrap();
+        let target = Repository::open_in_memory().unwrap();
+
+        // Cut off before the session
+        let truncated: String = text.lines().take(5).map(|l| format!("{l}\n")).collect();
+        let err = import(&target, &mut truncated.as_bytes()).unwrap_err();
+        assert!(matches!(err, ArchiveError::Incomplete { .. }), "{err}");
+        assert_eq!(target.entity_count().unwrap(), 0);
+
+        let newer = text.replacen("\"version\":1", "\"version\":99", 1);
+        let err = import(&target, &mut newer.as_bytes()).unwrap_err();
+        assert!(matches!(err, ArchiveError::UnsupportedVersion(99)), "{err}");
+
+        let err = import(&target, &mut "{\"hello\":1}\n".as_bytes()).unwrap_err();
+        assert!(matches!(err, ArchiveError::Json { line: 1, .. }), "{err}");
+        assert_eq!(target.entity_count().unwrap(), 0);
+    }
+}
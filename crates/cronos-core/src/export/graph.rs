@@ -0,0 +1,360 @@
+//! The context graph as DOT, GraphML or JSON Graph Format, for viewers like
+//! Graphviz, Gephi or Cytoscape.
+//!
+//! Every format carries the same attributes: entity name, kind and first/last
+//! seen times on nodes, and relation, strength and created/reinforced times on
+//! edges. Times are Unix milliseconds.
+
+use cronos_model::{Edge, Entity, EntityKind, Relation, Timestamp};
+use std::collections::HashSet;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use crate::storage::repo::Filter;
+use crate::storage::Repository;
+
+/// Which part of the graph to export. Empty lists and `None` select everything.
+#[derive(Debug, Clone, Default)]
+pub struct GraphSelection {
+    /// Project path or its last component: the project and entities linked to it.
+    pub project: Option<String>,
+    pub entity_kinds: Vec<EntityKind>,
+    /// Only entities seen, and edges active, at some point in `[from, to]`.
+    pub from: Option<Timestamp>,
+    pub to: Option<Timestamp>,
+    /// Only edges at least this strong.
+    pub min_strength: f32,
+}
+
+/// Selected entities and the selected edges between them.
+#[derive(Debug, Clone, Default)]
+pub struct GraphSlice {
+    pub entities: Vec<Entity>,
+    pub edges: Vec<Edge>,
+}
+
+impl GraphSlice {
+    pub fn select(repo: &Repository, selection: &GraphSelection) -> rusqlite::Result<Self> {
+        let from = selection.from.unwrap_or(Timestamp::MIN);
+        let to = selection.to.unwrap_or(Timestamp::MAX);
+        let filter = Filter {
+            entity_kinds: selection.entity_kinds.clone(),
+            project: selection.project.clone(),
+            ..Filter::default()
+        };
+        let entities = repo.entities_seen_between(&filter, from, to)?;
+        let ids: HashSet<_> = entities.iter().map(|e| e.id).collect();
+        let edges = repo
+            .edges_active_between(selection.min_strength, from, to)?
+            .into_iter()
+            .filter(|e| ids.contains(&e.from) && ids.contains(&e.to))
+            .collect();
+        Ok(Self { entities, edges })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphFormat {
+    Dot,
+    GraphMl,
+    /// JSON Graph Format, version 2.
+    Jgf,
+}
+
+impl FromStr for GraphFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "dot" | "gv" => Ok(Self::Dot),
+            "graphml" => Ok(Self::GraphMl),
+            "jgf" | "json" => Ok(Self::Jgf),
+            other => Err(format!(
+                "unknown graph format '{other}' (expected dot, graphml or jgf)"
+            )),
+        }
+    }
+}
+
+impl GraphFormat {
+    pub fn write(self, slice: &GraphSlice, out: &mut dyn Write) -> io::Result<()> {
+        match self {
+            Self::Dot => write_dot(slice, out),
+            Self::GraphMl => write_graphml(slice, out),
+            Self::Jgf => write_jgf(slice, out),
+        }
+    }
+}
+
+fn write_dot(slice: &GraphSlice, out: &mut dyn Write) -> io::Result<()> {
+    writeln!(out, "digraph cronos {{")?;
+    for e in &slice.entities {
+        writeln!(
+            out,
+            "  \"{}\" [label=\"{}\", kind=\"{}\", first_seen={}, last_seen={}];",
+            e.id,
+            dot_escape(&e.name),
+            dot_escape(&e.kind.to_string()),
+            e.first_seen,
+            e.last_seen
+        )?;
+    }
+    for e in &slice.edges {
+        let relation = dot_escape(&relation_name(&e.relation));
+        writeln!(
+            out,
+            "  \"{}\" -> \"{}\" [label=\"{relation}\", relation=\"{relation}\", strength={}, created_at={}, last_reinforced={}];",
+            e.from, e.to, e.strength, e.created_at, e.last_reinforced
+        )?;
+    }
+    writeln!(out, "}}")
+}
+
+fn write_graphml(slice: &GraphSlice, out: &mut dyn Write) -> io::Result<()> {
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        out,
+        r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+    )?;
+    for (id, domain, kind) in [
+        ("name", "node", "string"),
+        ("kind", "node", "string"),
+        ("first_seen", "node", "long"),
+        ("last_seen", "node", "long"),
+        ("relation", "edge", "string"),
+        ("strength", "edge", "double"),
+        ("created_at", "edge", "long"),
+        ("last_reinforced", "edge", "long"),
+    ] {
+        writeln!(
+            out,
+            r#"  <key id="{id}" for="{domain}" attr.name="{id}" attr.type="{kind}"/>"#
+        )?;
+    }
+    writeln!(out, r#"  <graph id="cronos" edgedefault="directed">"#)?;
+    for e in &slice.entities {
+        writeln!(out, r#"    <node id="{}">"#, e.id)?;
+        writeln!(
+            out,
+            r#"      <data key="name">{}</data>"#,
+            xml_escape(&e.name)
+        )?;
+        writeln!(
+            out,
+            r#"      <data key="kind">{}</data>"#,
+            xml_escape(&e.kind.to_string())
+        )?;
+        writeln!(
+            out,
+            r#"      <data key="first_seen">{}</data>"#,
+            e.first_seen
+        )?;
+        writeln!(out, r#"      <data key="last_seen">{}</data>"#, e.last_seen)?;
+        writeln!(out, "    </node>")?;
+    }
+    for e in &slice.edges {
+        writeln!(
+            out,
+            r#"    <edge id="{}" source="{}" target="{}">"#,
+            e.id, e.from, e.to
+        )?;
+        writeln!(
+            out,
+            r#"      <data key="relation">{}</data>"#,
+            xml_escape(&relation_name(&e.relation))
+        )?;
+        writeln!(out, r#"      <data key="strength">{}</data>"#, e.strength)?;
+        writeln!(
+            out,
+            r#"      <data key="created_at">{}</data>"#,
+            e.created_at
+        )?;
+        writeln!(
+            out,
+            r#"      <data key="last_reinforced">{}</data>"#,
+            e.last_reinforced
+        )?;
+        writeln!(out, "    </edge>")?;
+    }
+    writeln!(out, "  </graph>")?;
+    writeln!(out, "</graphml>")
+}
+
+fn write_jgf(slice: &GraphSlice, out: &mut dyn Write) -> io::Result<()> {
+    let nodes: serde_json::Map<String, serde_json::Value> = slice
+        .entities
+        .iter()
+        .map(|e| {
+            let node = serde_json::json!({
+                "label": e.name,
+                "metadata": {
+                    "kind": e.kind.to_string(),
+                    "first_seen": e.first_seen,
+                    "last_seen": e.last_seen,
+                },
+            });
+            (e.id.to_string(), node)
+        })
+        .collect();
+    let edges: Vec<serde_json::Value> = slice
+        .edges
+        .iter()
+        .map(|e| {
+            serde_json::json!({
+                "id": e.id.to_string(),
+                "source": e.from.to_string(),
+                "target": e.to.to_string(),
+                "relation": relation_name(&e.relation),
+                "metadata": {
+                    "strength": e.strength,
+                    "created_at": e.created_at,
+                    "last_reinforced": e.last_reinforced,
+                },
+            })
+        })
+        .collect();
+    let document = serde_json::json!({
+        "graph": {
+            "id": "cronos",
+            "directed": true,
+            "nodes": nodes,
+            "edges": edges,
+        }
+    });
+    serde_json::to_writer_pretty(&mut *out, &document)?;
+    writeln!(out)
+}
+
+/// `belongs_to`, or `custom:<name>` like `EntityKind`'s display form.
+fn relation_name(relation: &Relation) -> String {
+    match relation {
+        Relation::Custom(name) => format!("custom:{name}"),
+        other => serde_json::to_value(other)
+            .ok()
+            .and_then(|v| v.as_str().map(str::to_string))
+            .unwrap_or_default(),
+    }
+}
+
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures as fixtures;
+
+    /// Two projects, a file in each, and a URL the first file refers to.
+    fn repo() -> Repository {
+        let repo = Repository::open_in_memory().unwrap();
+        let cronos = fixtures::entity(EntityKind::Project, "/work/cronos", 1000);
+        let other = fixtures::entity(EntityKind::Project, "/work/other", 1000);
+        let main = fixtures::entity(EntityKind::File, "/work/cronos/\"main\".rs", 2000);
+        let lib = fixtures::entity(EntityKind::File, "/work/other/lib.rs", 2000);
+        let url = fixtures::entity(EntityKind::Url, "https://docs.rs/?a=1&b=<2>", 5000);
+        for e in [&cronos, &other, &main, &lib, &url] {
+            repo.insert_entity(e).unwrap();
+        }
+        for e in [
+            fixtures::edge(main.id, cronos.id, Relation::BelongsTo, 1.0, 2000),
+            fixtures::edge(lib.id, other.id, Relation::BelongsTo, 1.0, 2000),
+            fixtures::edge(main.id, url.id, Relation::References, 0.2, 5000),
+        ] {
+            repo.insert_edge(&e).unwrap();
+        }
+        repo
+    }
+
+    fn names(slice: &GraphSlice) -> Vec<&str> {
+        let mut names: Vec<_> = slice.entities.iter().map(|e| e.name.as_str()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn selection_filters_entities_and_keeps_edges_between_them() {
+        let repo = repo();
+        let all = GraphSlice::select(&repo, &GraphSelection::default()).unwrap();
+        assert_eq!(all.entities.len(), 5);
+        assert_eq!(all.edges.len(), 3);
+
+        let project = GraphSelection {
+            project: Some("cronos".to_string()),
+            ..GraphSelection::default()
+        };
+        let slice = GraphSlice::select(&repo, &project).unwrap();
+        assert_eq!(names(&slice), ["/work/cronos", "/work/cronos/\"main\".rs"]);
+        assert_eq!(slice.edges.len(), 1);
+
+        let files = GraphSelection {
+            entity_kinds: vec![EntityKind::File, EntityKind::Url],
+            ..GraphSelection::default()
+        };
+        let slice = GraphSlice::select(&repo, &files).unwrap();
+        assert_eq!(slice.entities.len(), 3);
+        assert_eq!(slice.edges.len(), 1);
+        assert_eq!(slice.edges[0].relation, Relation::References);
+
+        let strong = GraphSelection {
+            min_strength: 0.5,
+            ..GraphSelection::default()
+        };
+        assert_eq!(GraphSlice::select(&repo, &strong).unwrap().edges.len(), 2);
+
+        let early = GraphSelection {
+            from: Some(0),
+            to: Some(3000),
+            ..GraphSelection::default()
+        };
+        let slice = GraphSlice::select(&repo, &early).unwrap();
+        assert_eq!(slice.entities.len(), 4);
+        assert!(slice
+            .edges
+            .iter()
+            .all(|e| e.relation == Relation::BelongsTo));
+    }
+
+    #[test]
+    fn every_format_carries_names_kinds_relations_and_strengths() {
+        let slice = GraphSlice::select(&repo(), &GraphSelection::default()).unwrap();
+        let render = |format: GraphFormat| {
+            let mut out = Vec::new();
+            format.write(&slice, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let dot = render(GraphFormat::Dot);
+        assert!(dot.starts_with("digraph cronos {"));
+        assert!(dot.contains(r#"label="/work/cronos/\"main\".rs", kind="file""#));
+        assert!(dot.contains(r#"relation="references", strength=0.2"#));
+
+        let graphml = render(GraphFormat::GraphMl);
+        assert!(graphml.contains(r#"<data key="name">https://docs.rs/?a=1&amp;b=&lt;2&gt;</data>"#));
+        assert!(graphml.contains(r#"<data key="kind">url</data>"#));
+        assert!(graphml.contains(r#"<data key="relation">belongs_to</data>"#));
+        assert_eq!(graphml.matches("<edge ").count(), 3);
+
+        let jgf: serde_json::Value = serde_json::from_str(&render(GraphFormat::Jgf)).unwrap();
+        let graph = &jgf["graph"];
+        assert_eq!(graph["nodes"].as_object().unwrap().len(), 5);
+        let edge = &graph["edges"].as_array().unwrap()[0];
+        let source = edge["source"].as_str().unwrap();
+        assert!(graph["nodes"][source]["metadata"]["kind"].is_string());
+        assert!(edge["relation"].is_string());
+        assert!(edge["metadata"]["strength"].is_number());
+
+        assert_eq!("GraphML".parse(), Ok(GraphFormat::GraphMl));
+        assert!("svg".parse::<GraphFormat>().is_err());
+    }
+}
@@ -0,0 +1,5 @@
+//! Getting data out of the store in formats other tools understand.
+
+pub mod archive;
+pub mod graph;
+pub mod timesheet;
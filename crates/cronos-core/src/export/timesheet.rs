@@ -0,0 +1,288 @@
+//! Timesheets as CSV for spreadsheets and invoicing tools, JSON, or
+//! iCalendar with one event per block for calendar apps.
+//!
+//! CSV dates and times are local to the zone passed in; iCalendar times are
+//! UTC, which calendar apps show in their own zone.
+
+use chrono_tz::Tz;
+use cronos_model::Timestamp;
+use cronos_proto::{TimesheetGroup, TimesheetInfo};
+use std::io::{self, Write};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimesheetFormat {
+    Csv,
+    Json,
+    Ical,
+}
+
+impl FromStr for TimesheetFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            "ical" | "ics" | "icalendar" => Ok(Self::Ical),
+            other => Err(format!(
+                "unknown timesheet format '{other}' (expected csv, json or ical)"
+            )),
+        }
+    }
+}
+
+impl TimesheetFormat {
+    pub fn write(self, sheet: &TimesheetInfo, tz: Tz, out: &mut dyn Write) -> io::Result<()> {
+        match self {
+            Self::Csv => write_csv(sheet, tz, out),
+            Self::Json => {
+                serde_json::to_writer_pretty(&mut *out, sheet)?;
+                writeln!(out)
+            }
+            Self::Ical => write_ical(sheet, cronos_common::now_ms(), out),
+        }
+    }
+}
+
+fn group_name(by: TimesheetGroup) -> &'static str {
+    match by {
+        TimesheetGroup::Project => "project",
+        TimesheetGroup::Category => "category",
+    }
+}
+
+fn hours(secs: i64) -> String {
+    format!("{:.2}", secs as f64 / 3600.0)
+}
+
+fn write_csv(sheet: &TimesheetInfo, tz: Tz, out: &mut dyn Write) -> io::Result<()> {
+    let local = |ms: Timestamp| {
+        chrono::DateTime::from_timestamp_millis(ms)
+            .unwrap_or_default()
+            .with_timezone(&tz)
+    };
+    writeln!(
+        out,
+        "date,start,end_date,end,{},hours,tracked_hours,notes",
+        group_name(sheet.by)
+    )?;
+    for block in &sheet.blocks {
+        let (start, end) = (local(block.start), local(block.end));
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{},{}",
+            start.format("%Y-%m-%d"),
+            start.format("%H:%M"),
+            end.format("%Y-%m-%d"),
+            end.format("%H:%M"),
+            csv_field(block.name.as_deref().unwrap_or_default()),
+            hours(block.billed_secs),
+            hours(block.tracked_secs),
+            csv_field(&block.notes.join("; ")),
+        )?;
+    }
+    Ok(())
+}
+
+/// `s` quoted when it holds a separator, quote or line break (RFC 4180).
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn write_ical(sheet: &TimesheetInfo, now: Timestamp, out: &mut dyn Write) -> io::Result<()> {
+    let utc = |ms: Timestamp| {
+        chrono::DateTime::from_timestamp_millis(ms)
+            .unwrap_or_default()
+            .format("%Y%m%dT%H%M%SZ")
+            .to_string()
+    };
+    let mut line = |text: String| -> io::Result<()> { write!(out, "{}\r\n", ical_fold(&text)) };
+    line("BEGIN:VCALENDAR".to_string())?;
+    line("VERSION:2.0".to_string())?;
+    line("PRODID:-//Cronos//Timesheet//EN".to_string())?;
+    line("CALSCALE:GREGORIAN".to_string())?;
+    for block in &sheet.blocks {
+        let name = block.name.as_deref().unwrap_or(match sheet.by {
+            TimesheetGroup::Project => "No project",
+            TimesheetGroup::Category => "No category",
+        });
+        line("BEGIN:VEVENT".to_string())?;
+        // Stable across exports, so re-importing a corrected sheet updates events
+        line(format!(
+            "UID:{}-{}-{}@timesheet.cronos",
+            block.start,
+            block.end,
+            hex::encode(name)
+        ))?;
+        line(format!("DTSTAMP:{}", utc(now)))?;
+        line(format!("DTSTART:{}", utc(block.start)))?;
+        line(format!("DTEND:{}", utc(block.end)))?;
+        line(format!(
+            "SUMMARY:{} ({} h)",
+            ical_escape(name),
+            hours(block.billed_secs)
+        ))?;
+        let mut description = format!(
+            "Billed {} h, tracked {} h",
+            hours(block.billed_secs),
+            hours(block.tracked_secs)
+        );
+        for note in &block.notes {
+            description.push('\n');
+            description.push_str(note);
+        }
+        line(format!("DESCRIPTION:{}", ical_escape(&description)))?;
+        line(format!("CATEGORIES:{}", group_name(sheet.by)))?;
+        line("END:VEVENT".to_string())?;
+    }
+    line("END:VCALENDAR".to_string())
+}
+
+fn ical_escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// `line` folded into lines of at most 75 octets, continuations starting
+/// with a space (RFC 5545 section 3.1).
+fn ical_fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len());
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(c);
+        width += c.len_utf8();
+    }
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cronos_proto::{TimeTotal, TimesheetBlock};
+
+    fn sheet() -> TimesheetInfo {
+        TimesheetInfo {
+            from: 1_709_596_800_000,
+            to: 1_709_683_200_000,
+            by: TimesheetGroup::Project,
+            round_secs: 900,
+            blocks: vec![
+                TimesheetBlock {
+                    name: Some("acme, inc".to_string()),
+                    // 2024-03-05 09:00 UTC
+                    start: 1_709_629_200_000,
+                    end: 1_709_631_480_000,
+                    tracked_secs: 2280,
+                    billed_secs: 2700,
+                    notes: vec!["kickoff; \"phase 1\"".to_string()],
+                },
+                TimesheetBlock {
+                    name: None,
+                    start: 1_709_640_000_000,
+                    end: 1_709_641_800_000,
+                    tracked_secs: 1800,
+                    billed_secs: 1800,
+                    notes: vec![],
+                },
+                TimesheetBlock {
+                    name: Some("globex".to_string()),
+                    // 2024-03-05 22:30 UTC, ending after midnight in Berlin
+                    start: 1_709_677_800_000,
+                    end: 1_709_680_500_000,
+                    tracked_secs: 2700,
+                    billed_secs: 2700,
+                    notes: vec![],
+                },
+            ],
+            totals: vec![TimeTotal {
+                name: "acme, inc".to_string(),
+                id: None,
+                secs: 2700,
+            }],
+        }
+    }
+
+    fn render(format: TimesheetFormat, tz: Tz) -> String {
+        let mut out = Vec::new();
+        format.write(&sheet(), tz, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn csv_has_a_row_per_block_in_local_time() {
+        let csv = render(TimesheetFormat::Csv, "Europe/Berlin".parse().unwrap());
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "date,start,end_date,end,project,hours,tracked_hours,notes"
+        );
+        assert_eq!(
+            lines[1],
+            r#"2024-03-05,10:00,2024-03-05,10:38,"acme, inc",0.75,0.63,"kickoff; ""phase 1""""#
+        );
+        assert_eq!(lines[2], "2024-03-05,13:00,2024-03-05,13:30,,0.50,0.50,");
+        assert_eq!(
+            lines[3],
+            "2024-03-05,23:30,2024-03-06,00:15,globex,0.75,0.75,"
+        );
+    }
+
+    #[test]
+    fn ical_has_an_event_per_block() {
+        let ics = render(TimesheetFormat::Ical, Tz::UTC);
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 3);
+        assert!(ics.contains("DTSTART:20240305T090000Z\r\nDTEND:20240305T093800Z\r\n"));
+        assert!(ics.contains("SUMMARY:acme\\, inc (0.75 h)"));
+        assert!(ics.contains("SUMMARY:No project (0.50 h)"));
+        assert!(ics.lines().all(|l| l.len() <= 75));
+
+        // Blocks of different groups can share a span
+        let mut overlapping = sheet();
+        overlapping.blocks[1].start = overlapping.blocks[0].start;
+        overlapping.blocks[1].end = overlapping.blocks[0].end;
+        let mut out = Vec::new();
+        TimesheetFormat::Ical
+            .write(&overlapping, Tz::UTC, &mut out)
+            .unwrap();
+        let ics = String::from_utf8(out).unwrap();
+        let uids: std::collections::HashSet<_> =
+            ics.lines().filter(|l| l.starts_with("UID:")).collect();
+        assert_eq!(uids.len(), 3);
+
+        // Dropping a block leaves the other events' UIDs alone
+        let mut corrected = sheet();
+        corrected.blocks.remove(0);
+        let mut out = Vec::new();
+        TimesheetFormat::Ical
+            .write(&corrected, Tz::UTC, &mut out)
+            .unwrap();
+        let uid = |ics: &str, n: usize| {
+            ics.lines()
+                .filter(|l| l.starts_with("UID:"))
+                .nth(n)
+                .unwrap()
+                .to_string()
+        };
+        let full = render(TimesheetFormat::Ical, Tz::UTC);
+        assert_eq!(uid(&String::from_utf8(out).unwrap(), 0), uid(&full, 1));
+
+        let json: TimesheetInfo =
+            serde_json::from_str(&render(TimesheetFormat::Json, Tz::UTC)).unwrap();
+        assert_eq!(json, sheet());
+        assert_eq!("ICS".parse(), Ok(TimesheetFormat::Ical));
+        assert!("xlsx".parse::<TimesheetFormat>().is_err());
+    }
+}
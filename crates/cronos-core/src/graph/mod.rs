@@ -15,6 +15,17 @@ pub struct EdgeInfo {
     pub strength: f32,
 }
 
+/// An entity reached by `ContextGraph::ranked_related`, with the path that
+/// explains the connection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RankedEntity {
+    pub entity_id: EntityId,
+    /// Product of edge strengths along `path`, in `(0, 1]`.
+    pub score: f32,
+    /// Edges from the start entity to this one, in traversal order.
+    pub path: Vec<EdgeId>,
+}
+
 impl ContextGraph {
     pub fn new() -> Self {
         Self {
@@ -112,6 +123,116 @@ impl ContextGraph {
             .collect()
     }
 
+    /// Entities within `depth` hops of `entity_id`, strongest connection first.
+    ///
+    /// Edges are followed in either direction. An entity scores the product
+    /// of edge strengths along its strongest path of at most `depth` edges, so
+    /// one weak link ranks a neighbour below a chain of strong ones. When
+    /// `relations` is non-empty only edges with those relations are followed.
+    pub fn ranked_related(
+        &self,
+        entity_id: &EntityId,
+        depth: u8,
+        relations: &[Relation],
+    ) -> Vec<RankedEntity> {
+        let Some(&start) = self.entity_index.get(entity_id) else {
+            return vec![];
+        };
+        let mut ranked: Vec<RankedEntity> = self
+            .strongest_paths(start, depth, relations)
+            .into_iter()
+            .filter(|(idx, _)| *idx != start)
+            .map(|(idx, (score, path))| RankedEntity {
+                entity_id: self.graph[idx],
+                score,
+                path,
+            })
+            .collect();
+        ranked.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then(a.path.len().cmp(&b.path.len()))
+                .then(a.entity_id.0.cmp(&b.entity_id.0))
+        });
+        ranked
+    }
+
+    /// The strongest path of at most `max_depth` edges from `from` to `to`,
+    /// scored like `ranked_related`; `None` when they are not connected
+    /// within that many hops.
+    pub fn connection(
+        &self,
+        from: &EntityId,
+        to: &EntityId,
+        max_depth: u8,
+    ) -> Option<RankedEntity> {
+        let start = *self.entity_index.get(from)?;
+        let end = *self.entity_index.get(to)?;
+        if start == end {
+            return None;
+        }
+        let (score, path) = self.strongest_paths(start, max_depth, &[]).remove(&end)?;
+        Some(RankedEntity {
+            entity_id: *to,
+            score,
+            path,
+        })
+    }
+
+    /// Best score and path to every node reachable from `start` in at most
+    /// `depth` edges, including `start` itself with an empty path.
+    fn strongest_paths(
+        &self,
+        start: NodeIndex,
+        depth: u8,
+        relations: &[Relation],
+    ) -> HashMap<NodeIndex, (f32, Vec<EdgeId>)> {
+        // Bounded-hop relaxation: round k extends the paths that improved in
+        // round k - 1, read from a copy taken before the round so it never
+        // builds on its own updates. Every recorded path has at most `depth`
+        // edges.
+        let mut best: HashMap<NodeIndex, (f32, Vec<EdgeId>)> = HashMap::new();
+        best.insert(start, (1.0, Vec::new()));
+        let mut frontier = vec![start];
+        for _ in 0..depth {
+            let previous = best.clone();
+            let mut improved = Vec::new();
+            for node in frontier {
+                let (score, path) = &previous[&node];
+                let edges = self
+                    .graph
+                    .edges_directed(node, petgraph::Direction::Outgoing)
+                    .map(|e| (e.target(), e.weight()))
+                    .chain(
+                        self.graph
+                            .edges_directed(node, petgraph::Direction::Incoming)
+                            .map(|e| (e.source(), e.weight())),
+                    );
+                for (neighbor, info) in edges {
+                    if !relations.is_empty() && !relations.contains(&info.relation) {
+                        continue;
+                    }
+                    let strength = info.strength.min(1.0);
+                    if strength <= 0.0 || neighbor == start {
+                        continue;
+                    }
+                    let candidate = score * strength;
+                    if best.get(&neighbor).is_some_and(|(s, _)| *s >= candidate) {
+                        continue;
+                    }
+                    let mut via = path.clone();
+                    via.push(info.edge_id);
+                    best.insert(neighbor, (candidate, via));
+                    improved.push(neighbor);
+                }
+            }
+            improved.sort();
+            improved.dedup();
+            frontier = improved;
+        }
+        best
+    }
+
     pub fn entity_count(&self) -> usize {
         self.graph.node_count()
     }
@@ -134,6 +255,7 @@ impl Default for ContextGraph {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_fixtures as fixtures;
 
     #[test]
     fn add_and_query_entities() {
@@ -193,6 +315,119 @@ mod tests {
         assert!(related.contains(&repo));
     }
 
+    #[test]
+    fn ranked_related_prefers_strong_paths_and_explains_them() {
+        let mut g = ContextGraph::new();
+        let [file, project, repo, url, weak] = [(); 5].map(|_| EntityId::new());
+        let belongs = fixtures::edge(file, project, Relation::BelongsTo, 0.9, 1000);
+        let contains = fixtures::edge(project, repo, Relation::Contains, 0.9, 1000);
+        let visited = fixtures::edge(url, file, Relation::RelatedTo, 0.3, 1000);
+        // A direct but weak link loses to the strong two-hop path
+        let direct = fixtures::edge(file, repo, Relation::RelatedTo, 0.2, 1000);
+        let faint = fixtures::edge(url, weak, Relation::RelatedTo, 0.5, 1000);
+        for e in [&belongs, &contains, &visited, &direct, &faint] {
+            g.add_edge(e);
+        }
+
+        let ranked = g.ranked_related(&file, 2, &[]);
+        let ids: Vec<_> = ranked.iter().map(|r| r.entity_id).collect();
+        assert_eq!(ids, vec![project, repo, url, weak]);
+        assert!((ranked[1].score - 0.81).abs() < 1e-6);
+        assert_eq!(ranked[1].path, vec![belongs.id, contains.id]);
+        assert_eq!(ranked[2].path, vec![visited.id]);
+        assert_eq!(ranked[3].path, vec![visited.id, faint.id]);
+
+        // One hop only reaches direct neighbours
+        let ranked = g.ranked_related(&file, 1, &[]);
+        let repo_hit = ranked.iter().find(|r| r.entity_id == repo).unwrap();
+        assert_eq!(repo_hit.path, vec![direct.id]);
+        assert_eq!(ranked.len(), 3);
+
+        let ranked = g.ranked_related(&file, 3, &[Relation::BelongsTo, Relation::Contains]);
+        let ids: Vec<_> = ranked.iter().map(|r| r.entity_id).collect();
+        assert_eq!(ids, vec![project, repo]);
+    }
+
+    /// `s - a - b - c - d` at full strength, plus a weak shortcut `s - b`.
+    /// Nodes are added in order so one round visits `a` before `b`.
+    fn chain_with_shortcut() -> (ContextGraph, [EntityId; 5], [Edge; 5]) {
+        let mut g = ContextGraph::new();
+        let nodes = [(); 5].map(|_| EntityId::new());
+        for id in nodes {
+            g.add_entity(id);
+        }
+        let [s, a, b, c, d] = nodes;
+        let edges = [
+            fixtures::edge(s, a, Relation::RelatedTo, 1.0, 1000),
+            fixtures::edge(a, b, Relation::RelatedTo, 1.0, 1000),
+            fixtures::edge(b, c, Relation::RelatedTo, 1.0, 1000),
+            fixtures::edge(c, d, Relation::RelatedTo, 1.0, 1000),
+            fixtures::edge(s, b, Relation::RelatedTo, 0.1, 1000),
+        ];
+        for e in &edges {
+            g.add_edge(e);
+        }
+        (g, nodes, edges)
+    }
+
+    #[test]
+    fn ranked_related_paths_stay_within_the_depth() {
+        let (g, [s, a, b, c, d], [sa, ab, bc, _, sb]) = chain_with_shortcut();
+        let ranked = g.ranked_related(&s, 2, &[]);
+        assert!(ranked.iter().all(|r| r.path.len() <= 2));
+        let path = |id| {
+            ranked
+                .iter()
+                .find(|r| r.entity_id == id)
+                .map(|r| r.path.clone())
+        };
+        assert_eq!(path(a), Some(vec![sa.id]));
+        assert_eq!(path(b), Some(vec![sa.id, ab.id]));
+        // Only the weak shortcut reaches `c` in two hops, and `d` needs three
+        assert_eq!(path(c), Some(vec![sb.id, bc.id]));
+        assert_eq!(path(d), None);
+    }
+
+    #[test]
+    fn connection_finds_the_strongest_bounded_path() {
+        let mut g = ContextGraph::new();
+        let [url, domain, file, commit, island] = [(); 5].map(|_| EntityId::new());
+        g.add_entity(island);
+        let visited = fixtures::edge(url, file, Relation::RelatedTo, 0.6, 1000);
+        let touched = fixtures::edge(commit, file, Relation::Contains, 0.9, 1000);
+        let belongs = fixtures::edge(url, domain, Relation::BelongsTo, 1.0, 1000);
+        let weak = fixtures::edge(domain, commit, Relation::RelatedTo, 0.1, 1000);
+        for e in [&visited, &touched, &belongs, &weak] {
+            g.add_edge(e);
+        }
+
+        let found = g.connection(&url, &commit, 4).unwrap();
+        assert_eq!(found.entity_id, commit);
+        assert_eq!(found.path, vec![visited.id, touched.id]);
+        assert!((found.score - 0.54).abs() < 1e-6);
+
+        // Too few hops allowed for the strong path, so the weak one wins
+        let found = g.connection(&commit, &domain, 1).unwrap();
+        assert_eq!(found.path, vec![weak.id]);
+
+        assert!(g.connection(&url, &island, 4).is_none());
+        assert!(g.connection(&url, &url, 4).is_none());
+        assert!(g.connection(&url, &EntityId::new(), 4).is_none());
+    }
+
+    #[test]
+    fn connections_stay_within_max_depth() {
+        let (g, [s, _, _, c, d], [sa, ab, bc, cd, sb]) = chain_with_shortcut();
+        let found = g.connection(&s, &c, 2).unwrap();
+        assert_eq!(found.path, vec![sb.id, bc.id]);
+        assert!((found.score - 0.1).abs() < 1e-6);
+        assert!(g.connection(&s, &d, 2).is_none());
+
+        let found = g.connection(&s, &d, 4).unwrap();
+        assert_eq!(found.path, vec![sa.id, ab.id, bc.id, cd.id]);
+        assert_eq!(found.score, 1.0);
+    }
+
     #[test]
     fn rebuild_from_data() {
         let e1 = Entity {
@@ -1,8 +1,17 @@
 use cronos_model::*;
 use std::collections::HashMap;
 
+/// Why the pipeline refused an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    /// The event is malformed (e.g. empty subject identity) and was dropped.
+    Invalid,
+    /// The event repeats one seen within the dedup window.
+    Duplicate,
+}
+
 pub struct IngestPipeline {
-    dedup_cache: HashMap<(String, String), Timestamp>,
+    dedup_cache: HashMap<(String, EventKind, String), Timestamp>,
     dedup_window_ms: u64,
 }
 
@@ -16,21 +25,26 @@ impl IngestPipeline {
 
     /// Returns None if the event is a duplicate, Some(event) if it should be processed
     pub fn process(&mut self, event: Event) -> Option<Event> {
+        self.check(event).ok()
+    }
+
+    /// Like `process`, but reports why an event was rejected.
+    pub fn check(&mut self, event: Event) -> Result<Event, Rejection> {
         if event.subject.identity.is_empty() {
             tracing::warn!(event_id = %event.id, "dropping event with empty subject identity");
-            return None;
+            return Err(Rejection::Invalid);
         }
 
         let source_str = format!("{:?}", event.source);
-        let key = (source_str, event.subject.identity.clone());
+        let key = (source_str, event.kind.clone(), event.subject.identity.clone());
         if let Some(&last_ts) = self.dedup_cache.get(&key) {
             if (event.timestamp - last_ts).unsigned_abs() < self.dedup_window_ms {
                 tracing::debug!(event_id = %event.id, "deduplicating event");
-                return None;
+                return Err(Rejection::Duplicate);
             }
         }
         self.dedup_cache.insert(key, event.timestamp);
-        Some(event)
+        Ok(event)
     }
 
     pub fn prune_cache(&mut self, before: Timestamp) {
@@ -95,6 +109,18 @@ mod tests {
         assert!(pipeline.process(e2).is_some());
     }
 
+    #[test]
+    fn check_reports_rejection_reason() {
+        let mut pipeline = IngestPipeline::new(1000);
+        let empty = make_event(CollectorSource::Filesystem, "", 5000);
+        assert_eq!(pipeline.check(empty).unwrap_err(), Rejection::Invalid);
+
+        let e1 = make_event(CollectorSource::Filesystem, "/src/main.rs", 5000);
+        let e2 = make_event(CollectorSource::Filesystem, "/src/main.rs", 5100);
+        assert!(pipeline.check(e1).is_ok());
+        assert_eq!(pipeline.check(e2).unwrap_err(), Rejection::Duplicate);
+    }
+
     #[test]
     fn different_files_not_deduped() {
         let mut pipeline = IngestPipeline::new(1000);
@@ -104,4 +130,20 @@ mod tests {
         assert!(pipeline.process(e1).is_some());
         assert!(pipeline.process(e2).is_some());
     }
+
+    #[test]
+    fn different_kinds_not_deduped() {
+        let mut pipeline = IngestPipeline::new(1000);
+        let started = Event {
+            kind: EventKind::IdleStarted,
+            ..make_event(CollectorSource::AppMonitor, "laptop", 5000)
+        };
+        let ended = Event {
+            kind: EventKind::IdleEnded,
+            ..make_event(CollectorSource::AppMonitor, "laptop", 5200)
+        };
+
+        assert!(pipeline.process(started).is_some());
+        assert!(pipeline.process(ended).is_some());
+    }
 }
@@ -1,7 +1,18 @@
 pub mod aggregator;
+pub mod attribution;
+pub mod categorize;
 pub mod engine;
+pub mod export;
 pub mod graph;
 pub mod ingest;
 pub mod linker;
+pub mod metrics;
+pub mod query;
 pub mod server;
 pub mod storage;
+pub mod summary;
+pub mod supervisor;
+pub mod timesheet;
+
+#[cfg(test)]
+pub(crate) mod test_fixtures;
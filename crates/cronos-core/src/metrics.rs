@@ -0,0 +1,284 @@
+//! Deep-work metrics: how much tracked time went to long, uninterrupted
+//! stretches on one project, and what broke the rest of it up.
+
+use crate::storage::repo::Session;
+use crate::storage::Repository;
+use cronos_common::config::FocusConfig;
+use cronos_model::{EntityId, Timestamp};
+use cronos_proto::{DeepWorkBlock, FocusReportInfo, InterruptionSource};
+use std::collections::HashMap;
+
+pub struct FocusMetrics {
+    min_block_ms: i64,
+    grace_ms: i64,
+    interruption_categories: Vec<String>,
+}
+
+/// Project work in progress while walking sessions in order.
+struct Run {
+    project: String,
+    project_id: Option<EntityId>,
+    start: Timestamp,
+    end: Timestamp,
+    secs: i64,
+    apps: HashMap<String, i64>,
+}
+
+impl Run {
+    fn start(project: &str, session: &Session) -> Self {
+        let mut run = Self {
+            project: project.to_string(),
+            project_id: session.project_id,
+            start: session.start_time,
+            end: session.end_time,
+            secs: 0,
+            apps: HashMap::new(),
+        };
+        run.add(session);
+        run
+    }
+
+    fn add(&mut self, session: &Session) {
+        self.end = self.end.max(session.end_time);
+        self.secs += session.duration_secs;
+        *self.apps.entry(session.app_name.clone()).or_default() += session.duration_secs;
+        if self.project_id.is_none() {
+            self.project_id = session.project_id;
+        }
+    }
+}
+
+impl FocusMetrics {
+    pub fn new(config: &FocusConfig) -> Self {
+        Self {
+            min_block_ms: config.min_block_ms as i64,
+            grace_ms: config.grace_ms as i64,
+            interruption_categories: config.interruption_categories.clone(),
+        }
+    }
+
+    /// Focus report over the sessions starting in `[from, to)`.
+    pub fn report(
+        &self,
+        repo: &Repository,
+        from: Timestamp,
+        to: Timestamp,
+    ) -> rusqlite::Result<FocusReportInfo> {
+        let sessions = repo.sessions_for_day(from, to)?;
+        Ok(self.measure(&sessions, from, to))
+    }
+
+    /// `sessions` must be ordered by start time.
+    pub(crate) fn measure(&self, sessions: &[Session], from: Timestamp, to: Timestamp) -> FocusReportInfo {
+        let mut blocks = Vec::new();
+        let mut interruptions: HashMap<(String, String), (u64, i64)> = HashMap::new();
+        let mut run: Option<Run> = None;
+
+        for session in sessions {
+            let follows = |run: &Run| session.start_time - run.end <= self.grace_ms;
+            if self.interruption_categories.contains(&session.category) {
+                if let Some(run) = run.take() {
+                    if follows(&run) {
+                        let key = (session.app_name.clone(), session.category.clone());
+                        let entry = interruptions.entry(key).or_default();
+                        entry.0 += 1;
+                        entry.1 += session.duration_secs;
+                    }
+                    self.close(run, &mut blocks);
+                }
+                continue;
+            }
+            match (&session.project, run.as_mut()) {
+                (Some(project), Some(run)) if *project == run.project && follows(run) => {
+                    run.add(session)
+                }
+                // A quick look elsewhere, e.g. at docs in a browser
+                (None, Some(run))
+                    if session.end_time - session.start_time <= self.grace_ms && follows(run) =>
+                {
+                    run.end = run.end.max(session.end_time)
+                }
+                _ => {
+                    if let Some(run) = run.take() {
+                        self.close(run, &mut blocks);
+                    }
+                    run = session.project.as_ref().map(|p| Run::start(p, session));
+                }
+            }
+        }
+        if let Some(run) = run {
+            self.close(run, &mut blocks);
+        }
+        blocks.sort_by(|a, b| b.secs.cmp(&a.secs).then_with(|| a.start.cmp(&b.start)));
+
+        let mut interruptions: Vec<_> = interruptions
+            .into_iter()
+            .map(|((app_name, category), (count, secs))| InterruptionSource {
+                app_name,
+                category,
+                count,
+                secs,
+            })
+            .collect();
+        interruptions.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| b.secs.cmp(&a.secs))
+                .then_with(|| a.app_name.cmp(&b.app_name))
+        });
+
+        let (pieces, context_switches) = self.pieces(sessions);
+        let tracked_secs: i64 = sessions.iter().map(|s| s.duration_secs).sum();
+        let tracked_hours = tracked_secs as f64 / 3600.0;
+        FocusReportInfo {
+            from,
+            to,
+            tracked_secs,
+            focus_secs: blocks.iter().map(|b| b.secs).sum(),
+            blocks,
+            context_switches,
+            switches_per_hour: if tracked_secs > 0 {
+                context_switches as f64 / tracked_hours
+            } else {
+                0.0
+            },
+            fragmentation: fragmentation(&pieces),
+            interruptions,
+        }
+    }
+
+    fn close(&self, run: Run, blocks: &mut Vec<DeepWorkBlock>) {
+        if run.secs * 1000 < self.min_block_ms {
+            return;
+        }
+        let mut apps: Vec<_> = run.apps.into_iter().collect();
+        apps.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        blocks.push(DeepWorkBlock {
+            project: run.project,
+            project_id: run.project_id,
+            start: run.start,
+            end: run.end,
+            secs: run.secs,
+            apps: apps.into_iter().map(|(app, _)| app).collect(),
+        });
+    }
+
+    /// Seconds in each stretch of one context (a project, or an app outside
+    /// any project), and how many times a stretch gave way to another
+    /// without a break.
+    fn pieces(&self, sessions: &[Session]) -> (Vec<i64>, u64) {
+        let context = |s: &Session| match &s.project {
+            Some(project) => (true, project.clone()),
+            None => (false, s.app_name.clone()),
+        };
+        let mut pieces: Vec<i64> = Vec::new();
+        let mut switches = 0;
+        for (i, session) in sessions.iter().enumerate() {
+            if let Some(prev) = i.checked_sub(1).map(|i| &sessions[i]) {
+                if session.start_time - prev.end_time <= self.grace_ms {
+                    if context(prev) == context(session) {
+                        *pieces.last_mut().expect("a piece per earlier session") +=
+                            session.duration_secs;
+                        continue;
+                    }
+                    switches += 1;
+                }
+            }
+            pieces.push(session.duration_secs);
+        }
+        (pieces, switches)
+    }
+}
+
+/// One minus the sum of squared shares of each piece: 0 for a single piece,
+/// approaching 1 for many equal small ones.
+fn fragmentation(pieces: &[i64]) -> f64 {
+    let total: i64 = pieces.iter().sum();
+    if total <= 0 {
+        return 0.0;
+    }
+    let total = total as f64;
+    1.0 - pieces
+        .iter()
+        .map(|&p| (p as f64 / total).powi(2))
+        .sum::<f64>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures as fixtures;
+
+    const MIN: i64 = 60_000;
+
+    fn session(
+        app: &str,
+        category: &str,
+        project: Option<&str>,
+        start_min: i64,
+        mins: i64,
+    ) -> Session {
+        Session {
+            project: project.map(String::from),
+            ..fixtures::session(app, category, start_min * MIN, (start_min + mins) * MIN)
+        }
+    }
+
+    fn metrics() -> FocusMetrics {
+        FocusMetrics::new(&FocusConfig::default())
+    }
+
+    #[test]
+    fn blocks_span_apps_and_brief_detours_but_not_interruptions() {
+        let sessions = [
+            session("code", "coding", Some("cronos"), 0, 20),
+            session("firefox", "browsing", None, 20, 1),
+            session("kitty", "coding", Some("cronos"), 21, 10),
+            session("slack", "communication", None, 31, 3),
+            session("code", "coding", Some("cronos"), 34, 10),
+        ];
+        let report = metrics().measure(&sessions, 0, 60 * MIN);
+
+        assert_eq!(report.blocks.len(), 1);
+        let block = &report.blocks[0];
+        assert_eq!((block.start, block.end, block.secs), (0, 31 * MIN, 30 * 60));
+        assert_eq!(block.apps, ["code", "kitty"]);
+        assert_eq!(report.focus_secs, 30 * 60);
+        assert_eq!(report.tracked_secs, 44 * 60);
+        assert_eq!(
+            report.interruptions,
+            [InterruptionSource {
+                app_name: "slack".to_string(),
+                category: "communication".to_string(),
+                count: 1,
+                secs: 180,
+            }]
+        );
+        // cronos -> firefox -> cronos -> slack -> cronos
+        assert_eq!(report.context_switches, 4);
+    }
+
+    #[test]
+    fn other_projects_and_breaks_end_blocks() {
+        let sessions = [
+            session("code", "coding", Some("cronos"), 0, 30),
+            session("code", "coding", Some("website"), 30, 30),
+            // After lunch
+            session("code", "coding", Some("website"), 120, 10),
+        ];
+        let report = metrics().measure(&sessions, 0, 180 * MIN);
+
+        let projects: Vec<_> = report.blocks.iter().map(|b| b.project.as_str()).collect();
+        assert_eq!(projects, ["cronos", "website"]);
+        assert_eq!(report.context_switches, 1);
+        assert!(report.interruptions.is_empty());
+    }
+
+    #[test]
+    fn fragmentation_grows_with_the_number_of_pieces() {
+        assert_eq!(fragmentation(&[]), 0.0);
+        assert_eq!(fragmentation(&[600]), 0.0);
+        assert_eq!(fragmentation(&[300, 300]), 0.5);
+        assert!(fragmentation(&[60; 10]) > 0.89);
+    }
+}
@@ -0,0 +1,643 @@
+//! Lowering a parsed query to one parameterised SQLite SELECT.
+
+use super::{AggFunc, Aggregate, Expr, Literal, Op, Query, QueryError, Source, Stage};
+use chrono::{NaiveDate, NaiveDateTime};
+use chrono_tz::Tz;
+use cronos_common::tz;
+use cronos_model::{CollectorSource, EntityKind, EventKind, Relation, Timestamp};
+use rusqlite::types::Value;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Rows returned when the query has no `limit` stage.
+pub const DEFAULT_LIMIT: u32 = 100;
+/// Upper bound on `limit`.
+pub const MAX_LIMIT: u32 = 10_000;
+
+/// A compiled query, ready to run against the store.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Compiled {
+    pub sql: String,
+    pub params: Vec<Value>,
+    pub output: Output,
+    /// Zone the `local_hour` and `local_weekday` functions in `sql` use.
+    pub tz: Tz,
+}
+
+/// Shape of the rows `Compiled::sql` selects.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Output {
+    /// `id, timestamp, source, kind, subject_id, metadata` of events.
+    Events,
+    /// `id, kind, name, attributes, first_seen, last_seen` of entities.
+    Entities,
+    /// All session columns, in `sessions_page` order.
+    Sessions,
+    /// Aggregated rows with these column names.
+    Table { columns: Vec<String> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EnumKind {
+    Event,
+    Source,
+    Entity,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Ty {
+    /// JSON-encoded enum column.
+    Enum(EnumKind),
+    Text,
+    /// Milliseconds since the epoch.
+    Time,
+    Int,
+    /// Seconds.
+    Duration,
+    /// 0 = Sunday.
+    Weekday,
+    /// Project path, matched by path or last component.
+    Project,
+}
+
+struct Field {
+    sql: String,
+    ty: Ty,
+}
+
+/// What field names resolve against: the query's source, or the entity a
+/// `linked` stage joins (under `alias`).
+#[derive(Clone, Copy)]
+enum Scope<'a> {
+    Events,
+    Entities(&'a str),
+    Sessions,
+}
+
+/// The hour of `column` in the query's zone. SQLite's `'localtime'` would
+/// use the daemon's process zone rather than the configured one, so this is
+/// a function `Repository::run_query` provides.
+fn local_hour(column: &str) -> String {
+    format!("local_hour({column})")
+}
+
+fn local_weekday(column: &str) -> String {
+    format!("local_weekday({column})")
+}
+
+/// Fields by source:
+///
+/// - events: `kind`, `source`, `time`, `hour`, `weekday`, `subject` (or
+///   `name`), `entity_kind`, `project`
+/// - entities: `kind`, `name`, `time` (or `last_seen`), `first_seen`, `hour`,
+///   `weekday`, `project`
+/// - sessions: `app`, `category`, `title`, `project`, `time` (or `start`),
+///   `end`, `duration`, `events`, `hour`, `weekday`
+fn field(scope: Scope, name: &str) -> Option<Field> {
+    let (sql, ty) = match scope {
+        Scope::Events => match name {
+            "kind" => ("ev.kind".to_string(), Ty::Enum(EnumKind::Event)),
+            "source" => ("ev.source".to_string(), Ty::Enum(EnumKind::Source)),
+            "time" => ("ev.timestamp".to_string(), Ty::Time),
+            "hour" => (local_hour("ev.timestamp"), Ty::Int),
+            "weekday" => (local_weekday("ev.timestamp"), Ty::Weekday),
+            "subject" | "name" => ("s.name".to_string(), Ty::Text),
+            "entity_kind" => ("s.kind".to_string(), Ty::Enum(EnumKind::Entity)),
+            "project" => (
+                "(SELECT p.name FROM event_context c JOIN entities p ON p.id = c.entity_id
+                  WHERE c.event_id = ev.id AND p.kind = '\"project\"' LIMIT 1)"
+                    .to_string(),
+                Ty::Project,
+            ),
+            _ => return None,
+        },
+        Scope::Entities(alias) => match name {
+            "kind" => (format!("{alias}.kind"), Ty::Enum(EnumKind::Entity)),
+            "name" => (format!("{alias}.name"), Ty::Text),
+            "time" | "last_seen" => (format!("{alias}.last_seen"), Ty::Time),
+            "first_seen" => (format!("{alias}.first_seen"), Ty::Time),
+            "hour" => (local_hour(&format!("{alias}.last_seen")), Ty::Int),
+            "weekday" => (local_weekday(&format!("{alias}.last_seen")), Ty::Weekday),
+            "project" => (
+                format!(
+                    "(SELECT p.name FROM edges pe JOIN entities p ON p.id = pe.to_id
+                      WHERE pe.from_id = {alias}.id AND p.kind = '\"project\"' LIMIT 1)"
+                ),
+                Ty::Project,
+            ),
+            _ => return None,
+        },
+        Scope::Sessions => match name {
+            "app" => ("se.app_name".to_string(), Ty::Text),
+            "category" => ("se.category".to_string(), Ty::Text),
+            "title" => ("se.window_titles".to_string(), Ty::Text),
+            "project" => ("se.project".to_string(), Ty::Project),
+            "time" | "start" => ("se.start_time".to_string(), Ty::Time),
+            "end" => ("se.end_time".to_string(), Ty::Time),
+            "duration" => ("se.duration_secs".to_string(), Ty::Duration),
+            "events" => ("se.event_count".to_string(), Ty::Int),
+            "hour" => (local_hour("se.start_time"), Ty::Int),
+            "weekday" => (local_weekday("se.start_time"), Ty::Weekday),
+            _ => return None,
+        },
+    };
+    Some(Field { sql, ty })
+}
+
+fn scope_name(scope: Scope) -> &'static str {
+    match scope {
+        Scope::Events => "events",
+        Scope::Entities("e") => "entities",
+        Scope::Entities(_) => "linked entities",
+        Scope::Sessions => "sessions",
+    }
+}
+
+fn resolve(scope: Scope, name: &str) -> Result<Field, QueryError> {
+    field(scope, name).ok_or_else(|| {
+        QueryError::Invalid(format!("unknown field '{name}' for {}", scope_name(scope)))
+    })
+}
+
+fn invalid(message: impl Into<String>) -> QueryError {
+    QueryError::Invalid(message.into())
+}
+
+/// Lower a parsed query. Stages apply in order: filters first, then at most
+/// one grouping and one aggregate stage; `sort` and `limit` may go anywhere.
+pub(super) fn lower(query: &Query, now: Timestamp, tz: Tz) -> Result<Compiled, QueryError> {
+    let scope = match query.source {
+        Source::Events => Scope::Events,
+        Source::Entities => Scope::Entities("e"),
+        Source::Sessions => Scope::Sessions,
+    };
+    let mut lowering = Lowering {
+        now,
+        tz,
+        params: Vec::new(),
+    };
+    let mut filters = Vec::new();
+    let mut group_by: Option<&[String]> = None;
+    let mut aggregates: Option<&[Aggregate]> = None;
+    let mut sort: Option<(&str, bool)> = None;
+    let mut limit = DEFAULT_LIMIT;
+
+    for stage in &query.stages {
+        let grouped = group_by.is_some() || aggregates.is_some();
+        match stage {
+            Stage::Where(_) | Stage::Linked { .. } if grouped => {
+                return Err(invalid("filters must come before group by and aggregates"));
+            }
+            Stage::Where(expr) => filters.push(lowering.expr(scope, expr)?),
+            Stage::Linked { kind, via, filter } => {
+                filters.push(lowering.linked(query.source, kind, via.as_deref(), filter.as_ref())?)
+            }
+            Stage::GroupBy(_) if grouped => {
+                return Err(invalid("group by must come before aggregates, and only once"));
+            }
+            Stage::GroupBy(fields) => group_by = Some(fields),
+            Stage::Aggregate(_) if aggregates.is_some() => {
+                return Err(invalid("only one aggregate stage is allowed"));
+            }
+            Stage::Aggregate(list) => aggregates = Some(list),
+            Stage::Sort { key, descending } => sort = Some((key, *descending)),
+            Stage::Limit(n) => limit = (*n).min(MAX_LIMIT),
+        }
+    }
+
+    let (from, default_order, columns, output) = match query.source {
+        Source::Events => (
+            "events ev JOIN entities s ON s.id = ev.subject_id",
+            "ev.timestamp DESC, ev.id DESC",
+            "ev.id, ev.timestamp, ev.source, ev.kind, ev.subject_id, ev.metadata",
+            Output::Events,
+        ),
+        Source::Entities => (
+            "entities e",
+            "e.last_seen DESC, e.id DESC",
+            "e.id, e.kind, e.name, e.attributes, e.first_seen, e.last_seen",
+            Output::Entities,
+        ),
+        Source::Sessions => (
+            "sessions se",
+            "se.start_time DESC, se.id DESC",
+            "se.id, se.app_name, se.window_titles, se.project, se.category, se.start_time, \
+             se.end_time, se.duration_secs, se.event_count, se.metadata, se.project_id, \
+             se.project_confidence, se.open",
+            Output::Sessions,
+        ),
+    };
+    let where_sql = if filters.is_empty() {
+        String::new()
+    } else {
+        format!(" WHERE {}", filters.join(" AND "))
+    };
+
+    let sql;
+    let output = if group_by.is_none() && aggregates.is_none() {
+        let order = match sort {
+            Some((key, descending)) => {
+                let field = resolve(scope, key).map_err(|_| {
+                    invalid(format!("cannot sort by '{key}': not a field of {}", scope_name(scope)))
+                })?;
+                format!("{} {}", field.sql, direction(descending))
+            }
+            None => default_order.to_string(),
+        };
+        sql = format!("SELECT {columns} FROM {from}{where_sql} ORDER BY {order} LIMIT ?");
+        output
+    } else {
+        let keys = group_by.unwrap_or_default();
+        let count = [Aggregate {
+            func: AggFunc::Count,
+            field: None,
+        }];
+        let aggregates = aggregates.unwrap_or(&count);
+
+        let mut labels = Vec::new();
+        let mut selects = Vec::new();
+        let mut groups = Vec::new();
+        for key in keys {
+            let field = resolve(scope, key)?;
+            selects.push(format!("{} AS {}", display(&field), quote(key)));
+            groups.push(field.sql);
+            labels.push(key.clone());
+        }
+        for aggregate in aggregates {
+            let label = aggregate.label();
+            selects.push(format!("{} AS {}", aggregate_sql(scope, aggregate)?, quote(&label)));
+            labels.push(label);
+        }
+
+        let order = match sort {
+            Some((key, descending)) if labels.iter().any(|l| l == key) => {
+                format!("{} {}", quote(key), direction(descending))
+            }
+            Some((key, _)) => {
+                return Err(invalid(format!(
+                    "cannot sort by '{key}': sort by a grouped field or an aggregate"
+                )));
+            }
+            // Largest groups first, ties in key order
+            None => format!("{} DESC", quote(&aggregates[0].label())),
+        };
+        let group_sql = if groups.is_empty() {
+            String::new()
+        } else {
+            format!(" GROUP BY {}", groups.join(", "))
+        };
+        sql = format!(
+            "SELECT {} FROM {from}{where_sql}{group_sql} ORDER BY {order} LIMIT ?",
+            selects.join(", ")
+        );
+        Output::Table { columns: labels }
+    };
+
+    let mut params = lowering.params;
+    params.push(Value::Integer(limit.into()));
+    Ok(Compiled { sql, params, output, tz })
+}
+
+fn direction(descending: bool) -> &'static str {
+    if descending {
+        "DESC"
+    } else {
+        "ASC"
+    }
+}
+
+/// Quote a result column name as an SQL identifier.
+fn quote(label: &str) -> String {
+    format!("\"{}\"", label.replace('"', "\"\""))
+}
+
+/// How a field appears in a result table: enums without their JSON quoting.
+fn display(field: &Field) -> String {
+    match field.ty {
+        Ty::Enum(_) => format!("json_extract({}, '$')", field.sql),
+        _ => field.sql.clone(),
+    }
+}
+
+fn aggregate_sql(scope: Scope, aggregate: &Aggregate) -> Result<String, QueryError> {
+    let Some(name) = &aggregate.field else {
+        return Ok("COUNT(*)".to_string());
+    };
+    let field = resolve(scope, name)?;
+    let numeric = matches!(field.ty, Ty::Time | Ty::Int | Ty::Duration);
+    let func = match aggregate.func {
+        AggFunc::Count => "COUNT",
+        AggFunc::Sum if numeric => "SUM",
+        AggFunc::Avg if numeric => "AVG",
+        AggFunc::Min => "MIN",
+        AggFunc::Max => "MAX",
+        AggFunc::Sum | AggFunc::Avg => {
+            return Err(invalid(format!("cannot {}: '{name}' is not numeric", aggregate.label())));
+        }
+    };
+    Ok(format!("{func}({})", display(&field)))
+}
+
+/// Accumulates positional parameters while lowering conditions.
+struct Lowering {
+    now: Timestamp,
+    /// Zone that `today`, `yesterday` and dates are read in.
+    tz: Tz,
+    params: Vec<Value>,
+}
+
+impl Lowering {
+    fn expr(&mut self, scope: Scope, expr: &Expr) -> Result<String, QueryError> {
+        Ok(match expr {
+            Expr::And(a, b) => format!("({} AND {})", self.expr(scope, a)?, self.expr(scope, b)?),
+            Expr::Or(a, b) => format!("({} OR {})", self.expr(scope, a)?, self.expr(scope, b)?),
+            Expr::Not(inner) => format!("NOT {}", self.expr(scope, inner)?),
+            Expr::Compare { field, op, value } => {
+                let field_def = resolve(scope, field)?;
+                self.compare(field, &field_def, *op, value)?
+            }
+            Expr::In { field, values } => {
+                let field_def = resolve(scope, field)?;
+                let alternatives = values
+                    .iter()
+                    .map(|value| self.compare(field, &field_def, Op::Eq, value))
+                    .collect::<Result<Vec<_>, _>>()?;
+                format!("({})", alternatives.join(" OR "))
+            }
+        })
+    }
+
+    fn compare(
+        &mut self,
+        name: &str,
+        field: &Field,
+        op: Op,
+        value: &Literal,
+    ) -> Result<String, QueryError> {
+        let column = &field.sql;
+        let sql_op = match op {
+            Op::Eq => "=",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::Contains => "LIKE",
+        };
+        let unsupported = || invalid(format!("'{name}' does not support this comparison"));
+        match field.ty {
+            Ty::Enum(kind) => {
+                let encoded = encode_enum(kind, name, value)?;
+                if !matches!(op, Op::Eq | Op::Ne) {
+                    return Err(unsupported());
+                }
+                self.params.push(Value::Text(encoded));
+                Ok(format!("{column} {sql_op} ?"))
+            }
+            Ty::Text => {
+                let text = text(name, value)?;
+                if op == Op::Contains {
+                    self.params.push(Value::Text(like_pattern(&text)));
+                    return Ok(format!("{column} LIKE ? ESCAPE '\\'"));
+                }
+                self.params.push(Value::Text(text));
+                Ok(format!("{column} {sql_op} ?"))
+            }
+            Ty::Project => {
+                let text = text(name, value)?;
+                match op {
+                    Op::Contains => {
+                        self.params.push(Value::Text(like_pattern(&text)));
+                        Ok(format!("{column} LIKE ? ESCAPE '\\'"))
+                    }
+                    Op::Eq | Op::Ne => {
+                        self.params.extend(std::iter::repeat_n(Value::Text(text), 3));
+                        let matches = format!(
+                            "COALESCE({column} = ? OR substr({column}, -length(?) - 1) = '/' || ?, 0)"
+                        );
+                        Ok(if op == Op::Eq {
+                            matches
+                        } else {
+                            format!("NOT {matches}")
+                        })
+                    }
+                    _ => Err(unsupported()),
+                }
+            }
+            Ty::Time | Ty::Int | Ty::Duration | Ty::Weekday => {
+                if op == Op::Contains {
+                    return Err(unsupported());
+                }
+                let n = match field.ty {
+                    Ty::Time => self.time(name, value)?,
+                    Ty::Duration => match value {
+                        Literal::Int(secs) | Literal::Duration(secs) => secs.abs(),
+                        _ => return Err(invalid(format!("'{name}' takes a duration like 30m"))),
+                    },
+                    Ty::Weekday => weekday(value)?,
+                    _ => match value {
+                        Literal::Int(n) => *n,
+                        _ => return Err(invalid(format!("'{name}' takes a number"))),
+                    },
+                };
+                self.params.push(Value::Integer(n));
+                Ok(format!("{column} {sql_op} ?"))
+            }
+        }
+    }
+
+    /// Resolve a time literal to milliseconds since the epoch.
+    fn time(&self, name: &str, value: &Literal) -> Result<Timestamp, QueryError> {
+        let today = tz::date_of(self.tz, self.now);
+        let resolved = match value {
+            Literal::Duration(secs) => Some(self.now + secs * 1000),
+            Literal::Int(ms) => Some(*ms),
+            Literal::Ident(word) => match word.to_ascii_lowercase().as_str() {
+                "now" => Some(self.now),
+                "today" => Some(tz::day_start(self.tz, today)),
+                "yesterday" => today.pred_opt().map(|d| tz::day_start(self.tz, d)),
+                _ => None,
+            },
+            Literal::Str(text) => NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+                .or_else(|| NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").ok())
+                .or_else(|| NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M").ok())
+                .and_then(|naive| tz::local_millis(self.tz, naive)),
+        };
+        resolved.ok_or_else(|| {
+            invalid(format!(
+                "'{name}' takes a time like -7d, today or \"2024-05-01 18:00\""
+            ))
+        })
+    }
+
+    /// `EXISTS` over edges from the row's entity to a matching entity `l`.
+    fn linked(
+        &mut self,
+        source: Source,
+        kind: &str,
+        via: Option<&str>,
+        filter: Option<&Expr>,
+    ) -> Result<String, QueryError> {
+        let anchor = match source {
+            Source::Events => "ev.subject_id",
+            Source::Entities => "e.id",
+            Source::Sessions => return Err(invalid("linked applies to events and entities")),
+        };
+        let mut sql = format!(
+            "EXISTS (SELECT 1 FROM edges ed
+             JOIN entities l ON l.id = CASE WHEN ed.from_id = {anchor} THEN ed.to_id ELSE ed.from_id END
+             WHERE (ed.from_id = {anchor} OR ed.to_id = {anchor}) AND l.kind = ?"
+        );
+        self.params
+            .push(Value::Text(encode::<EntityKind>(kind, "entity kind")?));
+        if let Some(relation) = via {
+            sql.push_str(" AND ed.relation = ?");
+            self.params
+                .push(Value::Text(encode::<Relation>(relation, "relation")?));
+        }
+        if let Some(filter) = filter {
+            sql.push_str(&format!(" AND {}", self.expr(Scope::Entities("l"), filter)?));
+        }
+        sql.push(')');
+        Ok(sql)
+    }
+}
+
+fn text(name: &str, value: &Literal) -> Result<String, QueryError> {
+    match value {
+        Literal::Str(text) | Literal::Ident(text) => Ok(text.clone()),
+        Literal::Int(n) => Ok(n.to_string()),
+        Literal::Duration(_) => Err(invalid(format!("'{name}' takes text"))),
+    }
+}
+
+/// `%text%` with LIKE wildcards in `text` escaped.
+fn like_pattern(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{escaped}%")
+}
+
+fn weekday(value: &Literal) -> Result<i64, QueryError> {
+    const DAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+    match value {
+        Literal::Int(n @ 0..=6) => Ok(*n),
+        Literal::Ident(day) | Literal::Str(day) => {
+            let day = day.to_ascii_lowercase();
+            DAYS.iter()
+                .position(|d| day.starts_with(d))
+                .map(|i| i as i64)
+                .ok_or_else(|| invalid(format!("unknown weekday '{day}'")))
+        }
+        _ => Err(invalid("weekday takes a day name or 0 (Sunday) to 6")),
+    }
+}
+
+fn encode_enum(kind: EnumKind, name: &str, value: &Literal) -> Result<String, QueryError> {
+    let (Literal::Ident(word) | Literal::Str(word)) = value else {
+        return Err(invalid(format!("'{name}' takes a name like file_modified")));
+    };
+    match kind {
+        EnumKind::Event => encode::<EventKind>(word, "event kind"),
+        EnumKind::Source => encode::<CollectorSource>(word, "source"),
+        EnumKind::Entity => encode::<EntityKind>(word, "entity kind"),
+    }
+}
+
+/// The stored JSON form of the enum variant named `word`.
+fn encode<T: DeserializeOwned + Serialize>(word: &str, what: &str) -> Result<String, QueryError> {
+    let value: T = serde_json::from_value(serde_json::Value::String(word.to_ascii_lowercase()))
+        .map_err(|_| invalid(format!("unknown {what} '{word}'")))?;
+    Ok(serde_json::to_string(&value).expect("enum serializes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: Timestamp = 1_700_000_000_000;
+
+    fn compile(src: &str, now: Timestamp) -> Result<Compiled, QueryError> {
+        super::super::compile(src, now, Tz::UTC)
+    }
+
+    #[test]
+    fn compiles_filters_to_parameters() {
+        let compiled = compile(
+            "events where kind in [file_modified, file_created] and time >= -1h | limit 5",
+            NOW,
+        )
+        .unwrap();
+        assert_eq!(compiled.output, Output::Events);
+        assert_eq!(
+            compiled.params,
+            vec![
+                Value::Text("\"file_modified\"".into()),
+                Value::Text("\"file_created\"".into()),
+                Value::Integer(NOW - 3_600_000),
+                Value::Integer(5),
+            ]
+        );
+        assert!(compiled.sql.contains("ev.timestamp >= ?"));
+    }
+
+    #[test]
+    fn grouping_produces_a_table() {
+        let compiled = compile(
+            "sessions | group by app | sum(duration), count | sort count desc | limit 100000",
+            NOW,
+        )
+        .unwrap();
+        assert_eq!(
+            compiled.output,
+            Output::Table {
+                columns: vec!["app".into(), "sum(duration)".into(), "count".into()]
+            }
+        );
+        assert!(compiled.sql.contains("GROUP BY se.app_name"));
+        assert!(compiled.sql.contains("ORDER BY \"count\" DESC"));
+        assert_eq!(compiled.params, vec![Value::Integer(MAX_LIMIT.into())]);
+    }
+
+    #[test]
+    fn rejects_unknown_names_and_misplaced_stages() {
+        let err = |src| compile(src, NOW).unwrap_err().to_string();
+        assert_eq!(err("events where colour = red"), "unknown field 'colour' for events");
+        assert_eq!(err("events where kind = teleported"), "unknown event kind 'teleported'");
+        assert_eq!(
+            err("events | linked url where colour = red"),
+            "unknown field 'colour' for linked entities"
+        );
+        assert_eq!(err("sessions | linked url"), "linked applies to events and entities");
+        assert_eq!(
+            err("sessions | count | where app = code"),
+            "filters must come before group by and aggregates"
+        );
+        assert_eq!(err("sessions | sum(app)"), "cannot sum(app): 'app' is not numeric");
+        assert_eq!(
+            err("sessions | group by app | sort duration"),
+            "cannot sort by 'duration': sort by a grouped field or an aggregate"
+        );
+        assert!(err("events where time > \"last tuesday\"").starts_with("'time' takes a time"));
+    }
+
+    #[test]
+    fn days_start_at_midnight_in_the_given_zone() {
+        let la: Tz = "America/Los_Angeles".parse().unwrap();
+        let params = |src| super::super::compile(src, NOW, la).unwrap().params;
+        // NOW is 2023-11-14 14:13 in Los Angeles, 22:13 UTC
+        assert_eq!(params("sessions where time >= today")[0], Value::Integer(1_699_948_800_000));
+        assert_eq!(
+            params("sessions where time >= yesterday")[0],
+            Value::Integer(1_699_862_400_000)
+        );
+        assert_eq!(
+            params("sessions where time >= \"2023-11-14\"")[0],
+            Value::Integer(1_699_948_800_000)
+        );
+    }
+}
@@ -0,0 +1,148 @@
+//! A small pipeline language for asking questions about recorded activity.
+//!
+//! ```text
+//! events where kind = file_modified and project = "cronos" and time >= -7d and hour >= 18
+//!   | linked url
+//!
+//! sessions where time >= today | group by app | sum(duration), count | sort sum(duration) desc
+//! ```
+//!
+//! A query names a source (`events`, `entities` or `sessions`) followed by
+//! stages separated by `|`:
+//!
+//! - `where <cond>` keeps rows matching `<cond>`. A condition compares a field
+//!   with `=`, `!=`, `<`, `<=`, `>`, `>=`, `~` (contains) or `in [a, b]`, and
+//!   conditions combine with `and`, `or`, `not` and parentheses.
+//! - `linked <entity kind> [via <relation>] [where <cond>]` keeps events or
+//!   entities whose entity shares an edge with an entity of that kind; the
+//!   condition applies to the linked entity.
+//! - `group by <field>, ...` groups rows for the aggregate stage after it.
+//! - `count`, `sum(f)`, `avg(f)`, `min(f)`, `max(f)` (comma separated) turn the
+//!   rows into a table: one row per group, or one row when not grouped. A
+//!   `group by` without aggregates counts.
+//! - `sort <field or aggregate> [asc|desc]` and `limit <n>`.
+//!
+//! Times take offsets from now (`-7d`, `-3h`, `-30m`), `now`, `today`,
+//! `yesterday`, or a local date or time (`"2024-05-01"`, `"2024-05-01 18:00"`).
+//! Durations take `90s`, `30m`, `2h`. The fields of each source are listed
+//! in [`compile`].
+
+mod compile;
+mod parser;
+
+pub use compile::{Compiled, Output, DEFAULT_LIMIT, MAX_LIMIT};
+pub use parser::parse;
+
+use cronos_model::Timestamp;
+
+/// Parse and compile `src` into a single SELECT. Relative times resolve
+/// against `now`, and days start at midnight in `tz`.
+pub fn compile(src: &str, now: Timestamp, tz: chrono_tz::Tz) -> Result<Compiled, QueryError> {
+    compile::lower(&parse(src)?, now, tz)
+}
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum QueryError {
+    #[error("syntax error at column {column}: {message}")]
+    Syntax { column: usize, message: String },
+    #[error("{0}")]
+    Invalid(String),
+}
+
+// ─── Syntax tree ─────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Events,
+    Entities,
+    Sessions,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    pub source: Source,
+    pub stages: Vec<Stage>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stage {
+    Where(Expr),
+    Linked {
+        kind: String,
+        via: Option<String>,
+        filter: Option<Expr>,
+    },
+    GroupBy(Vec<String>),
+    Aggregate(Vec<Aggregate>),
+    Sort { key: String, descending: bool },
+    Limit(u32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare {
+        field: String,
+        op: Op,
+        value: Literal,
+    },
+    In {
+        field: String,
+        values: Vec<Literal>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Ident(String),
+    Str(String),
+    Int(i64),
+    /// A signed duration in seconds, written like `-7d` or `30m`.
+    Duration(i64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggFunc {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aggregate {
+    pub func: AggFunc,
+    /// `None` only for `count`.
+    pub field: Option<String>,
+}
+
+impl Aggregate {
+    /// Column name in the result, as written in the query: `count`, `sum(duration)`.
+    pub fn label(&self) -> String {
+        let func = match self.func {
+            AggFunc::Count => "count",
+            AggFunc::Sum => "sum",
+            AggFunc::Avg => "avg",
+            AggFunc::Min => "min",
+            AggFunc::Max => "max",
+        };
+        match &self.field {
+            Some(field) => format!("{func}({field})"),
+            None => func.to_string(),
+        }
+    }
+}
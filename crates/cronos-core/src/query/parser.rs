@@ -0,0 +1,471 @@
+//! Tokenizer and recursive-descent parser for the query language.
+
+use super::{AggFunc, Aggregate, Expr, Literal, Op, Query, QueryError, Source, Stage};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Ident(String),
+    Str(String),
+    Int(i64),
+    Duration(i64),
+    Sym(&'static str),
+    End,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    tok: Tok,
+    /// 1-based column of the token's first character.
+    column: usize,
+}
+
+const SYMBOLS: &[&str] = &["!=", "<=", ">=", "|", "(", ")", "[", "]", ",", "=", "<", ">", "~"];
+
+fn syntax(column: usize, message: impl Into<String>) -> QueryError {
+    QueryError::Syntax {
+        column,
+        message: message.into(),
+    }
+}
+
+fn tokenize(src: &str) -> Result<Vec<Token>, QueryError> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '"' || c == '\'' {
+            let mut text = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err(syntax(column, "unterminated string")),
+                    Some('\\') if chars.get(i + 1).is_some() => {
+                        text.push(chars[i + 1]);
+                        i += 2;
+                    }
+                    Some(&q) if q == c => {
+                        i += 1;
+                        break;
+                    }
+                    Some(&other) => {
+                        text.push(other);
+                        i += 1;
+                    }
+                }
+            }
+            tokens.push(Token { tok: Tok::Str(text), column });
+        } else if c.is_ascii_digit() || (c == '-' && chars.get(i + 1).is_some_and(char::is_ascii_digit)) {
+            let start = i;
+            i += 1;
+            while chars.get(i).is_some_and(char::is_ascii_digit) {
+                i += 1;
+            }
+            let digits: String = chars[start..i].iter().collect();
+            let n: i64 = digits
+                .parse()
+                .map_err(|_| syntax(column, format!("number out of range: {digits}")))?;
+            let unit = match chars.get(i) {
+                Some('s') => Some(1),
+                Some('m') => Some(60),
+                Some('h') => Some(3600),
+                Some('d') => Some(86_400),
+                Some('w') => Some(7 * 86_400),
+                _ => None,
+            };
+            let tok = match unit {
+                Some(secs) if !chars.get(i + 1).is_some_and(|c| c.is_alphanumeric()) => {
+                    i += 1;
+                    Tok::Duration(n.saturating_mul(secs))
+                }
+                _ if chars.get(i).is_some_and(|c| c.is_alphabetic()) => {
+                    return Err(syntax(column, "expected a number or a duration like 7d"));
+                }
+                _ => Tok::Int(n),
+            };
+            tokens.push(Token { tok, column });
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            // Hyphens and dots allow bare names like cronos-core or main.rs
+            while chars
+                .get(i)
+                .is_some_and(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'))
+            {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            tokens.push(Token { tok: Tok::Ident(word), column });
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let Some(sym) = SYMBOLS.iter().find(|s| rest.starts_with(**s)) else {
+                return Err(syntax(column, format!("unexpected character '{c}'")));
+            };
+            i += sym.len();
+            tokens.push(Token { tok: Tok::Sym(sym), column });
+        }
+    }
+    tokens.push(Token {
+        tok: Tok::End,
+        column: chars.len() + 1,
+    });
+    Ok(tokens)
+}
+
+/// Parse a query into its syntax tree without checking field names.
+pub fn parse(src: &str) -> Result<Query, QueryError> {
+    let mut parser = Parser {
+        tokens: tokenize(src)?,
+        pos: 0,
+    };
+    let query = parser.query()?;
+    parser.expect_end()?;
+    Ok(query)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Tok {
+        &self.tokens[self.pos].tok
+    }
+
+    fn column(&self) -> usize {
+        self.tokens[self.pos].column
+    }
+
+    fn next(&mut self) -> Tok {
+        let tok = self.tokens[self.pos].tok.clone();
+        if tok != Tok::End {
+            self.pos += 1;
+        }
+        tok
+    }
+
+    fn error(&self, message: impl Into<String>) -> QueryError {
+        syntax(self.column(), message)
+    }
+
+    /// Consume the keyword `word` (case-insensitive) if it is next.
+    fn keyword(&mut self, word: &str) -> bool {
+        match self.peek() {
+            Tok::Ident(w) if w.eq_ignore_ascii_case(word) => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn symbol(&mut self, sym: &str) -> bool {
+        if matches!(self.peek(), Tok::Sym(s) if *s == sym) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_symbol(&mut self, sym: &str) -> Result<(), QueryError> {
+        if self.symbol(sym) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected '{sym}'")))
+        }
+    }
+
+    fn expect_end(&self) -> Result<(), QueryError> {
+        match self.peek() {
+            Tok::End => Ok(()),
+            _ => Err(self.error("expected '|' or end of query")),
+        }
+    }
+
+    fn ident(&mut self, what: &str) -> Result<String, QueryError> {
+        match self.peek().clone() {
+            Tok::Ident(word) => {
+                self.pos += 1;
+                Ok(word.to_ascii_lowercase())
+            }
+            _ => Err(self.error(format!("expected {what}"))),
+        }
+    }
+
+    fn query(&mut self) -> Result<Query, QueryError> {
+        let source = match self.ident("events, entities or sessions")?.as_str() {
+            "events" => Source::Events,
+            "entities" => Source::Entities,
+            "sessions" => Source::Sessions,
+            other => {
+                return Err(syntax(
+                    1,
+                    format!("unknown source '{other}', expected events, entities or sessions"),
+                ))
+            }
+        };
+        let mut stages = Vec::new();
+        if self.keyword("where") {
+            stages.push(Stage::Where(self.expr()?));
+        }
+        while self.symbol("|") {
+            stages.push(self.stage()?);
+        }
+        Ok(Query { source, stages })
+    }
+
+    fn stage(&mut self) -> Result<Stage, QueryError> {
+        let column = self.column();
+        let word = self.ident("a stage")?;
+        match word.as_str() {
+            "where" => Ok(Stage::Where(self.expr()?)),
+            "linked" => {
+                let kind = self.ident("an entity kind")?;
+                let via = if self.keyword("via") {
+                    Some(self.ident("a relation")?)
+                } else {
+                    None
+                };
+                let filter = if self.keyword("where") {
+                    Some(self.expr()?)
+                } else {
+                    None
+                };
+                Ok(Stage::Linked { kind, via, filter })
+            }
+            "group" => {
+                if !self.keyword("by") {
+                    return Err(self.error("expected 'by'"));
+                }
+                let mut fields = vec![self.ident("a field")?];
+                while self.symbol(",") {
+                    fields.push(self.ident("a field")?);
+                }
+                Ok(Stage::GroupBy(fields))
+            }
+            "sort" => {
+                let key = self.sort_key()?;
+                let descending = if self.keyword("desc") {
+                    true
+                } else {
+                    self.keyword("asc");
+                    false
+                };
+                Ok(Stage::Sort { key, descending })
+            }
+            "limit" => match self.next() {
+                Tok::Int(n) if n > 0 => Ok(Stage::Limit(n.min(u32::MAX.into()) as u32)),
+                _ => Err(syntax(column, "limit takes a positive number")),
+            },
+            _ => {
+                // Anything else must be the aggregate list.
+                self.pos -= 1;
+                let mut aggregates = vec![self.aggregate()?];
+                while self.symbol(",") {
+                    aggregates.push(self.aggregate()?);
+                }
+                Ok(Stage::Aggregate(aggregates))
+            }
+        }
+    }
+
+    fn aggregate(&mut self) -> Result<Aggregate, QueryError> {
+        let column = self.column();
+        let func = match self.ident("a stage")?.as_str() {
+            "count" => return Ok(Aggregate { func: AggFunc::Count, field: None }),
+            "sum" => AggFunc::Sum,
+            "avg" => AggFunc::Avg,
+            "min" => AggFunc::Min,
+            "max" => AggFunc::Max,
+            other => {
+                return Err(syntax(
+                    column,
+                    format!(
+                        "unknown stage '{other}', expected where, linked, group by, sort, limit or an aggregate"
+                    ),
+                ))
+            }
+        };
+        self.expect_symbol("(")?;
+        let field = self.ident("a field")?;
+        self.expect_symbol(")")?;
+        Ok(Aggregate {
+            func,
+            field: Some(field),
+        })
+    }
+
+    /// A field name, or an aggregate written as in its stage.
+    fn sort_key(&mut self) -> Result<String, QueryError> {
+        let word = self.ident("a field or aggregate")?;
+        if self.symbol("(") {
+            let field = self.ident("a field")?;
+            self.expect_symbol(")")?;
+            return Ok(format!("{word}({field})"));
+        }
+        Ok(word)
+    }
+
+    fn expr(&mut self) -> Result<Expr, QueryError> {
+        let mut left = self.and_expr()?;
+        while self.keyword("or") {
+            let right = self.and_expr()?;
+            left = Expr::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn and_expr(&mut self) -> Result<Expr, QueryError> {
+        let mut left = self.unary()?;
+        while self.keyword("and") {
+            let right = self.unary()?;
+            left = Expr::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, QueryError> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.symbol("(") {
+            let inner = self.expr()?;
+            self.expect_symbol(")")?;
+            return Ok(inner);
+        }
+        let field = self.ident("a field")?;
+        if self.keyword("in") {
+            self.expect_symbol("[")?;
+            let mut values = vec![self.literal()?];
+            while self.symbol(",") {
+                values.push(self.literal()?);
+            }
+            self.expect_symbol("]")?;
+            return Ok(Expr::In { field, values });
+        }
+        let op = match self.next() {
+            Tok::Sym("=") => Op::Eq,
+            Tok::Sym("!=") => Op::Ne,
+            Tok::Sym("<") => Op::Lt,
+            Tok::Sym("<=") => Op::Le,
+            Tok::Sym(">") => Op::Gt,
+            Tok::Sym(">=") => Op::Ge,
+            Tok::Sym("~") => Op::Contains,
+            _ => {
+                self.pos -= 1;
+                return Err(self.error("expected a comparison (=, !=, <, <=, >, >=, ~ or in)"));
+            }
+        };
+        let value = self.literal()?;
+        Ok(Expr::Compare { field, op, value })
+    }
+
+    fn literal(&mut self) -> Result<Literal, QueryError> {
+        let literal = match self.peek().clone() {
+            Tok::Ident(word) => Literal::Ident(word),
+            Tok::Str(text) => Literal::Str(text),
+            Tok::Int(n) => Literal::Int(n),
+            Tok::Duration(secs) => Literal::Duration(secs),
+            _ => return Err(self.error("expected a value")),
+        };
+        self.pos += 1;
+        Ok(literal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compare(field: &str, op: Op, value: Literal) -> Expr {
+        Expr::Compare {
+            field: field.to_string(),
+            op,
+            value,
+        }
+    }
+
+    #[test]
+    fn parses_filters_and_pipeline_stages() {
+        let query = parse(
+            "events where kind = file_modified and (project = \"cronos\" or time >= -7d) \
+             | linked url via references where name ~ 'github' | sort time desc | limit 20",
+        )
+        .unwrap();
+        assert_eq!(query.source, Source::Events);
+        assert_eq!(
+            query.stages,
+            vec![
+                Stage::Where(Expr::And(
+                    Box::new(compare("kind", Op::Eq, Literal::Ident("file_modified".into()))),
+                    Box::new(Expr::Or(
+                        Box::new(compare("project", Op::Eq, Literal::Str("cronos".into()))),
+                        Box::new(compare("time", Op::Ge, Literal::Duration(-7 * 86_400))),
+                    )),
+                )),
+                Stage::Linked {
+                    kind: "url".into(),
+                    via: Some("references".into()),
+                    filter: Some(compare("name", Op::Contains, Literal::Str("github".into()))),
+                },
+                Stage::Sort {
+                    key: "time".into(),
+                    descending: true,
+                },
+                Stage::Limit(20),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_grouping_and_aggregates() {
+        let query =
+            parse("sessions | group by app, category | sum(duration), count | sort sum(duration)")
+                .unwrap();
+        assert_eq!(
+            query.stages,
+            vec![
+                Stage::GroupBy(vec!["app".into(), "category".into()]),
+                Stage::Aggregate(vec![
+                    Aggregate {
+                        func: AggFunc::Sum,
+                        field: Some("duration".into()),
+                    },
+                    Aggregate {
+                        func: AggFunc::Count,
+                        field: None,
+                    },
+                ]),
+                Stage::Sort {
+                    key: "sum(duration)".into(),
+                    descending: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_the_column_of_syntax_errors() {
+        assert_eq!(
+            parse("events where kind file_modified").unwrap_err(),
+            QueryError::Syntax {
+                column: 19,
+                message: "expected a comparison (=, !=, <, <=, >, >=, ~ or in)".into(),
+            }
+        );
+        assert!(matches!(
+            parse("events | frobnicate").unwrap_err(),
+            QueryError::Syntax { column: 10, .. }
+        ));
+        assert!(matches!(
+            parse("events where name = \"open").unwrap_err(),
+            QueryError::Syntax { column: 21, .. }
+        ));
+        assert!(parse("logs").is_err());
+        assert!(parse("events where hour >= 18 )").is_err());
+    }
+}
@@ -1,8 +1,15 @@
-use crate::engine::Engine;
-use cronos_proto::{read_frame, write_frame};
+pub mod tls;
+
+use crate::engine::{ConnectionState, Engine};
+use cronos_proto::{
+    into_chunks, read_frame, write_frame_with, ErrorCode, FrameError, Message,
+    CHUNK_TARGET_BYTES, FEATURE_CHUNKED_RESULTS,
+};
 use std::path::Path;
 use std::sync::Arc;
-use tokio::net::UnixListener;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, UnixListener};
+use tokio_rustls::TlsAcceptor;
 
 pub async fn run(engine: Arc<Engine>, socket_path: &Path) -> anyhow::Result<()> {
     if let Some(parent) = socket_path.parent() {
@@ -14,23 +21,103 @@ pub async fn run(engine: Arc<Engine>, socket_path: &Path) -> anyhow::Result<()>
 
     loop {
         let (stream, _addr) = listener.accept().await?;
+        let uid = match stream.peer_cred() {
+            Ok(cred) => cred.uid(),
+            Err(e) => {
+                tracing::warn!("cannot read peer credentials, dropping connection: {}", e);
+                continue;
+            }
+        };
+        if !engine.accepts_local_peer(uid) {
+            tracing::warn!(uid, "rejecting unix socket peer with a different uid");
+            continue;
+        }
+        let conn = engine.local_connection();
         let engine = Arc::clone(&engine);
         tokio::spawn(async move {
-            if let Err(e) = handle_connection(engine, stream).await {
+            if let Err(e) = handle_connection(engine, stream, conn).await {
                 tracing::debug!("connection closed: {}", e);
             }
         });
     }
 }
 
-async fn handle_connection(
+/// Serve the protocol on a TCP listener, optionally wrapped in TLS.
+///
+/// Peers start without capabilities and must present a token through
+/// `Authenticate` or `CollectorHandshake`, unless the acceptor verified a
+/// client certificate.
+pub async fn run_tcp(
     engine: Arc<Engine>,
-    stream: tokio::net::UnixStream,
+    listener: TcpListener,
+    tls: Option<TlsAcceptor>,
 ) -> anyhow::Result<()> {
-    let (mut reader, mut writer) = stream.into_split();
+    tracing::info!(addr = %listener.local_addr()?, tls = tls.is_some(), "listening on tcp");
+
     loop {
-        let msg = read_frame(&mut reader).await?;
-        let response = engine.handle_message(msg);
-        write_frame(&mut writer, &response).await?;
+        let (stream, addr) = listener.accept().await?;
+        // Only latency suffers without it, so keep serving the peer
+        if let Err(e) = stream.set_nodelay(true) {
+            tracing::warn!(%addr, "cannot disable Nagle's algorithm: {}", e);
+        }
+        let engine = Arc::clone(&engine);
+        let tls = tls.clone();
+        tokio::spawn(async move {
+            let result = match tls {
+                Some(acceptor) => match acceptor.accept(stream).await {
+                    Ok(stream) => {
+                        let verified = stream.get_ref().1.peer_certificates().is_some();
+                        let conn = engine.remote_connection(verified);
+                        handle_connection(engine, stream, conn).await
+                    }
+                    Err(e) => Err(e.into()),
+                },
+                None => {
+                    let conn = engine.remote_connection(false);
+                    handle_connection(engine, stream, conn).await
+                }
+            };
+            if let Err(e) = result {
+                tracing::debug!(%addr, "tcp connection closed: {}", e);
+            }
+        });
+    }
+}
+
+async fn handle_connection<S: AsyncRead + AsyncWrite>(
+    engine: Arc<Engine>,
+    stream: S,
+    mut conn: ConnectionState,
+) -> anyhow::Result<()> {
+    let (mut reader, mut writer) = tokio::io::split(stream);
+    let result: anyhow::Result<()> = async {
+        loop {
+            let msg = read_frame(&mut reader).await?;
+            let response = engine.handle_connection_message(&mut conn, msg);
+            let request_id = response.id.clone();
+            let frames = if conn.has_feature(FEATURE_CHUNKED_RESULTS) {
+                into_chunks(response, conn.codec, CHUNK_TARGET_BYTES)?
+            } else {
+                vec![response]
+            };
+            for frame in frames {
+                match write_frame_with(&mut writer, &frame, conn.codec).await {
+                    Err(FrameError::TooLarge { size }) => {
+                        // Nothing was written; tell the peer instead of dropping it.
+                        let error = Message::error(
+                            request_id.clone(),
+                            ErrorCode::BadRequest,
+                            format!("response of {size} bytes exceeds the frame limit; negotiate chunked_results or narrow the query"),
+                        );
+                        write_frame_with(&mut writer, &error, conn.codec).await?;
+                        break;
+                    }
+                    result => result?,
+                }
+            }
+        }
     }
+    .await;
+    engine.collector_disconnected(&conn);
+    result
 }
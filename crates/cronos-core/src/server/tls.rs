@@ -0,0 +1,47 @@
+use cronos_common::config::TcpConfig;
+use cronos_proto::transport::{crypto_provider, load_certs, load_key};
+use std::path::Path;
+use std::sync::Arc;
+use tokio_rustls::TlsAcceptor;
+
+/// Build a TLS acceptor from the listener config, or `None` for plaintext TCP.
+///
+/// When `client_ca` is set, clients must present a certificate signed by it
+/// (mutual TLS); such connections are treated as authenticated.
+pub fn acceptor(config: &TcpConfig) -> anyhow::Result<Option<TlsAcceptor>> {
+    if config.tls_cert.is_empty() {
+        return Ok(None);
+    }
+    let certs = load_certs(Path::new(&config.tls_cert))?;
+    let key = load_key(Path::new(&config.tls_key))?;
+
+    let builder = rustls::ServerConfig::builder_with_provider(crypto_provider())
+        .with_safe_default_protocol_versions()?;
+    let builder = if config.client_ca.is_empty() {
+        builder.with_no_client_auth()
+    } else {
+        let mut roots = rustls::RootCertStore::empty();
+        for cert in load_certs(Path::new(&config.client_ca))? {
+            roots.add(cert)?;
+        }
+        let verifier = rustls::server::WebPkiClientVerifier::builder_with_provider(
+            Arc::new(roots),
+            crypto_provider(),
+        )
+        .build()?;
+        builder.with_client_cert_verifier(verifier)
+    };
+    let server_config = builder.with_single_cert(certs, key)?;
+    Ok(Some(TlsAcceptor::from(Arc::new(server_config))))
+}
+
+/// Refuse to expose an unauthenticated TCP listener.
+pub fn validate(config: &TcpConfig) -> anyhow::Result<()> {
+    if config.token.is_empty() && config.client_ca.is_empty() {
+        anyhow::bail!("daemon.tcp requires a token or a client_ca for mutual TLS");
+    }
+    if !config.client_ca.is_empty() && config.tls_cert.is_empty() {
+        anyhow::bail!("daemon.tcp.client_ca requires tls_cert and tls_key");
+    }
+    Ok(())
+}
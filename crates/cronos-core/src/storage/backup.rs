@@ -0,0 +1,358 @@
+//! Backups of the store taken while the daemon runs, and restoring them.
+//!
+//! Copying `cronos.db` directly can tear: recent writes live in the WAL file
+//! until a checkpoint. Backups go through SQLite's online backup API instead
+//! (see [`Repository::backup_to`]) and are written next to their destination
+//! first, then renamed into place, so a backup file is always complete.
+
+use rusqlite::{Connection, OpenFlags};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use super::encryption::{self, DbKey};
+use super::migrations::CURRENT_VERSION;
+use super::Repository;
+use crate::engine::Engine;
+
+/// Scheduled backups are named `<prefix><local time>.db`.
+const SCHEDULED_PREFIX: &str = "cronos-";
+
+#[derive(Debug, thiserror::Error)]
+pub enum BackupError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("{0} is not a Cronos database")]
+    NotCronos(PathBuf),
+    #[error("{path} failed its integrity check: {detail}")]
+    Corrupt { path: PathBuf, detail: String },
+    #[error(
+        "backup has schema version {found}, newer than this build supports ({CURRENT_VERSION})"
+    )]
+    NewerSchema { found: i32 },
+    #[error("{0} exists and is not a Cronos backup; not replacing it")]
+    WouldReplace(PathBuf),
+}
+
+/// Back up `repo` to `path`, replacing any file there only once the backup
+/// is complete. Only an earlier backup is replaced: anything else already at
+/// `path` is left alone.
+pub fn write(repo: &Repository, path: &Path) -> Result<(), BackupError> {
+    if path.symlink_metadata().is_ok() && verify(path, repo.key()).is_err() {
+        return Err(BackupError::WouldReplace(path.to_path_buf()));
+    }
+    let partial = with_suffix(path, ".partial");
+    let _ = std::fs::remove_file(&partial);
+    if let Err(e) = repo.backup_to(&partial) {
+        let _ = std::fs::remove_file(&partial);
+        return Err(e.into());
+    }
+    std::fs::rename(&partial, path)?;
+    Ok(())
+}
+
+/// Check that `path` is an intact Cronos database this build can open, and
+/// return its schema version. Older versions are migrated when opened.
+/// `key` opens encrypted backups; plaintext ones are read without it.
+pub fn verify(path: &Path, key: Option<&DbKey>) -> Result<i32, BackupError> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    if let Some(key) = key {
+        if !encryption::is_plaintext(path)? {
+            conn.pragma_update(None, "key", key.pragma_value())?;
+        }
+    }
+    let detail: String = conn
+        .query_row("PRAGMA quick_check", [], |row| row.get(0))
+        .map_err(|e| match e {
+            rusqlite::Error::SqliteFailure(f, _) if f.code == rusqlite::ErrorCode::NotADatabase => {
+                BackupError::NotCronos(path.to_path_buf())
+            }
+            other => other.into(),
+        })?;
+    if detail != "ok" {
+        return Err(BackupError::Corrupt {
+            path: path.to_path_buf(),
+            detail,
+        });
+    }
+    let version: Option<i32> = conn
+        .query_row("SELECT MAX(version) FROM schema_version", [], |row| {
+            row.get(0)
+        })
+        .map_err(|_| BackupError::NotCronos(path.to_path_buf()))?;
+    let version = version.ok_or_else(|| BackupError::NotCronos(path.to_path_buf()))?;
+    if version > CURRENT_VERSION {
+        return Err(BackupError::NewerSchema { found: version });
+    }
+    Ok(version)
+}
+
+/// Replace the database at `db_path` with the backup at `backup`, which is
+/// verified first. The daemon must not be running. The replaced database and
+/// its WAL are kept beside it with a `.pre-restore-<local time>` suffix,
+/// whose path is returned, if there was one; an earlier one is never
+/// replaced. A plaintext backup restored while encryption is on is
+/// encrypted when the database is next opened.
+pub fn restore(
+    backup: &Path,
+    db_path: &Path,
+    key: Option<&DbKey>,
+) -> Result<Option<PathBuf>, BackupError> {
+    verify(backup, key)?;
+    let previous = with_suffix(db_path, &pre_restore_suffix(chrono::Local::now()));
+    if previous.symlink_metadata().is_ok() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("{} already exists", previous.display()),
+        )
+        .into());
+    }
+    let incoming = with_suffix(db_path, ".restoring");
+    std::fs::copy(backup, &incoming)?;
+
+    let kept = if db_path.exists() {
+        std::fs::rename(db_path, &previous)?;
+        Some(previous.clone())
+    } else {
+        None
+    };
+    // A WAL left beside the new file would be replayed into it
+    for suffix in ["-wal", "-shm"] {
+        let side = with_suffix(db_path, suffix);
+        if side.exists() {
+            std::fs::rename(&side, with_suffix(&previous, suffix))?;
+        }
+    }
+    std::fs::rename(&incoming, db_path)?;
+    Ok(kept)
+}
+
+/// Delete all but the newest `keep` scheduled backups in `dir`; returns how
+/// many were deleted.
+pub fn prune(dir: &Path, keep: usize) -> std::io::Result<usize> {
+    let mut backups = scheduled_backups(dir)?;
+    // Names sort by the time they were taken
+    backups.sort();
+    let excess = backups.len().saturating_sub(keep);
+    for path in &backups[..excess] {
+        std::fs::remove_file(path)?;
+    }
+    Ok(excess)
+}
+
+/// Take a backup into `dir` every `interval`, keeping the newest `keep`. The
+/// first one is due `interval` after the newest backup already in `dir`.
+pub fn spawn_backups(engine: Arc<Engine>, dir: PathBuf, interval: Duration, keep: usize) {
+    tokio::spawn(async move {
+        loop {
+            let last = scheduled_backups(&dir)
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|p| p.metadata().and_then(|m| m.modified()).ok())
+                .max();
+            let wait = last
+                .map(|t| {
+                    (t + interval)
+                        .duration_since(SystemTime::now())
+                        .unwrap_or_default()
+                })
+                .unwrap_or_default();
+            tokio::time::sleep(wait).await;
+
+            // The backup holds the repository lock and does file I/O throughout
+            let (engine, dir) = (Arc::clone(&engine), dir.clone());
+            let backed_up = tokio::task::spawn_blocking(move || {
+                let path = dir.join(scheduled_name(chrono::Local::now()));
+                let result = std::fs::create_dir_all(&dir)
+                    .map_err(BackupError::from)
+                    .and_then(|()| engine.backup(&path));
+                match result {
+                    Ok(()) => {
+                        tracing::info!(path = %path.display(), "backed up database");
+                        if let Err(e) = prune(&dir, keep) {
+                            tracing::warn!("pruning backups: {e}");
+                        }
+                        true
+                    }
+                    Err(e) => {
+                        tracing::warn!("backup failed: {e}");
+                        false
+                    }
+                }
+            })
+            .await
+            .unwrap_or(false);
+            if !backed_up {
+                // Retry after a full interval rather than spinning
+                tokio::time::sleep(interval).await;
+            }
+        }
+    });
+}
+
+fn scheduled_name(now: chrono::DateTime<chrono::Local>) -> String {
+    format!("{SCHEDULED_PREFIX}{}.db", now.format("%Y%m%d-%H%M%S"))
+}
+
+fn pre_restore_suffix(now: chrono::DateTime<chrono::Local>) -> String {
+    format!(".pre-restore-{}", now.format("%Y%m%d-%H%M%S"))
+}
+
+fn scheduled_backups(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut backups = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        if name.starts_with(SCHEDULED_PREFIX) && name.ends_with(".db") {
+            backups.push(path);
+        }
+    }
+    Ok(backups)
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures as fixtures;
+    use cronos_model::EntityKind;
+    use tempfile::TempDir;
+
+    fn add_entity(repo: &Repository, name: &str) {
+        let entity = fixtures::entity(EntityKind::File, name, 0);
+        repo.insert_entity(&entity).unwrap();
+    }
+
+    #[test]
+    fn backups_are_complete_single_files_that_restore() {
+        let dir = TempDir::new().unwrap();
+        let db_path = dir.path().join("cronos.db");
+        let repo = Repository::open(&db_path).unwrap();
+        add_entity(&repo, "/a.rs");
+
+        let backup = dir.path().join("backup.db");
+        write(&repo, &backup).unwrap();
+        assert!(!with_suffix(&backup, ".partial").exists());
+        assert!(!with_suffix(&backup, "-wal").exists());
+        assert_eq!(verify(&backup, None).unwrap(), CURRENT_VERSION);
+        // An earlier backup is replaced
+        write(&repo, &backup).unwrap();
+
+        // Written after the backup, so gone once it is restored
+        add_entity(&repo, "/b.rs");
+        drop(repo);
+
+        let kept = restore(&backup, &db_path, None).unwrap().unwrap();
+        assert!(kept.to_string_lossy().contains("cronos.db.pre-restore-"));
+        assert_eq!(Repository::open(&kept).unwrap().entity_count().unwrap(), 2);
+        assert_eq!(
+            Repository::open(&db_path).unwrap().entity_count().unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn restore_refuses_what_is_not_a_usable_backup() {
+        let dir = TempDir::new().unwrap();
+        let db_path = dir.path().join("cronos.db");
+        Repository::open(&db_path).unwrap();
+
+        let junk = dir.path().join("junk.db");
+        std::fs::write(&junk, vec![7u8; 4096]).unwrap();
+        assert!(matches!(
+            restore(&junk, &db_path, None),
+            Err(BackupError::NotCronos(_))
+        ));
+
+        let foreign = dir.path().join("foreign.db");
+        Connection::open(&foreign)
+            .unwrap()
+            .execute_batch("CREATE TABLE t (x)")
+            .unwrap();
+        assert!(matches!(
+            restore(&foreign, &db_path, None),
+            Err(BackupError::NotCronos(_))
+        ));
+
+        let newer = dir.path().join("newer.db");
+        let repo = Repository::open(&newer).unwrap();
+        drop(repo);
+        Connection::open(&newer)
+            .unwrap()
+            .execute(
+                "UPDATE schema_version SET version = ?1",
+                [CURRENT_VERSION + 1],
+            )
+            .unwrap();
+        assert!(matches!(
+            restore(&newer, &db_path, None),
+            Err(BackupError::NewerSchema { .. })
+        ));
+
+        // The database was left alone
+        assert!(std::fs::read_dir(dir.path()).unwrap().all(|e| !e
+            .unwrap()
+            .file_name()
+            .to_string_lossy()
+            .contains(".pre-restore")));
+        assert!(Repository::open(&db_path).is_ok());
+    }
+
+    #[test]
+    fn backups_do_not_replace_other_files() {
+        let dir = TempDir::new().unwrap();
+        let repo = Repository::open(&dir.path().join("cronos.db")).unwrap();
+
+        let notes = dir.path().join("notes.txt");
+        std::fs::write(&notes, b"keep me").unwrap();
+        assert!(matches!(
+            write(&repo, &notes),
+            Err(BackupError::WouldReplace(_))
+        ));
+        assert_eq!(std::fs::read(&notes).unwrap(), b"keep me");
+        assert!(matches!(
+            write(&repo, dir.path()),
+            Err(BackupError::WouldReplace(_))
+        ));
+    }
+
+    #[test]
+    fn prune_keeps_the_newest_scheduled_backups() {
+        let dir = TempDir::new().unwrap();
+        for name in [
+            "cronos-20240101-000000.db",
+            "cronos-20240102-000000.db",
+            "cronos-20240103-000000.db",
+            "manual.db",
+        ] {
+            std::fs::write(dir.path().join(name), b"").unwrap();
+        }
+        assert_eq!(prune(dir.path(), 2).unwrap(), 1);
+        let mut left: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        left.sort();
+        assert_eq!(
+            left,
+            [
+                "cronos-20240102-000000.db",
+                "cronos-20240103-000000.db",
+                "manual.db"
+            ]
+        );
+        assert_eq!(prune(&dir.path().join("missing"), 2).unwrap(), 0);
+    }
+}
@@ -0,0 +1,433 @@
+//! Optional at-rest encryption of the database with SQLCipher.
+//!
+//! SQLCipher encrypts every page, so names, attributes, metadata and window
+//! titles are unreadable without the key while queries, full-text search and
+//! the online backup API keep working. It needs a build with the `encryption`
+//! feature. Plain SQLite silently ignores `PRAGMA key`, so keying checks that
+//! SQLCipher is really there rather than writing plaintext.
+//!
+//! The key is either a random 256-bit key kept in the OS keyring (macOS
+//! Keychain via `security`, or the Secret Service via `secret-tool`), or a
+//! passphrase from `CRONOS_DB_PASSPHRASE`. A keyring key is only created
+//! while the database does not exist or is still plaintext; a stored key is
+//! never replaced, and a keyring that cannot be read is an error rather
+//! than a reason to make a new key.
+
+use cronos_common::config::EncryptionConfig;
+use rusqlite::{Connection, OptionalExtension};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Environment variable holding the passphrase for `key_source = "passphrase"`.
+pub const PASSPHRASE_ENV: &str = "CRONOS_DB_PASSPHRASE";
+
+const KEYRING_SERVICE: &str = "cronos";
+const KEYRING_ACCOUNT: &str = "database";
+
+/// First bytes of every unencrypted SQLite database.
+const PLAINTEXT_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+#[derive(Debug, thiserror::Error)]
+pub enum EncryptionError {
+    #[error("encryption is configured but this build has no SQLCipher; rebuild with the `encryption` feature")]
+    Unavailable,
+    #[error("wrong database key, or the database is not encrypted with it")]
+    WrongKey,
+    #[error("{PASSPHRASE_ENV} is not set")]
+    MissingPassphrase,
+    #[error("unknown key source '{0}' (expected keyring or passphrase)")]
+    UnknownKeySource(String),
+    #[error("keyring: {0}")]
+    Keyring(String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+}
+
+/// Key for an encrypted database.
+#[derive(Clone)]
+pub enum DbKey {
+    /// Stretched into a key by SQLCipher.
+    Passphrase(String),
+    Raw([u8; 32]),
+}
+
+impl std::fmt::Debug for DbKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Passphrase(_) => "DbKey::Passphrase(..)",
+            Self::Raw(_) => "DbKey::Raw(..)",
+        })
+    }
+}
+
+impl DbKey {
+    /// Value for `PRAGMA key` and `ATTACH ... KEY`.
+    pub(crate) fn pragma_value(&self) -> String {
+        match self {
+            Self::Passphrase(passphrase) => passphrase.clone(),
+            Self::Raw(bytes) => format!("x'{}'", hex::encode(bytes)),
+        }
+    }
+}
+
+/// The key `config` asks for the database at `db_path`, or `None` when
+/// encryption is off.
+pub fn resolve_key(
+    config: &EncryptionConfig,
+    db_path: &Path,
+) -> Result<Option<DbKey>, EncryptionError> {
+    if !config.enabled {
+        return Ok(None);
+    }
+    match config.key_source.as_str() {
+        "passphrase" => match std::env::var(PASSPHRASE_ENV) {
+            Ok(passphrase) if !passphrase.is_empty() => Ok(Some(DbKey::Passphrase(passphrase))),
+            _ => Err(EncryptionError::MissingPassphrase),
+        },
+        "keyring" => keyring_key(needs_new_key(db_path)?).map(Some),
+        other => Err(EncryptionError::UnknownKeySource(other.to_string())),
+    }
+}
+
+/// Key `conn`, which must not have run any statement yet, and check that
+/// the key opens the database.
+pub(crate) fn apply_key(conn: &Connection, key: &DbKey) -> Result<(), EncryptionError> {
+    conn.pragma_update(None, "key", key.pragma_value())?;
+    require_sqlcipher(conn)?;
+    conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| {
+        row.get::<_, i64>(0)
+    })
+    .map_err(|e| match e.sqlite_error_code() {
+        Some(rusqlite::ErrorCode::NotADatabase) => EncryptionError::WrongKey,
+        _ => e.into(),
+    })?;
+    Ok(())
+}
+
+/// Whether `path` holds an unencrypted SQLite database.
+pub fn is_plaintext(path: &Path) -> std::io::Result<bool> {
+    let mut header = [0u8; 16];
+    let mut file = std::fs::File::open(path)?;
+    match file.read_exact(&mut header) {
+        Ok(()) => Ok(&header == PLAINTEXT_HEADER),
+        // Empty or truncated: nothing to encrypt
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Encrypt the plaintext database at `path` in place with `key`. The
+/// encrypted copy is built beside it and only then replaces it; the
+/// plaintext file and its WAL are deleted.
+pub(crate) fn encrypt_in_place(path: &Path, key: &DbKey) -> Result<(), EncryptionError> {
+    let encrypting = with_suffix(path, ".encrypting");
+    let _ = std::fs::remove_file(&encrypting);
+    {
+        let plain = Connection::open(path)?;
+        require_sqlcipher(&plain)?;
+        // Fold the WAL in so the export sees every write
+        plain.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")?;
+        plain.execute(
+            "ATTACH DATABASE ?1 AS encrypted KEY ?2",
+            [
+                encrypting.to_string_lossy().into_owned(),
+                key.pragma_value(),
+            ],
+        )?;
+        plain.query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()))?;
+        plain.execute_batch("DETACH DATABASE encrypted;")?;
+    }
+    for suffix in ["-wal", "-shm"] {
+        let side = with_suffix(path, suffix);
+        if side.exists() {
+            std::fs::remove_file(side)?;
+        }
+    }
+    std::fs::rename(&encrypting, path)?;
+    tracing::info!(path = %path.display(), "encrypted plaintext database");
+    Ok(())
+}
+
+/// Whether a key may be created for `path`: nothing is encrypted there yet.
+fn needs_new_key(path: &Path) -> std::io::Result<bool> {
+    match std::fs::metadata(path) {
+        Ok(meta) if meta.len() == 0 => Ok(true),
+        Ok(_) => is_plaintext(path),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(true),
+        Err(e) => Err(e),
+    }
+}
+
+fn require_sqlcipher(conn: &Connection) -> Result<(), EncryptionError> {
+    let version: Option<String> = conn
+        .query_row("PRAGMA cipher_version", [], |row| row.get(0))
+        .optional()?;
+    match version {
+        Some(_) => Ok(()),
+        None => Err(EncryptionError::Unavailable),
+    }
+}
+
+/// The database key from the OS keyring. When none is stored and `create`
+/// is set, a new one is generated and stored.
+fn keyring_key(create: bool) -> Result<DbKey, EncryptionError> {
+    let parse = |stored: &str| {
+        hex::decode(stored.trim())
+            .ok()
+            .and_then(|b| <[u8; 32]>::try_from(b).ok())
+            .map(DbKey::Raw)
+            .ok_or_else(|| EncryptionError::Keyring("stored key is malformed".to_string()))
+    };
+    if let Some(stored) = keyring_lookup()? {
+        return parse(&stored);
+    }
+    if !create {
+        return Err(EncryptionError::Keyring(
+            "the database is encrypted but the keyring holds no key for it".to_string(),
+        ));
+    }
+    use rand::Rng;
+    let bytes: [u8; 32] = rand::rng().random();
+    let secret = hex::encode(bytes);
+    keyring_store(&secret)?;
+    // Another process may have stored a key first; that one wins
+    match keyring_lookup()? {
+        Some(stored) => parse(&stored),
+        None => Err(EncryptionError::Keyring(
+            "the database key was not stored".to_string(),
+        )),
+    }
+}
+
+fn keyring_lookup() -> Result<Option<String>, EncryptionError> {
+    let mut command = if cfg!(target_os = "macos") {
+        let mut c = Command::new("security");
+        c.args([
+            "find-generic-password",
+            "-s",
+            KEYRING_SERVICE,
+            "-a",
+            KEYRING_ACCOUNT,
+            "-w",
+        ]);
+        c
+    } else {
+        let mut c = Command::new("secret-tool");
+        c.args([
+            "lookup",
+            "service",
+            KEYRING_SERVICE,
+            "account",
+            KEYRING_ACCOUNT,
+        ]);
+        c
+    };
+    let output = command.output().map_err(|e| {
+        EncryptionError::Keyring(format!("running {:?}: {e}", command.get_program()))
+    })?;
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    if !output.status.success() {
+        if lookup_missed(output.status.code(), &stderr) {
+            return Ok(None);
+        }
+        return Err(EncryptionError::Keyring(format!(
+            "reading the database key: {}",
+            if stderr.is_empty() {
+                output.status.to_string()
+            } else {
+                stderr
+            }
+        )));
+    }
+    let value = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Ok((!value.is_empty()).then_some(value))
+}
+
+/// Whether a failed lookup means nothing is stored, as opposed to a locked
+/// keychain, denied access or an unreachable Secret Service.
+fn lookup_missed(code: Option<i32>, stderr: &str) -> bool {
+    if cfg!(target_os = "macos") {
+        // errSecItemNotFound
+        code == Some(44)
+    } else {
+        // secret-tool exits 1 without a message when nothing matches
+        code == Some(1) && stderr.is_empty()
+    }
+}
+
+/// Store `secret` without replacing a stored key. It goes through stdin so
+/// it never shows in the process list.
+fn keyring_store(secret: &str) -> Result<(), EncryptionError> {
+    let (mut command, input) = if cfg!(target_os = "macos") {
+        // `security -i` reads commands from stdin; without `-U` an existing
+        // item is left alone.
+        let mut c = Command::new("security");
+        c.arg("-i");
+        let input =
+            format!("add-generic-password -s {KEYRING_SERVICE} -a {KEYRING_ACCOUNT} -w {secret}\n");
+        (c, input)
+    } else {
+        let mut c = Command::new("secret-tool");
+        c.args([
+            "store",
+            "--label=Cronos database key",
+            "service",
+            KEYRING_SERVICE,
+            "account",
+            KEYRING_ACCOUNT,
+        ]);
+        (c, secret.to_string())
+    };
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .map_err(|e| {
+            EncryptionError::Keyring(format!("running {:?}: {e}", command.get_program()))
+        })?;
+    child
+        .stdin
+        .take()
+        .expect("piped stdin")
+        .write_all(input.as_bytes())?;
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(EncryptionError::Keyring(
+            "could not store the database key".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{backup, Repository};
+    use crate::test_fixtures as fixtures;
+    use cronos_model::EntityKind;
+    use tempfile::TempDir;
+
+    const SECRET_NAME: &str = "/home/me/secret-plan.txt";
+
+    fn contains(haystack: &[u8], needle: &str) -> bool {
+        haystack
+            .windows(needle.len())
+            .any(|w| w == needle.as_bytes())
+    }
+
+    #[test]
+    fn plaintext_databases_are_encrypted_in_place() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("cronos.db");
+        let repo = Repository::open(&path).unwrap();
+        let secret = fixtures::entity(EntityKind::File, SECRET_NAME, 0);
+        repo.insert_entity(&secret).unwrap();
+        drop(repo);
+        assert!(is_plaintext(&path).unwrap());
+
+        let key = DbKey::Passphrase("correct horse".to_string());
+        // Readers neither encrypt nor migrate, whatever the key
+        let reader = Repository::open_read_only(&path, Some(&key)).unwrap();
+        assert_eq!(reader.entity_count().unwrap(), 1);
+        drop(reader);
+        assert!(is_plaintext(&path).unwrap());
+
+        let opened = Repository::open_encrypted(&path, &key);
+        if !cfg!(feature = "encryption") {
+            // Refuses rather than silently staying plaintext, and leaves the file alone
+            assert!(matches!(opened, Err(EncryptionError::Unavailable)));
+            assert!(is_plaintext(&path).unwrap());
+            return;
+        }
+
+        let repo = opened.unwrap();
+        assert_eq!(repo.search_entities("secret", 10).unwrap().len(), 1);
+        let copy = dir.path().join("backup.db");
+        backup::write(&repo, &copy).unwrap();
+        drop(repo);
+
+        for file in [&path, &copy] {
+            assert!(!is_plaintext(file).unwrap());
+            assert!(!contains(&std::fs::read(file).unwrap(), SECRET_NAME));
+        }
+        assert!(backup::verify(&copy, Some(&key)).is_ok());
+        assert!(Repository::open(&path).is_err());
+        let wrong = DbKey::Raw([7; 32]);
+        assert!(matches!(
+            Repository::open_encrypted(&path, &wrong),
+            Err(EncryptionError::WrongKey)
+        ));
+        assert_eq!(
+            Repository::open_encrypted(&path, &key)
+                .unwrap()
+                .entity_count()
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            Repository::open_read_only(&path, Some(&key))
+                .unwrap()
+                .entity_count()
+                .unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn keys_are_only_created_before_the_database_is_encrypted() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("cronos.db");
+        assert!(needs_new_key(&path).unwrap());
+        std::fs::write(&path, b"").unwrap();
+        assert!(needs_new_key(&path).unwrap());
+        drop(Repository::open(&path).unwrap());
+        assert!(needs_new_key(&path).unwrap());
+        std::fs::write(&path, [0x5a; 4096]).unwrap();
+        assert!(!needs_new_key(&path).unwrap());
+
+        // Only an empty miss means nothing is stored
+        let missed = if cfg!(target_os = "macos") { 44 } else { 1 };
+        assert!(lookup_missed(Some(missed), ""));
+        assert!(!lookup_missed(
+            Some(1),
+            "Cannot autolaunch D-Bus without X11 $DISPLAY"
+        ));
+        assert!(!lookup_missed(Some(51), ""));
+        assert!(!lookup_missed(None, ""));
+    }
+
+    #[test]
+    fn keys_come_from_the_configured_source() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("cronos.db");
+        let off = EncryptionConfig::default();
+        assert!(resolve_key(&off, &path).unwrap().is_none());
+
+        let unknown = EncryptionConfig {
+            enabled: true,
+            key_source: "tpm".to_string(),
+        };
+        assert!(matches!(
+            resolve_key(&unknown, &path),
+            Err(EncryptionError::UnknownKeySource(_))
+        ));
+
+        assert_eq!(
+            DbKey::Raw([0xab; 32]).pragma_value(),
+            format!("x'{}'", "ab".repeat(32))
+        );
+        assert_eq!(
+            format!("{:?}", DbKey::Passphrase("hunter2".to_string())),
+            "DbKey::Passphrase(..)"
+        );
+    }
+}
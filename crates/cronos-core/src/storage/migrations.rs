@@ -1,6 +1,7 @@
 use rusqlite::Connection;
 
-const CURRENT_VERSION: i32 = 2;
+/// Schema version this build migrates databases to.
+pub const CURRENT_VERSION: i32 = 7;
 
 /// Run all migrations on the given database connection.
 ///
@@ -134,6 +135,78 @@ pub fn run_migrations(conn: &Connection) -> rusqlite::Result<()> {
         )?;
     } // end v2
 
+    // === V3 migrations: indexes for filtered, keyset-paginated queries ===
+    if current < 3 {
+        conn.execute_batch(
+            "CREATE INDEX IF NOT EXISTS idx_events_time_id        ON events(timestamp, id);
+            CREATE INDEX IF NOT EXISTS idx_events_kind           ON events(kind);
+            CREATE INDEX IF NOT EXISTS idx_event_context_entity  ON event_context(entity_id);
+            CREATE INDEX IF NOT EXISTS idx_entities_last_seen_id ON entities(last_seen, id);
+            CREATE INDEX IF NOT EXISTS idx_sessions_start_id     ON sessions(start_time, id);
+            CREATE INDEX IF NOT EXISTS idx_sessions_project      ON sessions(project);",
+        )?;
+    } // end v3
+
+    // === V4 migrations: project attribution of sessions ===
+    if current < 4 {
+        conn.execute_batch(
+            "ALTER TABLE sessions ADD COLUMN project_id TEXT;
+            ALTER TABLE sessions ADD COLUMN project_confidence REAL NOT NULL DEFAULT 0;",
+        )?;
+    } // end v4
+
+    // === V5 migrations: sessions still being extended by the aggregator ===
+    if current < 5 {
+        conn.execute_batch("ALTER TABLE sessions ADD COLUMN open INTEGER NOT NULL DEFAULT 0;")?;
+    } // end v5
+
+    // === V6 migrations: manual corrections applied to timesheets ===
+    if current < 6 {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS time_overrides (
+                id         TEXT PRIMARY KEY NOT NULL,
+                start_time INTEGER NOT NULL,
+                end_time   INTEGER NOT NULL,
+                action     TEXT NOT NULL,
+                note       TEXT NOT NULL DEFAULT '',
+                created_at INTEGER NOT NULL
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_time_overrides_time ON time_overrides(start_time, end_time);",
+        )?;
+    } // end v6
+
+    // === V7 migrations: annotations and their tags ===
+    if current < 7 {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS annotations (
+                id         TEXT PRIMARY KEY NOT NULL,
+                target     TEXT NOT NULL,
+                start_time INTEGER,
+                end_time   INTEGER,
+                session_id TEXT,
+                entity_id  TEXT,
+                text       TEXT NOT NULL,
+                proposed   INTEGER NOT NULL DEFAULT 0,
+                created_at INTEGER NOT NULL
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_annotations_time ON annotations(start_time, end_time);
+            CREATE INDEX IF NOT EXISTS idx_annotations_session ON annotations(session_id);
+            CREATE INDEX IF NOT EXISTS idx_annotations_entity ON annotations(entity_id);
+
+            CREATE TABLE IF NOT EXISTS annotation_tags (
+                annotation_id TEXT NOT NULL REFERENCES annotations(id) ON DELETE CASCADE,
+                tag           TEXT NOT NULL,
+                PRIMARY KEY (annotation_id, tag)
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_annotation_tags_tag ON annotation_tags(tag);
+
+            CREATE VIRTUAL TABLE IF NOT EXISTS annotations_fts USING fts5(annotation_id UNINDEXED, text, tags);",
+        )?;
+    } // end v7
+
     // Record schema version
     if version.is_none() {
         conn.execute(
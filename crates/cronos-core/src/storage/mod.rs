@@ -1,4 +1,7 @@
+pub mod backup;
+pub mod encryption;
 pub mod migrations;
 pub mod repo;
+pub mod sql;
 
 pub use repo::Repository;
@@ -1,15 +1,20 @@
 use cronos_model::{
-    Attributes, CollectorSource, Edge, EdgeId, Entity, EntityId, EntityKind, Event, EventId,
-    EventKind, Relation, Timestamp,
+    Annotation, AnnotationId, AnnotationTarget, Attributes, CollectorSource, Edge, EdgeId, Entity,
+    EntityId, EntityKind, Event, EventId, EventKind, OverrideId, Relation, TimeOverride, Timestamp,
 };
-use rusqlite::{params, Connection};
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::{params, params_from_iter, Connection, OpenFlags, OptionalExtension};
 use serde::{Deserialize, Serialize};
 
+use super::encryption::{self, DbKey, EncryptionError};
 use super::migrations::run_migrations;
+use crate::query::{Compiled, Output};
 
 /// SQLite-backed repository for entities, events, and edges.
 pub struct Repository {
     conn: Connection,
+    /// Key the database is encrypted with, if it is.
+    key: Option<DbKey>,
 }
 
 impl Repository {
@@ -17,22 +22,98 @@ impl Repository {
     pub fn open(path: &std::path::Path) -> rusqlite::Result<Self> {
         let conn = Connection::open(path)?;
         run_migrations(&conn)?;
-        Ok(Self { conn })
+        Ok(Self { conn, key: None })
+    }
+
+    /// Open a repository backed by a file at `path` encrypted with `key`. A
+    /// plaintext database there is encrypted in place first.
+    pub fn open_encrypted(path: &std::path::Path, key: &DbKey) -> Result<Self, EncryptionError> {
+        if path.exists() && encryption::is_plaintext(path)? {
+            encryption::encrypt_in_place(path, key)?;
+        }
+        let conn = Connection::open(path)?;
+        encryption::apply_key(&conn, key)?;
+        run_migrations(&conn)?;
+        Ok(Self {
+            conn,
+            key: Some(key.clone()),
+        })
+    }
+
+    /// Open the database at `path` for reading only. It is neither migrated
+    /// nor encrypted in place, so this is safe while the daemon has it open;
+    /// `key` is only applied when the database is already encrypted.
+    pub fn open_read_only(
+        path: &std::path::Path,
+        key: Option<&DbKey>,
+    ) -> Result<Self, EncryptionError> {
+        let conn = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        let key = match key {
+            Some(key) if !encryption::is_plaintext(path)? => {
+                encryption::apply_key(&conn, key)?;
+                Some(key.clone())
+            }
+            _ => None,
+        };
+        Ok(Self { conn, key })
     }
 
     /// Open an in-memory repository (useful for tests).
     pub fn open_in_memory() -> rusqlite::Result<Self> {
         let conn = Connection::open_in_memory()?;
         run_migrations(&conn)?;
-        Ok(Self { conn })
+        Ok(Self { conn, key: None })
+    }
+
+    /// Run `f` in a transaction: committed if it succeeds, rolled back if
+    /// not. Reads inside see one consistent snapshot.
+    pub fn transaction<T, E: From<rusqlite::Error>>(
+        &self,
+        f: impl FnOnce(&Self) -> Result<T, E>,
+    ) -> Result<T, E> {
+        let tx = self.conn.unchecked_transaction()?;
+        let value = f(self)?;
+        tx.commit()?;
+        Ok(value)
+    }
+
+    /// The key this database is encrypted with, if it is.
+    pub fn key(&self) -> Option<&DbKey> {
+        self.key.as_ref()
+    }
+
+    /// Copy the whole database to a new file at `path` with SQLite's online
+    /// backup API, so the copy is consistent even while WAL is in use. The
+    /// copy uses a rollback journal and so is a single self-contained file,
+    /// encrypted with the same key as this database.
+    pub fn backup_to(&self, path: &std::path::Path) -> rusqlite::Result<()> {
+        let mut dst = Connection::open(path)?;
+        if let Some(key) = &self.key {
+            dst.pragma_update(None, "key", key.pragma_value())?;
+        }
+        rusqlite::backup::Backup::new(&self.conn, &mut dst)?.run_to_completion(
+            1024,
+            std::time::Duration::ZERO,
+            None,
+        )?;
+        dst.pragma_update(None, "journal_mode", "DELETE")
+    }
+
+    /// Schema version recorded by the migrations.
+    pub fn schema_version(&self) -> rusqlite::Result<i32> {
+        self.conn
+            .query_row("SELECT MAX(version) FROM schema_version", [], |row| row.get(0))
     }
 
     // ─── Entity operations ───────────────────────────────────────────
 
     /// Insert or update an entity.
     ///
-    /// On conflict (same id), updates `last_seen`, `name`, and `attributes`.
-    /// Also maintains the FTS index.
+    /// On conflict (same id), updates `last_seen`, `name`, and `attributes`,
+    /// and keeps the earlier `first_seen`. Also maintains the FTS index.
     pub fn insert_entity(&self, entity: &Entity) -> rusqlite::Result<()> {
         let kind_str = serde_json::to_string(&entity.kind).unwrap();
         let attrs_str = serde_json::to_string(&entity.attributes).unwrap();
@@ -41,6 +122,7 @@ impl Repository {
             "INSERT INTO entities (id, kind, name, attributes, first_seen, last_seen)
              VALUES (?1, ?2, ?3, ?4, ?5, ?6)
              ON CONFLICT(id) DO UPDATE SET
+                first_seen = MIN(first_seen, excluded.first_seen),
                 last_seen  = excluded.last_seen,
                 name       = excluded.name,
                 attributes = excluded.attributes",
@@ -150,6 +232,31 @@ impl Repository {
         Ok(entities)
     }
 
+    /// Return all entities of `kind`.
+    pub fn entities_of_kind(&self, kind: &EntityKind) -> rusqlite::Result<Vec<Entity>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, kind, name, attributes, first_seen, last_seen FROM entities WHERE kind = ?1",
+        )?;
+
+        let kind_str = serde_json::to_string(kind).unwrap();
+        let rows = stmt.query_map([kind_str], |row| {
+            Ok(EntityRow {
+                id: row.get(0)?,
+                kind: row.get(1)?,
+                name: row.get(2)?,
+                attributes: row.get(3)?,
+                first_seen: row.get(4)?,
+                last_seen: row.get(5)?,
+            })
+        })?;
+
+        let mut entities = Vec::new();
+        for r in rows {
+            entities.push(entity_from_row(r?));
+        }
+        Ok(entities)
+    }
+
     /// Return the total count of entities.
     pub fn entity_count(&self) -> rusqlite::Result<i64> {
         self.conn
@@ -168,16 +275,55 @@ impl Repository {
         subject_entity_id: EntityId,
         context_entity_ids: &[EntityId],
     ) -> rusqlite::Result<()> {
-        let source_str = serde_json::to_string(&event.source).unwrap();
-        let kind_str = serde_json::to_string(&event.kind).unwrap();
-        let metadata_str = serde_json::to_string(&event.metadata).unwrap();
+        self.insert_event_row(
+            event.id,
+            event.timestamp,
+            &event.source,
+            &event.kind,
+            subject_entity_id,
+            &event.metadata,
+            context_entity_ids,
+        )
+    }
+
+    /// Insert an already resolved event, e.g. one read back from an archive.
+    pub fn insert_stored_event(
+        &self,
+        event: &StoredEvent,
+        context_entity_ids: &[EntityId],
+    ) -> rusqlite::Result<()> {
+        self.insert_event_row(
+            event.id,
+            event.timestamp,
+            &event.source,
+            &event.kind,
+            event.subject_id,
+            &event.metadata,
+            context_entity_ids,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn insert_event_row(
+        &self,
+        id: EventId,
+        timestamp: Timestamp,
+        source: &CollectorSource,
+        kind: &EventKind,
+        subject_entity_id: EntityId,
+        metadata: &Attributes,
+        context_entity_ids: &[EntityId],
+    ) -> rusqlite::Result<()> {
+        let source_str = serde_json::to_string(source).unwrap();
+        let kind_str = serde_json::to_string(kind).unwrap();
+        let metadata_str = serde_json::to_string(metadata).unwrap();
 
         self.conn.execute(
             "INSERT INTO events (id, timestamp, source, kind, subject_id, metadata)
              VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
             params![
-                event.id.to_string(),
-                event.timestamp,
+                id.to_string(),
+                timestamp,
                 source_str,
                 kind_str,
                 subject_entity_id.to_string(),
@@ -190,7 +336,7 @@ impl Repository {
             "INSERT INTO event_context (event_id, entity_id) VALUES (?1, ?2)",
         )?;
         for ctx_id in context_entity_ids {
-            stmt.execute(params![event.id.to_string(), ctx_id.to_string()])?;
+            stmt.execute(params![id.to_string(), ctx_id.to_string()])?;
         }
 
         Ok(())
@@ -227,6 +373,55 @@ impl Repository {
         Ok(events)
     }
 
+    /// Return when filesystem, git and terminal events in `[start, end]`
+    /// touched a project, as `(timestamp, project id)` ordered by time.
+    pub fn project_activity(
+        &self,
+        start: Timestamp,
+        end: Timestamp,
+    ) -> rusqlite::Result<Vec<(Timestamp, EntityId)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT e.timestamp, p.id
+             FROM events e
+             JOIN event_context c ON c.event_id = e.id
+             JOIN entities p ON p.id = c.entity_id
+             WHERE p.kind = '\"project\"'
+               AND e.source IN ('\"filesystem\"', '\"git\"', '\"terminal\"')
+               AND e.timestamp >= ?1 AND e.timestamp <= ?2
+             ORDER BY e.timestamp ASC",
+        )?;
+
+        let rows = stmt.query_map(params![start, end], |row| {
+            let id: String = row.get(1)?;
+            Ok((row.get(0)?, EntityId(parse_ulid(&id))))
+        })?;
+        rows.collect()
+    }
+
+    /// Return how many events in `[start, end)` had each entity of `kind` as
+    /// their subject, as `(name, count)`, most first.
+    pub fn subject_event_counts(
+        &self,
+        start: Timestamp,
+        end: Timestamp,
+        kind: &EntityKind,
+    ) -> rusqlite::Result<Vec<(String, u64)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT s.name, COUNT(*) AS n
+             FROM events e
+             JOIN entities s ON s.id = e.subject_id
+             WHERE s.kind = ?3 AND e.timestamp >= ?1 AND e.timestamp < ?2
+             GROUP BY s.id
+             ORDER BY n DESC, s.name ASC",
+        )?;
+
+        let kind_str = serde_json::to_string(kind).unwrap();
+        let rows = stmt.query_map(params![start, end, kind_str], |row| {
+            Ok((row.get(0)?, row.get::<_, i64>(1)? as u64))
+        })?;
+        rows.collect()
+    }
+
     /// Return the most recent events, ordered by timestamp descending.
     pub fn recent_events(&self, limit: u32) -> rusqlite::Result<Vec<StoredEvent>> {
         let mut stmt = self.conn.prepare(
@@ -254,6 +449,62 @@ impl Repository {
         Ok(events)
     }
 
+    /// Up to `limit` events with ids after `after`, ordered by id, each with
+    /// its context entity ids.
+    pub fn events_with_context(
+        &self,
+        after: Option<EventId>,
+        limit: u32,
+    ) -> rusqlite::Result<Vec<(StoredEvent, Vec<EntityId>)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, timestamp, source, kind, subject_id, metadata,
+                    (SELECT group_concat(entity_id) FROM event_context c WHERE c.event_id = events.id)
+             FROM events
+             WHERE id > ?1
+             ORDER BY id ASC
+             LIMIT ?2",
+        )?;
+
+        let after = after.map(|id| id.to_string()).unwrap_or_default();
+        let rows = stmt.query_map(params![after, limit], |row| {
+            let context: Option<String> = row.get(6)?;
+            Ok((
+                EventRow {
+                    id: row.get(0)?,
+                    timestamp: row.get(1)?,
+                    source: row.get(2)?,
+                    kind: row.get(3)?,
+                    subject_id: row.get(4)?,
+                    metadata: row.get(5)?,
+                },
+                context,
+            ))
+        })?;
+
+        let mut events = Vec::new();
+        for r in rows {
+            let (row, context) = r?;
+            let context = context
+                .as_deref()
+                .unwrap_or("")
+                .split(',')
+                .filter(|id| !id.is_empty())
+                .map(|id| EntityId(parse_ulid(id)))
+                .collect();
+            events.push((stored_event_from_row(row), context));
+        }
+        Ok(events)
+    }
+
+    /// Whether an event with this id is stored.
+    pub fn has_event(&self, id: EventId) -> rusqlite::Result<bool> {
+        self.conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM events WHERE id = ?1)",
+            [id.to_string()],
+            |row| row.get(0),
+        )
+    }
+
     /// Return the total count of events.
     pub fn event_count(&self) -> rusqlite::Result<i64> {
         self.conn
@@ -325,6 +576,36 @@ impl Repository {
         }
     }
 
+    /// Edges with the given ids, in no particular order; unknown ids are skipped.
+    pub fn edges_by_ids(&self, ids: &[EdgeId]) -> rusqlite::Result<Vec<Edge>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let sql = format!(
+            "SELECT id, from_id, to_id, relation, strength, created_at, last_reinforced
+             FROM edges WHERE id IN ({})",
+            vec!["?"; ids.len()].join(", ")
+        );
+        let mut stmt = self.conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(ids.iter().map(|id| id.to_string())), |row| {
+            Ok(EdgeRow {
+                id: row.get(0)?,
+                from_id: row.get(1)?,
+                to_id: row.get(2)?,
+                relation: row.get(3)?,
+                strength: row.get(4)?,
+                created_at: row.get(5)?,
+                last_reinforced: row.get(6)?,
+            })
+        })?;
+
+        let mut edges = Vec::new();
+        for r in rows {
+            edges.push(edge_from_row(r?));
+        }
+        Ok(edges)
+    }
+
     /// Return all edges from a given entity.
     pub fn edges_from(&self, entity_id: EntityId) -> rusqlite::Result<Vec<Edge>> {
         let mut stmt = self.conn.prepare(
@@ -423,8 +704,8 @@ impl Repository {
         let metadata_str = serde_json::to_string(&session.metadata).unwrap();
 
         self.conn.execute(
-            "INSERT INTO sessions (id, app_name, window_titles, project, category, start_time, end_time, duration_secs, event_count, metadata)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            "INSERT INTO sessions (id, app_name, window_titles, project, category, start_time, end_time, duration_secs, event_count, metadata, project_id, project_confidence, open)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
             params![
                 session.id,
                 session.app_name,
@@ -436,11 +717,55 @@ impl Repository {
                 session.duration_secs,
                 session.event_count,
                 metadata_str,
+                session.project_id.map(|id| id.0.to_string()),
+                session.project_confidence,
+                session.open,
             ],
         )?;
         Ok(())
     }
 
+    /// Change the category of a stored session; returns whether it exists.
+    pub fn set_session_category(&self, id: &str, category: &str) -> rusqlite::Result<bool> {
+        let changed = self.conn.execute(
+            "UPDATE sessions SET category = ?2 WHERE id = ?1",
+            params![id, category],
+        )?;
+        Ok(changed > 0)
+    }
+
+    /// Delete a session; returns whether it existed.
+    pub fn delete_session(&self, id: &str) -> rusqlite::Result<bool> {
+        let deleted = self.conn.execute("DELETE FROM sessions WHERE id = ?1", [id])?;
+        Ok(deleted > 0)
+    }
+
+    /// Whether a session with this id is stored.
+    pub fn has_session(&self, id: &str) -> rusqlite::Result<bool> {
+        self.conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM sessions WHERE id = ?1)",
+            [id],
+            |row| row.get(0),
+        )
+    }
+
+    /// Start and end time of a session, if it exists.
+    pub fn session_span(&self, id: &str) -> rusqlite::Result<Option<(Timestamp, Timestamp)>> {
+        self.conn
+            .query_row(
+                "SELECT start_time, end_time FROM sessions WHERE id = ?1",
+                [id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+    }
+
+    /// Return the total count of sessions.
+    pub fn session_count(&self) -> rusqlite::Result<i64> {
+        self.conn
+            .query_row("SELECT COUNT(*) FROM sessions", [], |row| row.get(0))
+    }
+
     /// Return sessions within the given timestamp range, ordered by start_time ascending.
     pub fn sessions_in_range(
         &self,
@@ -449,33 +774,15 @@ impl Repository {
         limit: u32,
     ) -> rusqlite::Result<Vec<Session>> {
         let mut stmt = self.conn.prepare(
-            "SELECT id, app_name, window_titles, project, category, start_time, end_time, duration_secs, event_count, metadata
+            "SELECT id, app_name, window_titles, project, category, start_time, end_time, duration_secs, event_count, metadata, project_id, project_confidence, open
              FROM sessions
              WHERE start_time >= ?1 AND start_time <= ?2
              ORDER BY start_time ASC
              LIMIT ?3",
         )?;
 
-        let rows = stmt.query_map(params![start, end, limit], |row| {
-            Ok(SessionRow {
-                id: row.get(0)?,
-                app_name: row.get(1)?,
-                window_titles: row.get(2)?,
-                project: row.get(3)?,
-                category: row.get(4)?,
-                start_time: row.get(5)?,
-                end_time: row.get(6)?,
-                duration_secs: row.get(7)?,
-                event_count: row.get(8)?,
-                metadata: row.get(9)?,
-            })
-        })?;
-
-        let mut sessions = Vec::new();
-        for r in rows {
-            sessions.push(session_from_row(r?));
-        }
-        Ok(sessions)
+        let rows = stmt.query_map(params![start, end, limit], session_from_sql)?;
+        rows.collect()
     }
 
     /// Return sessions for a given day (start_time within the day bounds).
@@ -485,43 +792,725 @@ impl Repository {
         day_end: Timestamp,
     ) -> rusqlite::Result<Vec<Session>> {
         let mut stmt = self.conn.prepare(
-            "SELECT id, app_name, window_titles, project, category, start_time, end_time, duration_secs, event_count, metadata
+            "SELECT id, app_name, window_titles, project, category, start_time, end_time, duration_secs, event_count, metadata, project_id, project_confidence, open
              FROM sessions
              WHERE start_time >= ?1 AND start_time < ?2
              ORDER BY start_time ASC",
         )?;
 
-        let rows = stmt.query_map(params![day_start, day_end], |row| {
-            Ok(SessionRow {
+        let rows = stmt.query_map(params![day_start, day_end], session_from_sql)?;
+        rows.collect()
+    }
+
+    /// Return sessions ending at or after `start` that start before `end`,
+    /// ordered by start_time ascending.
+    pub fn sessions_overlapping(
+        &self,
+        start: Timestamp,
+        end: Timestamp,
+    ) -> rusqlite::Result<Vec<Session>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, app_name, window_titles, project, category, start_time, end_time, duration_secs, event_count, metadata, project_id, project_confidence, open
+             FROM sessions
+             WHERE end_time >= ?1 AND start_time < ?2
+             ORDER BY start_time ASC",
+        )?;
+
+        let rows = stmt.query_map(params![start, end], session_from_sql)?;
+        rows.collect()
+    }
+
+    // ─── Time overrides ──────────────────────────────────────────────
+
+    pub fn insert_time_override(&self, record: &TimeOverride) -> rusqlite::Result<()> {
+        let action_str = serde_json::to_string(&record.action).unwrap();
+        self.conn.execute(
+            "INSERT INTO time_overrides (id, start_time, end_time, action, note, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                record.id.0.to_string(),
+                record.start,
+                record.end,
+                action_str,
+                record.note,
+                record.created_at,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Delete an override; returns whether it existed.
+    pub fn delete_time_override(&self, id: OverrideId) -> rusqlite::Result<bool> {
+        let deleted = self
+            .conn
+            .execute("DELETE FROM time_overrides WHERE id = ?1", [id.0.to_string()])?;
+        Ok(deleted > 0)
+    }
+
+    /// Overrides overlapping `[start, end)`, oldest first, the order they
+    /// apply in.
+    pub fn time_overrides_overlapping(
+        &self,
+        start: Timestamp,
+        end: Timestamp,
+    ) -> rusqlite::Result<Vec<TimeOverride>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, start_time, end_time, action, note, created_at
+             FROM time_overrides
+             WHERE end_time > ?1 AND start_time < ?2
+             ORDER BY created_at ASC, id ASC",
+        )?;
+
+        let rows = stmt.query_map(params![start, end], |row| {
+            let id: String = row.get(0)?;
+            let action: String = row.get(3)?;
+            Ok(TimeOverride {
+                id: OverrideId(parse_ulid(&id)),
+                start: row.get(1)?,
+                end: row.get(2)?,
+                action: serde_json::from_str(&action).expect("invalid override action in database"),
+                note: row.get(4)?,
+                created_at: row.get(5)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    // ─── Annotations ─────────────────────────────────────────────────
+
+    /// Insert an annotation with its tags, indexed for search. `start` and
+    /// `end` are stored as given; callers resolve them from the target.
+    pub fn insert_annotation(&self, annotation: &Annotation) -> rusqlite::Result<()> {
+        let (session_id, entity_id) = match &annotation.target {
+            AnnotationTarget::Range { .. } => (None, None),
+            AnnotationTarget::Session { session_id } => (Some(session_id.clone()), None),
+            AnnotationTarget::Entity { entity_id } => (None, Some(entity_id.0.to_string())),
+        };
+        let id = annotation.id.0.to_string();
+        self.transaction(|repo| {
+            repo.conn.execute(
+                "INSERT INTO annotations
+                    (id, target, start_time, end_time, session_id, entity_id, text, proposed, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    id,
+                    serde_json::to_string(&annotation.target).unwrap(),
+                    annotation.start,
+                    annotation.end,
+                    session_id,
+                    entity_id,
+                    annotation.text,
+                    annotation.proposed,
+                    annotation.created_at,
+                ],
+            )?;
+            for tag in &annotation.tags {
+                repo.conn.execute(
+                    "INSERT OR IGNORE INTO annotation_tags (annotation_id, tag) VALUES (?1, ?2)",
+                    params![id, tag],
+                )?;
+            }
+            repo.conn.execute(
+                "INSERT INTO annotations_fts (annotation_id, text, tags) VALUES (?1, ?2, ?3)",
+                params![id, annotation.text, annotation.tags.join(" ")],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Point the annotations of session `from` at `to`, which replaced it,
+    /// and give them its span; returns how many there were.
+    pub fn retarget_session_annotations(&self, from: &str, to: &Session) -> rusqlite::Result<usize> {
+        let target = AnnotationTarget::Session {
+            session_id: to.id.clone(),
+        };
+        self.conn.execute(
+            "UPDATE annotations SET target = ?2, session_id = ?3, start_time = ?4, end_time = ?5
+             WHERE session_id = ?1",
+            params![
+                from,
+                serde_json::to_string(&target).unwrap(),
+                to.id,
+                to.start_time,
+                to.end_time,
+            ],
+        )
+    }
+
+    /// Delete an annotation and its tags; returns whether it existed.
+    pub fn delete_annotation(&self, id: AnnotationId) -> rusqlite::Result<bool> {
+        let id = id.0.to_string();
+        self.transaction(|repo| {
+            repo.conn.execute(
+                "DELETE FROM annotations_fts WHERE annotation_id = ?1",
+                [&id],
+            )?;
+            let deleted = repo
+                .conn
+                .execute("DELETE FROM annotations WHERE id = ?1", [&id])?;
+            Ok(deleted > 0)
+        })
+    }
+
+    /// Mark a proposed annotation as accepted; returns whether it existed.
+    pub fn accept_annotation(&self, id: AnnotationId) -> rusqlite::Result<bool> {
+        let updated = self.conn.execute(
+            "UPDATE annotations SET proposed = 0 WHERE id = ?1",
+            [id.0.to_string()],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Annotations matching `filter`, by start time, then those without
+    /// one, oldest first.
+    pub fn annotations(&self, filter: &AnnotationFilter) -> rusqlite::Result<Vec<Annotation>> {
+        let mut cond = Conditions::default();
+        if let Some(from) = filter.from {
+            cond.push("a.end_time > ?", [Value::Integer(from)]);
+        }
+        if let Some(to) = filter.to {
+            cond.push("a.start_time < ?", [Value::Integer(to)]);
+        }
+        if let Some(tag) = &filter.tag {
+            cond.push(
+                "EXISTS (SELECT 1 FROM annotation_tags t WHERE t.annotation_id = a.id AND t.tag = ?)",
+                [Value::Text(tag.clone())],
+            );
+        }
+        if let Some(session_id) = &filter.session_id {
+            cond.push("a.session_id = ?", [Value::Text(session_id.clone())]);
+        }
+        if let Some(entity_id) = filter.entity_id {
+            cond.push("a.entity_id = ?", [Value::Text(entity_id.0.to_string())]);
+        }
+        if filter.proposed_only {
+            cond.push("a.proposed = 1", []);
+        }
+        let sql = format!(
+            "SELECT {ANNOTATION_COLUMNS}
+             FROM annotations a {}
+             ORDER BY a.start_time IS NULL, a.start_time ASC, a.created_at ASC, a.id ASC",
+            cond.sql()
+        );
+        let mut stmt = self.conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(cond.params), annotation_from_row)?;
+        rows.collect()
+    }
+
+    /// Annotations whose text or tags match the FTS5 `query`, newest first.
+    pub fn search_annotations(&self, query: &str, limit: u32) -> rusqlite::Result<Vec<Annotation>> {
+        let sql = format!(
+            "SELECT {ANNOTATION_COLUMNS}
+             FROM annotations_fts f
+             JOIN annotations a ON a.id = f.annotation_id
+             WHERE annotations_fts MATCH ?1
+             ORDER BY a.created_at DESC, a.id DESC
+             LIMIT ?2"
+        );
+        let mut stmt = self.conn.prepare(&sql)?;
+        let rows = stmt.query_map(params![query, limit], annotation_from_row)?;
+        rows.collect()
+    }
+
+    // ─── Filtered, paginated queries ─────────────────────────────────
+
+    /// Events in an optional time range matching `filter`, ordered by
+    /// `(timestamp, id)` in `order`, starting after `after`.
+    pub fn events_page(
+        &self,
+        from: Option<Timestamp>,
+        to: Option<Timestamp>,
+        filter: &Filter,
+        after: Option<&Cursor>,
+        order: Order,
+        limit: u32,
+    ) -> rusqlite::Result<Vec<StoredEvent>> {
+        let mut cond = Conditions::default();
+        if let Some(from) = from {
+            cond.push("timestamp >= ?", [Value::Integer(from)]);
+        }
+        if let Some(to) = to {
+            cond.push("timestamp <= ?", [Value::Integer(to)]);
+        }
+        cond.any_of("source", &filter.sources);
+        cond.any_of("kind", &filter.event_kinds);
+        if !filter.entity_kinds.is_empty() {
+            let mut kinds = Conditions::default();
+            kinds.any_of("kind", &filter.entity_kinds);
+            cond.push(
+                format!("subject_id IN (SELECT id FROM entities {})", kinds.sql()),
+                kinds.params,
+            );
+        }
+        if let Some(project) = &filter.project {
+            cond.project(
+                "EXISTS (SELECT 1 FROM event_context c JOIN entities p ON p.id = c.entity_id
+                         WHERE c.event_id = events.id AND p.kind = '\"project\"' AND {})",
+                "p.name",
+                project,
+            );
+        }
+        cond.after("timestamp", "id", after, order);
+
+        let sql = format!(
+            "SELECT id, timestamp, source, kind, subject_id, metadata
+             FROM events {}
+             ORDER BY timestamp {dir}, id {dir}
+             LIMIT ?",
+            cond.sql(),
+            dir = order.sql(),
+        );
+        cond.params.push(Value::Integer(limit.into()));
+        let mut stmt = self.conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(cond.params), |row| {
+            Ok(EventRow {
+                id: row.get(0)?,
+                timestamp: row.get(1)?,
+                source: row.get(2)?,
+                kind: row.get(3)?,
+                subject_id: row.get(4)?,
+                metadata: row.get(5)?,
+            })
+        })?;
+
+        let mut events = Vec::new();
+        for r in rows {
+            events.push(stored_event_from_row(r?));
+        }
+        Ok(events)
+    }
+
+    /// Full-text search matching `filter`, most recently seen first.
+    pub fn search_entities_page(
+        &self,
+        query: &str,
+        filter: &Filter,
+        after: Option<&Cursor>,
+        limit: u32,
+    ) -> rusqlite::Result<Vec<Entity>> {
+        let mut cond = Conditions::default();
+        cond.push("entities_fts MATCH ?", [Value::Text(query.to_string())]);
+        cond.any_of("e.kind", &filter.entity_kinds);
+        if let Some(project) = &filter.project {
+            cond.project(PROJECT_EDGE_TEMPLATE, "p.name", project);
+        }
+        cond.after("e.last_seen", "e.id", after, Order::Desc);
+
+        let sql = format!(
+            "SELECT e.id, e.kind, e.name, e.attributes, e.first_seen, e.last_seen
+             FROM entities_fts f
+             JOIN entities e ON e.rowid = f.rowid
+             {}
+             ORDER BY e.last_seen DESC, e.id DESC
+             LIMIT ?",
+            cond.sql()
+        );
+        cond.params.push(Value::Integer(limit.into()));
+        let mut stmt = self.conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(cond.params), |row| {
+            Ok(EntityRow {
                 id: row.get(0)?,
-                app_name: row.get(1)?,
-                window_titles: row.get(2)?,
-                project: row.get(3)?,
-                category: row.get(4)?,
-                start_time: row.get(5)?,
-                end_time: row.get(6)?,
-                duration_secs: row.get(7)?,
-                event_count: row.get(8)?,
-                metadata: row.get(9)?,
+                kind: row.get(1)?,
+                name: row.get(2)?,
+                attributes: row.get(3)?,
+                first_seen: row.get(4)?,
+                last_seen: row.get(5)?,
             })
         })?;
 
-        let mut sessions = Vec::new();
+        let mut entities = Vec::new();
         for r in rows {
-            sessions.push(session_from_row(r?));
+            entities.push(entity_from_row(r?));
         }
-        Ok(sessions)
+        Ok(entities)
     }
 
-    /// Return the end_time of the most recent session, used as watermark by the aggregator.
+    /// Entities among `ids` matching `filter`, ordered by id.
+    pub fn entities_page(
+        &self,
+        ids: &[EntityId],
+        filter: &Filter,
+        after: Option<&Cursor>,
+        limit: u32,
+    ) -> rusqlite::Result<Vec<Entity>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut cond = Conditions::default();
+        cond.push(
+            format!("e.id IN ({})", vec!["?"; ids.len()].join(", ")),
+            ids.iter().map(|id| Value::Text(id.to_string())),
+        );
+        cond.any_of("e.kind", &filter.entity_kinds);
+        if let Some(project) = &filter.project {
+            cond.project(PROJECT_EDGE_TEMPLATE, "p.name", project);
+        }
+        if let Some(cursor) = after {
+            cond.push("e.id > ?", [Value::Text(cursor.id.clone())]);
+        }
+
+        let sql = format!(
+            "SELECT e.id, e.kind, e.name, e.attributes, e.first_seen, e.last_seen
+             FROM entities e {}
+             ORDER BY e.id ASC
+             LIMIT ?",
+            cond.sql()
+        );
+        cond.params.push(Value::Integer(limit.into()));
+        let mut stmt = self.conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(cond.params), |row| {
+            Ok(EntityRow {
+                id: row.get(0)?,
+                kind: row.get(1)?,
+                name: row.get(2)?,
+                attributes: row.get(3)?,
+                first_seen: row.get(4)?,
+                last_seen: row.get(5)?,
+            })
+        })?;
+
+        let mut entities = Vec::new();
+        for r in rows {
+            entities.push(entity_from_row(r?));
+        }
+        Ok(entities)
+    }
+
+    /// Entities matching `filter.entity_kinds` and `filter.project` (the
+    /// project itself, or anything with an edge to it) that were seen at some
+    /// point during `[from, to]`, ordered by id.
+    pub fn entities_seen_between(
+        &self,
+        filter: &Filter,
+        from: Timestamp,
+        to: Timestamp,
+    ) -> rusqlite::Result<Vec<Entity>> {
+        let mut cond = Conditions::default();
+        cond.push(
+            "e.first_seen <= ? AND e.last_seen >= ?",
+            [Value::Integer(to), Value::Integer(from)],
+        );
+        cond.any_of("e.kind", &filter.entity_kinds);
+        if let Some(project) = &filter.project {
+            cond.project(PROJECT_OR_MEMBER_TEMPLATE, "p.name", project);
+        }
+
+        let sql = format!(
+            "SELECT e.id, e.kind, e.name, e.attributes, e.first_seen, e.last_seen
+             FROM entities e {}
+             ORDER BY e.id ASC",
+            cond.sql()
+        );
+        let mut stmt = self.conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(cond.params), |row| {
+            Ok(EntityRow {
+                id: row.get(0)?,
+                kind: row.get(1)?,
+                name: row.get(2)?,
+                attributes: row.get(3)?,
+                first_seen: row.get(4)?,
+                last_seen: row.get(5)?,
+            })
+        })?;
+
+        let mut entities = Vec::new();
+        for r in rows {
+            entities.push(entity_from_row(r?));
+        }
+        Ok(entities)
+    }
+
+    /// Edges at least `min_strength` strong that existed at some point during
+    /// `[from, to]`, ordered by id.
+    pub fn edges_active_between(
+        &self,
+        min_strength: f32,
+        from: Timestamp,
+        to: Timestamp,
+    ) -> rusqlite::Result<Vec<Edge>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, from_id, to_id, relation, strength, created_at, last_reinforced
+             FROM edges
+             WHERE strength >= ?1 AND created_at <= ?2 AND last_reinforced >= ?3
+             ORDER BY id ASC",
+        )?;
+        let rows = stmt.query_map(params![f64::from(min_strength), to, from], |row| {
+            Ok(EdgeRow {
+                id: row.get(0)?,
+                from_id: row.get(1)?,
+                to_id: row.get(2)?,
+                relation: row.get(3)?,
+                strength: row.get(4)?,
+                created_at: row.get(5)?,
+                last_reinforced: row.get(6)?,
+            })
+        })?;
+
+        let mut edges = Vec::new();
+        for r in rows {
+            edges.push(edge_from_row(r?));
+        }
+        Ok(edges)
+    }
+
+    /// Sessions starting in `[from, to]` matching `filter.project`, ordered by
+    /// `(start_time, id)`. Source and kind filters do not apply to sessions.
+    pub fn sessions_page(
+        &self,
+        from: Timestamp,
+        to: Timestamp,
+        filter: &Filter,
+        after: Option<&Cursor>,
+        limit: u32,
+    ) -> rusqlite::Result<Vec<Session>> {
+        let mut cond = Conditions::default();
+        cond.push(
+            "start_time >= ? AND start_time <= ?",
+            [Value::Integer(from), Value::Integer(to)],
+        );
+        if let Some(project) = &filter.project {
+            cond.project("{}", "project", project);
+        }
+        cond.after("start_time", "id", after, Order::Asc);
+
+        let sql = format!(
+            "SELECT id, app_name, window_titles, project, category, start_time, end_time, duration_secs, event_count, metadata, project_id, project_confidence, open
+             FROM sessions {}
+             ORDER BY start_time ASC, id ASC
+             LIMIT ?",
+            cond.sql()
+        );
+        cond.params.push(Value::Integer(limit.into()));
+        let mut stmt = self.conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(cond.params), session_from_sql)?;
+        rows.collect()
+    }
+
+    /// Run a compiled pipeline query; rows are read in the shape its
+    /// `output` describes.
+    pub fn run_query(&self, compiled: &Compiled) -> rusqlite::Result<QueryRows> {
+        register_local_time(&self.conn, compiled.tz)?;
+        let mut stmt = self.conn.prepare(&compiled.sql)?;
+        let params = params_from_iter(compiled.params.iter());
+        Ok(match &compiled.output {
+            Output::Events => QueryRows::Events(
+                stmt.query_map(params, |row| {
+                    Ok(stored_event_from_row(EventRow {
+                        id: row.get(0)?,
+                        timestamp: row.get(1)?,
+                        source: row.get(2)?,
+                        kind: row.get(3)?,
+                        subject_id: row.get(4)?,
+                        metadata: row.get(5)?,
+                    }))
+                })?
+                .collect::<rusqlite::Result<_>>()?,
+            ),
+            Output::Entities => QueryRows::Entities(
+                stmt.query_map(params, |row| {
+                    Ok(entity_from_row(EntityRow {
+                        id: row.get(0)?,
+                        kind: row.get(1)?,
+                        name: row.get(2)?,
+                        attributes: row.get(3)?,
+                        first_seen: row.get(4)?,
+                        last_seen: row.get(5)?,
+                    }))
+                })?
+                .collect::<rusqlite::Result<_>>()?,
+            ),
+            Output::Sessions => QueryRows::Sessions(
+                stmt.query_map(params, session_from_sql)?
+                .collect::<rusqlite::Result<_>>()?,
+            ),
+            Output::Table { columns } => {
+                let width = columns.len();
+                let rows = stmt
+                    .query_map(params, |row| {
+                        (0..width).map(|i| row.get_ref(i).map(json_value)).collect()
+                    })?
+                    .collect::<rusqlite::Result<_>>()?;
+                QueryRows::Table {
+                    columns: columns.clone(),
+                    rows,
+                }
+            }
+        })
+    }
+
+    /// Return the end_time of the most recent closed session, used as watermark by the aggregator.
     pub fn last_session_end_time(&self) -> rusqlite::Result<Option<Timestamp>> {
         self.conn
             .query_row(
-                "SELECT MAX(end_time) FROM sessions",
+                "SELECT MAX(end_time) FROM sessions WHERE open = 0",
                 [],
                 |row| row.get(0),
             )
     }
+
+    /// Return the start_time of the earliest open session.
+    pub fn first_open_session_start(&self) -> rusqlite::Result<Option<Timestamp>> {
+        self.conn.query_row(
+            "SELECT MIN(start_time) FROM sessions WHERE open = 1",
+            [],
+            |row| row.get(0),
+        )
+    }
+}
+
+// ─── Query filters and cursors ──────────────────────────────────────
+
+/// Narrows list queries. Empty lists and `None` match everything.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    pub sources: Vec<CollectorSource>,
+    pub event_kinds: Vec<EventKind>,
+    pub entity_kinds: Vec<EntityKind>,
+    /// Project path, or its last path component.
+    pub project: Option<String>,
+}
+
+/// Narrows annotation lists. `None` matches everything; a time range
+/// leaves out annotations without one.
+#[derive(Debug, Clone, Default)]
+pub struct AnnotationFilter {
+    pub from: Option<Timestamp>,
+    pub to: Option<Timestamp>,
+    pub tag: Option<String>,
+    pub session_id: Option<String>,
+    pub entity_id: Option<EntityId>,
+    /// Only annotations still awaiting acceptance.
+    pub proposed_only: bool,
+}
+
+/// Keyset pagination position: the sort key and id of the last item of the
+/// previous page. Encoded as `"<key>:<id>"` on the wire.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor {
+    pub key: i64,
+    pub id: String,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        format!("{}:{}", self.key, self.id)
+    }
+
+    pub fn decode(s: &str) -> Option<Self> {
+        let (key, id) = s.split_once(':')?;
+        Some(Self {
+            key: key.parse().ok()?,
+            id: id.to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    Asc,
+    Desc,
+}
+
+impl Order {
+    fn sql(self) -> &'static str {
+        match self {
+            Self::Asc => "ASC",
+            Self::Desc => "DESC",
+        }
+    }
+}
+
+/// Entity `e` has an edge to a project whose name matches `{}`.
+const PROJECT_EDGE_TEMPLATE: &str = "EXISTS (SELECT 1 FROM edges ed JOIN entities p ON p.id = ed.to_id
+     WHERE ed.from_id = e.id AND p.kind = '\"project\"' AND {})";
+
+/// Entity `e` is a project whose name matches `{}`, or has an edge to one.
+const PROJECT_OR_MEMBER_TEMPLATE: &str = "EXISTS (SELECT 1 FROM entities p
+     WHERE p.kind = '\"project\"' AND {}
+       AND (p.id = e.id OR EXISTS (SELECT 1 FROM edges ed WHERE ed.from_id = e.id AND ed.to_id = p.id)))";
+
+/// WHERE-clause builder with positional `?` parameters.
+#[derive(Default)]
+struct Conditions {
+    clauses: Vec<String>,
+    params: Vec<Value>,
+}
+
+impl Conditions {
+    fn push(&mut self, clause: impl Into<String>, params: impl IntoIterator<Item = Value>) {
+        self.clauses.push(clause.into());
+        self.params.extend(params);
+    }
+
+    /// `column IN (...)` over the JSON encoding used for enum columns.
+    fn any_of<T: Serialize>(&mut self, column: &str, values: &[T]) {
+        if values.is_empty() {
+            return;
+        }
+        let placeholders = vec!["?"; values.len()].join(", ");
+        self.push(
+            format!("{column} IN ({placeholders})"),
+            values
+                .iter()
+                .map(|v| Value::Text(serde_json::to_string(v).unwrap())),
+        );
+    }
+
+    /// Match `column` against a project path or its last component, inside
+    /// `template` (whose `{}` is replaced by the match expression).
+    fn project(&mut self, template: &str, column: &str, project: &str) {
+        let matches = format!("({column} = ? OR substr({column}, -length(?) - 1) = '/' || ?)");
+        self.push(
+            template.replace("{}", &matches),
+            std::iter::repeat_n(Value::Text(project.to_string()), 3),
+        );
+    }
+
+    /// Resume strictly after `cursor` in `order` on `(key, id)`.
+    fn after(&mut self, key: &str, id: &str, cursor: Option<&Cursor>, order: Order) {
+        if let Some(cursor) = cursor {
+            let op = match order {
+                Order::Asc => ">",
+                Order::Desc => "<",
+            };
+            self.push(
+                format!("({key}, {id}) {op} (?, ?)"),
+                [Value::Integer(cursor.key), Value::Text(cursor.id.clone())],
+            );
+        }
+    }
+
+    fn sql(&self) -> String {
+        if self.clauses.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", self.clauses.join(" AND "))
+        }
+    }
+}
+
+/// Rows of a pipeline query, by its `Output`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryRows {
+    Events(Vec<StoredEvent>),
+    Entities(Vec<Entity>),
+    Sessions(Vec<Session>),
+    Table {
+        columns: Vec<String>,
+        rows: Vec<Vec<serde_json::Value>>,
+    },
+}
+
+/// A column value as JSON; text and blobs become strings.
+pub(crate) fn json_value(value: ValueRef) -> serde_json::Value {
+    match value {
+        ValueRef::Null => serde_json::Value::Null,
+        ValueRef::Integer(n) => n.into(),
+        ValueRef::Real(f) => f.into(),
+        ValueRef::Text(bytes) | ValueRef::Blob(bytes) => {
+            String::from_utf8_lossy(bytes).into_owned().into()
+        }
+    }
 }
 
 // ─── Stored event (flattened, with resolved subject_id) ─────────────
@@ -547,16 +1536,47 @@ pub struct Session {
     pub app_name: String,
     pub window_titles: Vec<String>,
     pub project: Option<String>,
+    /// The entity `project` names, when the session was attributed to one.
+    #[serde(default)]
+    pub project_id: Option<EntityId>,
+    /// How sure the attribution is, from 0 to 1; 0 without a project.
+    #[serde(default)]
+    pub project_confidence: f32,
     pub category: String,
     pub start_time: Timestamp,
     pub end_time: Timestamp,
     pub duration_secs: i64,
     pub event_count: i64,
     pub metadata: Attributes,
+    /// Still being extended: the aggregator rebuilds it on its next run.
+    #[serde(default)]
+    pub open: bool,
 }
 
 // ─── Internal row types for deserialization ──────────────────────────
 
+const ANNOTATION_COLUMNS: &str =
+    "a.id, a.target, a.start_time, a.end_time, a.text, a.proposed, a.created_at,
+     (SELECT json_group_array(tag) FROM annotation_tags t WHERE t.annotation_id = a.id)";
+
+fn annotation_from_row(row: &rusqlite::Row) -> rusqlite::Result<Annotation> {
+    let id: String = row.get(0)?;
+    let target: String = row.get(1)?;
+    let tags: String = row.get(7)?;
+    let mut tags: Vec<String> = serde_json::from_str(&tags).expect("invalid tags in database");
+    tags.sort();
+    Ok(Annotation {
+        id: AnnotationId(parse_ulid(&id)),
+        target: serde_json::from_str(&target).expect("invalid annotation target in database"),
+        text: row.get(4)?,
+        tags,
+        start: row.get(2)?,
+        end: row.get(3)?,
+        proposed: row.get(5)?,
+        created_at: row.get(6)?,
+    })
+}
+
 struct EntityRow {
     id: String,
     kind: String,
@@ -596,6 +1616,29 @@ struct SessionRow {
     duration_secs: i64,
     event_count: i64,
     metadata: String,
+    project_id: Option<String>,
+    project_confidence: f64,
+    open: bool,
+}
+
+/// Read a session selected with the columns of the `sessions` table in
+/// declaration order.
+fn session_from_sql(row: &rusqlite::Row<'_>) -> rusqlite::Result<Session> {
+    Ok(session_from_row(SessionRow {
+        id: row.get(0)?,
+        app_name: row.get(1)?,
+        window_titles: row.get(2)?,
+        project: row.get(3)?,
+        category: row.get(4)?,
+        start_time: row.get(5)?,
+        end_time: row.get(6)?,
+        duration_secs: row.get(7)?,
+        event_count: row.get(8)?,
+        metadata: row.get(9)?,
+        project_id: row.get(10)?,
+        project_confidence: row.get(11)?,
+        open: row.get(12)?,
+    }))
 }
 
 fn session_from_row(r: SessionRow) -> Session {
@@ -604,12 +1647,15 @@ fn session_from_row(r: SessionRow) -> Session {
         app_name: r.app_name,
         window_titles: serde_json::from_str(&r.window_titles).unwrap_or_default(),
         project: r.project,
+        project_id: r.project_id.map(|id| EntityId(parse_ulid(&id))),
+        project_confidence: r.project_confidence as f32,
         category: r.category,
         start_time: r.start_time,
         end_time: r.end_time,
         duration_secs: r.duration_secs,
         event_count: r.event_count,
         metadata: serde_json::from_str(&r.metadata).unwrap_or_default(),
+        open: r.open,
     }
 }
 
@@ -651,6 +1697,25 @@ fn edge_from_row(r: EdgeRow) -> Edge {
     }
 }
 
+/// `local_hour(ms)` and `local_weekday(ms)` (0 = Sunday) in `tz`, for the
+/// `hour` and `weekday` fields of compiled queries.
+fn register_local_time(conn: &Connection, tz: chrono_tz::Tz) -> rusqlite::Result<()> {
+    use chrono::{Datelike, Timelike};
+    use rusqlite::functions::FunctionFlags;
+
+    let local = move |t: Option<Timestamp>| {
+        t.and_then(chrono::DateTime::from_timestamp_millis)
+            .map(|t| t.with_timezone(&tz))
+    };
+    let flags = FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC;
+    conn.create_scalar_function("local_hour", 1, flags, move |ctx| {
+        Ok(local(ctx.get(0)?).map(|t| i64::from(t.hour())))
+    })?;
+    conn.create_scalar_function("local_weekday", 1, flags, move |ctx| {
+        Ok(local(ctx.get(0)?).map(|t| i64::from(t.weekday().num_days_from_sunday())))
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -836,15 +1901,39 @@ mod tests {
             app_name: app.to_string(),
             window_titles: vec!["window1".to_string()],
             project: None,
+            project_id: None,
+            project_confidence: 0.0,
             category: category.to_string(),
             start_time: start,
             end_time: end,
             duration_secs: (end - start) / 1000,
             event_count: 5,
             metadata: HashMap::new(),
+            open: false,
         }
     }
 
+    #[test]
+    fn pipeline_hours_and_weekdays_use_the_query_zone() {
+        let repo = Repository::open_in_memory().unwrap();
+        // Tuesday 2024-03-05 23:30 UTC is Wednesday 08:30 in Tokyo
+        let start = 1_709_681_400_000;
+        repo.insert_session(&make_session("VS Code", "coding", start, start + 60_000))
+            .unwrap();
+
+        let count = |src: &str, tz: chrono_tz::Tz| {
+            let compiled = crate::query::compile(src, start, tz).unwrap();
+            match repo.run_query(&compiled).unwrap() {
+                QueryRows::Sessions(sessions) => sessions.len(),
+                _ => panic!("expected sessions"),
+            }
+        };
+        let tokyo = chrono_tz::Asia::Tokyo;
+        assert_eq!(count("sessions where hour = 8 and weekday = wednesday", tokyo), 1);
+        assert_eq!(count("sessions where hour = 23", tokyo), 0);
+        assert_eq!(count("sessions where hour = 23 and weekday = tuesday", chrono_tz::UTC), 1);
+    }
+
     #[test]
     fn insert_and_query_session() {
         let repo = Repository::open_in_memory().unwrap();
@@ -897,4 +1986,153 @@ mod tests {
         let last = repo.last_session_end_time().unwrap();
         assert_eq!(last, Some(9000));
     }
+
+    /// Insert `count` file events one ms apart; even ones belong to `/work/cronos`.
+    fn seed_events(repo: &Repository, count: i64) -> Vec<EventId> {
+        let project = make_entity(EntityKind::Project, "/work/cronos", 0);
+        let url = make_entity(EntityKind::Url, "https://example.com", 0);
+        repo.insert_entity(&project).unwrap();
+        repo.insert_entity(&url).unwrap();
+        (0..count)
+            .map(|i| {
+                let (subject, source, kind) = if i % 3 == 2 {
+                    (url.id, CollectorSource::Browser, EventKind::UrlVisited)
+                } else {
+                    let file = make_entity(EntityKind::File, &format!("/work/cronos/{i}.rs"), i);
+                    repo.insert_entity(&file).unwrap();
+                    (file.id, CollectorSource::Filesystem, EventKind::FileModified)
+                };
+                let event = Event {
+                    id: EventId::new(),
+                    timestamp: 1000 + i,
+                    source,
+                    kind,
+                    subject: EntityRef {
+                        kind: EntityKind::File,
+                        identity: String::new(),
+                        attributes: HashMap::new(),
+                    },
+                    context: vec![],
+                    metadata: HashMap::new(),
+                };
+                let context = if i % 2 == 0 { vec![project.id] } else { vec![] };
+                repo.insert_event(&event, subject, &context).unwrap();
+                event.id
+            })
+            .collect()
+    }
+
+    #[test]
+    fn events_page_walks_keyset_cursor() {
+        let repo = Repository::open_in_memory().unwrap();
+        let ids = seed_events(&repo, 10);
+        let filter = Filter::default();
+
+        let mut seen = Vec::new();
+        let mut after: Option<Cursor> = None;
+        loop {
+            let page = repo
+                .events_page(Some(0), Some(i64::MAX), &filter, after.as_ref(), Order::Asc, 4)
+                .unwrap();
+            if page.is_empty() {
+                break;
+            }
+            let last = page.last().unwrap();
+            after = Some(Cursor {
+                key: last.timestamp,
+                id: last.id.to_string(),
+            });
+            seen.extend(page.into_iter().map(|e| e.id));
+        }
+        assert_eq!(seen, ids);
+
+        // Descending order resumes below the cursor
+        let cursor = Cursor {
+            key: 1005,
+            id: ids[5].to_string(),
+        };
+        let page = repo
+            .events_page(None, None, &filter, Some(&cursor), Order::Desc, 2)
+            .unwrap();
+        assert_eq!(page.iter().map(|e| e.id).collect::<Vec<_>>(), vec![ids[4], ids[3]]);
+    }
+
+    #[test]
+    fn events_page_applies_filters() {
+        let repo = Repository::open_in_memory().unwrap();
+        seed_events(&repo, 9);
+        let page = |filter: Filter| {
+            repo.events_page(None, None, &filter, None, Order::Asc, 100)
+                .unwrap()
+                .into_iter()
+                .map(|e| e.timestamp - 1000)
+                .collect::<Vec<_>>()
+        };
+
+        let browser = Filter {
+            sources: vec![CollectorSource::Browser],
+            ..Filter::default()
+        };
+        assert_eq!(page(browser), vec![2, 5, 8]);
+
+        let files = Filter {
+            entity_kinds: vec![EntityKind::File],
+            event_kinds: vec![EventKind::FileModified],
+            ..Filter::default()
+        };
+        assert_eq!(page(files), vec![0, 1, 3, 4, 6, 7]);
+
+        // Project matches by full path or last component
+        for project in ["/work/cronos", "cronos"] {
+            let in_project = Filter {
+                project: Some(project.to_string()),
+                ..Filter::default()
+            };
+            assert_eq!(page(in_project), vec![0, 2, 4, 6, 8]);
+        }
+        let elsewhere = Filter {
+            project: Some("ronos".to_string()),
+            ..Filter::default()
+        };
+        assert!(page(elsewhere).is_empty());
+    }
+
+    #[test]
+    fn sessions_page_filters_by_project() {
+        let repo = Repository::open_in_memory().unwrap();
+        for (i, project) in [Some("/work/cronos"), None, Some("/work/other")].iter().enumerate() {
+            let start = 1000 * (i as i64 + 1);
+            let mut session = make_session("VS Code", "coding", start, start + 500);
+            session.project = project.map(str::to_string);
+            repo.insert_session(&session).unwrap();
+        }
+        let filter = Filter {
+            project: Some("cronos".to_string()),
+            ..Filter::default()
+        };
+        let sessions = repo.sessions_page(0, 10_000, &filter, None, 10).unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].start_time, 1000);
+
+        let all = repo.sessions_page(0, 10_000, &Filter::default(), None, 2).unwrap();
+        let after = Cursor {
+            key: all[1].start_time,
+            id: all[1].id.clone(),
+        };
+        let rest = repo
+            .sessions_page(0, 10_000, &Filter::default(), Some(&after), 2)
+            .unwrap();
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].start_time, 3000);
+    }
+
+    #[test]
+    fn cursor_roundtrips() {
+        let cursor = Cursor {
+            key: -5,
+            id: "01ARZ3NDEKTSV4RRFFQ69G5FAV".to_string(),
+        };
+        assert_eq!(Cursor::decode(&cursor.encode()), Some(cursor));
+        assert_eq!(Cursor::decode("garbage"), None);
+    }
 }
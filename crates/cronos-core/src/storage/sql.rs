@@ -0,0 +1,259 @@
+//! Ad-hoc SQL for power users, on its own read-only connection.
+//!
+//! Three layers keep statements read-only: the connection is opened
+//! `SQLITE_OPEN_READ_ONLY` with `query_only` set, an authorizer denies
+//! anything but reads (no writes, ATTACH, transactions or pragma changes),
+//! and prepared statements must report themselves read-only. A progress
+//! handler interrupts statements that run past the time limit.
+
+use rusqlite::hooks::{AuthAction, AuthContext, Authorization};
+use rusqlite::{Connection, ErrorCode, OpenFlags};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::encryption::DbKey;
+use super::repo::json_value;
+
+/// Pragmas that only describe the schema and may be read.
+const SCHEMA_PRAGMAS: &[&str] = &[
+    "table_info",
+    "table_xinfo",
+    "table_list",
+    "index_list",
+    "index_info",
+    "index_xinfo",
+    "foreign_key_list",
+];
+
+#[derive(Debug, thiserror::Error)]
+pub enum SqlError {
+    #[error("not allowed in read-only SQL: {0}")]
+    Forbidden(String),
+    #[error("statement exceeded the {0} ms time limit")]
+    Timeout(u128),
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+}
+
+/// Result of a statement: column names and at most `max_rows` rows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SqlRows {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<serde_json::Value>>,
+    /// The statement produced more than `max_rows` rows.
+    pub truncated: bool,
+}
+
+pub struct SqlReader {
+    conn: Connection,
+    max_rows: usize,
+    timeout: Duration,
+    /// When the running statement must stop; `None` between statements.
+    deadline: Arc<Mutex<Option<Instant>>>,
+    /// What the authorizer last refused, for the error message.
+    denied: Arc<Mutex<Option<String>>>,
+}
+
+impl SqlReader {
+    /// Open a read-only connection to the database at `path`, which must
+    /// already exist, encrypted with `key` if given.
+    pub fn open(
+        path: &Path,
+        key: Option<&DbKey>,
+        max_rows: usize,
+        timeout: Duration,
+    ) -> rusqlite::Result<Self> {
+        let conn = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        if let Some(key) = key {
+            conn.pragma_update(None, "key", key.pragma_value())?;
+        }
+        conn.pragma_update(None, "query_only", true)?;
+        // Wait out the writer's locks, but no longer than a statement may run.
+        conn.busy_timeout(timeout)?;
+
+        let denied = Arc::new(Mutex::new(None));
+        let denied_by_hook = Arc::clone(&denied);
+        conn.authorizer(Some(move |ctx: AuthContext<'_>| match refusal(&ctx.action) {
+            None => Authorization::Allow,
+            Some(what) => {
+                *denied_by_hook.lock().unwrap() = Some(what);
+                Authorization::Deny
+            }
+        }))?;
+
+        let deadline: Arc<Mutex<Option<Instant>>> = Arc::new(Mutex::new(None));
+        let deadline_by_hook = Arc::clone(&deadline);
+        conn.progress_handler(
+            1000,
+            Some(move || {
+                deadline_by_hook
+                    .lock()
+                    .unwrap()
+                    .is_some_and(|deadline| Instant::now() >= deadline)
+            }),
+        )?;
+
+        Ok(Self {
+            conn,
+            max_rows,
+            timeout,
+            deadline,
+            denied,
+        })
+    }
+
+    /// Run one read-only statement.
+    pub fn query(&self, statement: &str) -> Result<SqlRows, SqlError> {
+        *self.denied.lock().unwrap() = None;
+        *self.deadline.lock().unwrap() = Some(Instant::now() + self.timeout);
+        let result = self.run(statement);
+        *self.deadline.lock().unwrap() = None;
+
+        result.map_err(|e| match e {
+            SqlError::Sqlite(rusqlite::Error::SqliteFailure(failure, _))
+                if failure.code == ErrorCode::OperationInterrupted =>
+            {
+                SqlError::Timeout(self.timeout.as_millis())
+            }
+            SqlError::Sqlite(rusqlite::Error::SqliteFailure(failure, message))
+                if failure.code == ErrorCode::AuthorizationForStatementDenied =>
+            {
+                let what = self.denied.lock().unwrap().take();
+                SqlError::Forbidden(what.or(message).unwrap_or_else(|| "statement".to_string()))
+            }
+            other => other,
+        })
+    }
+
+    fn run(&self, statement: &str) -> Result<SqlRows, SqlError> {
+        let mut stmt = self.conn.prepare(statement)?;
+        if !stmt.readonly() {
+            return Err(SqlError::Forbidden("statements that modify the database".to_string()));
+        }
+        let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+        let width = columns.len();
+
+        let mut rows = Vec::new();
+        let mut truncated = false;
+        let mut cursor = stmt.query([])?;
+        while let Some(row) = cursor.next()? {
+            if rows.len() == self.max_rows {
+                truncated = true;
+                break;
+            }
+            rows.push(
+                (0..width)
+                    .map(|i| row.get_ref(i).map(json_value))
+                    .collect::<rusqlite::Result<_>>()?,
+            );
+        }
+        Ok(SqlRows {
+            columns,
+            rows,
+            truncated,
+        })
+    }
+}
+
+/// Why `action` is refused, or `None` when it only reads.
+fn refusal(action: &AuthAction<'_>) -> Option<String> {
+    match action {
+        AuthAction::Select | AuthAction::Read { .. } | AuthAction::Function { .. } => None,
+        AuthAction::Recursive => None,
+        AuthAction::Pragma { pragma_name, .. }
+            if SCHEMA_PRAGMAS.contains(&pragma_name.to_ascii_lowercase().as_str()) => None,
+        AuthAction::Pragma { pragma_name, .. } => Some(format!("PRAGMA {pragma_name}")),
+        AuthAction::Attach { .. } => Some("ATTACH".to_string()),
+        AuthAction::Detach { .. } => Some("DETACH".to_string()),
+        AuthAction::Transaction { .. } | AuthAction::Savepoint { .. } => {
+            Some("transactions".to_string())
+        }
+        other => Some(format!("{other:?}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Repository;
+    use tempfile::TempDir;
+
+    fn reader(dir: &TempDir, max_rows: usize, timeout_ms: u64) -> SqlReader {
+        let path = dir.path().join("cronos.db");
+        Repository::open(&path).unwrap();
+        SqlReader::open(&path, None, max_rows, Duration::from_millis(timeout_ms)).unwrap()
+    }
+
+    #[test]
+    fn selects_return_columns_and_rows() {
+        let dir = TempDir::new().unwrap();
+        let sql = reader(&dir, 100, 5000);
+        let rows = sql
+            .query("SELECT 1 AS one, 'two' AS two, NULL AS three, (SELECT COUNT(*) FROM events) AS n")
+            .unwrap();
+        assert_eq!(rows.columns, ["one", "two", "three", "n"]);
+        assert_eq!(
+            rows.rows,
+            vec![vec![
+                serde_json::json!(1),
+                serde_json::json!("two"),
+                serde_json::Value::Null,
+                serde_json::json!(0),
+            ]]
+        );
+        assert!(!rows.truncated);
+
+        let columns = sql.query("PRAGMA table_info(events)").unwrap();
+        assert!(!columns.rows.is_empty());
+    }
+
+    #[test]
+    fn rows_beyond_the_limit_are_cut_off() {
+        let dir = TempDir::new().unwrap();
+        let sql = reader(&dir, 3, 5000);
+        let rows = sql
+            .query("WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 10) SELECT i FROM n")
+            .unwrap();
+        assert_eq!(rows.rows.len(), 3);
+        assert!(rows.truncated);
+    }
+
+    #[test]
+    fn writes_and_escapes_are_forbidden() {
+        let dir = TempDir::new().unwrap();
+        let sql = reader(&dir, 100, 5000);
+        for statement in [
+            "DELETE FROM events",
+            "INSERT INTO entities (id) VALUES ('x')",
+            "DROP TABLE events",
+            "CREATE TABLE t (x)",
+            "ATTACH DATABASE ':memory:' AS other",
+            "PRAGMA query_only = OFF",
+            "PRAGMA journal_mode = DELETE",
+            "BEGIN",
+        ] {
+            let err = sql.query(statement).unwrap_err();
+            assert!(matches!(err, SqlError::Forbidden(_)), "{statement}: {err}");
+        }
+        assert!(matches!(
+            sql.query("SELECT 1; DELETE FROM events").unwrap_err(),
+            SqlError::Forbidden(_) | SqlError::Sqlite(rusqlite::Error::MultipleStatement)
+        ));
+        // The reader keeps working after a refusal
+        assert_eq!(sql.query("SELECT 2").unwrap().rows, vec![vec![serde_json::json!(2)]]);
+    }
+
+    #[test]
+    fn long_statements_time_out() {
+        let dir = TempDir::new().unwrap();
+        let sql = reader(&dir, 100, 50);
+        let err = sql
+            .query("WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n) SELECT COUNT(*) FROM n")
+            .unwrap_err();
+        assert!(matches!(err, SqlError::Timeout(50)), "{err}");
+    }
+}
@@ -0,0 +1,278 @@
+//! Day, week and month summaries: the totals a reader would otherwise add
+//! up from raw sessions, computed once in the daemon.
+
+use crate::metrics::FocusMetrics;
+use crate::storage::repo::{AnnotationFilter, Session};
+use crate::storage::Repository;
+use chrono::{Datelike, Days, Months, NaiveDate, Offset, Timelike};
+use chrono_tz::Tz;
+use cronos_common::tz;
+use cronos_model::{EntityId, EntityKind, Timestamp};
+use cronos_proto::{CountTotal, Period, SummaryInfo, TimeTotal};
+use std::collections::HashMap;
+
+/// Entries kept in the top files, top domains and focus block lists.
+const TOP_N: usize = 10;
+const HOUR_MS: i64 = 3_600_000;
+
+/// `[start, end)` of the `period` containing `anchor`, in `tz`. Weeks start
+/// on Monday.
+pub fn period_bounds(period: Period, anchor: NaiveDate, tz: Tz) -> (Timestamp, Timestamp) {
+    let (first, next) = match period {
+        Period::Day => (anchor, anchor + Days::new(1)),
+        Period::Week => {
+            let monday = anchor.week(chrono::Weekday::Mon).first_day();
+            (monday, monday + Days::new(7))
+        }
+        Period::Month => {
+            let first = anchor.with_day(1).expect("every month has a first day");
+            (first, first + Months::new(1))
+        }
+    };
+    (tz::day_start(tz, first), tz::day_start(tz, next))
+}
+
+/// Summarize the sessions starting in the `period` containing `anchor`, and
+/// the events and annotations in it. Focus blocks and context switches are
+/// those `focus` reports for the period.
+pub fn summarize(
+    repo: &Repository,
+    period: Period,
+    anchor: NaiveDate,
+    tz: Tz,
+    focus: &FocusMetrics,
+) -> rusqlite::Result<SummaryInfo> {
+    let (start, end) = period_bounds(period, anchor, tz);
+    let sessions = repo.sessions_for_day(start, end)?;
+    let files = repo.subject_event_counts(start, end, &EntityKind::File)?;
+    let urls = repo.subject_event_counts(start, end, &EntityKind::Url)?;
+    let annotations = repo.annotations(&AnnotationFilter {
+        from: Some(start),
+        to: Some(end),
+        ..AnnotationFilter::default()
+    })?;
+
+    let mut domains: HashMap<String, u64> = HashMap::new();
+    for (url, count) in urls {
+        if let Some(domain) = domain(&url) {
+            *domains.entry(domain.to_string()).or_default() += count;
+        }
+    }
+    let mut focus = focus.measure(&sessions, start, end);
+    focus.blocks.truncate(TOP_N);
+
+    Ok(SummaryInfo {
+        start,
+        end,
+        timezone: tz.name().to_string(),
+        total_secs: sessions.iter().map(|s| s.duration_secs).sum(),
+        session_count: sessions.len() as u64,
+        categories: totals(&sessions, |s| Some((s.category.clone(), None))),
+        apps: totals(&sessions, |s| Some((s.app_name.clone(), None))),
+        projects: totals(&sessions, |s| s.project.clone().map(|p| (p, s.project_id))),
+        hours: hours(&sessions, tz),
+        top_files: top(files),
+        top_domains: top(domains.into_iter().collect()),
+        focus_blocks: focus.blocks,
+        context_switches: focus.context_switches,
+        annotations,
+    })
+}
+
+/// Time per `key`, most first; sessions without a key are left out.
+fn totals(
+    sessions: &[Session],
+    key: impl Fn(&Session) -> Option<(String, Option<EntityId>)>,
+) -> Vec<TimeTotal> {
+    let mut secs: HashMap<(String, Option<EntityId>), i64> = HashMap::new();
+    for session in sessions {
+        if let Some(key) = key(session) {
+            *secs.entry(key).or_default() += session.duration_secs;
+        }
+    }
+    let mut totals: Vec<_> = secs
+        .into_iter()
+        .map(|((name, id), secs)| TimeTotal { name, id, secs })
+        .collect();
+    totals.sort_by(|a, b| b.secs.cmp(&a.secs).then_with(|| a.name.cmp(&b.name)));
+    totals
+}
+
+/// Seconds of session time in each local hour of the day.
+fn hours(sessions: &[Session], tz: Tz) -> Vec<i64> {
+    let mut ms = [0i64; 24];
+    for session in sessions {
+        let mut t = session.start_time;
+        while t < session.end_time {
+            let local = chrono::DateTime::from_timestamp_millis(t)
+                .unwrap_or_default()
+                .with_timezone(&tz);
+            let offset = local.offset().fix().local_minus_utc() as i64 * 1000;
+            let next_hour = ((t + offset).div_euclid(HOUR_MS) + 1) * HOUR_MS - offset;
+            let until = next_hour.min(session.end_time);
+            ms[local.hour() as usize] += until - t;
+            t = until;
+        }
+    }
+    ms.iter().map(|ms| ms / 1000).collect()
+}
+
+fn top(mut counts: Vec<(String, u64)>) -> Vec<CountTotal> {
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts
+        .into_iter()
+        .take(TOP_N)
+        .map(|(name, count)| CountTotal { name, count })
+        .collect()
+}
+
+/// Host of `url` without a leading `www.`.
+fn domain(url: &str) -> Option<&str> {
+    let rest = url.split_once("://")?.1;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit('@').next()?.split(':').next()?;
+    (!host.is_empty()).then(|| host.strip_prefix("www.").unwrap_or(host))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures as fixtures;
+    use cronos_common::config::FocusConfig;
+    use cronos_model::{
+        Annotation, AnnotationId, AnnotationTarget, EntityRef, Event, EventId, EventKind,
+    };
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    fn session(
+        app: &str,
+        category: &str,
+        project: Option<&str>,
+        start: Timestamp,
+        mins: i64,
+    ) -> Session {
+        Session {
+            project: project.map(String::from),
+            ..fixtures::session(app, category, start, start + mins * 60_000)
+        }
+    }
+
+    #[test]
+    fn periods_cover_the_week_and_month_of_the_anchor() {
+        let utc = Tz::UTC;
+        let day = |d| tz::day_start(utc, date(d));
+        // 2024-03-06 is a Wednesday
+        assert_eq!(
+            period_bounds(Period::Week, date("2024-03-06"), utc),
+            (day("2024-03-04"), day("2024-03-11"))
+        );
+        assert_eq!(
+            period_bounds(Period::Month, date("2024-02-14"), utc),
+            (day("2024-02-01"), day("2024-03-01"))
+        );
+        assert_eq!(
+            period_bounds(Period::Day, date("2024-12-31"), utc),
+            (day("2024-12-31"), day("2025-01-01"))
+        );
+    }
+
+    #[test]
+    fn summary_totals_sessions_and_events() {
+        let repo = Repository::open_in_memory().unwrap();
+        // 2024-03-05 09:00 UTC
+        let nine = 1_709_629_200_000;
+        for s in [
+            session("Code", "coding", Some("cronos"), nine, 30),
+            session("Code", "coding", Some("cronos"), nine + 30 * 60_000, 45),
+            session("Slack", "communication", None, nine + 76 * 60_000, 10),
+            session("Code", "coding", Some("other"), nine + 87 * 60_000, 20),
+            // The next day
+            session("Code", "coding", Some("cronos"), nine + 86_400_000, 60),
+        ] {
+            repo.insert_session(&s).unwrap();
+        }
+        let visit = |url: &str, kind: EntityKind, at: Timestamp| {
+            let entity = fixtures::entity(kind.clone(), url, at);
+            repo.insert_entity(&entity).unwrap();
+            let event = Event {
+                id: EventId::new(),
+                timestamp: at,
+                source: cronos_model::CollectorSource::Browser,
+                kind: EventKind::UrlVisited,
+                subject: EntityRef {
+                    kind,
+                    identity: url.to_string(),
+                    attributes: HashMap::new(),
+                },
+                context: vec![],
+                metadata: HashMap::new(),
+            };
+            repo.insert_event(&event, entity.id, &[]).unwrap();
+        };
+        visit("https://docs.rs/chrono", EntityKind::Url, nine);
+        visit("https://www.docs.rs/tokio", EntityKind::Url, nine + 1);
+        visit("https://github.com/x:8080", EntityKind::Url, nine + 2);
+        visit("/work/cronos/src/main.rs", EntityKind::File, nine + 3);
+        for start in [nine, nine + 86_400_000] {
+            let end = start + 3_600_000;
+            repo.insert_annotation(&Annotation {
+                id: AnnotationId::new(),
+                target: AnnotationTarget::Range { start, end },
+                text: "sprint planning".to_string(),
+                tags: vec![],
+                start: Some(start),
+                end: Some(end),
+                proposed: false,
+                created_at: start,
+            })
+            .unwrap();
+        }
+
+        let focus = FocusMetrics::new(&FocusConfig::default());
+        let summary = summarize(&repo, Period::Day, date("2024-03-05"), Tz::UTC, &focus).unwrap();
+        assert_eq!(summary.session_count, 4);
+        assert_eq!(summary.total_secs, 105 * 60);
+        let names = |totals: &[TimeTotal]| {
+            totals
+                .iter()
+                .map(|t| (t.name.clone(), t.secs / 60))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(&summary.categories),
+            [
+                ("coding".to_string(), 95),
+                ("communication".to_string(), 10)
+            ]
+        );
+        assert_eq!(
+            names(&summary.projects),
+            [("cronos".to_string(), 75), ("other".to_string(), 20)]
+        );
+        assert_eq!(summary.hours[9], 60 * 60);
+        assert_eq!(summary.hours[10], 45 * 60);
+        assert_eq!(summary.hours.iter().sum::<i64>(), summary.total_secs);
+        assert_eq!(
+            summary.top_domains[0],
+            CountTotal {
+                name: "docs.rs".to_string(),
+                count: 2
+            }
+        );
+        assert_eq!(summary.top_domains[1].name, "github.com");
+        assert_eq!(summary.top_files[0].name, "/work/cronos/src/main.rs");
+        // Matches the focus report: "other" is too short to be a block
+        assert_eq!(summary.focus_blocks.len(), 1);
+        assert_eq!(summary.focus_blocks[0].secs, 75 * 60);
+        assert_eq!(summary.focus_blocks[0].project, "cronos");
+        assert_eq!(summary.context_switches, 2);
+        let report = focus.report(&repo, summary.start, summary.end).unwrap();
+        assert_eq!(summary.focus_blocks, report.blocks);
+        assert_eq!(summary.context_switches, report.context_switches);
+        assert_eq!(summary.annotations.len(), 1);
+        assert_eq!(summary.annotations[0].start, Some(nine));
+    }
+}
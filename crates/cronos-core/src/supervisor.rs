@@ -0,0 +1,342 @@
+use cronos_common::config::{CollectorsConfig, SupervisorConfig};
+use cronos_model::CollectorSource;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// How to launch a collector process.
+#[derive(Debug, Clone)]
+pub struct CollectorSpec {
+    /// Name the collector reports in its handshake (also the binary name).
+    pub name: String,
+    pub source: CollectorSource,
+    pub binary: PathBuf,
+    pub args: Vec<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SupervisorError {
+    #[error("unknown collector: {0}")]
+    UnknownCollector(String),
+    #[error("failed to spawn {name}: {source}")]
+    Spawn {
+        name: String,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+/// Lifecycle state of a single supervised collector.
+struct Supervised {
+    spec: CollectorSpec,
+    child: Option<Child>,
+    /// Whether the collector should be running (false after an explicit stop).
+    desired: bool,
+    restarts: u32,
+    backoff: Duration,
+    next_restart: Option<Instant>,
+    last_started: Option<Instant>,
+}
+
+/// Owns collector processes: spawns them, reaps them when they exit and
+/// restarts them with exponential backoff.
+pub struct Supervisor {
+    collectors: HashMap<String, Supervised>,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Supervisor {
+    pub fn new(specs: Vec<CollectorSpec>, config: &SupervisorConfig) -> Self {
+        let initial_backoff = Duration::from_millis(config.initial_backoff_ms);
+        let collectors = specs
+            .into_iter()
+            .map(|spec| {
+                (
+                    spec.name.clone(),
+                    Supervised {
+                        spec,
+                        child: None,
+                        desired: false,
+                        restarts: 0,
+                        backoff: initial_backoff,
+                        next_restart: None,
+                        last_started: None,
+                    },
+                )
+            })
+            .collect();
+        Self {
+            collectors,
+            initial_backoff,
+            max_backoff: Duration::from_millis(config.max_backoff_ms),
+        }
+    }
+
+    /// Build a supervisor for the collectors enabled in `collectors`, looking
+    /// for their binaries in `bin_dir`. Collectors whose binary is missing are skipped.
+    pub fn from_config(
+        collectors: &CollectorsConfig,
+        config: &SupervisorConfig,
+        bin_dir: &Path,
+    ) -> Self {
+        let mut specs = Vec::new();
+        if collectors.fs.enabled {
+            specs.push(("cronos-collect-fs", CollectorSource::Filesystem));
+        }
+        if collectors.appmon.enabled {
+            specs.push(("cronos-collect-appmon", CollectorSource::AppMonitor));
+        }
+        let specs = specs
+            .into_iter()
+            .filter_map(|(name, source)| {
+                let binary = bin_dir.join(name);
+                if !binary.exists() {
+                    tracing::warn!(collector = name, path = %binary.display(), "collector binary not found");
+                    return None;
+                }
+                Some(CollectorSpec {
+                    name: name.to_string(),
+                    source,
+                    binary,
+                    args: vec![],
+                })
+            })
+            .collect();
+        Self::new(specs, config)
+    }
+
+    /// Names of all collectors known to the supervisor.
+    pub fn names(&self) -> Vec<String> {
+        self.collectors.keys().cloned().collect()
+    }
+
+    pub fn spec(&self, name: &str) -> Option<&CollectorSpec> {
+        self.collectors.get(name).map(|c| &c.spec)
+    }
+
+    /// PID of the running process for `name`, if any.
+    pub fn pid(&self, name: &str) -> Option<u32> {
+        self.collectors
+            .get(name)
+            .and_then(|c| c.child.as_ref())
+            .map(|child| child.id())
+    }
+
+    pub fn restarts(&self, name: &str) -> u32 {
+        self.collectors.get(name).map(|c| c.restarts).unwrap_or(0)
+    }
+
+    /// Start every known collector.
+    pub fn start_all(&mut self) {
+        for name in self.names() {
+            if let Err(e) = self.start(&name) {
+                tracing::warn!("{e}");
+            }
+        }
+    }
+
+    /// Start a collector. Does nothing if it is already running.
+    pub fn start(&mut self, name: &str) -> Result<(), SupervisorError> {
+        let c = self
+            .collectors
+            .get_mut(name)
+            .ok_or_else(|| SupervisorError::UnknownCollector(name.to_string()))?;
+        c.desired = true;
+        c.next_restart = None;
+        if c.child.is_some() {
+            return Ok(());
+        }
+        spawn(c)
+    }
+
+    /// Stop a collector and keep it stopped until the next `start`.
+    pub fn stop(&mut self, name: &str) -> Result<(), SupervisorError> {
+        let c = self
+            .collectors
+            .get_mut(name)
+            .ok_or_else(|| SupervisorError::UnknownCollector(name.to_string()))?;
+        c.desired = false;
+        c.next_restart = None;
+        c.backoff = self.initial_backoff;
+        kill(c);
+        Ok(())
+    }
+
+    /// Stop and immediately start a collector again.
+    pub fn restart(&mut self, name: &str) -> Result<(), SupervisorError> {
+        self.stop(name)?;
+        self.start(name)
+    }
+
+    /// Stop every collector (used on daemon shutdown).
+    pub fn stop_all(&mut self) {
+        for c in self.collectors.values_mut() {
+            c.desired = false;
+            c.next_restart = None;
+            kill(c);
+        }
+    }
+
+    /// Reap exited collectors and restart those whose backoff has elapsed.
+    pub fn tick(&mut self, now: Instant) {
+        for c in self.collectors.values_mut() {
+            if let Some(child) = c.child.as_mut() {
+                match child.try_wait() {
+                    Ok(Some(status)) => {
+                        tracing::warn!(collector = %c.spec.name, %status, "collector exited");
+                        c.child = None;
+                        // A collector that stayed up for a while gets a fresh backoff.
+                        if c.last_started.is_some_and(|t| now.duration_since(t) > self.max_backoff) {
+                            c.backoff = self.initial_backoff;
+                        }
+                        if c.desired {
+                            c.next_restart = Some(now + c.backoff);
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => tracing::warn!(collector = %c.spec.name, "try_wait failed: {e}"),
+                }
+            }
+
+            if !c.desired || c.child.is_some() {
+                continue;
+            }
+            let due = c.next_restart.is_none_or(|t| now >= t);
+            if !due {
+                continue;
+            }
+            c.restarts += 1;
+            c.backoff = (c.backoff * 2).min(self.max_backoff);
+            match spawn(c) {
+                Ok(()) => {
+                    tracing::info!(collector = %c.spec.name, restarts = c.restarts, "restarted collector");
+                    c.next_restart = None;
+                }
+                Err(e) => {
+                    tracing::warn!("{e}");
+                    c.next_restart = Some(now + c.backoff);
+                }
+            }
+        }
+    }
+}
+
+impl Default for Supervisor {
+    fn default() -> Self {
+        Self::new(vec![], &SupervisorConfig::default())
+    }
+}
+
+impl Drop for Supervisor {
+    fn drop(&mut self) {
+        self.stop_all();
+    }
+}
+
+fn spawn(c: &mut Supervised) -> Result<(), SupervisorError> {
+    let child = Command::new(&c.spec.binary)
+        .args(&c.spec.args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|source| SupervisorError::Spawn {
+            name: c.spec.name.clone(),
+            source,
+        })?;
+    tracing::info!(collector = %c.spec.name, pid = child.id(), "spawned collector");
+    c.child = Some(child);
+    c.last_started = Some(Instant::now());
+    Ok(())
+}
+
+fn kill(c: &mut Supervised) {
+    if let Some(mut child) = c.child.take() {
+        let _ = child.kill();
+        let _ = child.wait();
+        tracing::info!(collector = %c.spec.name, "stopped collector");
+    }
+}
+
+/// Spawn a background task that periodically ticks the engine's supervisor
+/// and refreshes collector liveness.
+pub fn spawn_supervisor(engine: std::sync::Arc<crate::engine::Engine>, interval_secs: u64) {
+    tokio::spawn(async move {
+        let interval = Duration::from_secs(interval_secs.max(1));
+        loop {
+            tokio::time::sleep(interval).await;
+            engine.supervise();
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(name: &str, binary: &str, args: &[&str]) -> CollectorSpec {
+        CollectorSpec {
+            name: name.to_string(),
+            source: CollectorSource::Custom(name.to_string()),
+            binary: PathBuf::from(binary),
+            args: args.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    fn config() -> SupervisorConfig {
+        SupervisorConfig {
+            initial_backoff_ms: 100,
+            max_backoff_ms: 400,
+            ..SupervisorConfig::default()
+        }
+    }
+
+    #[test]
+    fn start_and_stop_tracks_pid() {
+        let mut sup = Supervisor::new(vec![spec("sleeper", "sleep", &["30"])], &config());
+        assert!(sup.pid("sleeper").is_none());
+
+        sup.start("sleeper").unwrap();
+        assert!(sup.pid("sleeper").is_some());
+
+        sup.stop("sleeper").unwrap();
+        assert!(sup.pid("sleeper").is_none());
+
+        // A stopped collector is not restarted by tick
+        sup.tick(Instant::now() + Duration::from_secs(10));
+        assert!(sup.pid("sleeper").is_none());
+    }
+
+    #[test]
+    fn unknown_collector_is_an_error() {
+        let mut sup = Supervisor::new(vec![], &config());
+        assert!(matches!(
+            sup.start("nope"),
+            Err(SupervisorError::UnknownCollector(_))
+        ));
+    }
+
+    #[test]
+    fn crashed_collector_restarts_after_backoff() {
+        let mut sup = Supervisor::new(vec![spec("crasher", "false", &[])], &config());
+        sup.start("crasher").unwrap();
+
+        // Wait for the process to exit
+        std::thread::sleep(Duration::from_millis(200));
+        let now = Instant::now();
+        sup.tick(now);
+        assert!(sup.pid("crasher").is_none());
+        assert_eq!(sup.restarts("crasher"), 0);
+
+        // Not yet due
+        sup.tick(now + Duration::from_millis(50));
+        assert_eq!(sup.restarts("crasher"), 0);
+
+        // Backoff elapsed
+        sup.tick(now + Duration::from_millis(150));
+        assert_eq!(sup.restarts("crasher"), 1);
+        sup.stop_all();
+    }
+}
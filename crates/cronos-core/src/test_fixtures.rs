@@ -0,0 +1,60 @@
+//! Builders for the entities, edges and sessions unit tests store.
+//!
+//! Tests override whatever they care about with struct update syntax, e.g.
+//! `Session { project: Some(..), ..session("code", "coding", 0, 60_000) }`.
+
+use std::collections::HashMap;
+
+use cronos_model::{Edge, EdgeId, Entity, EntityId, EntityKind, Relation, Timestamp};
+
+use crate::storage::repo::Session;
+
+/// An entity without attributes, first and last seen at `seen`.
+pub(crate) fn entity(kind: EntityKind, name: &str, seen: Timestamp) -> Entity {
+    Entity {
+        id: EntityId::new(),
+        kind,
+        name: name.to_string(),
+        attributes: HashMap::new(),
+        first_seen: seen,
+        last_seen: seen,
+    }
+}
+
+/// An edge created and last reinforced at `at`.
+pub(crate) fn edge(
+    from: EntityId,
+    to: EntityId,
+    relation: Relation,
+    strength: f32,
+    at: Timestamp,
+) -> Edge {
+    Edge {
+        id: EdgeId::new(),
+        from,
+        to,
+        relation,
+        strength,
+        created_at: at,
+        last_reinforced: at,
+    }
+}
+
+/// A closed single-event session over `start..end`, without titles or a project.
+pub(crate) fn session(app: &str, category: &str, start: Timestamp, end: Timestamp) -> Session {
+    Session {
+        id: ulid::Ulid::new().to_string(),
+        app_name: app.to_string(),
+        window_titles: vec![],
+        project: None,
+        project_id: None,
+        project_confidence: 0.0,
+        category: category.to_string(),
+        start_time: start,
+        end_time: end,
+        duration_secs: (end - start) / 1000,
+        event_count: 1,
+        metadata: HashMap::new(),
+        open: false,
+    }
+}
@@ -0,0 +1,341 @@
+//! Timesheets: tracked time grouped into billable blocks per project or
+//! category. Manual overrides are applied on top of the recorded sessions,
+//! which are left as they were.
+
+use crate::storage::repo::Session;
+use crate::storage::Repository;
+use cronos_model::{OverrideAction, TimeOverride, Timestamp};
+use cronos_proto::{TimeTotal, TimesheetBlock, TimesheetGroup, TimesheetInfo};
+use std::collections::HashMap;
+
+/// Time for one project or category at most this far apart is billed as
+/// one block.
+const MERGE_GAP_MS: i64 = 300_000;
+
+/// A stretch of time with what it is billed to.
+#[derive(Debug, Clone)]
+struct Piece {
+    start: Timestamp,
+    end: Timestamp,
+    project: Option<String>,
+    category: String,
+    notes: Vec<String>,
+}
+
+impl Piece {
+    /// The parts of the piece outside `[start, end)`, and the part inside.
+    fn split(self, start: Timestamp, end: Timestamp) -> (Vec<Piece>, Option<Piece>) {
+        if self.end <= start || self.start >= end {
+            return (vec![self], None);
+        }
+        let mut outside = Vec::new();
+        if self.start < start {
+            outside.push(Piece {
+                end: start,
+                ..self.clone()
+            });
+        }
+        if self.end > end {
+            outside.push(Piece {
+                start: end,
+                ..self.clone()
+            });
+        }
+        let inside = Piece {
+            start: self.start.max(start),
+            end: self.end.min(end),
+            ..self
+        };
+        (outside, Some(inside))
+    }
+
+    fn noted(mut self, record: &TimeOverride) -> Self {
+        if !record.note.is_empty() && !self.notes.contains(&record.note) {
+            self.notes.push(record.note.clone());
+        }
+        self
+    }
+}
+
+/// Timesheet for `[from, to)` from the sessions and overrides stored there.
+pub fn build(
+    repo: &Repository,
+    from: Timestamp,
+    to: Timestamp,
+    by: TimesheetGroup,
+    round_secs: u32,
+) -> rusqlite::Result<TimesheetInfo> {
+    let sessions = repo.sessions_overlapping(from, to)?;
+    let overrides = repo.time_overrides_overlapping(from, to)?;
+    Ok(timesheet(&sessions, &overrides, from, to, by, round_secs))
+}
+
+/// `overrides` apply in order, each to the result of those before it.
+fn timesheet(
+    sessions: &[Session],
+    overrides: &[TimeOverride],
+    from: Timestamp,
+    to: Timestamp,
+    by: TimesheetGroup,
+    round_secs: u32,
+) -> TimesheetInfo {
+    let mut pieces: Vec<Piece> = sessions
+        .iter()
+        .filter_map(|s| {
+            let (start, end) = (s.start_time.max(from), s.end_time.min(to));
+            (start < end).then(|| Piece {
+                start,
+                end,
+                project: s.project.clone(),
+                category: s.category.clone(),
+                notes: Vec::new(),
+            })
+        })
+        .collect();
+
+    for record in overrides {
+        let (start, end) = (record.start.max(from), record.end.min(to));
+        if start >= end {
+            continue;
+        }
+        let mut next = Vec::with_capacity(pieces.len());
+        for piece in pieces {
+            let (outside, inside) = piece.split(start, end);
+            next.extend(outside);
+            if let (Some(mut inside), OverrideAction::Reassign { project, category }) =
+                (inside, &record.action)
+            {
+                if let Some(project) = project {
+                    inside.project = Some(project.clone());
+                }
+                if let Some(category) = category {
+                    inside.category = category.clone();
+                }
+                next.push(inside.noted(record));
+            }
+        }
+        if let OverrideAction::Add { project, category } = &record.action {
+            let added = Piece {
+                start,
+                end,
+                project: project.clone(),
+                category: category.clone(),
+                notes: Vec::new(),
+            };
+            next.push(added.noted(record));
+        }
+        pieces = next;
+    }
+    pieces.sort_by_key(|p| p.start);
+
+    struct Block {
+        name: Option<String>,
+        start: Timestamp,
+        end: Timestamp,
+        tracked_ms: i64,
+        notes: Vec<String>,
+    }
+    let mut blocks: Vec<Block> = Vec::new();
+    for piece in pieces {
+        let name = match by {
+            TimesheetGroup::Project => piece.project,
+            TimesheetGroup::Category => Some(piece.category),
+        };
+        match blocks.last_mut() {
+            Some(block) if block.name == name && piece.start - block.end <= MERGE_GAP_MS => {
+                block.end = block.end.max(piece.end);
+                block.tracked_ms += piece.end - piece.start;
+                for note in piece.notes {
+                    if !block.notes.contains(&note) {
+                        block.notes.push(note);
+                    }
+                }
+            }
+            _ => blocks.push(Block {
+                name,
+                start: piece.start,
+                end: piece.end,
+                tracked_ms: piece.end - piece.start,
+                notes: piece.notes,
+            }),
+        }
+    }
+
+    let blocks: Vec<TimesheetBlock> = blocks
+        .into_iter()
+        .map(|b| {
+            let tracked_secs = b.tracked_ms / 1000;
+            TimesheetBlock {
+                name: b.name,
+                start: b.start,
+                end: b.end,
+                tracked_secs,
+                billed_secs: round(tracked_secs, round_secs as i64),
+                notes: b.notes,
+            }
+        })
+        .filter(|b| b.billed_secs > 0)
+        .collect();
+
+    let mut secs: HashMap<String, i64> = HashMap::new();
+    for block in &blocks {
+        *secs
+            .entry(block.name.clone().unwrap_or_default())
+            .or_default() += block.billed_secs;
+    }
+    let mut totals: Vec<_> = secs
+        .into_iter()
+        .map(|(name, secs)| TimeTotal {
+            name,
+            id: None,
+            secs,
+        })
+        .collect();
+    totals.sort_by(|a, b| b.secs.cmp(&a.secs).then_with(|| a.name.cmp(&b.name)));
+
+    TimesheetInfo {
+        from,
+        to,
+        by,
+        round_secs,
+        blocks,
+        totals,
+    }
+}
+
+/// `secs` to the nearest multiple of `step`, halves up; unchanged when
+/// `step` is zero.
+fn round(secs: i64, step: i64) -> i64 {
+    if step <= 0 {
+        return secs;
+    }
+    (secs + step / 2) / step * step
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures as fixtures;
+    use cronos_model::OverrideId;
+
+    const MIN: i64 = 60_000;
+
+    fn session(project: Option<&str>, category: &str, start_min: i64, mins: i64) -> Session {
+        Session {
+            project: project.map(String::from),
+            ..fixtures::session("code", category, start_min * MIN, (start_min + mins) * MIN)
+        }
+    }
+
+    fn record(start_min: i64, end_min: i64, action: OverrideAction, note: &str) -> TimeOverride {
+        TimeOverride {
+            id: OverrideId::new(),
+            start: start_min * MIN,
+            end: end_min * MIN,
+            action,
+            note: note.to_string(),
+            created_at: 0,
+        }
+    }
+
+    fn billed(sheet: &TimesheetInfo) -> Vec<(Option<&str>, i64)> {
+        sheet
+            .blocks
+            .iter()
+            .map(|b| (b.name.as_deref(), b.billed_secs / 60))
+            .collect()
+    }
+
+    #[test]
+    fn blocks_merge_nearby_time_for_the_same_project() {
+        let sessions = [
+            session(Some("acme"), "coding", 0, 20),
+            session(Some("acme"), "browsing", 22, 10),
+            session(Some("globex"), "coding", 32, 15),
+            session(None, "communication", 60, 5),
+            session(Some("acme"), "coding", 120, 30),
+        ];
+        let sheet = timesheet(&sessions, &[], 0, 1000 * MIN, TimesheetGroup::Project, 0);
+        assert_eq!(
+            billed(&sheet),
+            [
+                (Some("acme"), 30),
+                (Some("globex"), 15),
+                (None, 5),
+                (Some("acme"), 30)
+            ]
+        );
+        assert_eq!(sheet.blocks[0].end, 32 * MIN);
+        assert_eq!(sheet.totals[0].name, "acme");
+        assert_eq!(sheet.totals[0].secs, 60 * 60);
+
+        let by_category = timesheet(&sessions, &[], 0, 1000 * MIN, TimesheetGroup::Category, 0);
+        let categories: Vec<_> = by_category.totals.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(categories, ["coding", "browsing", "communication"]);
+    }
+
+    #[test]
+    fn overrides_reassign_exclude_and_add_time() {
+        let sessions = [
+            session(Some("acme"), "coding", 0, 60),
+            session(None, "browsing", 60, 30),
+        ];
+        let overrides = [
+            // The first half hour was for another client
+            record(
+                0,
+                30,
+                OverrideAction::Reassign {
+                    project: Some("globex".to_string()),
+                    category: None,
+                },
+                "globex bug",
+            ),
+            record(60, 90, OverrideAction::Exclude, "personal"),
+            // A meeting away from the computer, partly over tracked time
+            record(
+                80,
+                120,
+                OverrideAction::Add {
+                    project: Some("acme".to_string()),
+                    category: "meeting".to_string(),
+                },
+                "standup",
+            ),
+        ];
+        let sheet = timesheet(
+            &sessions,
+            &overrides,
+            0,
+            1000 * MIN,
+            TimesheetGroup::Project,
+            0,
+        );
+        assert_eq!(
+            billed(&sheet),
+            [(Some("globex"), 30), (Some("acme"), 30), (Some("acme"), 40)]
+        );
+        assert_eq!(sheet.blocks[0].notes, ["globex bug"]);
+        assert_eq!(sheet.blocks[2].notes, ["standup"]);
+        assert_eq!(
+            (sheet.blocks[2].start, sheet.blocks[2].end),
+            (80 * MIN, 120 * MIN)
+        );
+    }
+
+    #[test]
+    fn blocks_round_to_the_nearest_step_and_clip_to_the_range() {
+        let sessions = [
+            session(Some("acme"), "coding", 0, 38),
+            session(Some("globex"), "coding", 100, 7),
+            session(Some("initech"), "coding", 200, 60),
+        ];
+        let sheet = timesheet(&sessions, &[], 0, 230 * MIN, TimesheetGroup::Project, 900);
+        // 38m rounds to 45m, 7m to nothing, and only 30m of initech is in range
+        assert_eq!(billed(&sheet), [(Some("acme"), 45), (Some("initech"), 30)]);
+        assert_eq!(sheet.blocks[0].tracked_secs, 38 * 60);
+        assert_eq!(round(450, 900), 900);
+        assert_eq!(round(449, 900), 0);
+        assert_eq!(round(449, 0), 449);
+    }
+}
@@ -65,9 +65,7 @@ fn engine_handles_event_and_query() {
     let query_msg = Message::new(
         "q1",
         MessageKind::Query {
-            query: QueryRequest {
-                kind: QueryKind::Recent { limit: 10 },
-            },
+            query: QueryRequest::new(QueryKind::Recent { limit: 10 }),
         },
     );
     let query_resp = engine.handle_message(query_msg);
@@ -130,6 +128,10 @@ fn engine_handles_collector_handshake() {
             name: "fs-collector".to_string(),
             collector_version: "0.1.0".to_string(),
             source: CollectorSource::Filesystem,
+            token: None,
+            protocol: None,
+            features: vec![],
+            codecs: vec![],
         },
     );
     let handshake_resp = engine.handle_message(handshake_msg);
@@ -150,3 +152,215 @@ fn engine_handles_collector_handshake() {
         other => panic!("expected CollectorList, got {:?}", other),
     }
 }
+
+// ─── TCP / TLS transport ────────────────────────────────────────────────
+
+mod tcp {
+    use super::*;
+    use cronos_common::config::TcpConfig;
+    use cronos_proto::transport::{self, ClientTls, Endpoint};
+    use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair};
+    use std::path::Path;
+    use std::sync::Arc;
+
+    /// Start an engine listening on an ephemeral localhost port.
+    async fn start_daemon(dir: &TempDir, tcp: TcpConfig) -> String {
+        let config = DaemonConfig {
+            tcp,
+            ..DaemonConfig::default()
+        };
+        let engine = Arc::new(Engine::open(&dir.path().join("test.db"), &config).unwrap());
+        let acceptor = cronos_core::server::tls::acceptor(&config.tcp).unwrap();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(cronos_core::server::run_tcp(engine, listener, acceptor));
+        addr
+    }
+
+    async fn request(
+        stream: &mut Box<dyn transport::Stream>,
+        kind: MessageKind,
+    ) -> MessageKind {
+        write_frame(stream, &Message::new("r1", kind)).await.unwrap();
+        read_frame(stream).await.unwrap().kind
+    }
+
+    fn handshake(token: Option<&str>) -> MessageKind {
+        MessageKind::CollectorHandshake {
+            name: "remote-fs".to_string(),
+            collector_version: "0.1.0".to_string(),
+            source: CollectorSource::Filesystem,
+            token: token.map(str::to_string),
+            protocol: None,
+            features: vec![],
+            codecs: vec![],
+        }
+    }
+
+    /// Write a CA plus a leaf certificate for "localhost" signed by it.
+    fn write_pki(dir: &Path) -> (String, String, String, String, String) {
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(vec![]).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+
+        let leaf = |name: &str| {
+            let key = KeyPair::generate().unwrap();
+            let cert = CertificateParams::new(vec!["localhost".to_string()])
+                .unwrap()
+                .signed_by(&key, &ca, &ca_key)
+                .unwrap();
+            let cert_path = dir.join(format!("{name}.pem"));
+            let key_path = dir.join(format!("{name}.key"));
+            std::fs::write(&cert_path, cert.pem()).unwrap();
+            std::fs::write(&key_path, key.serialize_pem()).unwrap();
+            (cert_path.display().to_string(), key_path.display().to_string())
+        };
+        let (server_cert, server_key) = leaf("server");
+        let (client_cert, client_key) = leaf("client");
+        let ca_path = dir.join("ca.pem");
+        std::fs::write(&ca_path, ca.pem()).unwrap();
+        (ca_path.display().to_string(), server_cert, server_key, client_cert, client_key)
+    }
+
+    #[tokio::test]
+    async fn tcp_requires_token_handshake() {
+        let dir = TempDir::new().unwrap();
+        let addr = start_daemon(
+            &dir,
+            TcpConfig {
+                enabled: true,
+                token: "s3cret".to_string(),
+                ..TcpConfig::default()
+            },
+        )
+        .await;
+        let endpoint = Endpoint::Tcp { addr, tls: None };
+
+        // Wrong token is rejected, and the connection stays unauthenticated
+        let mut stream = transport::connect(&endpoint).await.unwrap();
+        let resp = request(&mut stream, handshake(Some("nope"))).await;
+        assert!(matches!(resp, MessageKind::Error { .. }));
+        let resp = request(&mut stream, MessageKind::Status).await;
+        assert!(matches!(
+            resp,
+            MessageKind::Error { code: ErrorCode::Unauthorized, .. }
+        ));
+
+        // Correct token authenticates the connection, for emitting only
+        let mut stream = transport::connect(&endpoint).await.unwrap();
+        let resp = request(&mut stream, handshake(Some("s3cret"))).await;
+        assert!(matches!(resp, MessageKind::Ack { .. }));
+        let resp = request(&mut stream, MessageKind::Heartbeat).await;
+        assert!(matches!(resp, MessageKind::Ack { .. }));
+        let resp = request(&mut stream, MessageKind::Status).await;
+        assert!(matches!(
+            resp,
+            MessageKind::Error { code: ErrorCode::Unauthorized, .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn hello_negotiates_binary_codec() {
+        let dir = TempDir::new().unwrap();
+        let addr = start_daemon(
+            &dir,
+            TcpConfig {
+                enabled: true,
+                token: "s3cret".to_string(),
+                capabilities: vec!["emit".to_string(), "query".to_string()],
+                ..TcpConfig::default()
+            },
+        )
+        .await;
+        let mut stream = transport::connect(&Endpoint::Tcp { addr, tls: None }).await.unwrap();
+
+        let hello = MessageKind::Hello {
+            protocol: VersionRange::SUPPORTED,
+            features: supported_features(),
+            codecs: vec![Codec::Msgpack],
+        };
+        match request(&mut stream, hello).await {
+            MessageKind::Welcome { version, codec, .. } => {
+                assert_eq!(version, PROTOCOL_VERSION);
+                assert_eq!(codec, Codec::Msgpack);
+            }
+            other => panic!("expected Welcome, got {:?}", other),
+        }
+
+        // Requests may now be binary too; responses decode transparently
+        let auth = Message::new("a1", MessageKind::Authenticate { token: "s3cret".to_string() });
+        write_frame_with(&mut stream, &auth, Codec::Msgpack).await.unwrap();
+        assert!(matches!(
+            read_response(&mut stream).await.unwrap().kind,
+            MessageKind::Authenticated { .. }
+        ));
+        write_frame_with(&mut stream, &Message::new("s1", MessageKind::Status), Codec::Msgpack)
+            .await
+            .unwrap();
+        assert!(matches!(
+            read_response(&mut stream).await.unwrap().kind,
+            MessageKind::StatusResult { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn tls_with_token_and_mutual_tls() {
+        let dir = TempDir::new().unwrap();
+        let (ca, server_cert, server_key, client_cert, client_key) = write_pki(dir.path());
+
+        // Server-authenticated TLS plus token
+        let addr = start_daemon(
+            &dir,
+            TcpConfig {
+                enabled: true,
+                token: "s3cret".to_string(),
+                tls_cert: server_cert.clone(),
+                tls_key: server_key.clone(),
+                ..TcpConfig::default()
+            },
+        )
+        .await;
+        let endpoint = Endpoint::Tcp {
+            addr,
+            tls: Some(ClientTls {
+                ca_cert: ca.clone().into(),
+                server_name: "localhost".to_string(),
+                client_identity: None,
+            }),
+        };
+        let mut stream = transport::connect(&endpoint).await.unwrap();
+        let resp = request(&mut stream, handshake(Some("s3cret"))).await;
+        assert!(matches!(resp, MessageKind::Ack { .. }));
+
+        // Mutual TLS: a verified client certificate authenticates without a token
+        let addr = start_daemon(
+            &dir,
+            TcpConfig {
+                enabled: true,
+                tls_cert: server_cert,
+                tls_key: server_key,
+                client_ca: ca.clone(),
+                ..TcpConfig::default()
+            },
+        )
+        .await;
+        let tls = ClientTls {
+            ca_cert: ca.into(),
+            server_name: "localhost".to_string(),
+            client_identity: Some((client_cert.into(), client_key.into())),
+        };
+        let endpoint = Endpoint::Tcp {
+            addr,
+            tls: Some(tls),
+        };
+        let mut stream = transport::connect(&endpoint).await.unwrap();
+        let resp = request(&mut stream, handshake(None)).await;
+        assert!(matches!(resp, MessageKind::Ack { .. }));
+        let resp = request(&mut stream, MessageKind::Status).await;
+        assert!(matches!(
+            resp,
+            MessageKind::Error { code: ErrorCode::Unauthorized, .. }
+        ));
+    }
+}
@@ -21,6 +21,12 @@ pub struct EventId(pub Ulid);
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct EdgeId(pub Ulid);
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct OverrideId(pub Ulid);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct AnnotationId(pub Ulid);
+
 impl EntityId {
     pub fn new() -> Self {
         Self(Ulid::new())
@@ -39,6 +45,18 @@ impl EdgeId {
     }
 }
 
+impl OverrideId {
+    pub fn new() -> Self {
+        Self(Ulid::new())
+    }
+}
+
+impl AnnotationId {
+    pub fn new() -> Self {
+        Self(Ulid::new())
+    }
+}
+
 impl Default for EntityId {
     fn default() -> Self {
         Self::new()
@@ -57,6 +75,18 @@ impl Default for EdgeId {
     }
 }
 
+impl Default for OverrideId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Default for AnnotationId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl std::fmt::Display for EntityId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.0)
@@ -75,6 +105,18 @@ impl std::fmt::Display for EdgeId {
     }
 }
 
+impl std::fmt::Display for OverrideId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::fmt::Display for AnnotationId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 // === Entity ===
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -158,6 +200,10 @@ pub enum EventKind {
     BranchChanged,
     CommandExecuted,
     AppFocused,
+    /// No input since the event's timestamp, or the screen was locked.
+    IdleStarted,
+    /// Input resumed or the screen was unlocked.
+    IdleEnded,
     Custom(String),
 }
 
@@ -193,6 +239,73 @@ pub enum Relation {
     Custom(String),
 }
 
+// === Time overrides ===
+
+/// A manual correction to tracked time in `[start, end)`. Overrides are
+/// applied when timesheets are built; recorded sessions are never edited.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimeOverride {
+    pub id: OverrideId,
+    pub start: Timestamp,
+    pub end: Timestamp,
+    pub action: OverrideAction,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub note: String,
+    pub created_at: Timestamp,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OverrideAction {
+    /// Bill tracked time in the range to another project or category;
+    /// `None` keeps what was tracked.
+    Reassign {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        project: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        category: Option<String>,
+    },
+    /// Leave tracked time in the range off timesheets, e.g. personal time.
+    Exclude,
+    /// Bill the whole range in place of whatever was tracked, e.g. for an
+    /// offline meeting.
+    Add {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        project: Option<String>,
+        category: String,
+    },
+}
+
+// === Annotations ===
+
+/// Context people add to the record themselves, e.g. "on-call for INC-42".
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Annotation {
+    pub id: AnnotationId,
+    pub target: AnnotationTarget,
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Time the annotation covers, set by the daemon: the range, or the
+    /// session's span when it was annotated. `None` for entities.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<Timestamp>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<Timestamp>,
+    /// Suggested by the assistant and not yet accepted.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub proposed: bool,
+    pub created_at: Timestamp,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnnotationTarget {
+    Range { start: Timestamp, end: Timestamp },
+    Session { session_id: String },
+    Entity { entity_id: EntityId },
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
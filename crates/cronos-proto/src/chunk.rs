@@ -0,0 +1,232 @@
+//! Splitting large query results across frames and reassembling them.
+
+use crate::frame::{read_frame, Codec, FrameError};
+use crate::message::{Message, MessageKind, QueryResponse, Table};
+use serde::Serialize;
+use tokio::io::AsyncReadExt;
+
+/// Encoded size each chunk aims to stay under, well below `MAX_FRAME_SIZE`.
+pub const CHUNK_TARGET_BYTES: usize = 4 * 1024 * 1024;
+
+/// Split a `QueryResult` whose encoding exceeds `target` bytes into
+/// `QueryResultChunk`s. Any other message is returned as the only element.
+pub fn into_chunks(msg: Message, codec: Codec, target: usize) -> Result<Vec<Message>, FrameError> {
+    if !matches!(msg.kind, MessageKind::QueryResult { .. }) || codec.encode(&msg)?.len() <= target {
+        return Ok(vec![msg]);
+    }
+    let MessageKind::QueryResult { response } = msg.kind else {
+        unreachable!("checked above");
+    };
+
+    let mut parts = Parts {
+        codec,
+        target,
+        parts: vec![(QueryResponse::default(), 0)],
+    };
+    parts.pack(response.entities, |r| &mut r.entities)?;
+    parts.pack(response.edges, |r| &mut r.edges)?;
+    parts.pack(response.events, |r| &mut r.events)?;
+    parts.pack(response.sessions, |r| &mut r.sessions)?;
+    parts.pack(response.related, |r| &mut r.related)?;
+    parts.pack(response.annotations, |r| &mut r.annotations)?;
+    if let Some(table) = response.table {
+        parts.pack(table.rows, |r| &mut r.table.get_or_insert_with(Table::default).rows)?;
+        // Every part carrying rows repeats the column names.
+        for (part, _) in &mut parts.parts {
+            if let Some(part) = &mut part.table {
+                part.columns = table.columns.clone();
+                part.truncated = table.truncated;
+            }
+        }
+    }
+    if let Some((last, _)) = parts.parts.last_mut() {
+        last.next_cursor = response.next_cursor;
+    }
+    // Small and indivisible, so it rides in the first chunk.
+    if let Some((first, _)) = parts.parts.first_mut() {
+        first.summary = response.summary;
+        first.focus = response.focus;
+        first.timesheet = response.timesheet;
+        first.overrides = response.overrides;
+    }
+
+    let count = parts.parts.len();
+    Ok(parts
+        .parts
+        .into_iter()
+        .enumerate()
+        .map(|(seq, (response, _))| Message {
+            version: msg.version,
+            id: msg.id.clone(),
+            kind: MessageKind::QueryResultChunk {
+                response,
+                seq: seq as u32,
+                last: seq + 1 == count,
+            },
+        })
+        .collect())
+}
+
+/// Greedily fills chunks item by item, tracking each chunk's encoded size.
+struct Parts {
+    codec: Codec,
+    target: usize,
+    parts: Vec<(QueryResponse, usize)>,
+}
+
+impl Parts {
+    fn pack<T: Serialize>(
+        &mut self,
+        items: Vec<T>,
+        field: fn(&mut QueryResponse) -> &mut Vec<T>,
+    ) -> Result<(), FrameError> {
+        for item in items {
+            let size = match self.codec {
+                Codec::Json => serde_json::to_vec(&item)?.len(),
+                Codec::Msgpack => rmp_serde::to_vec_named(&item)?.len(),
+            };
+            let (_, used) = self.parts.last().expect("at least one part");
+            if *used > 0 && used + size > self.target {
+                self.parts.push((QueryResponse::default(), 0));
+            }
+            let (part, used) = self.parts.last_mut().expect("at least one part");
+            field(part).push(item);
+            *used += size;
+        }
+        Ok(())
+    }
+}
+
+/// Read one response, reassembling `QueryResultChunk`s into a `QueryResult`.
+pub async fn read_response<R: AsyncReadExt + Unpin>(reader: &mut R) -> Result<Message, FrameError> {
+    let first = read_frame(reader).await?;
+    let (mut combined, mut done) = match first.kind {
+        MessageKind::QueryResultChunk { response, last, .. } => (response, last),
+        _ => return Ok(first),
+    };
+    let mut expected_seq = 1;
+    while !done {
+        let next = read_frame(reader).await?;
+        match next.kind {
+            MessageKind::QueryResultChunk { response, seq, last }
+                if next.id == first.id && seq == expected_seq =>
+            {
+                combined.extend(response);
+                done = last;
+                expected_seq += 1;
+            }
+            other => {
+                return Err(FrameError::BadChunk(format!(
+                    "expected chunk {expected_seq} of {}, got {other:?}",
+                    first.id
+                )));
+            }
+        }
+    }
+    Ok(Message {
+        version: first.version,
+        id: first.id,
+        kind: MessageKind::QueryResult { response: combined },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cronos_model::*;
+    use std::collections::HashMap;
+
+    fn big_result(events: usize) -> Message {
+        let events = (0..events)
+            .map(|i| Event {
+                id: EventId::new(),
+                timestamp: i as i64,
+                source: CollectorSource::Filesystem,
+                kind: EventKind::FileModified,
+                subject: EntityRef {
+                    kind: EntityKind::File,
+                    identity: format!("/src/file_{i}.rs"),
+                    attributes: HashMap::new(),
+                },
+                context: vec![],
+                metadata: HashMap::new(),
+            })
+            .collect();
+        Message::new(
+            "q1",
+            MessageKind::QueryResult {
+                response: QueryResponse {
+                    events,
+                    ..QueryResponse::default()
+                },
+            },
+        )
+    }
+
+    #[test]
+    fn small_results_are_not_chunked() {
+        let chunks = into_chunks(big_result(3), Codec::Json, CHUNK_TARGET_BYTES).unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert!(matches!(chunks[0].kind, MessageKind::QueryResult { .. }));
+    }
+
+    #[tokio::test]
+    async fn chunks_reassemble_in_order() {
+        for codec in Codec::ALL {
+            let chunks = into_chunks(big_result(500), codec, 4096).unwrap();
+            assert!(chunks.len() > 1);
+
+            let mut buf = Vec::new();
+            for chunk in &chunks {
+                assert!(codec.encode(chunk).unwrap().len() < 8192);
+                crate::frame::write_frame_with(&mut buf, chunk, codec).await.unwrap();
+            }
+            let mut cursor = std::io::Cursor::new(buf);
+            let msg = read_response(&mut cursor).await.unwrap();
+            assert_eq!(msg.id, "q1");
+            match msg.kind {
+                MessageKind::QueryResult { response } => {
+                    assert_eq!(response.events.len(), 500);
+                    assert_eq!(response.events[499].timestamp, 499);
+                }
+                other => panic!("expected QueryResult, got {:?}", other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn table_rows_are_chunked_with_their_columns() {
+        let rows = (0..500)
+            .map(|i| vec![serde_json::json!(format!("app-{i}")), serde_json::json!(i)])
+            .collect();
+        let msg = Message::new(
+            "q2",
+            MessageKind::QueryResult {
+                response: QueryResponse {
+                    table: Some(Table {
+                        columns: vec!["app".to_string(), "count".to_string()],
+                        rows,
+                        truncated: true,
+                    }),
+                    ..QueryResponse::default()
+                },
+            },
+        );
+        let chunks = into_chunks(msg, Codec::Msgpack, 2048).unwrap();
+        assert!(chunks.len() > 1);
+
+        let mut buf = Vec::new();
+        for chunk in &chunks {
+            crate::frame::write_frame_with(&mut buf, chunk, Codec::Msgpack).await.unwrap();
+        }
+        let msg = read_response(&mut std::io::Cursor::new(buf)).await.unwrap();
+        let MessageKind::QueryResult { response } = msg.kind else {
+            panic!("expected QueryResult");
+        };
+        let table = response.table.unwrap();
+        assert_eq!(table.columns, ["app", "count"]);
+        assert_eq!(table.rows.len(), 500);
+        assert_eq!(table.rows[499][1], 499);
+        assert!(table.truncated);
+    }
+}
@@ -1,7 +1,59 @@
 use crate::message::Message;
+use serde::{Deserialize, Serialize};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
-const MAX_FRAME_SIZE: u32 = 16 * 1024 * 1024; // 16 MB
+pub const MAX_FRAME_SIZE: u32 = 16 * 1024 * 1024; // 16 MB
+
+/// Payload encoding of a frame.
+///
+/// The writer's codec is negotiated per connection (`Hello`/`CollectorHandshake`
+/// offer codecs, `Welcome` picks one). Readers need no state: JSON payloads
+/// start with `{` or `[` after any whitespace, MessagePack ones with a map
+/// marker, and neither of those bytes can begin a MessagePack message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Codec {
+    #[default]
+    Json,
+    Msgpack,
+}
+
+impl Codec {
+    /// Codecs this build can speak, most preferred first.
+    pub const ALL: [Codec; 2] = [Codec::Msgpack, Codec::Json];
+
+    pub fn encode(self, msg: &Message) -> Result<Vec<u8>, FrameError> {
+        match self {
+            Self::Json => Ok(serde_json::to_vec(msg)?),
+            // Named fields keep internally tagged enums and serde defaults working.
+            Self::Msgpack => Ok(rmp_serde::to_vec_named(msg)?),
+        }
+    }
+
+    pub fn decode(payload: &[u8]) -> Result<Message, FrameError> {
+        match Self::detect(payload) {
+            Self::Json => Ok(serde_json::from_slice(payload)?),
+            Self::Msgpack => Ok(rmp_serde::from_slice(payload)?),
+        }
+    }
+
+    /// The codec a payload was written with.
+    pub fn detect(payload: &[u8]) -> Codec {
+        match payload.iter().find(|b| !b.is_ascii_whitespace()) {
+            Some(b'{' | b'[') => Self::Json,
+            _ => Self::Msgpack,
+        }
+    }
+
+    /// The first of `offered` this build supports, falling back to JSON.
+    pub fn choose(offered: &[Codec]) -> Codec {
+        offered
+            .iter()
+            .copied()
+            .find(|c| Self::ALL.contains(c))
+            .unwrap_or_default()
+    }
+}
 
 #[derive(Debug, thiserror::Error)]
 pub enum FrameError {
@@ -9,17 +61,33 @@ pub enum FrameError {
     Io(#[from] std::io::Error),
     #[error("json error: {0}")]
     Json(#[from] serde_json::Error),
+    #[error("msgpack encode error: {0}")]
+    MsgpackEncode(#[from] rmp_serde::encode::Error),
+    #[error("msgpack decode error: {0}")]
+    MsgpackDecode(#[from] rmp_serde::decode::Error),
     #[error("frame too large: {size} bytes (max {MAX_FRAME_SIZE})")]
     TooLarge { size: u32 },
+    #[error("malformed chunked response: {0}")]
+    BadChunk(String),
     #[error("connection closed")]
     ConnectionClosed,
 }
 
+/// Write a JSON frame. JSON is what every peer understands before negotiation.
 pub async fn write_frame<W: AsyncWriteExt + Unpin>(
     writer: &mut W,
     msg: &Message,
 ) -> Result<(), FrameError> {
-    let payload = serde_json::to_vec(msg)?;
+    write_frame_with(writer, msg, Codec::Json).await
+}
+
+/// Write a frame encoded with `codec`.
+pub async fn write_frame_with<W: AsyncWriteExt + Unpin>(
+    writer: &mut W,
+    msg: &Message,
+    codec: Codec,
+) -> Result<(), FrameError> {
+    let payload = codec.encode(msg)?;
     let len = payload.len() as u32;
     if len > MAX_FRAME_SIZE {
         return Err(FrameError::TooLarge { size: len });
@@ -47,8 +115,7 @@ pub async fn read_frame<R: AsyncReadExt + Unpin>(
     }
     let mut payload = vec![0u8; len as usize];
     reader.read_exact(&mut payload).await?;
-    let msg = serde_json::from_slice(&payload)?;
-    Ok(msg)
+    Codec::decode(&payload)
 }
 
 #[cfg(test)]
@@ -80,6 +147,45 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn frame_msgpack_roundtrip_is_smaller() {
+        let msg = Message::new(
+            "req-7",
+            MessageKind::Welcome {
+                version: PROTOCOL_VERSION,
+                features: vec!["heartbeat".to_string()],
+                codec: Codec::Msgpack,
+            },
+        );
+        let mut buf = Vec::new();
+        write_frame_with(&mut buf, &msg, Codec::Msgpack).await.unwrap();
+        let json_len = Codec::Json.encode(&msg).unwrap().len();
+        assert!(buf.len() - 4 < json_len);
+
+        let mut cursor = std::io::Cursor::new(buf);
+        let decoded = read_frame(&mut cursor).await.unwrap();
+        assert_eq!(decoded.id, "req-7");
+        match decoded.kind {
+            MessageKind::Welcome { codec, .. } => assert_eq!(codec, Codec::Msgpack),
+            other => panic!("expected Welcome, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn json_is_detected_after_leading_whitespace() {
+        let json = Codec::Json.encode(&Message::ack("req-9")).unwrap();
+        let padded = [b" \r\n\t".as_slice(), &json].concat();
+        assert_eq!(Codec::detect(&padded), Codec::Json);
+        assert_eq!(Codec::decode(&padded).unwrap().id, "req-9");
+        // Not a message, but reported as the JSON error it is
+        assert!(matches!(
+            Codec::decode(b"\n[1, 2]"),
+            Err(FrameError::Json(_))
+        ));
+        let msgpack = Codec::Msgpack.encode(&Message::ack("req-9")).unwrap();
+        assert_eq!(Codec::detect(&msgpack), Codec::Msgpack);
+    }
+
     #[tokio::test]
     async fn frame_connection_closed() {
         let mut cursor = std::io::Cursor::new(Vec::<u8>::new());
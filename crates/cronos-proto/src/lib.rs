@@ -1,5 +1,8 @@
+pub mod chunk;
 pub mod frame;
 pub mod message;
+pub mod transport;
 
-pub use frame::{read_frame, write_frame, FrameError};
+pub use chunk::{into_chunks, read_response, CHUNK_TARGET_BYTES};
+pub use frame::{read_frame, write_frame, write_frame_with, Codec, FrameError, MAX_FRAME_SIZE};
 pub use message::*;
@@ -1,7 +1,97 @@
+use crate::frame::Codec;
 use cronos_model::*;
 use serde::{Deserialize, Serialize};
 
-pub const PROTOCOL_VERSION: u8 = 1;
+/// Protocol version this build speaks and stamps on outgoing messages.
+pub const PROTOCOL_VERSION: u8 = 2;
+/// Oldest protocol version this build still accepts.
+pub const MIN_PROTOCOL_VERSION: u8 = 1;
+
+/// Optional behaviours a peer may advertise during negotiation.
+pub const FEATURES: &[&str] = &["heartbeat", "auth", "collector_control", "chunked_results"];
+
+/// Feature letting the daemon split large query results into `QueryResultChunk`s.
+pub const FEATURE_CHUNKED_RESULTS: &str = "chunked_results";
+
+/// An inclusive range of protocol versions a peer can speak.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionRange {
+    pub min: u8,
+    pub max: u8,
+}
+
+impl VersionRange {
+    /// The range supported by this build.
+    pub const SUPPORTED: VersionRange = VersionRange {
+        min: MIN_PROTOCOL_VERSION,
+        max: PROTOCOL_VERSION,
+    };
+
+    /// A peer that only speaks `version` (one that predates negotiation).
+    pub fn exactly(version: u8) -> Self {
+        Self {
+            min: version,
+            max: version,
+        }
+    }
+
+    pub fn contains(&self, version: u8) -> bool {
+        (self.min..=self.max).contains(&version)
+    }
+
+    /// The highest version both ranges contain, if any.
+    pub fn negotiate(&self, other: &VersionRange) -> Option<u8> {
+        let version = self.max.min(other.max);
+        (version >= self.min.max(other.min)).then_some(version)
+    }
+}
+
+/// The features in `offered` that this build also supports.
+pub fn common_features(offered: &[String]) -> Vec<String> {
+    offered
+        .iter()
+        .filter(|f| FEATURES.contains(&f.as_str()))
+        .cloned()
+        .collect()
+}
+
+/// This build's features, as sent in `Hello` and `CollectorHandshake`.
+pub fn supported_features() -> Vec<String> {
+    FEATURES.iter().map(|f| f.to_string()).collect()
+}
+
+/// What a handshake settled on, as the daemon reported it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Negotiated {
+    pub version: u8,
+    pub features: Vec<String>,
+    pub codec: Codec,
+}
+
+impl Negotiated {
+    /// The settlement in a handshake response, or `None` if it does not accept
+    /// the handshake. Daemons that predate negotiation accept with a plain
+    /// `Ack`, which means version 1, JSON and no features.
+    pub fn from_response(kind: &MessageKind) -> Option<Self> {
+        match kind {
+            MessageKind::Welcome {
+                version,
+                features,
+                codec,
+            } => Some(Self {
+                version: *version,
+                features: features.clone(),
+                codec: *codec,
+            }),
+            MessageKind::Ack { .. } => Some(Self {
+                version: 1,
+                features: Vec::new(),
+                codec: Codec::Json,
+            }),
+            _ => None,
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
@@ -19,19 +109,82 @@ pub enum MessageKind {
         name: String,
         collector_version: String,
         source: CollectorSource,
+        /// Pre-shared token, required when connecting over TCP.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token: Option<String>,
+        /// Versions the collector speaks; absent for collectors that predate
+        /// negotiation, which are assumed to speak only `Message::version`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        protocol: Option<VersionRange>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        features: Vec<String>,
+        /// Frame codecs the collector can read, most preferred first.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        codecs: Vec<Codec>,
     },
     Heartbeat,
 
+    // Any client -> Core
+    /// Negotiate a protocol version and feature set before other requests.
+    Hello {
+        protocol: VersionRange,
+        #[serde(default)]
+        features: Vec<String>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        codecs: Vec<Codec>,
+    },
+    /// Present a token to obtain the capabilities configured for it.
+    Authenticate { token: String },
+
     // CLI/UI -> Core
     Query { query: QueryRequest },
     Status,
     ListCollectors,
     SetTrackingPaused { paused: bool },
+    StartCollector { name: String },
+    StopCollector { name: String },
+    RestartCollector { name: String },
+    /// Write a consistent copy of the database to `path`, an absolute path
+    /// on the daemon's machine. Answered with `Ack` once the copy is complete.
+    Backup { path: String },
+    /// Re-apply the categorization rules to stored sessions starting in
+    /// `[from, to)`, e.g. after the rules changed.
+    RecategorizeSessions { from: Timestamp, to: Timestamp },
+    /// Recompute the sessions in `[from, to)` from raw events, e.g. after
+    /// events arrived late. Sessions crossing either bound are rebuilt whole.
+    RebuildSessions { from: Timestamp, to: Timestamp },
+    /// Store a manual correction applied to timesheets. Answered with `Ack`.
+    AddTimeOverride { record: TimeOverride },
+    /// Delete a stored override. Answered with `Ack`, or `NotFound`.
+    RemoveTimeOverride { id: OverrideId },
+    /// Store a note on a time range, session or entity. The daemon sets
+    /// its `start` and `end` from the target. Answered with `Ack`, or
+    /// `NotFound` when the session or entity does not exist.
+    AddAnnotation { annotation: Annotation },
+    /// Delete a stored annotation. Answered with `Ack`, or `NotFound`.
+    DeleteAnnotation { id: AnnotationId },
+    /// Accept an annotation the assistant proposed. Answered with `Ack`,
+    /// or `NotFound`.
+    AcceptAnnotation { id: AnnotationId },
 
     // Core -> CLI/UI
     TrackingStatus { paused: bool },
+    /// Reply to `RecategorizeSessions`: how many sessions changed category.
+    Recategorized { updated: u64 },
+    /// Reply to `RebuildSessions`: sessions removed and built in their place.
+    SessionsRebuilt { removed: u64, created: u64 },
+    Authenticated { capabilities: Vec<Capability> },
 
     // Core -> Collector/CLI
+    /// Reply to `Hello` and negotiating handshakes: the version both sides
+    /// will use, the features both support and the codec the daemon will
+    /// write from now on.
+    Welcome {
+        version: u8,
+        features: Vec<String>,
+        #[serde(default)]
+        codec: Codec,
+    },
     Ack { request_id: String },
     Error {
         request_id: String,
@@ -39,33 +192,475 @@ pub enum MessageKind {
         message: String,
     },
     QueryResult { response: QueryResponse },
+    /// One part of a `QueryResult` too large for a single frame. Parts share
+    /// the request id; the receiver concatenates them until `last`.
+    QueryResultChunk {
+        response: QueryResponse,
+        seq: u32,
+        last: bool,
+    },
     StatusResult { info: StatusInfo },
     CollectorList { collectors: Vec<CollectorInfo> },
 }
 
+/// A permission granted to a client connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    /// Register as a collector and send events and heartbeats.
+    Emit,
+    /// Read activity history, status and collector state.
+    Query,
+    /// Change daemon state: pause tracking, control collectors, take backups,
+    /// recategorize and rebuild sessions.
+    Admin,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 3] = [Capability::Emit, Capability::Query, Capability::Admin];
+}
+
+impl std::str::FromStr for Capability {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "emit" => Ok(Self::Emit),
+            "query" => Ok(Self::Query),
+            "admin" => Ok(Self::Admin),
+            other => Err(format!("unknown capability: {other}")),
+        }
+    }
+}
+
+impl std::fmt::Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Emit => "emit",
+            Self::Query => "query",
+            Self::Admin => "admin",
+        })
+    }
+}
+
+impl MessageKind {
+    /// The capability a client needs to send this message, or `None` for
+    /// messages that are not requests (`Authenticate`, `Hello` and core responses).
+    pub fn required_capability(&self) -> Option<Capability> {
+        match self {
+            Self::EmitEvent { .. } | Self::CollectorHandshake { .. } | Self::Heartbeat => {
+                Some(Capability::Emit)
+            }
+            Self::Query { .. } | Self::Status | Self::ListCollectors => Some(Capability::Query),
+            Self::SetTrackingPaused { .. }
+            | Self::StartCollector { .. }
+            | Self::StopCollector { .. }
+            | Self::RestartCollector { .. }
+            | Self::Backup { .. }
+            | Self::RecategorizeSessions { .. }
+            | Self::RebuildSessions { .. }
+            | Self::AddTimeOverride { .. }
+            | Self::RemoveTimeOverride { .. }
+            | Self::AddAnnotation { .. }
+            | Self::DeleteAnnotation { .. }
+            | Self::AcceptAnnotation { .. } => Some(Capability::Admin),
+            Self::Authenticate { .. }
+            | Self::Hello { .. }
+            | Self::TrackingStatus { .. }
+            | Self::Recategorized { .. }
+            | Self::SessionsRebuilt { .. }
+            | Self::Authenticated { .. }
+            | Self::Welcome { .. }
+            | Self::Ack { .. }
+            | Self::Error { .. }
+            | Self::QueryResult { .. }
+            | Self::QueryResultChunk { .. }
+            | Self::StatusResult { .. }
+            | Self::CollectorList { .. } => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QueryRequest {
     pub kind: QueryKind,
+    /// Narrows list queries; ignored by queries it does not apply to.
+    #[serde(default, skip_serializing_if = "QueryFilter::is_empty")]
+    pub filter: QueryFilter,
+    /// `next_cursor` from the previous page; `None` starts at the first page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+}
+
+impl QueryRequest {
+    /// An unfiltered request for the first page.
+    pub fn new(kind: QueryKind) -> Self {
+        Self {
+            kind,
+            filter: QueryFilter::default(),
+            cursor: None,
+        }
+    }
+}
+
+/// Filters for list queries. Empty lists and `None` match everything; a
+/// non-empty list matches any of its values.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct QueryFilter {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<CollectorSource>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub event_kinds: Vec<EventKind>,
+    /// Kind of the entity: the event subject, or the entity itself.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub entity_kinds: Vec<EntityKind>,
+    /// Project path, or its last path component.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+}
+
+impl QueryFilter {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// Page size for `Timeline` when the request does not set one.
+pub const DEFAULT_TIMELINE_LIMIT: u32 = 1000;
+/// Page size for `Related` when the request does not set one.
+pub const DEFAULT_RELATED_LIMIT: u32 = 100;
+/// Longest path `Connection` searches when the request does not set one.
+pub const DEFAULT_CONNECTION_DEPTH: u8 = 4;
+
+fn default_timeline_limit() -> u32 {
+    DEFAULT_TIMELINE_LIMIT
+}
+fn default_related_limit() -> u32 {
+    DEFAULT_RELATED_LIMIT
+}
+fn default_connection_depth() -> u8 {
+    DEFAULT_CONNECTION_DEPTH
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum QueryKind {
     Search { text: String, limit: u32 },
-    Timeline { from: Timestamp, to: Timestamp },
-    Related { entity_id: EntityId, depth: u8 },
+    Timeline {
+        from: Timestamp,
+        to: Timestamp,
+        #[serde(default = "default_timeline_limit")]
+        limit: u32,
+    },
+    /// Entities connected to `entity_id`, strongest connection first, with
+    /// `QueryResponse::related` explaining each.
+    Related {
+        entity_id: EntityId,
+        depth: u8,
+        #[serde(default = "default_related_limit")]
+        limit: u32,
+        /// Only follow edges with these relations; empty follows all.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        relations: Vec<Relation>,
+    },
+    /// How `from` and `to` are connected: the strongest path of at most
+    /// `max_depth` edges. `entities` lists the path from `from` to `to`,
+    /// `edges` its edges in order, and `related` scores it; all are empty
+    /// when there is no such path.
+    Connection {
+        from: EntityId,
+        to: EntityId,
+        #[serde(default = "default_connection_depth")]
+        max_depth: u8,
+    },
     Recent { limit: u32 },
     Sessions { from: Timestamp, to: Timestamp, limit: u32 },
-    DaySummary { date: String },
+    /// Sessions starting on `date` (`YYYY-MM-DD`), a day in `timezone`
+    /// (an IANA name) or else the daemon's configured zone, with the
+    /// annotations overlapping that day.
+    DaySummary {
+        date: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timezone: Option<String>,
+    },
+    /// Totals for the day, week (Monday to Sunday) or month containing
+    /// `anchor` (`YYYY-MM-DD`), in `timezone` or else the daemon's zone.
+    /// Answered in `QueryResponse::summary`.
+    Summary {
+        period: Period,
+        anchor: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timezone: Option<String>,
+    },
+    /// Deep-work metrics over sessions starting in `[from, to)`. Answered
+    /// in `QueryResponse::focus`.
+    FocusReport { from: Timestamp, to: Timestamp },
+    /// Tracked time in `[from, to)` grouped into billable blocks, after
+    /// applying stored overrides. Each block's time is rounded to the
+    /// nearest `round_secs` when that is not zero. Answered in
+    /// `QueryResponse::timesheet`.
+    Timesheet {
+        from: Timestamp,
+        to: Timestamp,
+        by: TimesheetGroup,
+        #[serde(default)]
+        round_secs: u32,
+    },
+    /// Stored overrides overlapping `[from, to)`, in the order they apply.
+    /// Answered in `QueryResponse::overrides`.
+    TimeOverrides { from: Timestamp, to: Timestamp },
+    /// Stored annotations, narrowed by each field that is set: overlapping
+    /// `[from, to)`, carrying `tag`, or on a session or entity. Answered
+    /// in `QueryResponse::annotations`.
+    Annotations {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        from: Option<Timestamp>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        to: Option<Timestamp>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tag: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        session_id: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        entity_id: Option<EntityId>,
+        /// Only proposals still awaiting acceptance.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        proposed: bool,
+    },
+    /// A query in the daemon's pipeline language, e.g.
+    /// `sessions where time >= -7d | group by app | sum(duration)`.
+    /// Filters and cursors on the request do not apply.
+    Expr { query: String },
+    /// A single read-only SQL statement over the store's tables.
+    Sql { statement: String },
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct QueryResponse {
     pub entities: Vec<Entity>,
     pub edges: Vec<Edge>,
     pub events: Vec<Event>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub sessions: Vec<SessionInfo>,
+    /// Set when more results exist; pass it back as `QueryRequest::cursor`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+    /// Rows of an aggregating query, which have no entity or event shape.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub table: Option<Table>,
+    /// Scores and explaining paths for `Related`, in `entities` order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub related: Vec<RelatedEntity>,
+    /// Result of a `Summary` query.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<SummaryInfo>,
+    /// Result of a `FocusReport` query.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub focus: Option<FocusReportInfo>,
+    /// Result of a `Timesheet` query.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timesheet: Option<TimesheetInfo>,
+    /// Result of a `TimeOverrides` query.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub overrides: Vec<TimeOverride>,
+    /// Result of an `Annotations` query, and annotations matching a
+    /// `Search` (on its first page).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub annotations: Vec<Annotation>,
+}
+
+/// How strongly an entity relates to the start of a `Related` query, and why.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RelatedEntity {
+    pub entity_id: EntityId,
+    /// Product of edge strengths along `path`, in `(0, 1]`.
+    pub score: f32,
+    /// Edges from the start entity to this one; each is in `edges`.
+    pub path: Vec<EdgeId>,
+}
+
+/// Column names and rows of values, one value per column.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Table {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<serde_json::Value>>,
+    /// More rows matched than the daemon's row limit allows.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+impl QueryResponse {
+    /// Append another part of the same result.
+    pub fn extend(&mut self, other: QueryResponse) {
+        self.entities.extend(other.entities);
+        self.edges.extend(other.edges);
+        self.events.extend(other.events);
+        self.sessions.extend(other.sessions);
+        self.related.extend(other.related);
+        self.overrides.extend(other.overrides);
+        self.annotations.extend(other.annotations);
+        if other.next_cursor.is_some() {
+            self.next_cursor = other.next_cursor;
+        }
+        if other.summary.is_some() {
+            self.summary = other.summary;
+        }
+        if other.focus.is_some() {
+            self.focus = other.focus;
+        }
+        if other.timesheet.is_some() {
+            self.timesheet = other.timesheet;
+        }
+        match (&mut self.table, other.table) {
+            (Some(table), Some(other)) => {
+                table.rows.extend(other.rows);
+                table.truncated |= other.truncated;
+            }
+            (None, other) => self.table = other,
+            (Some(_), None) => {}
+        }
+    }
+}
+
+/// The span a `Summary` covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Period {
+    Day,
+    Week,
+    Month,
+}
+
+/// Activity totals over a period, computed by the daemon from sessions
+/// starting in `[start, end)` and events in that range.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SummaryInfo {
+    pub start: Timestamp,
+    pub end: Timestamp,
+    /// IANA zone the period's days were counted in.
+    pub timezone: String,
+    pub total_secs: i64,
+    pub session_count: u64,
+    /// Each list is ordered by time spent, most first.
+    pub categories: Vec<TimeTotal>,
+    pub apps: Vec<TimeTotal>,
+    /// Sessions attributed to a project; `id` is the project entity.
+    pub projects: Vec<TimeTotal>,
+    /// Seconds of activity in each hour of the local day, from 0 to 23.
+    pub hours: Vec<i64>,
+    /// Files with the most events, most first.
+    pub top_files: Vec<CountTotal>,
+    /// Domains of visited URLs with the most events, most first.
+    pub top_domains: Vec<CountTotal>,
+    /// The longest deep-work blocks, as in the focus report for the period.
+    pub focus_blocks: Vec<DeepWorkBlock>,
+    /// Context switches, as in the focus report for the period.
+    pub context_switches: u64,
+    /// Annotations overlapping the period, by start time.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub annotations: Vec<Annotation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimeTotal {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<EntityId>,
+    pub secs: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CountTotal {
+    pub name: String,
+    pub count: u64,
+}
+
+/// How focused the time in `[from, to)` was.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FocusReportInfo {
+    pub from: Timestamp,
+    pub to: Timestamp,
+    /// Session time in the range.
+    pub tracked_secs: i64,
+    /// Session time inside `blocks`.
+    pub focus_secs: i64,
+    /// Uninterrupted stretches on one project at least the configured
+    /// minimum long, longest first.
+    pub blocks: Vec<DeepWorkBlock>,
+    /// Moves to another project, or another app outside any project,
+    /// without a break in between.
+    pub context_switches: u64,
+    /// `context_switches` per hour of tracked time.
+    pub switches_per_hour: f64,
+    /// 0 when the tracked time is one unbroken stretch, approaching 1 as it
+    /// splits into many short ones.
+    pub fragmentation: f64,
+    /// Apps that broke off project work, most frequent first.
+    pub interruptions: Vec<InterruptionSource>,
+}
+
+/// Time on one project with no interruption or break.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeepWorkBlock {
+    pub project: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project_id: Option<EntityId>,
+    pub start: Timestamp,
+    pub end: Timestamp,
+    /// Session time on the project; brief unattributed sessions in between
+    /// are not counted.
+    pub secs: i64,
+    /// Apps used, most time first.
+    pub apps: Vec<String>,
+}
+
+/// An app whose sessions ended project work.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InterruptionSource {
+    pub app_name: String,
+    pub category: String,
+    pub count: u64,
+    /// Time spent in the interrupting sessions.
+    pub secs: i64,
+}
+
+/// What timesheet blocks are billed to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimesheetGroup {
+    Project,
+    Category,
+}
+
+/// Billable time in `[from, to)`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimesheetInfo {
+    pub from: Timestamp,
+    pub to: Timestamp,
+    pub by: TimesheetGroup,
+    #[serde(default)]
+    pub round_secs: u32,
+    /// In time order; blocks that round to nothing are left out.
+    pub blocks: Vec<TimesheetBlock>,
+    /// Billed time per project or category, most first; time attributed
+    /// to no project is totalled under an empty name.
+    pub totals: Vec<TimeTotal>,
+}
+
+/// Time billed to one project or category, with no other work in between.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimesheetBlock {
+    /// The project or category; `None` for time attributed to no project.
+    pub name: Option<String>,
+    pub start: Timestamp,
+    pub end: Timestamp,
+    /// Time actually tracked in the block; short gaps are not counted.
+    pub tracked_secs: i64,
+    /// `tracked_secs` after rounding.
+    pub billed_secs: i64,
+    /// Notes of the overrides that shaped the block.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
 }
 
 /// A session as returned across the protocol boundary.
@@ -75,11 +670,20 @@ pub struct SessionInfo {
     pub app_name: String,
     pub window_titles: Vec<String>,
     pub project: Option<String>,
+    /// The project entity the session was attributed to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project_id: Option<EntityId>,
+    /// How sure that attribution is, from 0 to 1.
+    #[serde(default)]
+    pub project_confidence: f32,
     pub category: String,
     pub start_time: Timestamp,
     pub end_time: Timestamp,
     pub duration_secs: i64,
     pub event_count: i64,
+    /// Still in progress; its end moves as activity continues.
+    #[serde(default)]
+    pub open: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -98,6 +702,55 @@ pub struct CollectorInfo {
     pub connected: bool,
     pub last_heartbeat: Option<Timestamp>,
     pub events_sent: u64,
+    /// PID of the collector process when it is supervised by the daemon.
+    #[serde(default)]
+    pub pid: Option<u32>,
+    /// Number of times the supervisor has restarted this collector.
+    #[serde(default)]
+    pub restarts: u32,
+    /// Set when the collector was dropped because heartbeats stopped arriving.
+    #[serde(default)]
+    pub stale: bool,
+    /// When the current connection was established.
+    #[serde(default)]
+    pub connected_at: Option<Timestamp>,
+    /// Seconds since `connected_at` (0 when disconnected).
+    #[serde(default)]
+    pub uptime_secs: u64,
+    /// Average accepted events per minute over the current connection.
+    #[serde(default)]
+    pub events_per_min: f64,
+    /// Events that failed to be stored.
+    #[serde(default)]
+    pub errors: u64,
+    /// Events dropped as invalid by the ingest pipeline.
+    #[serde(default)]
+    pub dropped: u64,
+    /// Events discarded as duplicates by the ingest pipeline.
+    #[serde(default)]
+    pub deduplicated: u64,
+}
+
+impl CollectorInfo {
+    /// A collector entry with no connection and zeroed counters.
+    pub fn new(name: impl Into<String>, source: CollectorSource) -> Self {
+        Self {
+            name: name.into(),
+            source,
+            connected: false,
+            last_heartbeat: None,
+            events_sent: 0,
+            pid: None,
+            restarts: 0,
+            stale: false,
+            connected_at: None,
+            uptime_secs: 0,
+            events_per_min: 0.0,
+            errors: 0,
+            dropped: 0,
+            deduplicated: 0,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -107,6 +760,9 @@ pub enum ErrorCode {
     InternalError,
     NotFound,
     BadRequest,
+    Unauthorized,
+    /// The peer shares no protocol version with the daemon.
+    IncompatibleVersion,
 }
 
 impl Message {
@@ -177,12 +833,10 @@ mod tests {
         let msg = Message::new(
             "r2",
             MessageKind::Query {
-                query: QueryRequest {
-                    kind: QueryKind::Search {
-                        text: "billing".to_string(),
-                        limit: 10,
-                    },
-                },
+                query: QueryRequest::new(QueryKind::Search {
+                    text: "billing".to_string(),
+                    limit: 10,
+                }),
             },
         );
         let json = serde_json::to_string(&msg).unwrap();
@@ -198,4 +852,34 @@ mod tests {
             _ => panic!("wrong message kind"),
         }
     }
+
+    #[test]
+    fn version_ranges_negotiate_highest_common() {
+        let ours = VersionRange { min: 1, max: 3 };
+        assert_eq!(ours.negotiate(&VersionRange { min: 2, max: 5 }), Some(3));
+        assert_eq!(ours.negotiate(&VersionRange::exactly(1)), Some(1));
+        assert_eq!(ours.negotiate(&VersionRange { min: 4, max: 5 }), None);
+    }
+
+    #[test]
+    fn pre_negotiation_acks_settle_on_version_one_json() {
+        let ack = Message::ack("hs-1");
+        assert_eq!(
+            Negotiated::from_response(&ack.kind),
+            Some(Negotiated {
+                version: 1,
+                features: vec![],
+                codec: Codec::Json,
+            })
+        );
+        let welcome = MessageKind::Welcome {
+            version: 2,
+            features: vec![FEATURE_CHUNKED_RESULTS.to_string()],
+            codec: Codec::Msgpack,
+        };
+        let negotiated = Negotiated::from_response(&welcome).unwrap();
+        assert_eq!(negotiated.version, 2);
+        assert_eq!(negotiated.codec, Codec::Msgpack);
+        assert_eq!(Negotiated::from_response(&MessageKind::Heartbeat), None);
+    }
 }
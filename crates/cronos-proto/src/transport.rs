@@ -0,0 +1,169 @@
+use crate::frame::{read_frame, write_frame_with, Codec, FrameError};
+use crate::message::{Message, MessageKind};
+use cronos_common::{CronosConfig, CronosPaths};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// How often collectors tell the daemon they are still alive.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A bidirectional byte stream that frames can be read from and written to.
+pub trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+/// Where a client reaches the daemon.
+#[derive(Debug, Clone)]
+pub enum Endpoint {
+    Unix(PathBuf),
+    Tcp {
+        addr: String,
+        tls: Option<ClientTls>,
+    },
+}
+
+/// TLS settings for a TCP client connection.
+#[derive(Debug, Clone)]
+pub struct ClientTls {
+    /// PEM CA bundle used to verify the daemon certificate.
+    pub ca_cert: PathBuf,
+    /// Name the daemon certificate must be valid for.
+    pub server_name: String,
+    /// PEM certificate chain and key presented for mutual TLS.
+    pub client_identity: Option<(PathBuf, PathBuf)>,
+}
+
+impl std::fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unix(path) => write!(f, "{}", path.display()),
+            Self::Tcp { addr, tls: None } => write!(f, "tcp://{addr}"),
+            Self::Tcp { addr, tls: Some(_) } => write!(f, "tls://{addr}"),
+        }
+    }
+}
+
+/// How collectors reach the daemon: TCP when `daemon.remote.addr` is set,
+/// otherwise the local Unix socket.
+pub fn daemon_endpoint(config: &CronosConfig, paths: &CronosPaths) -> Endpoint {
+    let remote = &config.daemon.remote;
+    if remote.addr.is_empty() {
+        let socket_path = if config.daemon.socket_path.is_empty() {
+            paths.socket_file.clone()
+        } else {
+            PathBuf::from(&config.daemon.socket_path)
+        };
+        return Endpoint::Unix(socket_path);
+    }
+    let tls = (!remote.ca_cert.is_empty()).then(|| ClientTls {
+        ca_cert: PathBuf::from(&remote.ca_cert),
+        server_name: if remote.server_name.is_empty() {
+            remote
+                .addr
+                .rsplit_once(':')
+                .map_or(remote.addr.clone(), |(host, _)| host.to_string())
+        } else {
+            remote.server_name.clone()
+        },
+        client_identity: (!remote.client_cert.is_empty()).then(|| {
+            (
+                PathBuf::from(&remote.client_cert),
+                PathBuf::from(&remote.client_key),
+            )
+        }),
+    });
+    Endpoint::Tcp {
+        addr: remote.addr.clone(),
+        tls,
+    }
+}
+
+/// Send a heartbeat and wait for the daemon's ack.
+pub async fn send_heartbeat<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    codec: Codec,
+) -> Result<(), FrameError>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let msg = Message::new(
+        cronos_model::Ulid::new().to_string(),
+        MessageKind::Heartbeat,
+    );
+    write_frame_with(writer, &msg, codec).await?;
+    let _ack = read_frame(reader).await?;
+    Ok(())
+}
+
+/// Connect to the daemon at `endpoint`.
+pub async fn connect(endpoint: &Endpoint) -> std::io::Result<Box<dyn Stream>> {
+    match endpoint {
+        Endpoint::Unix(path) => Ok(Box::new(tokio::net::UnixStream::connect(path).await?)),
+        Endpoint::Tcp { addr, tls: None } => {
+            let stream = tokio::net::TcpStream::connect(addr).await?;
+            stream.set_nodelay(true)?;
+            Ok(Box::new(stream))
+        }
+        Endpoint::Tcp {
+            addr,
+            tls: Some(tls),
+        } => {
+            let stream = tokio::net::TcpStream::connect(addr).await?;
+            stream.set_nodelay(true)?;
+            let connector = tokio_rustls::TlsConnector::from(Arc::new(client_config(tls)?));
+            let server_name = ServerName::try_from(tls.server_name.clone())
+                .map_err(|e| invalid_input(format!("server name: {e}")))?;
+            Ok(Box::new(connector.connect(server_name, stream).await?))
+        }
+    }
+}
+
+fn client_config(tls: &ClientTls) -> std::io::Result<rustls::ClientConfig> {
+    let mut roots = rustls::RootCertStore::empty();
+    for cert in load_certs(&tls.ca_cert)? {
+        roots
+            .add(cert)
+            .map_err(|e| invalid_input(format!("ca cert: {e}")))?;
+    }
+    let builder = rustls::ClientConfig::builder_with_provider(crypto_provider())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| invalid_input(format!("tls: {e}")))?
+        .with_root_certificates(roots);
+    match &tls.client_identity {
+        Some((cert, key)) => builder
+            .with_client_auth_cert(load_certs(cert)?, load_key(key)?)
+            .map_err(|e| invalid_input(format!("client cert: {e}"))),
+        None => Ok(builder.with_no_client_auth()),
+    }
+}
+
+/// The crypto provider used for both ends of TLS connections.
+pub fn crypto_provider() -> Arc<rustls::crypto::CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+/// Read every certificate from a PEM file.
+pub fn load_certs(path: &Path) -> std::io::Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|iter| iter.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| invalid_input(format!("{}: {e}", path.display())))?;
+    if certs.is_empty() {
+        return Err(invalid_input(format!("{}: no certificates", path.display())));
+    }
+    Ok(certs)
+}
+
+/// Read the first private key from a PEM file.
+pub fn load_key(path: &Path) -> std::io::Result<PrivateKeyDer<'static>> {
+    PrivateKeyDer::from_pem_file(path).map_err(|e| invalid_input(format!("{}: {e}", path.display())))
+}
+
+fn invalid_input(msg: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, msg)
+}
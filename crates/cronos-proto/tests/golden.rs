@@ -0,0 +1,426 @@
+//! Golden wire fixtures: one JSON file per `MessageKind` under `tests/fixtures/`.
+//!
+//! A failure here means the wire format changed. If the change is intended,
+//! regenerate the fixtures with `UPDATE_FIXTURES=1 cargo test -p cronos-proto`
+//! and review the diff; older peers will see exactly what changed.
+
+use cronos_model::*;
+use cronos_proto::*;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Fixture file stem for a kind. Exhaustive so a new kind cannot be added
+/// without deciding on its fixture.
+fn fixture_name(kind: &MessageKind) -> &'static str {
+    match kind {
+        MessageKind::EmitEvent { .. } => "emit_event",
+        MessageKind::CollectorHandshake { .. } => "collector_handshake",
+        MessageKind::Heartbeat => "heartbeat",
+        MessageKind::Hello { .. } => "hello",
+        MessageKind::Authenticate { .. } => "authenticate",
+        MessageKind::Query { .. } => "query",
+        MessageKind::Status => "status",
+        MessageKind::ListCollectors => "list_collectors",
+        MessageKind::SetTrackingPaused { .. } => "set_tracking_paused",
+        MessageKind::StartCollector { .. } => "start_collector",
+        MessageKind::StopCollector { .. } => "stop_collector",
+        MessageKind::RestartCollector { .. } => "restart_collector",
+        MessageKind::Backup { .. } => "backup",
+        MessageKind::RecategorizeSessions { .. } => "recategorize_sessions",
+        MessageKind::RebuildSessions { .. } => "rebuild_sessions",
+        MessageKind::AddTimeOverride { .. } => "add_time_override",
+        MessageKind::RemoveTimeOverride { .. } => "remove_time_override",
+        MessageKind::AddAnnotation { .. } => "add_annotation",
+        MessageKind::DeleteAnnotation { .. } => "delete_annotation",
+        MessageKind::AcceptAnnotation { .. } => "accept_annotation",
+        MessageKind::TrackingStatus { .. } => "tracking_status",
+        MessageKind::Recategorized { .. } => "recategorized",
+        MessageKind::SessionsRebuilt { .. } => "sessions_rebuilt",
+        MessageKind::Authenticated { .. } => "authenticated",
+        MessageKind::Welcome { .. } => "welcome",
+        MessageKind::Ack { .. } => "ack",
+        MessageKind::Error { .. } => "error",
+        MessageKind::QueryResult { .. } => "query_result",
+        MessageKind::QueryResultChunk { .. } => "query_result_chunk",
+        MessageKind::StatusResult { .. } => "status_result",
+        MessageKind::CollectorList { .. } => "collector_list",
+    }
+}
+
+fn entity_ref(identity: &str) -> EntityRef {
+    EntityRef {
+        kind: EntityKind::File,
+        identity: identity.to_string(),
+        attributes: HashMap::new(),
+    }
+}
+
+fn sample_override() -> TimeOverride {
+    TimeOverride {
+        id: OverrideId(Ulid(5)),
+        start: 1_700_000_000_000,
+        end: 1_700_001_800_000,
+        action: OverrideAction::Reassign {
+            project: Some("cronos".to_string()),
+            category: None,
+        },
+        note: "client call".to_string(),
+        created_at: 1_700_090_000_000,
+    }
+}
+
+fn sample_annotation() -> Annotation {
+    Annotation {
+        id: AnnotationId(Ulid(6)),
+        target: AnnotationTarget::Session {
+            session_id: "01HSESSION".to_string(),
+        },
+        text: "pairing with Sam on the release".to_string(),
+        tags: vec!["pairing".to_string(), "release".to_string()],
+        start: Some(1_700_000_000_000),
+        end: Some(1_700_000_600_000),
+        proposed: true,
+        created_at: 1_700_090_000_000,
+    }
+}
+
+fn sample_event() -> Event {
+    Event {
+        id: EventId(Ulid(1)),
+        timestamp: 1_700_000_000_000,
+        source: CollectorSource::Filesystem,
+        kind: EventKind::FileModified,
+        subject: entity_ref("/src/main.rs"),
+        context: vec![entity_ref("/src")],
+        metadata: HashMap::from([("size".to_string(), serde_json::json!(42))]),
+    }
+}
+
+fn samples() -> Vec<MessageKind> {
+    let mut collector = CollectorInfo::new("cronos-collect-fs", CollectorSource::Filesystem);
+    collector.connected = true;
+    collector.last_heartbeat = Some(1_700_000_000_000);
+    collector.events_sent = 12;
+    collector.pid = Some(4242);
+    collector.connected_at = Some(1_699_999_000_000);
+    collector.uptime_secs = 1000;
+    collector.events_per_min = 0.5;
+
+    vec![
+        MessageKind::EmitEvent {
+            event: sample_event(),
+        },
+        MessageKind::CollectorHandshake {
+            name: "cronos-collect-fs".to_string(),
+            collector_version: "0.1.0".to_string(),
+            source: CollectorSource::Filesystem,
+            token: Some("s3cret".to_string()),
+            protocol: Some(VersionRange { min: 1, max: 2 }),
+            features: vec!["heartbeat".to_string()],
+            codecs: vec![Codec::Msgpack, Codec::Json],
+        },
+        MessageKind::Heartbeat,
+        MessageKind::Hello {
+            protocol: VersionRange { min: 1, max: 2 },
+            features: vec!["auth".to_string()],
+            codecs: vec![Codec::Msgpack],
+        },
+        MessageKind::Authenticate {
+            token: "s3cret".to_string(),
+        },
+        MessageKind::Query {
+            query: QueryRequest {
+                kind: QueryKind::Timeline {
+                    from: 1_700_000_000_000,
+                    to: 1_700_086_400_000,
+                    limit: 100,
+                },
+                filter: QueryFilter {
+                    sources: vec![CollectorSource::Filesystem],
+                    event_kinds: vec![EventKind::FileModified],
+                    entity_kinds: vec![EntityKind::File],
+                    project: Some("cronos".to_string()),
+                },
+                cursor: Some("1700000000000:00000000000000000000000001".to_string()),
+            },
+        },
+        MessageKind::Status,
+        MessageKind::ListCollectors,
+        MessageKind::SetTrackingPaused { paused: true },
+        MessageKind::StartCollector {
+            name: "cronos-collect-fs".to_string(),
+        },
+        MessageKind::StopCollector {
+            name: "cronos-collect-fs".to_string(),
+        },
+        MessageKind::RestartCollector {
+            name: "cronos-collect-fs".to_string(),
+        },
+        MessageKind::Backup {
+            path: "/home/me/cronos-backup.db".to_string(),
+        },
+        MessageKind::RecategorizeSessions {
+            from: 1_700_000_000_000,
+            to: 1_700_086_400_000,
+        },
+        MessageKind::RebuildSessions {
+            from: 1_700_000_000_000,
+            to: 1_700_086_400_000,
+        },
+        MessageKind::AddTimeOverride {
+            record: sample_override(),
+        },
+        MessageKind::RemoveTimeOverride {
+            id: OverrideId(Ulid(5)),
+        },
+        MessageKind::AddAnnotation {
+            annotation: sample_annotation(),
+        },
+        MessageKind::DeleteAnnotation {
+            id: AnnotationId(Ulid(6)),
+        },
+        MessageKind::AcceptAnnotation {
+            id: AnnotationId(Ulid(6)),
+        },
+        MessageKind::TrackingStatus { paused: false },
+        MessageKind::Recategorized { updated: 3 },
+        MessageKind::SessionsRebuilt {
+            removed: 4,
+            created: 3,
+        },
+        MessageKind::Authenticated {
+            capabilities: vec![Capability::Emit, Capability::Query],
+        },
+        MessageKind::Welcome {
+            version: 2,
+            features: vec!["heartbeat".to_string()],
+            codec: Codec::Msgpack,
+        },
+        MessageKind::Ack {
+            request_id: "r1".to_string(),
+        },
+        MessageKind::Error {
+            request_id: "r1".to_string(),
+            code: ErrorCode::IncompatibleVersion,
+            message: "daemon speaks protocol 1..=2, peer speaks 3..=3".to_string(),
+        },
+        MessageKind::QueryResult {
+            response: QueryResponse {
+                entities: vec![Entity {
+                    id: EntityId(Ulid(2)),
+                    kind: EntityKind::File,
+                    name: "main.rs".to_string(),
+                    attributes: HashMap::new(),
+                    first_seen: 1_699_000_000_000,
+                    last_seen: 1_700_000_000_000,
+                }],
+                edges: vec![Edge {
+                    id: EdgeId(Ulid(3)),
+                    from: EntityId(Ulid(2)),
+                    to: EntityId(Ulid(4)),
+                    relation: Relation::BelongsTo,
+                    strength: 0.5,
+                    created_at: 1_699_000_000_000,
+                    last_reinforced: 1_700_000_000_000,
+                }],
+                events: vec![sample_event()],
+                sessions: vec![SessionInfo {
+                    id: "s1".to_string(),
+                    app_name: "code".to_string(),
+                    window_titles: vec!["main.rs - cronos".to_string()],
+                    project: Some("cronos".to_string()),
+                    project_id: Some(EntityId(Ulid(4))),
+                    project_confidence: 0.9,
+                    category: "coding".to_string(),
+                    start_time: 1_700_000_000_000,
+                    end_time: 1_700_000_600_000,
+                    duration_secs: 600,
+                    event_count: 3,
+                    open: false,
+                }],
+                next_cursor: Some("1700000000000:00000000000000000000000001".to_string()),
+                related: vec![RelatedEntity {
+                    entity_id: EntityId(Ulid(2)),
+                    score: 0.5,
+                    path: vec![EdgeId(Ulid(3))],
+                }],
+                table: Some(Table {
+                    columns: vec!["app".to_string(), "sum(duration)".to_string()],
+                    rows: vec![vec![serde_json::json!("code"), serde_json::json!(600)]],
+                    truncated: true,
+                }),
+                summary: Some(SummaryInfo {
+                    start: 1_699_948_800_000,
+                    end: 1_700_035_200_000,
+                    timezone: "America/Los_Angeles".to_string(),
+                    total_secs: 600,
+                    session_count: 1,
+                    categories: vec![TimeTotal {
+                        name: "coding".to_string(),
+                        id: None,
+                        secs: 600,
+                    }],
+                    apps: vec![TimeTotal {
+                        name: "code".to_string(),
+                        id: None,
+                        secs: 600,
+                    }],
+                    projects: vec![TimeTotal {
+                        name: "cronos".to_string(),
+                        id: Some(EntityId(Ulid(4))),
+                        secs: 600,
+                    }],
+                    hours: (0..24).map(|h| if h == 14 { 600 } else { 0 }).collect(),
+                    top_files: vec![CountTotal {
+                        name: "main.rs".to_string(),
+                        count: 3,
+                    }],
+                    top_domains: vec![CountTotal {
+                        name: "docs.rs".to_string(),
+                        count: 2,
+                    }],
+                    focus_blocks: vec![DeepWorkBlock {
+                        project: "cronos".to_string(),
+                        project_id: Some(EntityId(Ulid(4))),
+                        start: 1_700_000_000_000,
+                        end: 1_700_001_800_000,
+                        secs: 1800,
+                        apps: vec!["code".to_string()],
+                    }],
+                    context_switches: 0,
+                    annotations: vec![sample_annotation()],
+                }),
+                focus: Some(FocusReportInfo {
+                    from: 1_699_948_800_000,
+                    to: 1_700_035_200_000,
+                    tracked_secs: 3600,
+                    focus_secs: 1800,
+                    blocks: vec![DeepWorkBlock {
+                        project: "cronos".to_string(),
+                        project_id: Some(EntityId(Ulid(4))),
+                        start: 1_700_000_000_000,
+                        end: 1_700_001_800_000,
+                        secs: 1800,
+                        apps: vec!["code".to_string(), "kitty".to_string()],
+                    }],
+                    context_switches: 3,
+                    switches_per_hour: 3.0,
+                    fragmentation: 0.5,
+                    interruptions: vec![InterruptionSource {
+                        app_name: "slack".to_string(),
+                        category: "communication".to_string(),
+                        count: 1,
+                        secs: 120,
+                    }],
+                }),
+                timesheet: Some(TimesheetInfo {
+                    from: 1_699_948_800_000,
+                    to: 1_700_035_200_000,
+                    by: TimesheetGroup::Project,
+                    round_secs: 900,
+                    blocks: vec![TimesheetBlock {
+                        name: Some("cronos".to_string()),
+                        start: 1_700_000_000_000,
+                        end: 1_700_001_800_000,
+                        tracked_secs: 1700,
+                        billed_secs: 1800,
+                        notes: vec!["client call".to_string()],
+                    }],
+                    totals: vec![TimeTotal {
+                        name: "cronos".to_string(),
+                        id: None,
+                        secs: 1800,
+                    }],
+                }),
+                overrides: vec![sample_override()],
+                annotations: vec![sample_annotation()],
+            },
+        },
+        MessageKind::QueryResultChunk {
+            response: QueryResponse {
+                events: vec![sample_event()],
+                ..QueryResponse::default()
+            },
+            seq: 1,
+            last: true,
+        },
+        MessageKind::StatusResult {
+            info: StatusInfo {
+                uptime_secs: 3600,
+                entity_count: 10,
+                edge_count: 20,
+                event_count: 30,
+                connected_collectors: 1,
+            },
+        },
+        MessageKind::CollectorList {
+            collectors: vec![collector],
+        },
+    ]
+}
+
+fn fixture_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+}
+
+#[test]
+fn every_message_kind_matches_its_golden_fixture() {
+    let update = std::env::var_os("UPDATE_FIXTURES").is_some();
+    let mut failures = Vec::new();
+
+    for kind in samples() {
+        let name = fixture_name(&kind);
+        let msg = Message::new(format!("golden-{name}"), kind);
+        let json = serde_json::to_string_pretty(&msg).unwrap() + "\n";
+        let path = fixture_dir().join(format!("{name}.json"));
+        if update {
+            std::fs::create_dir_all(fixture_dir()).unwrap();
+            std::fs::write(&path, &json).unwrap();
+            continue;
+        }
+        match std::fs::read_to_string(&path) {
+            Ok(expected) if expected == json => {}
+            Ok(_) => failures.push(format!("{name}: serialized form differs from fixture")),
+            Err(e) => failures.push(format!("{name}: {e}")),
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "wire format changed (UPDATE_FIXTURES=1 to accept):\n{}",
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn every_fixture_decodes_to_its_kind() {
+    let mut names: Vec<_> = samples().iter().map(fixture_name).collect();
+    names.sort();
+    names.dedup();
+    assert_eq!(names.len(), samples().len(), "duplicate sample kinds");
+
+    let mut files: Vec<_> = std::fs::read_dir(fixture_dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    files.sort();
+    assert_eq!(files.len(), names.len(), "fixture without a sample, or vice versa");
+
+    for path in files {
+        let json = std::fs::read_to_string(&path).unwrap();
+        let msg: Message = serde_json::from_str(&json)
+            .unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+        let stem = path.file_stem().unwrap().to_str().unwrap();
+        assert_eq!(fixture_name(&msg.kind), stem);
+        assert!(VersionRange::SUPPORTED.contains(msg.version));
+
+        // The binary codec carries exactly the same message
+        let packed = Codec::Msgpack.encode(&msg).unwrap();
+        assert_eq!(Codec::detect(&packed), Codec::Msgpack);
+        let unpacked = Codec::decode(&packed).unwrap_or_else(|e| panic!("{stem}: {e}"));
+        assert_eq!(
+            serde_json::to_value(&unpacked).unwrap(),
+            serde_json::to_value(&msg).unwrap(),
+            "{stem}: msgpack roundtrip changed the message"
+        );
+    }
+}
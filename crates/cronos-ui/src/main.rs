@@ -3,7 +3,7 @@
 use cronos_chat::openai::{Auth, ChatMessage};
 use cronos_chat::{autostart, credentials, oauth, openai, tools};
 use cronos_common::{CronosConfig, CronosPaths};
-use cronos_proto::{Message, MessageKind};
+use cronos_proto::{ErrorCode, Message, MessageKind};
 use std::path::PathBuf;
 use std::sync::Mutex;
 
@@ -83,31 +83,38 @@ async fn set_tracking_paused(
     }
 }
 
-#[tauri::command]
-async fn kill_collectors() -> Result<(), String> {
-    #[cfg(unix)]
-    {
-        let _ = std::process::Command::new("pkill")
-            .args(["-f", "cronos-collect"])
-            .output();
-    }
-    #[cfg(windows)]
-    {
-        let _ = std::process::Command::new("taskkill")
-            .args(["/F", "/IM", "cronos-collect-fs.exe"])
-            .output();
-        let _ = std::process::Command::new("taskkill")
-            .args(["/F", "/IM", "cronos-collect-appmon.exe"])
-            .output();
+/// Collectors managed by the daemon's supervisor.
+const COLLECTORS: &[&str] = &["cronos-collect-fs", "cronos-collect-appmon"];
+
+async fn control_collectors(
+    socket_path: &std::path::Path,
+    make: fn(String) -> MessageKind,
+) -> Result<(), String> {
+    for name in COLLECTORS {
+        let request_id = ulid::Ulid::new().to_string();
+        let msg = Message::new(request_id, make(name.to_string()));
+        let response = cronos_chat::daemon_client::send_request(msg, socket_path)
+            .await
+            .map_err(|e| e.to_string())?;
+        match response.kind {
+            MessageKind::Ack { .. } => {}
+            // Collectors that are disabled or not installed are not supervised
+            MessageKind::Error { code: ErrorCode::NotFound, .. } => {}
+            MessageKind::Error { message, .. } => return Err(message),
+            _ => return Err("Unexpected response from daemon".to_string()),
+        }
     }
     Ok(())
 }
 
 #[tauri::command]
-async fn start_collectors() -> Result<(), String> {
-    autostart::spawn_collector_if_absent();
-    autostart::spawn_appmon_if_absent();
-    Ok(())
+async fn kill_collectors(state: tauri::State<'_, AppState>) -> Result<(), String> {
+    control_collectors(&state.socket_path, |name| MessageKind::StopCollector { name }).await
+}
+
+#[tauri::command]
+async fn start_collectors(state: tauri::State<'_, AppState>) -> Result<(), String> {
+    control_collectors(&state.socket_path, |name| MessageKind::StartCollector { name }).await
 }
 
 // ---- Auth commands ----
@@ -243,23 +250,24 @@ fn main() {
         PathBuf::from(&config.daemon.socket_path)
     };
 
-    // Ensure daemon + collectors are running
+    // Ensure the daemon is running; it spawns and supervises the collectors
     let rt = tokio::runtime::Runtime::new().unwrap();
     rt.block_on(async {
         let _ = autostart::ensure_daemon(&socket_path).await;
     });
-    autostart::spawn_collector_if_absent();
-    autostart::spawn_appmon_if_absent();
 
     // System prompt
-    let now = chrono::Local::now().format("%Y-%m-%d %H:%M:%S %Z");
+    let timezone = cronos_common::tz::resolve(&config.daemon.timezone).unwrap_or(chrono_tz::Tz::UTC);
+    let now = cronos_common::tz::describe_now(timezone);
     let system_prompt = format!(
         "You are a personal developer assistant with access to the user's Cronos activity tracker. \
          Cronos tracks the user's app usage, window focus, and file changes. \
-         Use cronos_day_summary to see what they did on a given day, and cronos_sessions for detailed session breakdowns. \
-         Use cronos_recent for real-time file change events. \
+         Use cronos_day_summary to see what they did on a given day, cronos_summary for totals over a day, week or month, cronos_focus_report for how focused that time was, and cronos_sessions for detailed session breakdowns. \
+         Use cronos_recent for real-time file change events, and cronos_query for questions that combine filters, joins or totals. \
+         Use cronos_connection to explain how two things (e.g. a URL and a commit) are linked. \
+         Use cronos_annotations for notes the user attached to their time; when they describe what a stretch of time was for, offer to record it with cronos_propose_annotation, which they accept or remove themselves. \
          Use the provided tools to query the user's context and answer their questions. \
-         Today's date and time is {now}. Answer concisely."
+         The user's local time is {now}; dates they mention are days in that zone. Answer concisely."
     );
 
     let state = AppState {
@@ -80,11 +80,13 @@ pub async fn cmd_chat(model_override: Option<String>) -> anyhow::Result<()> {
         PathBuf::from(&config.daemon.socket_path)
     };
 
+    let timezone = cronos_common::tz::resolve(&config.daemon.timezone)
+        .map_err(|e| anyhow::anyhow!("daemon.timezone: {e}"))?;
+
+    // The daemon spawns and supervises the collectors itself.
     autostart::ensure_daemon(&socket_path).await?;
-    autostart::spawn_collector_if_absent();
-    autostart::spawn_appmon_if_absent();
 
-    repl::run_repl(auth, model, socket_path).await
+    repl::run_repl(auth, model, socket_path, timezone).await
 }
 
 fn build_chatgpt_auth(creds: &credentials::StoredCredentials) -> anyhow::Result<openai::Auth> {
@@ -13,7 +13,7 @@ use ratatui::{
     DefaultTerminal, Frame,
 };
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tokio::sync::mpsc;
 
 use cronos_chat::openai::{self, Auth, ChatMessage};
@@ -75,15 +75,17 @@ struct App {
 }
 
 impl App {
-    fn new(auth: Auth, model: String, socket_path: PathBuf) -> Self {
-        let now = chrono::Local::now().format("%Y-%m-%d %H:%M:%S %Z");
+    fn new(auth: Auth, model: String, socket_path: PathBuf, timezone: chrono_tz::Tz) -> Self {
+        let now = cronos_common::tz::describe_now(timezone);
         let system_prompt = format!(
             "You are a personal developer assistant with access to the user's Cronos activity tracker. \
              Cronos tracks the user's app usage, window focus, and file changes. \
-             Use cronos_day_summary to see what they did on a given day, and cronos_sessions for detailed session breakdowns. \
-             Use cronos_recent for real-time file change events. \
+             Use cronos_day_summary to see what they did on a given day, cronos_summary for totals over a day, week or month, cronos_focus_report for how focused that time was, and cronos_sessions for detailed session breakdowns. \
+             Use cronos_recent for real-time file change events, and cronos_query for questions that combine filters, joins or totals. \
+             Use cronos_connection to explain how two things (e.g. a URL and a commit) are linked. \
+             Use cronos_annotations for notes the user attached to their time; when they describe what a stretch of time was for, offer to record it with cronos_propose_annotation, which they accept or remove themselves. \
              Use the provided tools to query the user's context and answer their questions. \
-             Today's date and time is {now}. Answer concisely."
+             The user's local time is {now}; dates they mention are days in that zone. Answer concisely."
         );
 
         Self {
@@ -122,7 +124,12 @@ impl Drop for TerminalGuard {
 // Entry point
 // ---------------------------------------------------------------------------
 
-pub async fn run_repl(auth: Auth, model: String, socket_path: PathBuf) -> Result<()> {
+pub async fn run_repl(
+    auth: Auth,
+    model: String,
+    socket_path: PathBuf,
+    timezone: chrono_tz::Tz,
+) -> Result<()> {
     // Set up panic hook to restore terminal
     let default_hook = std::panic::take_hook();
     std::panic::set_hook(Box::new(move |info| {
@@ -136,7 +143,7 @@ pub async fn run_repl(auth: Auth, model: String, socket_path: PathBuf) -> Result
     let _guard = TerminalGuard;
 
     let terminal = ratatui::init();
-    let result = run_app(terminal, auth, model, socket_path).await;
+    let result = run_app(terminal, auth, model, socket_path, timezone).await;
 
     ratatui::restore();
     result
@@ -147,8 +154,9 @@ async fn run_app(
     auth: Auth,
     model: String,
     socket_path: PathBuf,
+    timezone: chrono_tz::Tz,
 ) -> Result<()> {
-    let mut app = App::new(auth, model, socket_path);
+    let mut app = App::new(auth, model, socket_path, timezone);
     let (backend_tx, mut backend_rx) = mpsc::unbounded_channel::<BackendMsg>();
     let mut event_stream = EventStream::new();
     let mut ticker = tokio::time::interval(tokio::time::Duration::from_millis(200));
@@ -164,10 +172,8 @@ async fn run_app(
         tokio::select! {
             // Terminal events
             maybe_event = event_stream.next() => {
-                if let Some(Ok(evt)) = maybe_event {
-                    if let Event::Key(key) = evt {
-                        handle_key_event(&mut app, key, &backend_tx);
-                    }
+                if let Some(Ok(Event::Key(key))) = maybe_event {
+                    handle_key_event(&mut app, key, &backend_tx);
                 }
             }
             // Backend messages
@@ -227,45 +233,37 @@ fn handle_key_event(app: &mut App, key: KeyEvent, backend_tx: &mpsc::UnboundedSe
             app.input.insert(app.cursor, c);
             app.cursor += c.len_utf8();
         }
-        KeyCode::Backspace if app.state == AppState::Idle => {
-            if app.cursor > 0 {
-                // Find the previous char boundary
-                let prev = app.input[..app.cursor]
-                    .char_indices()
-                    .next_back()
-                    .map(|(i, _)| i)
-                    .unwrap_or(0);
-                app.input.drain(prev..app.cursor);
-                app.cursor = prev;
-            }
+        KeyCode::Backspace if app.state == AppState::Idle && app.cursor > 0 => {
+            // Find the previous char boundary
+            let prev = app.input[..app.cursor]
+                .char_indices()
+                .next_back()
+                .map(|(i, _)| i)
+                .unwrap_or(0);
+            app.input.drain(prev..app.cursor);
+            app.cursor = prev;
         }
-        KeyCode::Delete if app.state == AppState::Idle => {
-            if app.cursor < app.input.len() {
-                let next = app.input[app.cursor..]
-                    .char_indices()
-                    .nth(1)
-                    .map(|(i, _)| app.cursor + i)
-                    .unwrap_or(app.input.len());
-                app.input.drain(app.cursor..next);
-            }
+        KeyCode::Delete if app.state == AppState::Idle && app.cursor < app.input.len() => {
+            let next = app.input[app.cursor..]
+                .char_indices()
+                .nth(1)
+                .map(|(i, _)| app.cursor + i)
+                .unwrap_or(app.input.len());
+            app.input.drain(app.cursor..next);
         }
-        KeyCode::Left if app.state == AppState::Idle => {
-            if app.cursor > 0 {
-                app.cursor = app.input[..app.cursor]
-                    .char_indices()
-                    .next_back()
-                    .map(|(i, _)| i)
-                    .unwrap_or(0);
-            }
+        KeyCode::Left if app.state == AppState::Idle && app.cursor > 0 => {
+            app.cursor = app.input[..app.cursor]
+                .char_indices()
+                .next_back()
+                .map(|(i, _)| i)
+                .unwrap_or(0);
         }
-        KeyCode::Right if app.state == AppState::Idle => {
-            if app.cursor < app.input.len() {
-                app.cursor = app.input[app.cursor..]
-                    .char_indices()
-                    .nth(1)
-                    .map(|(i, _)| app.cursor + i)
-                    .unwrap_or(app.input.len());
-            }
+        KeyCode::Right if app.state == AppState::Idle && app.cursor < app.input.len() => {
+            app.cursor = app.input[app.cursor..]
+                .char_indices()
+                .nth(1)
+                .map(|(i, _)| app.cursor + i)
+                .unwrap_or(app.input.len());
         }
         KeyCode::Home if app.state == AppState::Idle => {
             app.cursor = 0;
@@ -350,7 +348,7 @@ async fn run_agentic_loop(
     model: &str,
     history: &mut Vec<ChatMessage>,
     tool_defs: &[serde_json::Value],
-    socket_path: &PathBuf,
+    socket_path: &Path,
     tx: &mpsc::UnboundedSender<BackendMsg>,
 ) -> Result<String> {
     loop {
@@ -4,6 +4,21 @@ use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
 use cronos_common::{CronosConfig, CronosPaths};
 use cronos_core::engine::Engine;
+use cronos_core::export::archive;
+use cronos_core::export::graph::{GraphFormat, GraphSelection, GraphSlice};
+use cronos_core::export::timesheet::TimesheetFormat;
+use cronos_core::storage::backup;
+use cronos_core::storage::encryption;
+use cronos_core::storage::migrations;
+use cronos_core::storage::Repository;
+use cronos_model::{
+    Annotation, AnnotationId, AnnotationTarget, EntityId, EntityKind, OverrideAction, OverrideId,
+    TimeOverride,
+};
+use cronos_proto::{
+    CountTotal, Message, MessageKind, Period, QueryKind, QueryRequest, QueryResponse,
+    SummaryInfo, TimeTotal, TimesheetGroup,
+};
 use std::path::PathBuf;
 use std::sync::Arc;
 
@@ -29,6 +44,238 @@ enum Commands {
 
     /// Log out and remove stored credentials
     Logout,
+
+    /// Run a query against the daemon, e.g.
+    /// `cronos query 'sessions where time >= today | group by app | sum(duration)'`
+    Query {
+        /// Query in the pipeline language
+        expr: String,
+        /// Print the raw response as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Summarize a day, or the week or month around it
+    Summary {
+        /// Local date (YYYY-MM-DD); today when omitted
+        date: Option<chrono::NaiveDate>,
+        /// Summarize the week (Monday to Sunday) containing the date
+        #[arg(long, conflicts_with = "month")]
+        week: bool,
+        /// Summarize the month containing the date
+        #[arg(long)]
+        month: bool,
+        /// Print the raw response as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Run one read-only SQL statement against the daemon's store
+    Sql {
+        statement: String,
+        /// Print the raw response as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Export the context graph for Graphviz, Gephi or other graph tools
+    ExportGraph {
+        /// Output format: dot, graphml or jgf (JSON Graph Format)
+        #[arg(long, default_value = "dot")]
+        format: GraphFormat,
+        /// Only this project (path or directory name) and entities linked to it
+        #[arg(long)]
+        project: Option<String>,
+        /// Only entities of this kind, e.g. `file` or `url` (repeatable)
+        #[arg(long = "kind", value_parser = parse_entity_kind)]
+        kinds: Vec<EntityKind>,
+        /// Only entities and edges active on or after this local date (YYYY-MM-DD)
+        #[arg(long)]
+        since: Option<chrono::NaiveDate>,
+        /// Only entities and edges active on or before this local date (YYYY-MM-DD)
+        #[arg(long)]
+        until: Option<chrono::NaiveDate>,
+        /// Only edges at least this strong (0 to 1)
+        #[arg(long, default_value_t = 0.0)]
+        min_strength: f32,
+        /// Write to this file instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+
+    /// Export all recorded history to an archive (`-` for stdout)
+    Export {
+        path: PathBuf,
+    },
+
+    /// Merge an archive written by `cronos export` into this machine's history.
+    /// Stop the daemon first; it picks up the imported graph when it starts.
+    Import {
+        path: PathBuf,
+    },
+
+    /// Have the running daemon write a consistent copy of its database
+    Backup {
+        path: PathBuf,
+    },
+
+    /// Replace the database with a backup. Stop the daemon first; the current
+    /// database is kept beside it with a `.pre-restore-<time>` suffix.
+    Restore {
+        path: PathBuf,
+    },
+
+    /// Re-apply the daemon's categorization rules to recorded sessions
+    Recategorize {
+        /// Only sessions starting on or after this local date (YYYY-MM-DD)
+        #[arg(long)]
+        since: Option<chrono::NaiveDate>,
+        /// Only sessions starting on or before this local date (YYYY-MM-DD)
+        #[arg(long)]
+        until: Option<chrono::NaiveDate>,
+    },
+
+    /// Recompute recorded sessions from raw events
+    RebuildSessions {
+        /// From the start of this local date (YYYY-MM-DD)
+        #[arg(long)]
+        since: Option<chrono::NaiveDate>,
+        /// Through the end of this local date (YYYY-MM-DD)
+        #[arg(long)]
+        until: Option<chrono::NaiveDate>,
+    },
+
+    /// Billable time per project or category, e.g.
+    /// `cronos timesheet --from 2024-03-01 --to 2024-03-31 --round 15m`
+    Timesheet {
+        /// First local date (YYYY-MM-DD)
+        #[arg(long)]
+        from: chrono::NaiveDate,
+        /// Last local date, included (YYYY-MM-DD)
+        #[arg(long)]
+        to: chrono::NaiveDate,
+        /// Bill time to each `project` or `category`
+        #[arg(long, default_value = "project", value_parser = parse_timesheet_group)]
+        by: TimesheetGroup,
+        /// Round each block to the nearest multiple of this, e.g. 15m or 1h
+        #[arg(long, value_parser = parse_round)]
+        round: Option<u32>,
+        /// Output format: csv, json or ical
+        #[arg(long, default_value = "csv")]
+        format: TimesheetFormat,
+        /// Write to this file instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+
+    /// Correct tracked time for timesheets; recorded sessions stay as they are
+    Override {
+        #[command(subcommand)]
+        command: OverrideCommand,
+    },
+
+    /// Add notes and tags to time ranges, sessions or entities
+    Annotation {
+        #[command(subcommand)]
+        command: AnnotationCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum OverrideCommand {
+    /// Bill tracked time in a range to another project or category
+    Reassign {
+        #[command(flatten)]
+        span: OverrideSpan,
+        /// Project to bill the time to
+        #[arg(long, required_unless_present = "category")]
+        project: Option<String>,
+        /// Category to bill the time to
+        #[arg(long)]
+        category: Option<String>,
+    },
+    /// Leave tracked time in a range off timesheets, e.g. personal time
+    Exclude {
+        #[command(flatten)]
+        span: OverrideSpan,
+    },
+    /// Bill a whole range in place of whatever was tracked, e.g. an offline meeting
+    Add {
+        #[command(flatten)]
+        span: OverrideSpan,
+        /// Project the time was for
+        #[arg(long)]
+        project: Option<String>,
+        /// Category the time was for
+        #[arg(long, default_value = "other")]
+        category: String,
+    },
+    /// List overrides in the order they apply
+    List {
+        /// Only overrides reaching into this local date or later (YYYY-MM-DD)
+        #[arg(long)]
+        since: Option<chrono::NaiveDate>,
+        /// Only overrides starting on or before this local date (YYYY-MM-DD)
+        #[arg(long)]
+        until: Option<chrono::NaiveDate>,
+    },
+    /// Delete an override
+    Remove { id: String },
+}
+
+#[derive(Subcommand)]
+enum AnnotationCommand {
+    /// Annotate a time range, a session or an entity
+    Add {
+        text: String,
+        /// Local start of the range, e.g. "2024-03-05 09:00"
+        #[arg(long, value_parser = parse_local_time, requires = "to", conflicts_with_all = ["session", "entity"])]
+        from: Option<chrono::NaiveDateTime>,
+        /// Local end of the range
+        #[arg(long, value_parser = parse_local_time, requires = "from")]
+        to: Option<chrono::NaiveDateTime>,
+        /// Session id, as shown by `cronos sessions`
+        #[arg(long, conflicts_with = "entity")]
+        session: Option<String>,
+        /// Entity id
+        #[arg(long)]
+        entity: Option<String>,
+        /// Tag to file the annotation under; repeat for more
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+    },
+    /// List annotations by start time
+    List {
+        /// Only annotations reaching into this local date or later (YYYY-MM-DD)
+        #[arg(long)]
+        since: Option<chrono::NaiveDate>,
+        /// Only annotations starting on or before this local date (YYYY-MM-DD)
+        #[arg(long)]
+        until: Option<chrono::NaiveDate>,
+        /// Only annotations with this tag
+        #[arg(long)]
+        tag: Option<String>,
+        /// Only proposals from the assistant awaiting acceptance
+        #[arg(long)]
+        proposed: bool,
+    },
+    /// Accept an annotation the assistant proposed
+    Accept { id: String },
+    /// Delete an annotation
+    Remove { id: String },
+}
+
+#[derive(clap::Args)]
+struct OverrideSpan {
+    /// Local start time, e.g. "2024-03-05 09:00"
+    #[arg(long, value_parser = parse_local_time)]
+    from: chrono::NaiveDateTime,
+    /// Local end time, e.g. "2024-03-05 10:30"
+    #[arg(long, value_parser = parse_local_time)]
+    to: chrono::NaiveDateTime,
+    /// Why, shown with the blocks it affects
+    #[arg(long, default_value = "", hide_default_value = true)]
+    note: String,
 }
 
 // ---------------------------------------------------------------------------
@@ -43,6 +290,76 @@ async fn main() -> Result<()> {
         Some(Commands::Daemon) => cmd_daemon().await,
         Some(Commands::Login) => chat::cmd_login().await,
         Some(Commands::Logout) => chat::cmd_logout().await,
+        Some(Commands::Query { expr, json }) => {
+            cmd_query(QueryKind::Expr { query: expr }, json).await
+        }
+        Some(Commands::Summary {
+            date,
+            week,
+            month,
+            json,
+        }) => {
+            let period = if week {
+                Period::Week
+            } else if month {
+                Period::Month
+            } else {
+                Period::Day
+            };
+            cmd_summary(period, date, json).await
+        }
+        Some(Commands::Sql { statement, json }) => cmd_query(QueryKind::Sql { statement }, json).await,
+        Some(Commands::ExportGraph {
+            format,
+            project,
+            kinds,
+            since,
+            until,
+            min_strength,
+            output,
+        }) => {
+            let selection = GraphSelection {
+                project,
+                entity_kinds: kinds,
+                from: since.map(local_day_start).transpose()?,
+                // Through the end of `until`
+                to: until
+                    .map(|d| local_day_start(d + chrono::Days::new(1)).map(|ms| ms - 1))
+                    .transpose()?,
+                min_strength,
+            };
+            cmd_export_graph(format, &selection, output)
+        }
+        Some(Commands::Export { path }) => cmd_export(&path),
+        Some(Commands::Import { path }) => cmd_import(&path),
+        Some(Commands::Backup { path }) => cmd_backup(&path).await,
+        Some(Commands::Restore { path }) => cmd_restore(&path),
+        Some(Commands::Recategorize { since, until }) => {
+            let from = since.map(local_day_start).transpose()?.unwrap_or(0);
+            let to = until
+                .map(|d| local_day_start(d + chrono::Days::new(1)))
+                .transpose()?
+                .unwrap_or(i64::MAX);
+            cmd_recategorize(from, to).await
+        }
+        Some(Commands::RebuildSessions { since, until }) => {
+            let from = since.map(local_day_start).transpose()?.unwrap_or(0);
+            let to = until
+                .map(|d| local_day_start(d + chrono::Days::new(1)))
+                .transpose()?
+                .unwrap_or(i64::MAX);
+            cmd_rebuild_sessions(from, to).await
+        }
+        Some(Commands::Timesheet {
+            from,
+            to,
+            by,
+            round,
+            format,
+            output,
+        }) => cmd_timesheet(from, to, by, round.unwrap_or(0), format, output).await,
+        Some(Commands::Override { command }) => cmd_override(command).await,
+        Some(Commands::Annotation { command }) => cmd_annotation(command).await,
         None => {
             let paths = CronosPaths::resolve()?;
             std::fs::create_dir_all(&paths.config_dir)?;
@@ -166,7 +483,37 @@ async fn cmd_daemon() -> Result<()> {
     cronos_core::aggregator::spawn_aggregator(
         Arc::clone(&engine),
         config.daemon.aggregator.interval_secs,
-        config.daemon.aggregator.session_gap_ms,
+    );
+
+    // Rolling backups
+    if config.daemon.backup.enabled {
+        let backup = &config.daemon.backup;
+        let dir = if backup.dir.is_empty() {
+            paths.data_dir.join("backups")
+        } else {
+            PathBuf::from(&backup.dir)
+        };
+        backup::spawn_backups(
+            Arc::clone(&engine),
+            dir,
+            std::time::Duration::from_secs(backup.interval_hours.max(1) * 3600),
+            backup.keep,
+        );
+    }
+
+    // Spawn and supervise enabled collectors
+    if config.daemon.supervisor.enabled {
+        let exe = std::env::current_exe().context("locating cronos binary")?;
+        let bin_dir = exe.parent().context("locating binary directory")?;
+        engine.install_supervisor(cronos_core::supervisor::Supervisor::from_config(
+            &config.collectors,
+            &config.daemon.supervisor,
+            bin_dir,
+        ));
+    }
+    cronos_core::supervisor::spawn_supervisor(
+        Arc::clone(&engine),
+        config.daemon.supervisor.check_interval_secs,
     );
 
     let engine_ref = Arc::clone(&engine);
@@ -174,12 +521,806 @@ async fn cmd_daemon() -> Result<()> {
         cronos_core::server::run(engine_ref, &socket_path).await
     });
 
+    // Optional TCP/TLS listener for collectors outside this machine
+    let tcp_handle = if config.daemon.tcp.enabled {
+        let tcp = &config.daemon.tcp;
+        cronos_core::server::tls::validate(tcp)?;
+        let acceptor = cronos_core::server::tls::acceptor(tcp).context("loading TLS config")?;
+        let listener = tokio::net::TcpListener::bind(&tcp.listen_addr)
+            .await
+            .with_context(|| format!("binding {}", tcp.listen_addr))?;
+        let engine_ref = Arc::clone(&engine);
+        Some(tokio::spawn(async move {
+            cronos_core::server::run_tcp(engine_ref, listener, acceptor).await
+        }))
+    } else {
+        None
+    };
+
     tokio::signal::ctrl_c().await?;
     tracing::info!("shutting down");
 
     server_handle.abort();
     let _ = server_handle.await;
+    if let Some(handle) = tcp_handle {
+        handle.abort();
+        let _ = handle.await;
+    }
+    engine.stop_collectors();
+
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Query and SQL commands
+// ---------------------------------------------------------------------------
+
+async fn cmd_query(kind: QueryKind, json: bool) -> Result<()> {
+    let paths = CronosPaths::resolve().context("resolving paths")?;
+    let config = CronosConfig::load(&paths.config_file).context("loading config")?;
+    let socket_path = resolve_socket_path(&config, &paths);
+    // Times are shown in the zone the daemon filters hours and days in.
+    let tz = cronos_common::tz::resolve(&config.daemon.timezone).context("daemon.timezone")?;
+
+    let msg = Message::new(
+        ulid::Ulid::new().to_string(),
+        MessageKind::Query {
+            query: QueryRequest::new(kind),
+        },
+    );
+    let response = cronos_chat::daemon_client::send_request(msg, &socket_path).await?;
+    let response = match response.kind {
+        MessageKind::QueryResult { response } => response,
+        MessageKind::Error { message, .. } => anyhow::bail!("{message}"),
+        other => anyhow::bail!("unexpected response: {other:?}"),
+    };
+    if json {
+        println!("{}", serde_json::to_string_pretty(&response)?);
+        return Ok(());
+    }
+
+    let rows: Vec<Vec<String>> = if let Some(table) = &response.table {
+        std::iter::once(table.columns.clone())
+            .chain(table.rows.iter().map(|row| row.iter().map(cell).collect()))
+            .collect()
+    } else {
+        let events = response.events.iter().map(|e| {
+            vec![
+                local_time(e.timestamp, tz),
+                cell(&serde_json::to_value(&e.kind).unwrap_or_default()),
+                e.subject.identity.clone(),
+            ]
+        });
+        let entities = response
+            .entities
+            .iter()
+            .map(|e| vec![local_time(e.last_seen, tz), e.kind.to_string(), e.name.clone()]);
+        let sessions = response.sessions.iter().map(|s| {
+            vec![
+                local_time(s.start_time, tz),
+                format!("{}m", s.duration_secs / 60),
+                s.app_name.clone(),
+                s.project.clone().unwrap_or_default(),
+            ]
+        });
+        events.chain(entities).chain(sessions).collect()
+    };
+    if rows.is_empty() {
+        println!("No results.");
+    }
+    print_columns(&rows);
+    if response.table.is_some_and(|t| t.truncated) {
+        println!("(truncated at the daemon's row limit)");
+    }
+    Ok(())
+}
+
+fn local_time(ms: i64, tz: chrono_tz::Tz) -> String {
+    chrono::DateTime::from_timestamp_millis(ms)
+        .map(|t| t.with_timezone(&tz).format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default()
+}
+
+fn cell(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/// Print rows as left-aligned columns separated by two spaces.
+fn print_columns(rows: &[Vec<String>]) {
+    let mut widths = Vec::new();
+    for row in rows {
+        for (i, value) in row.iter().enumerate() {
+            let width = value.chars().count();
+            match widths.get_mut(i) {
+                Some(w) if *w < width => *w = width,
+                Some(_) => {}
+                None => widths.push(width),
+            }
+        }
+    }
+    for row in rows {
+        let line: Vec<String> = row
+            .iter()
+            .enumerate()
+            .map(|(i, value)| format!("{value:<width$}", width = widths[i]))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+}
+
+async fn cmd_summary(period: Period, date: Option<chrono::NaiveDate>, json: bool) -> Result<()> {
+    let paths = CronosPaths::resolve().context("resolving paths")?;
+    let config = CronosConfig::load(&paths.config_file).context("loading config")?;
+    let socket_path = resolve_socket_path(&config, &paths);
+    let tz = cronos_common::tz::resolve(&config.daemon.timezone).context("daemon.timezone")?;
+    let date = date.unwrap_or_else(|| cronos_common::tz::date_of(tz, cronos_common::now_ms()));
+
+    let msg = Message::new(
+        ulid::Ulid::new().to_string(),
+        MessageKind::Query {
+            query: QueryRequest::new(QueryKind::Summary {
+                period,
+                anchor: date.to_string(),
+                timezone: Some(tz.name().to_string()),
+            }),
+        },
+    );
+    let response = cronos_chat::daemon_client::send_request(msg, &socket_path).await?;
+    let summary = match response.kind {
+        MessageKind::QueryResult { response } => response.summary,
+        MessageKind::Error { message, .. } => anyhow::bail!("{message}"),
+        other => anyhow::bail!("unexpected response: {other:?}"),
+    };
+    let Some(summary) = summary else {
+        anyhow::bail!("the daemon returned no summary");
+    };
+    if json {
+        println!("{}", serde_json::to_string_pretty(&summary)?);
+        return Ok(());
+    }
+    print_summary(&summary, tz);
+    Ok(())
+}
+
+fn print_summary(summary: &SummaryInfo, tz: chrono_tz::Tz) {
+    let local = |ms: i64, format: &str| {
+        chrono::DateTime::from_timestamp_millis(ms)
+            .map(|t| t.with_timezone(&tz).format(format).to_string())
+            .unwrap_or_default()
+    };
+    println!(
+        "{} to {} ({})",
+        local(summary.start, "%Y-%m-%d"),
+        // The last day, not the exclusive end
+        local(summary.end - 1, "%Y-%m-%d"),
+        summary.timezone
+    );
+    println!(
+        "{} tracked in {} sessions, {} context switches",
+        hours_minutes(summary.total_secs),
+        summary.session_count,
+        summary.context_switches
+    );
+    if !summary.annotations.is_empty() {
+        println!("\nAnnotations");
+        for annotation in &summary.annotations {
+            println!("  {}", describe_annotation(annotation, tz));
+        }
+    }
+    if summary.session_count == 0 {
+        return;
+    }
+
+    let totals = |title: &str, totals: &[TimeTotal]| {
+        if totals.is_empty() {
+            return;
+        }
+        println!("\n{title}");
+        let rows: Vec<Vec<String>> = totals
+            .iter()
+            .map(|t| vec![format!("  {}", t.name), hours_minutes(t.secs)])
+            .collect();
+        print_columns(&rows);
+    };
+    totals("Categories", &summary.categories);
+    totals("Apps", &summary.apps);
+    totals("Projects", &summary.projects);
+
+    println!("\nBy hour");
+    let busiest = summary.hours.iter().copied().max().unwrap_or(0).max(1);
+    for (hour, secs) in summary.hours.iter().enumerate() {
+        if *secs > 0 {
+            let bar = "#".repeat(((secs * 30 + busiest - 1) / busiest) as usize);
+            println!("  {hour:02}:00  {bar:<30}  {}", hours_minutes(*secs));
+        }
+    }
+
+    if !summary.focus_blocks.is_empty() {
+        println!("\nLongest focus blocks");
+        let rows: Vec<Vec<String>> = summary
+            .focus_blocks
+            .iter()
+            .map(|b| {
+                vec![
+                    format!("  {}", local(b.start, "%a %H:%M")),
+                    hours_minutes(b.secs),
+                    b.project.clone(),
+                    b.apps.join(", "),
+                ]
+            })
+            .collect();
+        print_columns(&rows);
+    }
+
+    let counts = |title: &str, counts: &[CountTotal]| {
+        if counts.is_empty() {
+            return;
+        }
+        println!("\n{title}");
+        let rows: Vec<Vec<String>> = counts
+            .iter()
+            .map(|c| vec![format!("  {}", c.count), c.name.clone()])
+            .collect();
+        print_columns(&rows);
+    };
+    counts("Top files", &summary.top_files);
+    counts("Top domains", &summary.top_domains);
+}
+
+/// `secs` as `3h 05m`, or `12m` under an hour.
+fn hours_minutes(secs: i64) -> String {
+    let minutes = secs / 60;
+    if minutes < 60 {
+        format!("{minutes}m")
+    } else {
+        format!("{}h {:02}m", minutes / 60, minutes % 60)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Graph export
+// ---------------------------------------------------------------------------
+
+fn cmd_export_graph(
+    format: GraphFormat,
+    selection: &GraphSelection,
+    output: Option<PathBuf>,
+) -> Result<()> {
+    let paths = CronosPaths::resolve().context("resolving paths")?;
+    let config = CronosConfig::load(&paths.config_file).context("loading config")?;
+    let db_path = resolve_db_path(&config, &paths);
+    anyhow::ensure!(db_path.exists(), "no database at {}", db_path.display());
+
+    let repo = open_repo_read_only(&config, &db_path)?;
+    let slice = GraphSlice::select(&repo, selection).context("reading graph")?;
+    match output {
+        Some(path) => {
+            let mut file = std::io::BufWriter::new(
+                std::fs::File::create(&path)
+                    .with_context(|| format!("creating {}", path.display()))?,
+            );
+            format.write(&slice, &mut file)?;
+            std::io::Write::flush(&mut file)?;
+            eprintln!(
+                "Wrote {} entities and {} edges to {}",
+                slice.entities.len(),
+                slice.edges.len(),
+                path.display()
+            );
+        }
+        None => format.write(&slice, &mut std::io::stdout().lock())?,
+    }
+    Ok(())
+}
+
+fn parse_entity_kind(s: &str) -> Result<EntityKind, String> {
+    serde_json::from_value(serde_json::Value::String(s.to_string()))
+        .map_err(|_| format!("unknown entity kind '{s}'"))
+}
+
+fn parse_timesheet_group(s: &str) -> Result<TimesheetGroup, String> {
+    serde_json::from_value(serde_json::Value::String(s.to_string()))
+        .map_err(|_| format!("cannot bill by '{s}' (expected project or category)"))
+}
+
+/// A rounding step like `15m`, `1h` or `90s`, in seconds.
+fn parse_round(s: &str) -> Result<u32, String> {
+    let (number, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+    let number: u32 = number
+        .parse()
+        .map_err(|_| format!("invalid rounding step '{s}', e.g. 15m"))?;
+    let scale = match unit {
+        "s" => 1,
+        "m" | "" => 60,
+        "h" => 3600,
+        _ => return Err(format!("invalid rounding step '{s}', e.g. 15m")),
+    };
+    number
+        .checked_mul(scale)
+        .ok_or_else(|| format!("rounding step '{s}' is too large"))
+}
+
+/// A local date and time like `2024-03-05 09:00`.
+fn parse_local_time(s: &str) -> Result<chrono::NaiveDateTime, String> {
+    ["%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S"]
+        .iter()
+        .find_map(|format| chrono::NaiveDateTime::parse_from_str(s, format).ok())
+        .ok_or_else(|| format!("invalid time '{s}', expected e.g. \"2024-03-05 09:00\""))
+}
+
+/// Milliseconds at the start of `date` in the configured timezone.
+fn local_day_start(date: chrono::NaiveDate) -> Result<i64> {
+    Ok(cronos_common::tz::day_start(configured_timezone()?, date))
+}
 
+/// The zone `daemon.timezone` names, or the system zone.
+fn configured_timezone() -> Result<chrono_tz::Tz> {
+    let paths = CronosPaths::resolve().context("resolving paths")?;
+    let config = CronosConfig::load(&paths.config_file).context("loading config")?;
+    cronos_common::tz::resolve(&config.daemon.timezone).context("daemon.timezone")
+}
+
+// ---------------------------------------------------------------------------
+// Archive export and import
+// ---------------------------------------------------------------------------
+
+fn cmd_export(path: &std::path::Path) -> Result<()> {
+    let paths = CronosPaths::resolve().context("resolving paths")?;
+    let config = CronosConfig::load(&paths.config_file).context("loading config")?;
+    let db_path = resolve_db_path(&config, &paths);
+    anyhow::ensure!(db_path.exists(), "no database at {}", db_path.display());
+    let repo = open_repo_read_only(&config, &db_path)?;
+
+    let manifest = if path.as_os_str() == "-" {
+        archive::export(&repo, &mut std::io::stdout().lock())?
+    } else {
+        let mut file = std::io::BufWriter::new(
+            std::fs::File::create(path).with_context(|| format!("creating {}", path.display()))?,
+        );
+        archive::export(&repo, &mut file)?
+    };
+    let counts = manifest.counts;
+    eprintln!(
+        "Exported {} entities, {} edges, {} events and {} sessions",
+        counts.entities, counts.edges, counts.events, counts.sessions
+    );
+    Ok(())
+}
+
+fn cmd_import(path: &std::path::Path) -> Result<()> {
+    let paths = CronosPaths::resolve().context("resolving paths")?;
+    let config = CronosConfig::load(&paths.config_file).context("loading config")?;
+    // The daemon keeps the graph in memory and would not see the import
+    ensure_daemon_stopped(&config, &paths, "importing")?;
+
+    let db_path = resolve_db_path(&config, &paths);
+    if let Some(dir) = db_path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let repo = open_repo(&config, &db_path)?;
+    let file = std::fs::File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    let stats = archive::import(&repo, &mut std::io::BufReader::new(file))
+        .with_context(|| format!("importing {}", path.display()))?;
+    println!(
+        "Entities: {} added, {} merged",
+        stats.entities_added, stats.entities_merged
+    );
+    println!("Edges:    {} added, {} merged", stats.edges_added, stats.edges_merged);
+    println!(
+        "Events:   {} added, {} already present",
+        stats.events_added, stats.events_skipped
+    );
+    println!(
+        "Sessions: {} added, {} already present",
+        stats.sessions_added, stats.sessions_skipped
+    );
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Backup and restore
+// ---------------------------------------------------------------------------
+
+async fn cmd_backup(path: &std::path::Path) -> Result<()> {
+    let paths = CronosPaths::resolve().context("resolving paths")?;
+    let config = CronosConfig::load(&paths.config_file).context("loading config")?;
+    let socket_path = resolve_socket_path(&config, &paths);
+    // The daemon resolves paths against its own working directory
+    let path = std::path::absolute(path)?;
+
+    let msg = Message::new(
+        ulid::Ulid::new().to_string(),
+        MessageKind::Backup {
+            path: path.display().to_string(),
+        },
+    );
+    let response = cronos_chat::daemon_client::send_request(msg, &socket_path).await?;
+    match response.kind {
+        MessageKind::Ack { .. } => {
+            println!("Backed up to {}", path.display());
+            Ok(())
+        }
+        MessageKind::Error { message, .. } => anyhow::bail!("{message}"),
+        other => anyhow::bail!("unexpected response: {other:?}"),
+    }
+}
+
+async fn cmd_recategorize(from: i64, to: i64) -> Result<()> {
+    let paths = CronosPaths::resolve().context("resolving paths")?;
+    let config = CronosConfig::load(&paths.config_file).context("loading config")?;
+    let socket_path = resolve_socket_path(&config, &paths);
+
+    let msg = Message::new(
+        ulid::Ulid::new().to_string(),
+        MessageKind::RecategorizeSessions { from, to },
+    );
+    let response = cronos_chat::daemon_client::send_request(msg, &socket_path).await?;
+    match response.kind {
+        MessageKind::Recategorized { updated } => {
+            println!("Recategorized {updated} sessions");
+            Ok(())
+        }
+        MessageKind::Error { message, .. } => anyhow::bail!("{message}"),
+        other => anyhow::bail!("unexpected response: {other:?}"),
+    }
+}
+
+async fn cmd_rebuild_sessions(from: i64, to: i64) -> Result<()> {
+    let paths = CronosPaths::resolve().context("resolving paths")?;
+    let config = CronosConfig::load(&paths.config_file).context("loading config")?;
+    let socket_path = resolve_socket_path(&config, &paths);
+
+    let msg = Message::new(
+        ulid::Ulid::new().to_string(),
+        MessageKind::RebuildSessions { from, to },
+    );
+    let response = cronos_chat::daemon_client::send_request(msg, &socket_path).await?;
+    match response.kind {
+        MessageKind::SessionsRebuilt { removed, created } => {
+            println!("Replaced {removed} sessions with {created}");
+            Ok(())
+        }
+        MessageKind::Error { message, .. } => anyhow::bail!("{message}"),
+        other => anyhow::bail!("unexpected response: {other:?}"),
+    }
+}
+
+/// Send `kind` to the daemon and return its reply, failing on `Error`.
+async fn request(kind: MessageKind) -> Result<MessageKind> {
+    let paths = CronosPaths::resolve().context("resolving paths")?;
+    let config = CronosConfig::load(&paths.config_file).context("loading config")?;
+    let socket_path = resolve_socket_path(&config, &paths);
+
+    let msg = Message::new(ulid::Ulid::new().to_string(), kind);
+    let response = cronos_chat::daemon_client::send_request(msg, &socket_path).await?;
+    match response.kind {
+        MessageKind::Error { message, .. } => anyhow::bail!("{message}"),
+        other => Ok(other),
+    }
+}
+
+async fn query(kind: QueryKind) -> Result<QueryResponse> {
+    let query = QueryRequest::new(kind);
+    match request(MessageKind::Query { query }).await? {
+        MessageKind::QueryResult { response } => Ok(response),
+        other => anyhow::bail!("unexpected response: {other:?}"),
+    }
+}
+
+async fn cmd_timesheet(
+    from: chrono::NaiveDate,
+    to: chrono::NaiveDate,
+    by: TimesheetGroup,
+    round_secs: u32,
+    format: TimesheetFormat,
+    output: Option<PathBuf>,
+) -> Result<()> {
+    let tz = configured_timezone()?;
+    let kind = QueryKind::Timesheet {
+        from: cronos_common::tz::day_start(tz, from),
+        to: cronos_common::tz::day_start(tz, to + chrono::Days::new(1)),
+        by,
+        round_secs,
+    };
+    let Some(sheet) = query(kind).await?.timesheet else {
+        anyhow::bail!("the daemon returned no timesheet");
+    };
+    match output {
+        Some(path) => {
+            let mut file = std::io::BufWriter::new(
+                std::fs::File::create(&path)
+                    .with_context(|| format!("creating {}", path.display()))?,
+            );
+            format.write(&sheet, tz, &mut file)?;
+            std::io::Write::flush(&mut file)?;
+            let billed: i64 = sheet.blocks.iter().map(|b| b.billed_secs).sum();
+            eprintln!(
+                "Wrote {} blocks ({}) to {}",
+                sheet.blocks.len(),
+                hours_minutes(billed),
+                path.display()
+            );
+        }
+        None => format.write(&sheet, tz, &mut std::io::stdout().lock())?,
+    }
+    Ok(())
+}
+
+async fn cmd_override(command: OverrideCommand) -> Result<()> {
+    let tz = configured_timezone()?;
+    let (span, action) = match command {
+        OverrideCommand::Reassign {
+            span,
+            project,
+            category,
+        } => (span, OverrideAction::Reassign { project, category }),
+        OverrideCommand::Exclude { span } => (span, OverrideAction::Exclude),
+        OverrideCommand::Add {
+            span,
+            project,
+            category,
+        } => (span, OverrideAction::Add { project, category }),
+        OverrideCommand::List { since, until } => {
+            let from = since.map(local_day_start).transpose()?.unwrap_or(0);
+            let to = until
+                .map(|d| local_day_start(d + chrono::Days::new(1)))
+                .transpose()?
+                .unwrap_or(i64::MAX);
+            let overrides = query(QueryKind::TimeOverrides { from, to }).await?.overrides;
+            if overrides.is_empty() {
+                println!("No overrides.");
+            }
+            let local = |ms: i64| {
+                chrono::DateTime::from_timestamp_millis(ms)
+                    .map(|t| t.with_timezone(&tz).format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_default()
+            };
+            let rows: Vec<Vec<String>> = overrides
+                .iter()
+                .map(|o| {
+                    vec![
+                        o.id.to_string(),
+                        local(o.start),
+                        local(o.end),
+                        describe_override(&o.action),
+                        o.note.clone(),
+                    ]
+                })
+                .collect();
+            print_columns(&rows);
+            return Ok(());
+        }
+        OverrideCommand::Remove { id } => {
+            let id = OverrideId(id.parse().map_err(|e| anyhow::anyhow!("invalid override id '{id}': {e}"))?);
+            request(MessageKind::RemoveTimeOverride { id }).await?;
+            println!("Removed override {id}");
+            return Ok(());
+        }
+    };
+
+    let local = |time: chrono::NaiveDateTime| {
+        cronos_common::tz::local_millis(tz, time)
+            .with_context(|| format!("{time} does not exist in {tz}"))
+    };
+    let record = TimeOverride {
+        id: OverrideId::new(),
+        start: local(span.from)?,
+        end: local(span.to)?,
+        action,
+        note: span.note,
+        created_at: cronos_common::now_ms(),
+    };
+    anyhow::ensure!(record.start < record.end, "--to must be after --from");
+    let id = record.id;
+    request(MessageKind::AddTimeOverride { record }).await?;
+    println!("Added override {id}");
+    Ok(())
+}
+
+fn describe_override(action: &OverrideAction) -> String {
+    let target = |project: &Option<String>, category: Option<&str>| {
+        let mut parts = Vec::new();
+        if let Some(project) = project {
+            parts.push(format!("project={project}"));
+        }
+        if let Some(category) = category {
+            parts.push(format!("category={category}"));
+        }
+        parts.join(" ")
+    };
+    match action {
+        OverrideAction::Reassign { project, category } => {
+            format!("reassign {}", target(project, category.as_deref()))
+        }
+        OverrideAction::Exclude => "exclude".to_string(),
+        OverrideAction::Add { project, category } => {
+            format!("add {}", target(project, Some(category)))
+        }
+    }
+}
+
+async fn cmd_annotation(command: AnnotationCommand) -> Result<()> {
+    let tz = configured_timezone()?;
+    let parse_id = |id: &str| -> Result<AnnotationId> {
+        Ok(AnnotationId(id.parse().map_err(|e| {
+            anyhow::anyhow!("invalid annotation id '{id}': {e}")
+        })?))
+    };
+    match command {
+        AnnotationCommand::Add {
+            text,
+            from,
+            to,
+            session,
+            entity,
+            tags,
+        } => {
+            let target = match (from.zip(to), session, entity) {
+                (Some((from, to)), _, _) => {
+                    let local = |time: chrono::NaiveDateTime| {
+                        cronos_common::tz::local_millis(tz, time)
+                            .with_context(|| format!("{time} does not exist in {tz}"))
+                    };
+                    let (start, end) = (local(from)?, local(to)?);
+                    anyhow::ensure!(start < end, "--to must be after --from");
+                    AnnotationTarget::Range { start, end }
+                }
+                (None, Some(session_id), _) => AnnotationTarget::Session { session_id },
+                (None, None, Some(entity)) => AnnotationTarget::Entity {
+                    entity_id: EntityId(
+                        entity
+                            .parse()
+                            .map_err(|e| anyhow::anyhow!("invalid entity id '{entity}': {e}"))?,
+                    ),
+                },
+                (None, None, None) => anyhow::bail!("give --from and --to, --session or --entity"),
+            };
+            let annotation = Annotation {
+                id: AnnotationId::new(),
+                target,
+                text,
+                tags,
+                start: None,
+                end: None,
+                proposed: false,
+                created_at: cronos_common::now_ms(),
+            };
+            let id = annotation.id;
+            request(MessageKind::AddAnnotation { annotation }).await?;
+            println!("Added annotation {id}");
+        }
+        AnnotationCommand::List {
+            since,
+            until,
+            tag,
+            proposed,
+        } => {
+            let from = since.map(local_day_start).transpose()?;
+            let to = until
+                .map(|d| local_day_start(d + chrono::Days::new(1)))
+                .transpose()?;
+            let annotations = query(QueryKind::Annotations {
+                from,
+                to,
+                tag,
+                session_id: None,
+                entity_id: None,
+                proposed,
+            })
+            .await?
+            .annotations;
+            if annotations.is_empty() {
+                println!("No annotations.");
+            }
+            let rows: Vec<Vec<String>> = annotations
+                .iter()
+                .map(|a| vec![a.id.to_string(), describe_annotation(a, tz)])
+                .collect();
+            print_columns(&rows);
+        }
+        AnnotationCommand::Accept { id } => {
+            let id = parse_id(&id)?;
+            request(MessageKind::AcceptAnnotation { id }).await?;
+            println!("Accepted annotation {id}");
+        }
+        AnnotationCommand::Remove { id } => {
+            let id = parse_id(&id)?;
+            request(MessageKind::DeleteAnnotation { id }).await?;
+            println!("Removed annotation {id}");
+        }
+    }
+    Ok(())
+}
+
+/// When or what `annotation` is on, its text and tags, e.g.
+/// `2024-03-05 09:00-10:30  Sprint planning #meeting (proposed)`.
+fn describe_annotation(annotation: &Annotation, tz: chrono_tz::Tz) -> String {
+    let local = |ms: i64, format: &str| {
+        chrono::DateTime::from_timestamp_millis(ms)
+            .map(|t| t.with_timezone(&tz).format(format).to_string())
+            .unwrap_or_default()
+    };
+    let span = |start: i64, end: i64| {
+        format!("{}-{}", local(start, "%Y-%m-%d %H:%M"), local(end, "%H:%M"))
+    };
+    let on = match &annotation.target {
+        AnnotationTarget::Range { start, end } => span(*start, *end),
+        AnnotationTarget::Session { session_id } => match annotation.start.zip(annotation.end) {
+            Some((start, end)) => span(start, end),
+            None => format!("session {session_id}"),
+        },
+        AnnotationTarget::Entity { entity_id } => format!("entity {entity_id}"),
+    };
+    let mut line = format!("{on}  {}", annotation.text);
+    for tag in &annotation.tags {
+        line.push_str(&format!(" #{tag}"));
+    }
+    if annotation.proposed {
+        line.push_str(" (proposed)");
+    }
+    line
+}
+
+fn cmd_restore(path: &std::path::Path) -> Result<()> {
+    let paths = CronosPaths::resolve().context("resolving paths")?;
+    let config = CronosConfig::load(&paths.config_file).context("loading config")?;
+    ensure_daemon_stopped(&config, &paths, "restoring")?;
+
+    let db_path = resolve_db_path(&config, &paths);
+    if let Some(dir) = db_path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let key = encryption::resolve_key(&config.daemon.encryption, &db_path).context("resolving database key")?;
+    let kept = backup::restore(path, &db_path, key.as_ref())
+        .with_context(|| format!("restoring {}", path.display()))?;
+    println!("Restored {} to {}", path.display(), db_path.display());
+    if let Some(kept) = kept {
+        println!("The previous database is at {}", kept.display());
+    }
+    Ok(())
+}
+
+/// Open the database at `db_path`, encrypted if the config says so. This
+/// may migrate it or encrypt it in place, so the daemon must be stopped.
+fn open_repo(config: &CronosConfig, db_path: &std::path::Path) -> Result<Repository> {
+    let key = encryption::resolve_key(&config.daemon.encryption, db_path).context("resolving database key")?;
+    let repo = match key {
+        Some(key) => Repository::open_encrypted(db_path, &key)?,
+        None => Repository::open(db_path)?,
+    };
+    Ok(repo)
+}
+
+/// Open the database at `db_path` to read it while the daemon may be
+/// running. A database still waiting for its migration or encryption is
+/// left for the daemon to upgrade.
+fn open_repo_read_only(config: &CronosConfig, db_path: &std::path::Path) -> Result<Repository> {
+    let key = if encryption::is_plaintext(db_path)? {
+        None
+    } else {
+        encryption::resolve_key(&config.daemon.encryption, db_path)
+            .context("resolving database key")?
+    };
+    let repo = Repository::open_read_only(db_path, key.as_ref())?;
+    let version = repo.schema_version()?;
+    anyhow::ensure!(
+        version == migrations::CURRENT_VERSION,
+        "the database has schema version {version}, not {}; start the daemon once to upgrade it",
+        migrations::CURRENT_VERSION
+    );
+    Ok(repo)
+}
+
+/// Fail if a daemon answers on the socket: `action` needs the database to itself.
+fn ensure_daemon_stopped(config: &CronosConfig, paths: &CronosPaths, action: &str) -> Result<()> {
+    let socket_path = resolve_socket_path(config, paths);
+    if std::os::unix::net::UnixStream::connect(&socket_path).is_ok() {
+        anyhow::bail!("the daemon is running; stop it before {action}");
+    }
     Ok(())
 }
 